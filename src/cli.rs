@@ -0,0 +1,93 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Command line interface.
+
+use std::path::PathBuf;
+
+pub mod argfile;
+pub mod audit;
+pub mod check;
+pub mod command;
+#[cfg(feature = "dynamic-completions")]
+pub mod complete;
+pub mod config;
+#[cfg(unix)]
+pub mod control;
+#[cfg(feature = "dbus")]
+pub mod dbus;
+pub mod discover;
+pub mod dns;
+pub mod doctor;
+pub mod edit;
+pub mod emit;
+pub mod ethernet;
+pub mod events;
+pub mod export;
+pub mod fmt;
+pub mod fuzzy;
+pub mod hint;
+pub mod history;
+pub mod hosts;
+pub mod import;
+pub mod input;
+pub mod inspect;
+pub mod interrupt;
+pub mod launchd;
+pub mod list;
+pub mod listen;
+pub mod log;
+pub mod manage;
+pub mod metrics;
+pub mod neighbours;
+pub mod online;
+pub mod oui;
+pub mod passwd;
+pub mod pcap;
+pub mod ratelimit;
+#[cfg(feature = "relay")]
+pub mod relay;
+pub mod reload;
+pub mod report;
+pub mod resolve;
+pub mod sandbox;
+pub mod scan;
+pub mod schedule;
+pub mod secureon;
+pub mod serve;
+pub mod sleep;
+pub mod socket;
+pub mod socks;
+pub mod ssh;
+pub mod status;
+pub mod subnet;
+pub mod systemd;
+pub mod target;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod tokens;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod validate;
+pub mod wake;
+#[cfg(all(windows, feature = "windows-service"))]
+pub mod windows;
+
+#[derive(Debug, Clone)]
+enum PathOrStdin {
+    Stdin,
+    Path(PathBuf),
+}
+
+impl From<String> for PathOrStdin {
+    fn from(value: String) -> Self {
+        if value == "-" {
+            Self::Stdin
+        } else {
+            Self::Path(value.into())
+        }
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Expand `@file` arguments.
+
+use std::ffi::OsString;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+/// Read arguments from the file at `path`.
+///
+/// Split the contents of the file into words like a POSIX shell does, and
+/// ignore comments starting with `#`.
+fn read(path: &Path) -> Result<Vec<OsString>> {
+    let contents = std::fs::read_to_string(path).map_err(|error| {
+        Error::new(
+            error.kind(),
+            format!("Failed to read arguments from {}: {error}", path.display()),
+        )
+    })?;
+    shlex::split(&contents)
+        .map(|words| words.into_iter().map(OsString::from).collect())
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid arguments in {}: Unbalanced quotes", path.display()),
+            )
+        })
+}
+
+/// Replace every `@file` in `args` with the arguments in `file`.
+///
+/// Leave the first argument, i.e. the program name, and all arguments after
+/// `--` alone.  Do not expand `@file` arguments within files.  Also leave
+/// `@name` alone if there's no file `name` and `name` has no path separator,
+/// because it may refer to a group in the hosts database.
+///
+/// # Errors
+///
+/// Return an error if a file could not be read or has unbalanced quotes.
+pub fn expand(args: impl IntoIterator<Item = OsString>) -> Result<Vec<OsString>> {
+    let mut args = args.into_iter();
+    let mut expanded = args.next().into_iter().collect::<Vec<_>>();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            expanded.push(arg);
+            expanded.extend(args);
+            break;
+        }
+        match arg.to_str().and_then(|arg| arg.strip_prefix('@')) {
+            Some(name) if !Path::new(name).exists() && !name.contains(['/', '\\']) => {
+                expanded.push(arg);
+            }
+            Some(path) if !path.is_empty() => expanded.extend(read(Path::new(path))?),
+            _ => expanded.push(arg),
+        }
+    }
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn expand_file() {
+        let path = std::env::temp_dir().join(format!("wol-argfile-{}", std::process::id()));
+        std::fs::write(
+            &path,
+            "# Lab systems\n--port 9\n'12:13:14:15:16:17' # Rack 3\n",
+        )
+        .unwrap();
+        let expanded = expand(args(&[
+            "wol",
+            "-v",
+            &format!("@{}", path.display()),
+            "--",
+            "@foo",
+        ]));
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            expanded.unwrap(),
+            args(&[
+                "wol",
+                "-v",
+                "--port",
+                "9",
+                "12:13:14:15:16:17",
+                "--",
+                "@foo"
+            ])
+        );
+    }
+
+    #[test]
+    fn leave_other_args() {
+        assert_eq!(
+            expand(args(&["@wol", "@", "foo@bar", "@renderfarm"])).unwrap(),
+            args(&["@wol", "@", "foo@bar", "@renderfarm"])
+        );
+    }
+
+    #[test]
+    fn missing_file() {
+        assert!(expand(args(&["wol", "@/does/not/exist"])).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Audit log of wake requests to the HTTP API.
+//!
+//! Keep the audit log apart from diagnostic logs: append it to a file, or send
+//! it to the systemd journal with structured fields.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Error, Result, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::SystemTime;
+
+use serde_json::{Value, json};
+
+use super::listen::format_time;
+
+/// A wake request to the HTTP API.
+#[derive(Debug, Clone)]
+pub struct Entry<'a> {
+    /// When the request arrived.
+    pub time: SystemTime,
+    /// The address of the client.
+    pub peer: SocketAddr,
+    /// The name of the API token of the client, if any.
+    pub client: Option<&'a str>,
+    /// The systems the client asked to wake up.
+    pub name: &'a str,
+    /// The status of the response.
+    pub status: u16,
+    /// The body of the response: the results for all systems, or an error.
+    pub response: &'a Value,
+}
+
+impl Entry<'_> {
+    /// The results of waking up systems, if any.
+    fn results(&self) -> Option<&Value> {
+        Some(self.response).filter(|response| response.is_array())
+    }
+
+    /// The error of the request, if any.
+    fn error(&self) -> Option<&str> {
+        self.response.get("error").and_then(Value::as_str)
+    }
+
+    /// This entry as JSON object.
+    pub fn to_json(&self) -> Value {
+        json!({
+            "time": format_time(self.time),
+            "peer": self.peer.to_string(),
+            "client": self.client,
+            "name": self.name,
+            "status": self.status,
+            "results": self.results(),
+            "error": self.error(),
+        })
+    }
+}
+
+/// Where to write the audit log.
+#[derive(Debug)]
+pub enum AuditLog {
+    /// Append one JSON object per line to a file.
+    File(File),
+    /// Send entries to the systemd journal.
+    #[cfg(feature = "journald")]
+    Journald(tracing::Dispatch),
+}
+
+impl AuditLog {
+    /// Append to the audit log at `path`, creating it if needed.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if opening the file fails.
+    pub fn open(path: &Path) -> Result<Self> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map(Self::File)
+            .map_err(|error| {
+                Error::new(
+                    error.kind(),
+                    format!("Failed to open audit log {}: {error}", path.display()),
+                )
+            })
+    }
+
+    /// Send the audit log to the systemd journal.
+    ///
+    /// Prefix all fields with `WOL_`, e.g. `WOL_PEER` or `WOL_STATUS`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if connecting to the journal fails.
+    #[cfg(feature = "journald")]
+    pub fn journald() -> Result<Self> {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let layer = tracing_journald::layer()?.with_field_prefix(Some("WOL".to_owned()));
+        Ok(Self::Journald(tracing::Dispatch::new(
+            tracing_subscriber::registry().with(layer),
+        )))
+    }
+
+    /// Write `entry` to this audit log.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if writing fails.
+    pub fn write(&self, entry: &Entry) -> Result<()> {
+        match self {
+            // Write the whole line at once, so that concurrent requests don't
+            // interleave their lines
+            Self::File(file) => (&*file).write_all(format!("{}\n", entry.to_json()).as_bytes()),
+            #[cfg(feature = "journald")]
+            Self::Journald(dispatch) => {
+                tracing::dispatcher::with_default(dispatch, || {
+                    tracing::info!(
+                        peer = %entry.peer,
+                        client = entry.client,
+                        name = entry.name,
+                        status = entry.status,
+                        results = entry.results().map(ToString::to_string),
+                        error = entry.error(),
+                        "{} asked to wake up {}: {}",
+                        entry.client.map_or_else(|| entry.peer.to_string(), ToOwned::to_owned),
+                        entry.name,
+                        entry.status
+                    );
+                });
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    fn append() {
+        let path = std::env::temp_dir().join(format!("wol-api-audit-{}.jsonl", std::process::id()));
+        let log = AuditLog::open(&path).unwrap();
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let peer = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 40000);
+        let results = json!([{ "mac": "12:13:14:15:16:17", "result": "ok" }]);
+        log.write(&Entry {
+            time,
+            peer,
+            client: Some("home-assistant"),
+            name: "nas",
+            status: 200,
+            response: &results,
+        })
+        .unwrap();
+        let error = json!({ "error": "API token may not wake up ceo" });
+        let denied = Entry {
+            time,
+            peer,
+            client: None,
+            name: "ceo",
+            status: 403,
+            response: &error,
+        };
+        log.write(&denied).unwrap();
+        drop(log);
+        // Append to existing entries
+        AuditLog::open(&path).unwrap().write(&denied).unwrap();
+
+        let mut contents = String::new();
+        File::open(&path)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        let entries = contents
+            .lines()
+            .map(|line| serde_json::from_str::<Value>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries.first(),
+            Some(&json!({
+                "time": "2023-11-14T22:13:20.000Z",
+                "peer": "127.0.0.1:40000",
+                "client": "home-assistant",
+                "name": "nas",
+                "status": 200,
+                "results": results,
+                "error": null,
+            }))
+        );
+        assert_eq!(
+            entries.get(1),
+            Some(&json!({
+                "time": "2023-11-14T22:13:20.000Z",
+                "peer": "127.0.0.1:40000",
+                "client": null,
+                "name": "ceo",
+                "status": 403,
+                "results": null,
+                "error": "API token may not wake up ceo",
+            }))
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Check whether network interfaces wake up on magic packets.
+
+use std::io::{Error, ErrorKind, Result};
+use std::process::ExitCode;
+
+use clap::{ArgAction, Args};
+
+use super::neighbours::run;
+
+/// The ethtool flag for wake up on magic packets.
+const MAGIC_PACKET: char = 'g';
+
+/// The ethtool flag for wake up on magic packets with SecureON password.
+const SECURE_ON: char = 's';
+
+/// The ethtool flag for disabled wake up.
+const DISABLED: char = 'd';
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct CheckArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Enable wake up on magic packets on IFACE.
+    ///
+    /// Requires root privileges.  The setting does not survive
+    /// a reboot on most systems; make it permanent with the
+    /// network configuration of the system, e.g. a systemd
+    /// .link file with WakeOnLan=magic.
+    #[arg(long = "enable", requires = "interface", verbatim_doc_comment)]
+    enable: bool,
+    /// The network interface to check.
+    ///
+    /// Check all interfaces except loopback if omitted.
+    #[arg(value_name = "IFACE", verbatim_doc_comment)]
+    interface: Option<String>,
+}
+
+/// The Wake-on-LAN settings of a network interface, as ethtool flags.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WakeOn {
+    /// The supported wake up modes.
+    supported: String,
+    /// The enabled wake up modes.
+    enabled: String,
+}
+
+impl WakeOn {
+    /// Parse the Wake-on-LAN settings from the output of `ethtool IFACE`.
+    ///
+    /// Return `None` if the output has no Wake-on-LAN settings, i.e. if the
+    /// driver does not report any.
+    pub fn parse(output: &str) -> Option<Self> {
+        let field = |name: &str| {
+            output.lines().find_map(|line| {
+                line.trim()
+                    .strip_prefix(name)
+                    .and_then(|rest| rest.strip_prefix(':'))
+                    .map(|value| value.trim().to_owned())
+            })
+        };
+        Some(Self {
+            supported: field("Supports Wake-on")?,
+            enabled: field("Wake-on")?,
+        })
+    }
+
+    /// Whether the interface wakes up on magic packets.
+    pub fn magic_packet_enabled(&self) -> bool {
+        self.enabled.contains(MAGIC_PACKET) || self.enabled.contains(SECURE_ON)
+    }
+
+    /// Describe these settings for `interface` in a line of text.
+    pub fn describe(&self, interface: &str) -> String {
+        if self.magic_packet_enabled() {
+            format!("{interface}: wake up on magic packets enabled")
+        } else if self.supported.contains(MAGIC_PACKET) {
+            let enabled = if self.enabled.is_empty() || self.enabled.contains(DISABLED) {
+                String::new()
+            } else {
+                format!(" (enabled: {})", self.enabled)
+            };
+            format!(
+                "{interface}: wake up on magic packets disabled{enabled}; enable with wol check --enable {interface}"
+            )
+        } else {
+            format!("{interface}: wake up on magic packets not supported")
+        }
+    }
+}
+
+/// List all network interfaces of the system, except loopback.
+fn interfaces() -> Result<Vec<String>> {
+    let mut interfaces = std::fs::read_dir("/sys/class/net")?
+        .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+        .collect::<Result<Vec<_>>>()?;
+    interfaces.retain(|interface| interface != "lo");
+    interfaces.sort();
+    Ok(interfaces)
+}
+
+/// Read the Wake-on-LAN settings of `interface` with ethtool.
+///
+/// # Errors
+///
+/// Return an error if ethtool is not installed, or if it fails.
+pub fn read_wake_on(interface: &str) -> Result<Option<WakeOn>> {
+    run("ethtool", &[interface])
+        .map(|output| WakeOn::parse(&output))
+        .map_err(|error| {
+            if error.kind() == ErrorKind::NotFound {
+                Error::new(
+                    ErrorKind::NotFound,
+                    "ethtool not found; install ethtool to check network interfaces",
+                )
+            } else {
+                error
+            }
+        })
+}
+
+/// Check whether network interfaces wake up on magic packets, and print a
+/// line for every interface.
+///
+/// With `--enable` enable wake up on magic packets on the interface first.
+/// Exit with 1 if any interface does not wake up on magic packets.
+///
+/// # Errors
+///
+/// Return an error if not running on Linux, if listing interfaces fails, or
+/// if running ethtool fails.
+pub fn check(args: &CheckArgs) -> Result<ExitCode> {
+    if !cfg!(target_os = "linux") {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "wol check is only supported on Linux",
+        ));
+    }
+    let interfaces = match &args.interface {
+        Some(interface) => vec![interface.clone()],
+        None => interfaces()?,
+    };
+    let mut all_enabled = true;
+    for interface in &interfaces {
+        if args.enable {
+            run(
+                "ethtool",
+                &["-s", interface, "wol", &MAGIC_PACKET.to_string()],
+            )?;
+        }
+        if let Some(wake_on) = read_wake_on(interface)? {
+            all_enabled &= wake_on.magic_packet_enabled();
+            anstream::println!("{}", wake_on.describe(interface));
+        } else {
+            all_enabled = false;
+            anstream::println!("{interface}: no Wake-on-LAN settings");
+        }
+    }
+    Ok(if all_enabled {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ethtool() {
+        let output = "Settings for eth0:
+\tSupported ports: [ TP ]
+\tSupported link modes:   10baseT/Half 10baseT/Full
+\tSupports Wake-on: pumbg
+\tWake-on: d
+\tCurrent message level: 0x00000007 (7)
+\tLink detected: yes
+";
+        let wake_on = WakeOn::parse(output).unwrap();
+        assert_eq!(
+            wake_on,
+            WakeOn {
+                supported: "pumbg".to_owned(),
+                enabled: "d".to_owned()
+            }
+        );
+        assert!(!wake_on.magic_packet_enabled());
+        assert_eq!(
+            wake_on.describe("eth0"),
+            "eth0: wake up on magic packets disabled; enable with wol check --enable eth0"
+        );
+        assert_eq!(
+            WakeOn::parse("Settings for wlan0:\n\tLink detected: yes\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn describe() {
+        let wake_on = |supported: &str, enabled: &str| WakeOn {
+            supported: supported.to_owned(),
+            enabled: enabled.to_owned(),
+        };
+        assert_eq!(
+            wake_on("pumbg", "g").describe("eth0"),
+            "eth0: wake up on magic packets enabled"
+        );
+        assert_eq!(
+            wake_on("pumbgs", "s").describe("eth0"),
+            "eth0: wake up on magic packets enabled"
+        );
+        assert_eq!(
+            wake_on("pumbg", "p").describe("eth0"),
+            "eth0: wake up on magic packets disabled (enabled: p); enable with wol check --enable eth0"
+        );
+        assert_eq!(
+            wake_on("d", "d").describe("eth0"),
+            "eth0: wake up on magic packets not supported"
+        );
+    }
+}
@@ -0,0 +1,144 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Run commands for woken up systems.
+
+use std::io::{Error, ErrorKind, Result};
+use std::process::Command;
+use std::str::FromStr;
+
+/// A command line with `{placeholder}`s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandTemplate(Vec<String>);
+
+impl FromStr for CommandTemplate {
+    type Err = String;
+
+    /// Split `s` into words like a POSIX shell does.
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match shlex::split(s) {
+            Some(words) if !words.is_empty() => Ok(Self(words)),
+            Some(_) => Err("Empty command".to_owned()),
+            None => Err(format!("Invalid command {s}: Unbalanced quotes")),
+        }
+    }
+}
+
+impl CommandTemplate {
+    /// Expand placeholders and create a command.
+    ///
+    /// Replace every `{key}` in the command line with the value `lookup`
+    /// returns for `key`; leave `{key}` as is if `lookup` returns `None`.
+    ///
+    /// # Errors
+    ///
+    /// Return any error from `lookup`.
+    pub fn command(
+        &self,
+        mut lookup: impl FnMut(&str) -> Result<Option<String>>,
+    ) -> Result<Command> {
+        let mut words = self
+            .0
+            .iter()
+            .map(|word| expand(word, &mut lookup))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter();
+        let program = words
+            .next()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Empty command"))?;
+        let mut command = Command::new(program);
+        command.args(words);
+        Ok(command)
+    }
+}
+
+/// Expand all `{key}` placeholders in `word` with `lookup`.
+///
+/// Replace every `{key}` with the value `lookup` returns for `key`; leave
+/// `{key}` as is if `lookup` returns `None`.
+///
+/// # Errors
+///
+/// Return any error from `lookup`.
+pub fn expand<E>(
+    word: &str,
+    lookup: &mut impl FnMut(&str) -> std::result::Result<Option<String>, E>,
+) -> std::result::Result<String, E> {
+    let mut expanded = String::with_capacity(word.len());
+    let mut rest = word;
+    while let Some((before, placeholder)) = rest.split_once('{') {
+        expanded.push_str(before);
+        expanded.push('{');
+        rest = placeholder;
+        if let Some((key, after)) = placeholder.split_once('}') {
+            if let Some(value) = lookup(key)? {
+                expanded.pop();
+                expanded.push_str(&value);
+                rest = after;
+            }
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(key: &str) -> Result<Option<String>> {
+        match key {
+            "host" => Ok(Some("nas.example.com".into())),
+            "name" => Ok(Some("rack 3".into())),
+            "ip" => Err(Error::new(ErrorKind::NotFound, "no ip")),
+            _ => Ok(None),
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            CommandTemplate::from_str("ssh -t {host} 'echo hello'").unwrap(),
+            CommandTemplate(vec![
+                "ssh".into(),
+                "-t".into(),
+                "{host}".into(),
+                "echo hello".into()
+            ])
+        );
+        assert!(CommandTemplate::from_str("").is_err());
+        assert!(CommandTemplate::from_str("ssh 'foo").is_err());
+    }
+
+    #[test]
+    fn expand_placeholders() {
+        let mut lookup = lookup;
+        assert_eq!(
+            expand("root@{host}", &mut lookup).unwrap(),
+            "root@nas.example.com"
+        );
+        assert_eq!(
+            expand("{name}:{host}", &mut lookup).unwrap(),
+            "rack 3:nas.example.com"
+        );
+        assert_eq!(expand("{}", &mut lookup).unwrap(), "{}");
+        assert_eq!(expand("{foo} {", &mut lookup).unwrap(), "{foo} {");
+        assert!(expand("{ip}", &mut lookup).is_err());
+    }
+
+    #[test]
+    fn command() {
+        let command = CommandTemplate::from_str("ssh {host} 'echo {name}'")
+            .unwrap()
+            .command(lookup)
+            .unwrap();
+        assert_eq!(command.get_program(), "ssh");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            vec!["nas.example.com", "echo rack 3"]
+        );
+    }
+}
@@ -0,0 +1,186 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Complete names of systems in the shell.
+
+use std::net::IpAddr;
+use std::time::Duration;
+
+use clap_complete::CompletionCandidate;
+use wol::MacAddress;
+
+use super::dns::{self, DnsOptions};
+use super::hosts::{self, Hosts};
+use super::neighbours::read_neighbours;
+
+/// How long to wait for the hostname of a neighbour.
+///
+/// Keep this short; the shell waits for all lookups before it shows
+/// completions.
+const DNS_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// Completion candidates for all systems and groups in `hosts`.
+///
+/// Complete the names of all systems, with their hardware address as help,
+/// and `@` and the names of all groups and tags.
+fn candidates(hosts: &Hosts) -> Vec<CompletionCandidate> {
+    let systems = hosts
+        .hosts()
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|host| {
+            let help = host.target.hardware_address().to_string();
+            host.target
+                .name()
+                .map(|name| CompletionCandidate::new(name).help(Some(help.into())))
+        });
+    let groups = hosts
+        .groups()
+        .unwrap_or_default()
+        .into_iter()
+        .map(|group| CompletionCandidate::new(format!("@{group}")).help(Some("group".into())));
+    systems.chain(groups).collect()
+}
+
+/// Completion candidates for all systems and groups in the default hosts
+/// database.
+///
+/// Complete nothing if reading the hosts database fails; completion has no
+/// way to report errors.
+pub fn systems() -> Vec<CompletionCandidate> {
+    hosts::default_path()
+        .and_then(|path| Hosts::read(&path).ok())
+        .map(|hosts| candidates(&hosts))
+        .unwrap_or_default()
+}
+
+/// Completion candidates for the hardware addresses of all `neighbours`.
+///
+/// Describe every hardware address with the hostname from `hostname` and the
+/// IP address of the neighbour, or only its IP address if it has no hostname.
+fn neighbour_candidates(
+    neighbours: &[(IpAddr, MacAddress)],
+    hostname: impl Fn(IpAddr) -> Option<String> + Sync,
+) -> Vec<CompletionCandidate> {
+    let mut unique: Vec<(IpAddr, MacAddress)> = Vec::new();
+    for (ip, hardware_address) in neighbours {
+        if !unique.iter().any(|(_, known)| known == hardware_address) {
+            unique.push((*ip, *hardware_address));
+        }
+    }
+    let hostname = &hostname;
+    std::thread::scope(|scope| {
+        let handles = unique
+            .iter()
+            .map(|(ip, _)| scope.spawn(move || hostname(*ip)))
+            .collect::<Vec<_>>();
+        unique
+            .iter()
+            .zip(handles)
+            .map(|((ip, hardware_address), handle)| {
+                let help = match handle.join().ok().flatten() {
+                    Some(hostname) => format!("{hostname} ({ip})"),
+                    None => ip.to_string(),
+                };
+                CompletionCandidate::new(hardware_address.to_string()).help(Some(help.into()))
+            })
+            .collect()
+    })
+}
+
+/// Completion candidates for the hardware addresses of all systems in the
+/// neighbour table.
+///
+/// Complete nothing if reading the neighbour table fails.
+pub fn neighbours() -> Vec<CompletionCandidate> {
+    let options = DnsOptions {
+        timeout: Some(DNS_TIMEOUT),
+        servers: Vec::new(),
+    };
+    read_neighbours()
+        .map(|neighbours| neighbour_candidates(&neighbours, |ip| dns::reverse_lookup(ip, &options)))
+        .unwrap_or_default()
+}
+
+/// Completion candidates for all systems and groups in the default hosts
+/// database, and all hardware addresses in the neighbour table.
+pub fn targets() -> Vec<CompletionCandidate> {
+    let mut candidates = systems();
+    candidates.extend(neighbours());
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn systems_and_groups() {
+        let hosts = Hosts::parse(
+            r#"
+[groups]
+lab = ["nas"]
+
+[nas]
+mac = "12:13:14:15:16:17"
+tags = ["storage"]
+"#,
+        )
+        .unwrap();
+        let candidates = candidates(&hosts);
+        assert_eq!(
+            candidates
+                .iter()
+                .map(|candidate| candidate.get_value().to_string_lossy())
+                .collect::<Vec<_>>(),
+            ["nas", "@lab", "@storage"]
+        );
+        assert_eq!(
+            candidates
+                .first()
+                .and_then(CompletionCandidate::get_help)
+                .map(ToString::to_string),
+            Some("12:13:14:15:16:17".to_owned())
+        );
+    }
+
+    #[test]
+    fn hardware_addresses() {
+        let nas = MacAddress::new([0x00, 0x1b, 0x21, 0x15, 0x16, 0x17]);
+        let router = MacAddress::new([0x00, 0x1b, 0x21, 0x15, 0x16, 0x18]);
+        let neighbours = [
+            (IpAddr::from(Ipv4Addr::new(192, 168, 1, 23)), nas),
+            (IpAddr::from(Ipv4Addr::new(192, 168, 1, 1)), router),
+            (IpAddr::from(Ipv4Addr::new(192, 168, 1, 24)), nas),
+        ];
+        let candidates = neighbour_candidates(&neighbours, |ip| {
+            (ip == IpAddr::from(Ipv4Addr::new(192, 168, 1, 23))).then(|| "nas.lan".to_owned())
+        });
+        assert_eq!(
+            candidates
+                .iter()
+                .map(|candidate| {
+                    (
+                        candidate.get_value().to_string_lossy().into_owned(),
+                        candidate.get_help().map(ToString::to_string),
+                    )
+                })
+                .collect::<Vec<_>>(),
+            [
+                (
+                    "00:1B:21:15:16:17".to_owned(),
+                    Some("nas.lan (192.168.1.23)".to_owned())
+                ),
+                (
+                    "00:1B:21:15:16:18".to_owned(),
+                    Some("192.168.1.1".to_owned())
+                ),
+            ]
+        );
+    }
+}
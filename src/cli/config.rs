@@ -0,0 +1,365 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Defaults for command line options from configuration files.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+use clap::builder::ArgPredicate;
+use clap::{Arg, ArgAction, ArgGroup, Command};
+use toml_edit::{DocumentMut, Value};
+
+/// The paths of all configuration files, in order of precedence.
+///
+/// Use `wol/config.toml` in `$XDG_CONFIG_HOME` or `~/.config`, or in
+/// `%APPDATA%` on Windows, and then `/etc/wol/config.toml` on Unix.
+pub fn paths() -> Vec<PathBuf> {
+    let user_dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    let system_dir = Some(PathBuf::from("/etc")).filter(|_| cfg!(unix));
+    user_dir
+        .into_iter()
+        .chain(system_dir)
+        .map(|dir| dir.join("wol").join("config.toml"))
+        .collect()
+}
+
+/// Convert a TOML `value` to the values of an option.
+fn option_values(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(value) => vec![value.value().clone()],
+        Value::Array(values) => values.iter().flat_map(option_values).collect(),
+        value => vec![value.to_string().trim().to_owned()],
+    }
+}
+
+/// Whether `arg` is the option `long`.
+fn is_option(arg: &Arg, long: &str) -> bool {
+    arg.get_long() == Some(long)
+        && !matches!(
+            arg.get_action(),
+            ArgAction::Help | ArgAction::HelpShort | ArgAction::HelpLong | ArgAction::Version
+        )
+}
+
+/// Find the option `long` in `command` or any of its subcommands.
+fn find_option<'a>(command: &'a Command, long: &str) -> Option<&'a Arg> {
+    command
+        .get_arguments()
+        .find(|arg| is_option(arg, long))
+        .or_else(|| {
+            command
+                .get_subcommands()
+                .find_map(|subcommand| find_option(subcommand, long))
+        })
+}
+
+/// Convert common boolean words in `value` of `arg` to `true` or `false`.
+fn normalize_flag(arg: &Arg, value: String) -> String {
+    let is_flag = matches!(arg.get_action(), ArgAction::SetTrue | ArgAction::SetFalse);
+    match value.to_lowercase().as_str() {
+        "1" | "yes" | "on" if is_flag => "true".to_owned(),
+        "0" | "no" | "off" | "" if is_flag => "false".to_owned(),
+        _ => value,
+    }
+}
+
+/// The prefix of environment variables for options.
+const ENV_PREFIX: &str = "WOL_";
+
+/// Defaults for command line options.
+///
+/// A TOML file which maps long names of options to their defaults, e.g.
+///
+/// ```toml
+/// port = 9
+/// host = "192.168.1.255"
+/// ipv4 = true
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Config(BTreeMap<String, Vec<String>>);
+
+impl Config {
+    /// Parse a configuration from `contents`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if `contents` is not valid TOML, or has tables.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let document = contents
+            .parse::<DocumentMut>()
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+        document
+            .iter()
+            .map(|(key, item)| {
+                item.as_value()
+                    .map(|value| (key.to_owned(), option_values(value)))
+                    .ok_or_else(|| {
+                        Error::new(
+                            ErrorKind::InvalidData,
+                            format!("Expected a value for {key}"),
+                        )
+                    })
+            })
+            .collect::<Result<_>>()
+            .map(Self)
+    }
+
+    /// Read the configuration at `path`.
+    ///
+    /// Return an empty configuration if `path` does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if `path` could not be read or is invalid.
+    pub fn read(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error),
+        }
+        .map_err(|error| {
+            Error::new(
+                error.kind(),
+                format!(
+                    "Failed to read configuration from {}: {error}",
+                    path.display()
+                ),
+            )
+        })
+    }
+
+    /// Read all configuration files.
+    ///
+    /// Options in earlier files take precedence over options in later files.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if any configuration file could not be read or is
+    /// invalid.
+    pub fn read_all() -> Result<Self> {
+        let mut config = Self::default();
+        for path in paths().iter().rev() {
+            config.0.extend(Self::read(path)?.0);
+        }
+        Ok(config)
+    }
+
+    /// Get defaults for options of `command` from environment `vars`.
+    ///
+    /// Map `WOL_FOO_BAR` to the option `--foo-bar`; ignore all variables which
+    /// do not name an option of `command`, or which are not valid unicode.
+    /// Accept 1, yes, and on, or 0, no, and off for flags.
+    pub fn from_env(
+        vars: impl IntoIterator<Item = (OsString, OsString)>,
+        command: &Command,
+    ) -> Self {
+        let options = vars
+            .into_iter()
+            .filter_map(|(name, value)| {
+                let long = name
+                    .to_str()?
+                    .strip_prefix(ENV_PREFIX)?
+                    .to_lowercase()
+                    .replace('_', "-");
+                let value = value.into_string().ok()?;
+                let value = normalize_flag(find_option(command, &long)?, value);
+                Some((long, vec![value]))
+            })
+            .collect();
+        Self(options)
+    }
+
+    /// Merge `other` into this configuration.
+    ///
+    /// Options in `other` take precedence.
+    #[must_use]
+    pub fn merge(mut self, other: Self) -> Self {
+        self.0.extend(other.0);
+        self
+    }
+
+    /// Set the default value of the option `long` in `command` to `values`.
+    ///
+    /// Return whether `command` has an option `long`.
+    fn set_default(command: Command, long: &str, values: &[String]) -> (Command, bool) {
+        let Some(id) = command
+            .get_arguments()
+            .find(|arg| is_option(arg, long))
+            .map(|arg| arg.get_id().clone())
+        else {
+            return (command, false);
+        };
+        // Drop the default if the command line has another option of an
+        // exclusive group, e.g. --ipv6 for ipv4 = true.
+        let exclusive = command
+            .get_groups()
+            // is_multiple needs a mutable group, for whatever reason
+            .filter(|group| {
+                !ArgGroup::clone(group).is_multiple() && group.get_args().any(|arg| *arg == id)
+            })
+            .flat_map(|group| {
+                group
+                    .get_args()
+                    .filter(|arg| **arg != id)
+                    .cloned()
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        // Change the option in place; mut_arg would move it to the end, and
+        // thus change the order of help and of default values.
+        let command = command.mut_args(|arg| {
+            if *arg.get_id() == id {
+                // Flags need a value nonetheless
+                let fallback = match arg.get_action() {
+                    ArgAction::SetTrue => Some("false"),
+                    ArgAction::SetFalse => Some("true"),
+                    _ => None,
+                };
+                let arg = arg
+                    .default_values(values.iter().cloned())
+                    // Do not show passwords in --help
+                    .hide_default_value(long == "passwd");
+                exclusive.iter().fold(arg, |arg, other| {
+                    arg.default_value_if(other, ArgPredicate::IsPresent, fallback)
+                })
+            } else {
+                arg
+            }
+        });
+        (command, true)
+    }
+
+    /// Use this configuration as defaults for options of `command` and all
+    /// its subcommands.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if neither `command` nor any subcommand has an option
+    /// of this configuration.
+    pub fn apply(&self, mut command: Command) -> Result<Command> {
+        let subcommands = command
+            .get_subcommands()
+            .map(|subcommand| subcommand.get_name().to_owned())
+            .collect::<Vec<_>>();
+        for (long, values) in &self.0 {
+            let (mut changed, mut found) = Self::set_default(command, long, values);
+            for name in &subcommands {
+                changed = changed.mut_subcommand(name, |subcommand| {
+                    let (subcommand, has_option) = Self::set_default(subcommand, long, values);
+                    found |= has_option;
+                    subcommand
+                });
+            }
+            if !found {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Unknown option {long} in configuration"),
+                ));
+            }
+            command = changed;
+        }
+        Ok(command)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command() -> Command {
+        Command::new("wol")
+            .arg(Arg::new("port").long("port").default_value("40000"))
+            .arg(Arg::new("ipv4").long("ipv4").action(ArgAction::SetTrue))
+            .arg(Arg::new("ipv6").long("ipv6").action(ArgAction::SetTrue))
+            .group(ArgGroup::new("address_family").args(["ipv4", "ipv6"]))
+            .subcommand(
+                Command::new("list").arg(Arg::new("hosts").long("hosts").action(ArgAction::Set)),
+            )
+    }
+
+    #[test]
+    fn parse() {
+        let config =
+            Config::parse("port = 9\nhost = \"nas.lan\"\nipv4 = true\nports = [7, 9]\n").unwrap();
+        assert_eq!(
+            config.0.into_iter().collect::<Vec<_>>(),
+            [
+                ("host".to_owned(), vec!["nas.lan".to_owned()]),
+                ("ipv4".to_owned(), vec!["true".to_owned()]),
+                ("port".to_owned(), vec!["9".to_owned()]),
+                ("ports".to_owned(), vec!["7".to_owned(), "9".to_owned()]),
+            ]
+        );
+        assert!(Config::parse("[port]\nfoo = 42\n").is_err());
+    }
+
+    #[test]
+    fn apply_defaults() {
+        let config = Config::parse("port = 9\nipv4 = true\nhosts = \"lab.toml\"\n").unwrap();
+        let command = config.apply(command()).unwrap();
+
+        let matches = command.clone().get_matches_from(["wol"]);
+        assert_eq!(matches.get_one::<String>("port").unwrap(), "9");
+        assert!(matches.get_flag("ipv4"));
+        let ids = command.get_arguments().map(|arg| arg.get_id().as_str());
+        assert_eq!(ids.collect::<Vec<_>>(), ["port", "ipv4", "ipv6"]);
+
+        let matches = command.clone().get_matches_from(["wol", "--ipv6"]);
+        assert!(!matches.get_flag("ipv4"));
+        assert!(matches.get_flag("ipv6"));
+
+        let matches = command.clone().get_matches_from(["wol", "--port", "42"]);
+        assert_eq!(matches.get_one::<String>("port").unwrap(), "42");
+
+        let matches = command.get_matches_from(["wol", "list"]);
+        let (_, list) = matches.subcommand().unwrap();
+        assert_eq!(list.get_one::<String>("hosts").unwrap(), "lab.toml");
+    }
+
+    #[test]
+    fn env() {
+        let vars = [
+            ("WOL_PORT", "7"),
+            ("WOL_HOSTS", "lab.toml"),
+            ("WOL_IPV6", "yes"),
+            ("WOL_COLOUR", "never"),
+            ("HOME", "/home/wol"),
+        ]
+        .map(|(name, value)| (OsString::from(name), OsString::from(value)));
+        let env = Config::from_env(vars, &command());
+        assert_eq!(
+            env.0.iter().collect::<Vec<_>>(),
+            [
+                (&"hosts".to_owned(), &vec!["lab.toml".to_owned()]),
+                (&"ipv6".to_owned(), &vec!["true".to_owned()]),
+                (&"port".to_owned(), &vec!["7".to_owned()]),
+            ]
+        );
+        let config = Config::parse("port = 9\nipv4 = true\n").unwrap().merge(env);
+        let matches = config.apply(command()).unwrap().get_matches_from(["wol"]);
+        assert_eq!(matches.get_one::<String>("port").unwrap(), "7");
+        assert!(matches.get_flag("ipv4"));
+    }
+
+    #[test]
+    fn apply_unknown() {
+        let error = Config::parse("colour = \"never\"\n")
+            .unwrap()
+            .apply(command())
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Unknown option colour in configuration");
+    }
+}
@@ -0,0 +1,301 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Control running daemons over a local Unix socket.
+//!
+//! Daemons accept one command per connection, as a single line, and answer
+//! with a single line of JSON, which has an `error` if the command failed:
+//!
+//! - `reload` reloads the daemon, like `SIGHUP`.
+//! - `wake NAME` wakes up systems by name, pattern, hardware address, or
+//!   `@GROUP`.
+//! - `state` describes the state of the daemon.
+//!
+//! Only the owner of the socket may connect, so the socket needs no further
+//! authentication.  `wol ctl` sends these commands.
+
+use std::fmt::Display;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result, Write};
+use std::net::Shutdown;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anstyle::{AnsiColor, Style};
+use clap::{ArgAction, Args, Subcommand, ValueHint};
+use serde_json::{Value, json};
+
+const ERROR: Style = AnsiColor::Red.on_default().bold();
+
+/// The maximum size of a command.
+const MAX_COMMAND_SIZE: u64 = 4096;
+
+/// How long to wait for a command from a client.
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A command for a daemon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    /// Reload the daemon.
+    Reload,
+    /// Wake up systems.
+    Wake(String),
+    /// Describe the state of the daemon.
+    State,
+}
+
+impl FromStr for Request {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().split_once(' ') {
+            None if s.trim() == "reload" => Ok(Self::Reload),
+            None if s.trim() == "state" => Ok(Self::State),
+            Some(("wake", name)) if !name.trim().is_empty() => {
+                Ok(Self::Wake(name.trim().to_owned()))
+            }
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unknown command {}", s.trim()),
+            )),
+        }
+    }
+}
+
+impl Display for Request {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Reload => write!(f, "reload"),
+            Self::Wake(name) => write!(f, "wake {name}"),
+            Self::State => write!(f, "state"),
+        }
+    }
+}
+
+/// Listen for commands on the Unix socket at `path`.
+///
+/// Replace a stale socket which no daemon listens on anymore, and make the
+/// socket accessible only to its owner.
+///
+/// # Errors
+///
+/// Return an error if another daemon listens on `path`, or if binding fails.
+pub fn bind(path: &Path) -> Result<UnixListener> {
+    if path.exists() {
+        match UnixStream::connect(path) {
+            Ok(_) => {
+                return Err(Error::new(
+                    ErrorKind::AddrInUse,
+                    format!("Another daemon listens on {}", path.display()),
+                ));
+            }
+            Err(error) if error.kind() == ErrorKind::ConnectionRefused => {
+                std::fs::remove_file(path)?;
+            }
+            Err(_) => {}
+        }
+    }
+    let listener = UnixListener::bind(path).map_err(|error| {
+        Error::new(
+            error.kind(),
+            format!("Failed to listen on {}: {error}", path.display()),
+        )
+    })?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Read a single command from `stream`, and write the answer of `handle`.
+fn answer(stream: &UnixStream, handle: impl Fn(&Request) -> Value) -> Result<()> {
+    stream.set_read_timeout(Some(COMMAND_TIMEOUT))?;
+    let mut line = String::new();
+    BufReader::new(stream.take(MAX_COMMAND_SIZE)).read_line(&mut line)?;
+    let response = match Request::from_str(&line) {
+        Ok(request) => {
+            tracing::info!("Control command {request}");
+            handle(&request)
+        }
+        Err(error) => json!({ "error": error.to_string() }),
+    };
+    (&*stream).write_all(format!("{response}\n").as_bytes())
+}
+
+/// Answer commands on `listener` with `handle`, forever.
+pub fn accept(listener: &UnixListener, handle: impl Fn(&Request) -> Value) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                if let Err(error) = answer(&stream, &handle) {
+                    tracing::warn!("Failed to answer control command: {error}");
+                }
+            }
+            Err(error) => tracing::warn!("Failed to accept control connection: {error}"),
+        }
+    }
+}
+
+/// Whether `response` has an error, or a failed result.
+fn failed(response: &Value) -> bool {
+    let has_error = |value: &Value| value.get("error").is_some_and(|error| !error.is_null());
+    has_error(response)
+        || response
+            .as_array()
+            .is_some_and(|results| results.iter().any(has_error))
+}
+
+/// Arguments to control a running daemon.
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct CtlArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Talk to the daemon listening on the Unix socket PATH.
+    #[arg(
+        long = "control-socket",
+        value_name = "PATH",
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    control_socket: PathBuf,
+    #[command(subcommand)]
+    command: CtlCommand,
+}
+
+/// Commands for a running daemon.
+#[derive(Subcommand, Debug, Clone)]
+enum CtlCommand {
+    /// Reload the hosts database, API tokens, and audit log.
+    Reload,
+    /// Wake up systems by name, pattern, hardware address, or
+    /// @GROUP.
+    #[command(verbatim_doc_comment)]
+    Wake {
+        /// The systems to wake up.
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+    /// Describe the state of the daemon.
+    State,
+}
+
+impl From<&CtlCommand> for Request {
+    fn from(command: &CtlCommand) -> Self {
+        match command {
+            CtlCommand::Reload => Self::Reload,
+            CtlCommand::Wake { name } => Self::Wake(name.clone()),
+            CtlCommand::State => Self::State,
+        }
+    }
+}
+
+/// Send a command to a running daemon, and print its answer.
+///
+/// # Errors
+///
+/// Return an error if talking to the daemon fails.
+pub fn ctl(args: &CtlArgs) -> Result<ExitCode> {
+    let mut stream = UnixStream::connect(&args.control_socket).map_err(|error| {
+        Error::new(
+            error.kind(),
+            format!(
+                "Failed to connect to {}: {error}",
+                args.control_socket.display()
+            ),
+        )
+    })?;
+    writeln!(stream, "{}", Request::from(&args.command))?;
+    stream.shutdown(Shutdown::Write)?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    let response = serde_json::from_str::<Value>(&line)
+        .map_err(|error| Error::new(ErrorKind::InvalidData, error))?;
+    if let Some(error) = response.get("error").and_then(Value::as_str) {
+        anstream::eprintln!("{ERROR}error{ERROR:#}: {error}");
+        return Ok(ExitCode::FAILURE);
+    }
+    anstream::println!(
+        "{}",
+        serde_json::to_string_pretty(&response).map_err(Error::other)?
+    );
+    Ok(if failed(&response) {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_request() {
+        assert_eq!(Request::from_str("reload\n").unwrap(), Request::Reload);
+        assert_eq!(Request::from_str("state").unwrap(), Request::State);
+        assert_eq!(
+            Request::from_str("wake @office\n").unwrap(),
+            Request::Wake("@office".to_owned())
+        );
+        assert_eq!(
+            Request::from_str("wake \n").unwrap_err().to_string(),
+            "Unknown command wake"
+        );
+        assert_eq!(
+            Request::from_str("shutdown").unwrap_err().to_string(),
+            "Unknown command shutdown"
+        );
+        for request in [
+            Request::Reload,
+            Request::State,
+            Request::Wake("nas".to_owned()),
+        ] {
+            assert_eq!(Request::from_str(&request.to_string()).unwrap(), request);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = std::env::temp_dir().join(format!("wol-control-{}.sock", std::process::id()));
+        let listener = bind(&path).unwrap();
+        assert_eq!(
+            std::fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+        assert_eq!(bind(&path).unwrap_err().kind(), ErrorKind::AddrInUse);
+        std::thread::spawn(move || {
+            accept(
+                &listener,
+                |request| json!({ "request": request.to_string() }),
+            );
+        });
+        for (command, response) in [
+            ("wake nas\n", json!({ "request": "wake nas" })),
+            ("halt\n", json!({ "error": "Unknown command halt" })),
+        ] {
+            let mut stream = UnixStream::connect(&path).unwrap();
+            stream.write_all(command.as_bytes()).unwrap();
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line).unwrap();
+            assert_eq!(serde_json::from_str::<Value>(&line).unwrap(), response);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn failures() {
+        assert!(!failed(&json!({ "result": "ok" })));
+        assert!(failed(&json!({ "error": "No system matches nas" })));
+        assert!(!failed(&json!([{ "result": "ok", "error": null }])));
+        assert!(failed(&json!([
+            { "result": "ok", "error": null },
+            { "result": "send-failed", "error": "Network unreachable" },
+        ])));
+    }
+}
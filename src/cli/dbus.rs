@@ -0,0 +1,255 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Serve daemons on D-Bus.
+//!
+//! Own the name `org.swsnr.wol` on the session or system bus, and serve the
+//! interface `org.swsnr.wol` at `/org/swsnr/wol`, so that desktop applets and
+//! shell extensions can wake up systems without running `wol`:
+//!
+//! - `Wake(s name) -> a(ssss)` wakes up systems by name, pattern, hardware
+//!   address, or `@GROUP`, and returns the name, hardware address, result,
+//!   and error of every system.
+//! - `ListHosts() -> a(sssqas)` lists the name, hardware address, destination,
+//!   port and tags of every system in the hosts database.
+//! - The signal `Woken(ssss)` tells the name, hardware address, result and
+//!   error whenever the daemon woke up a system, and `Online(ssss)` whether
+//!   the system came online afterwards.
+//!
+//! Empty strings and port 0 stand for missing values, as D-Bus has no null.
+
+use std::io::{Error, ErrorKind, Result};
+use std::sync::Arc;
+use std::sync::mpsc::Receiver;
+
+use clap::ValueEnum;
+use serde_json::Value;
+use zbus::blocking::Connection;
+use zbus::blocking::connection::Builder;
+use zbus::fdo;
+use zbus::object_server::SignalEmitter;
+
+use super::events::Event;
+
+/// The well-known name of our service, and the name of our interface.
+pub const NAME: &str = "org.swsnr.wol";
+
+/// The path of our object.
+pub const OBJECT_PATH: &str = "/org/swsnr/wol";
+
+/// The result of waking up a system: name, hardware address, result, and
+/// error.
+type WakeResult = (String, String, String, String);
+
+/// A system in the hosts database: name, hardware address, destination,
+/// port, and tags.
+type HostEntry = (String, String, String, u16, Vec<String>);
+
+/// A message bus.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Bus {
+    /// The session bus of the current user.
+    Session,
+    /// The system bus.
+    System,
+}
+
+/// What a daemon offers on D-Bus.
+pub trait Service: Send + Sync {
+    /// Wake up all systems for `name`.
+    ///
+    /// Return the results as JSON objects, like `wol wake --json`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if no system matches `name`, or if a rate limit
+    /// refuses to wake up systems.
+    fn wake(&self, name: &str) -> Result<Vec<Value>>;
+
+    /// List all systems in the hosts database as JSON objects.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if the hosts database is invalid.
+    fn hosts(&self) -> Result<Vec<Value>>;
+}
+
+/// The string at `key` in `value`, or an empty string.
+fn string(value: &Value, key: &str) -> String {
+    value
+        .get(key)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// The result of waking up a system, from its JSON object.
+fn wake_result(value: &Value) -> WakeResult {
+    (
+        string(value, "name"),
+        string(value, "mac"),
+        string(value, "result"),
+        string(value, "error"),
+    )
+}
+
+/// A system in the hosts database, from its JSON object.
+fn host_entry(value: &Value) -> HostEntry {
+    let port = value
+        .get("port")
+        .and_then(Value::as_u64)
+        .and_then(|port| u16::try_from(port).ok())
+        .unwrap_or_default();
+    let tags = value
+        .get("tags")
+        .and_then(Value::as_array)
+        .map(|tags| {
+            tags.iter()
+                .filter_map(Value::as_str)
+                .map(ToOwned::to_owned)
+                .collect()
+        })
+        .unwrap_or_default();
+    (
+        string(value, "name"),
+        string(value, "mac"),
+        string(value, "destination"),
+        port,
+        tags,
+    )
+}
+
+/// The D-Bus error for `error`.
+fn fdo_error(error: &Error) -> fdo::Error {
+    match error.kind() {
+        ErrorKind::InvalidInput | ErrorKind::NotFound => fdo::Error::InvalidArgs(error.to_string()),
+        ErrorKind::PermissionDenied => fdo::Error::AccessDenied(error.to_string()),
+        _ => fdo::Error::Failed(error.to_string()),
+    }
+}
+
+/// Our interface.
+struct Interface {
+    service: Arc<dyn Service>,
+}
+
+#[zbus::interface(name = "org.swsnr.wol")]
+impl Interface {
+    /// Wake up systems by name, pattern, hardware address, or @GROUP.
+    fn wake(&self, name: &str) -> fdo::Result<Vec<WakeResult>> {
+        let results = self.service.wake(name).map_err(|error| fdo_error(&error))?;
+        Ok(results.iter().map(wake_result).collect())
+    }
+
+    /// List all systems in the hosts database.
+    fn list_hosts(&self) -> fdo::Result<Vec<HostEntry>> {
+        let hosts = self.service.hosts().map_err(|error| fdo_error(&error))?;
+        Ok(hosts.iter().map(host_entry).collect())
+    }
+
+    /// A system was woken up.
+    #[zbus(signal)]
+    async fn woken(
+        emitter: &SignalEmitter<'_>,
+        name: &str,
+        mac: &str,
+        result: &str,
+        error: &str,
+    ) -> zbus::Result<()>;
+
+    /// A system came online after waking it up, or failed to.
+    #[zbus(signal)]
+    async fn online(
+        emitter: &SignalEmitter<'_>,
+        name: &str,
+        mac: &str,
+        result: &str,
+        error: &str,
+    ) -> zbus::Result<()>;
+}
+
+/// Serve `service` on `bus`.
+///
+/// # Errors
+///
+/// Return an error if connecting to `bus` fails, or if another process owns
+/// our name on `bus`.
+pub fn connect(bus: Bus, service: Arc<dyn Service>) -> Result<Connection> {
+    let builder = match bus {
+        Bus::Session => Builder::session(),
+        Bus::System => Builder::system(),
+    };
+    builder
+        .and_then(|builder| builder.name(NAME))
+        .and_then(|builder| builder.serve_at(OBJECT_PATH, Interface { service }))
+        .and_then(Builder::build)
+        .map_err(|error| Error::other(format!("Failed to serve {NAME} on D-Bus: {error}")))
+}
+
+/// Emit a signal on `connection` for every event in `events`.
+///
+/// Return when `events` disconnects.
+pub fn emit(connection: &Connection, events: &Receiver<Event>) {
+    for event in events {
+        let signal = match event.name() {
+            "wake" => "Woken",
+            "online" => "Online",
+            _ => continue,
+        };
+        let result = connection.emit_signal(
+            None::<&str>,
+            OBJECT_PATH,
+            NAME,
+            signal,
+            &wake_result(event.data()),
+        );
+        if let Err(error) = result {
+            tracing::warn!("Failed to emit D-Bus signal {signal}: {error}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn arguments() {
+        assert_eq!(
+            wake_result(&json!({
+                "mac": "12:13:14:15:16:17",
+                "name": null,
+                "result": "send-failed",
+                "error": "Network unreachable",
+                "port": 9,
+            })),
+            (
+                String::new(),
+                "12:13:14:15:16:17".to_owned(),
+                "send-failed".to_owned(),
+                "Network unreachable".to_owned()
+            )
+        );
+        assert_eq!(
+            host_entry(&json!({
+                "name": "nas",
+                "mac": "12:13:14:15:16:17",
+                "destination": null,
+                "port": 7,
+                "tags": ["office"],
+            })),
+            (
+                "nas".to_owned(),
+                "12:13:14:15:16:17".to_owned(),
+                String::new(),
+                7,
+                vec!["office".to_owned()]
+            )
+        );
+    }
+}
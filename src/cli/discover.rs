@@ -0,0 +1,345 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Discover systems passively, from the traffic they send anyway.
+
+use std::fmt::Display;
+use std::io::{ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant, SystemTime};
+
+use clap::{ArgAction, Args};
+use socket2::{Domain, Protocol, Socket, Type};
+use wol::MacAddress;
+
+use super::interrupt::Interrupt;
+use super::listen::format_time;
+use super::neighbours::read_neighbours;
+use super::wake::parse_secs;
+
+/// The DHCP server and client ports.
+const DHCP_PORTS: [u16; 2] = [67, 68];
+
+/// The magic cookie which starts the options of a DHCP message.
+const DHCP_MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+/// The DHCP options we understand.
+const OPTION_PAD: u8 = 0;
+const OPTION_HOSTNAME: u8 = 12;
+const OPTION_REQUESTED_ADDRESS: u8 = 50;
+const OPTION_END: u8 = 255;
+
+/// How often to read the neighbour table.
+const NEIGHBOUR_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often to check for interrupts while waiting for packets.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct DiscoverArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Only listen, and never send any packets.
+    ///
+    /// Required; use wol scan to actively probe a subnet.
+    #[arg(long = "passive", required = true, verbatim_doc_comment)]
+    passive: bool,
+    /// Listen for SECS seconds.
+    #[arg(
+        long = "duration",
+        value_name = "SECS",
+        default_value = "60",
+        value_parser = parse_secs
+    )]
+    duration: Duration,
+}
+
+/// Where we learned about a system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Source {
+    /// The neighbour table of the system, filled from ARP traffic.
+    Arp,
+    /// A DHCP broadcast.
+    Dhcp,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Arp => write!(f, "arp"),
+            Source::Dhcp => write!(f, "dhcp"),
+        }
+    }
+}
+
+/// What we learned about a system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Sighting {
+    hardware_address: MacAddress,
+    ip: Option<Ipv4Addr>,
+    hostname: Option<String>,
+    source: Source,
+}
+
+impl Sighting {
+    /// Describe this sighting at `time` in a line of text.
+    fn describe(&self, time: SystemTime) -> String {
+        format!(
+            "{} {} {} {} ({})",
+            format_time(time),
+            self.hardware_address,
+            self.ip.map_or_else(|| "-".to_owned(), |ip| ip.to_string()),
+            self.hostname.as_deref().unwrap_or("-"),
+            self.source
+        )
+    }
+}
+
+/// Parse a DHCP message in `packet`.
+///
+/// Take the hardware address of the client, its assigned, current, or
+/// requested IP address, and its hostname.  Return `None` if `packet` is no
+/// DHCP message for an Ethernet client.
+fn parse_dhcp(packet: &[u8]) -> Option<Sighting> {
+    let ipv4 = |offset: usize| {
+        <[u8; 4]>::try_from(packet.get(offset..offset + 4)?)
+            .ok()
+            .map(Ipv4Addr::from)
+            .filter(|ip| !ip.is_unspecified())
+    };
+    // Hardware type and length of Ethernet
+    if packet.get(1..3)? != [1, 6] || packet.get(236..240)? != DHCP_MAGIC_COOKIE {
+        return None;
+    }
+    let hardware_address = MacAddress::new(<[u8; 6]>::try_from(packet.get(28..34)?).ok()?);
+    let mut requested = None;
+    let mut hostname = None;
+    let mut options = packet.get(240..)?;
+    while let Some((&code, rest)) = options.split_first() {
+        match code {
+            OPTION_PAD => options = rest,
+            OPTION_END => break,
+            _ => {
+                let (&length, rest) = rest.split_first()?;
+                let (value, rest) = rest.split_at_checked(usize::from(length))?;
+                match code {
+                    OPTION_HOSTNAME => {
+                        hostname = Some(String::from_utf8_lossy(value).into_owned());
+                    }
+                    OPTION_REQUESTED_ADDRESS => {
+                        requested = <[u8; 4]>::try_from(value).ok().map(Ipv4Addr::from);
+                    }
+                    _ => {}
+                }
+                options = rest;
+            }
+        }
+    }
+    Some(Sighting {
+        hardware_address,
+        // The assigned address, the current address, or the requested address
+        ip: ipv4(16).or_else(|| ipv4(12)).or(requested),
+        hostname,
+        source: Source::Dhcp,
+    })
+}
+
+/// Merge `sighting` into the known `sightings`.
+///
+/// Return the merged sighting if `sighting` told us something new.
+fn record(sightings: &mut Vec<Sighting>, sighting: Sighting) -> Option<Sighting> {
+    match sightings
+        .iter_mut()
+        .find(|known| known.hardware_address == sighting.hardware_address)
+    {
+        None => {
+            sightings.push(sighting.clone());
+            Some(sighting)
+        }
+        Some(known) => {
+            let new_ip = sighting.ip.is_some() && sighting.ip != known.ip;
+            let new_hostname = sighting.hostname.is_some() && sighting.hostname != known.hostname;
+            if new_ip {
+                known.ip = sighting.ip;
+            }
+            if new_hostname {
+                known.hostname = sighting.hostname;
+            }
+            (new_ip || new_hostname).then(|| {
+                known.source = sighting.source;
+                known.clone()
+            })
+        }
+    }
+}
+
+/// Bind a socket to receive broadcasts on `port`, alongside other programs
+/// which use the port.
+fn bind(port: u16) -> Result<UdpSocket> {
+    let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+    socket.set_reuse_address(true)?;
+    socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)).into())?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    Ok(socket.into())
+}
+
+/// Receive DHCP messages on `socket` and send them to `sightings`, until
+/// interrupted.
+fn receive(socket: &UdpSocket, interrupt: &Interrupt, sightings: &mpsc::Sender<Sighting>) {
+    let mut buffer = vec![0; usize::from(u16::MAX)];
+    while !interrupt.is_set() {
+        match socket.recv(&mut buffer) {
+            Ok(size) => {
+                let sighting = buffer.get(..size).and_then(parse_dhcp);
+                if let Some(sighting) = sighting {
+                    if sightings.send(sighting).is_err() {
+                        break;
+                    }
+                }
+            }
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                ) => {}
+            Err(error) => {
+                tracing::warn!("Failed to receive DHCP messages: {error}");
+                break;
+            }
+        }
+    }
+}
+
+/// Listen for systems passively, and print every new system, until
+/// interrupted or the duration elapsed.
+///
+/// Record DHCP broadcasts, and IPv4 entries in the neighbour table, which
+/// the system fills from ARP traffic.
+///
+/// # Errors
+///
+/// Return an error if reading the neighbour table fails.
+pub fn discover(args: &DiscoverArgs) -> Result<()> {
+    let interrupt = Interrupt::register().with_timeout(Some(args.duration));
+    let (sender, received) = mpsc::channel();
+    for port in DHCP_PORTS {
+        match bind(port) {
+            Ok(socket) => {
+                tracing::info!("Listening for DHCP messages on port {port}");
+                let sender = sender.clone();
+                let interrupt = interrupt.clone();
+                std::thread::spawn(move || receive(&socket, &interrupt, &sender));
+            }
+            Err(error) => {
+                tracing::warn!("Failed to listen for DHCP messages on port {port}: {error}");
+            }
+        }
+    }
+    drop(sender);
+    let mut sightings = Vec::new();
+    let mut next_neighbours = Instant::now();
+    while !interrupt.is_set() {
+        if next_neighbours <= Instant::now() {
+            for (ip, hardware_address) in read_neighbours()? {
+                let IpAddr::V4(ip) = ip else {
+                    continue;
+                };
+                let sighting = Sighting {
+                    hardware_address,
+                    ip: Some(ip),
+                    hostname: None,
+                    source: Source::Arp,
+                };
+                if let Some(sighting) = record(&mut sightings, sighting) {
+                    anstream::println!("{}", sighting.describe(SystemTime::now()));
+                }
+            }
+            next_neighbours = Instant::now() + NEIGHBOUR_INTERVAL;
+        }
+        match received.recv_timeout(POLL_INTERVAL) {
+            Ok(sighting) => {
+                if let Some(sighting) = record(&mut sightings, sighting) {
+                    anstream::println!("{}", sighting.describe(SystemTime::now()));
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            // Without DHCP sockets only wait for the next neighbour table
+            Err(RecvTimeoutError::Disconnected) => {
+                interrupt.sleep(POLL_INTERVAL);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    /// A DHCP request from 12:13:14:15:16:17 for 192.168.1.23, with hostname
+    /// nas.
+    fn dhcp_request() -> Vec<u8> {
+        let mut packet = vec![1, 1, 6, 0];
+        packet.resize(28, 0);
+        packet.extend_from_slice(&[0x12, 0x13, 0x14, 0x15, 0x16, 0x17]);
+        packet.resize(236, 0);
+        packet.extend_from_slice(&DHCP_MAGIC_COOKIE);
+        packet.extend_from_slice(&[53, 1, 3, OPTION_PAD]);
+        packet.extend_from_slice(&[OPTION_REQUESTED_ADDRESS, 4, 192, 168, 1, 23]);
+        packet.extend_from_slice(&[OPTION_HOSTNAME, 3, b'n', b'a', b's', OPTION_END]);
+        packet
+    }
+
+    #[test]
+    fn dhcp() {
+        let hardware_address = MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]);
+        let mut packet = dhcp_request();
+        assert_eq!(
+            parse_dhcp(&packet),
+            Some(Sighting {
+                hardware_address,
+                ip: Some(Ipv4Addr::new(192, 168, 1, 23)),
+                hostname: Some("nas".to_owned()),
+                source: Source::Dhcp,
+            })
+        );
+        // Prefer the assigned address
+        packet.splice(16..20, [192, 168, 1, 42]);
+        assert_eq!(
+            parse_dhcp(&packet).unwrap().ip,
+            Some(Ipv4Addr::new(192, 168, 1, 42))
+        );
+        packet.truncate(238);
+        assert_eq!(parse_dhcp(&packet), None);
+        assert_eq!(parse_dhcp(b"hello"), None);
+    }
+
+    #[test]
+    fn merge() {
+        let mut sightings = Vec::new();
+        let arp = Sighting {
+            hardware_address: MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]),
+            ip: Some(Ipv4Addr::new(192, 168, 1, 23)),
+            hostname: None,
+            source: Source::Arp,
+        };
+        assert_eq!(record(&mut sightings, arp.clone()), Some(arp.clone()));
+        assert_eq!(record(&mut sightings, arp.clone()), None);
+        let dhcp = parse_dhcp(&dhcp_request()).unwrap();
+        let merged = record(&mut sightings, dhcp).unwrap();
+        assert_eq!(
+            merged.describe(UNIX_EPOCH),
+            "1970-01-01T00:00:00.000Z 12:13:14:15:16:17 192.168.1.23 nas (dhcp)"
+        );
+        assert_eq!(record(&mut sightings, arp), None);
+        assert_eq!(sightings, [merged]);
+    }
+}
@@ -0,0 +1,236 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Resolve hostnames.
+
+use std::collections::HashMap;
+use std::io::Result;
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use hickory_resolver::Resolver as HickoryResolver;
+use hickory_resolver::config::{
+    LookupIpStrategy, NameServerConfig, NameServerConfigGroup, Protocol, ResolverConfig,
+    ResolverOpts,
+};
+use hickory_resolver::system_conf::read_system_conf;
+
+/// The default port of DNS servers.
+const DNS_PORT: u16 = 53;
+
+/// The maximum number of hostnames to resolve at the same time.
+const MAX_CONCURRENT_LOOKUPS: usize = 16;
+
+/// Options for DNS resolution.
+#[derive(Debug, Default, Clone)]
+pub struct DnsOptions {
+    /// The timeout for DNS queries.
+    pub timeout: Option<Duration>,
+    /// DNS servers to query instead of the system's DNS servers.
+    pub servers: Vec<SocketAddr>,
+}
+
+impl DnsOptions {
+    fn resolver(&self) -> Result<HickoryResolver> {
+        let (config, mut options) = if self.servers.is_empty() {
+            read_system_conf()?
+        } else {
+            let name_servers = self
+                .servers
+                .iter()
+                .flat_map(|server| {
+                    [
+                        NameServerConfig::new(*server, Protocol::Udp),
+                        NameServerConfig::new(*server, Protocol::Tcp),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            (
+                ResolverConfig::from_parts(
+                    None,
+                    Vec::new(),
+                    NameServerConfigGroup::from(name_servers),
+                ),
+                ResolverOpts::default(),
+            )
+        };
+        // Leave the choice of address family to the caller
+        options.ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+        if let Some(timeout) = self.timeout {
+            options.timeout = timeout;
+        }
+        HickoryResolver::new(config, options)
+    }
+}
+
+/// Parse a DNS server address.
+///
+/// Accept an IP address, or an IP address and a port, and default to port 53.
+///
+/// # Errors
+///
+/// Return an error if `value` is neither an IP address nor a socket address.
+pub fn parse_server(value: &str) -> std::result::Result<SocketAddr, String> {
+    SocketAddr::from_str(value)
+        .or_else(|_| IpAddr::from_str(value).map(|ip| SocketAddr::new(ip, DNS_PORT)))
+        .map_err(|_| format!("Invalid DNS server address: {value}"))
+}
+
+/// Resolve `host` to IP addresses.
+///
+/// Use the system resolver if `options` are empty; otherwise query DNS servers
+/// directly, according to `options`.
+fn lookup(host: &str, options: &DnsOptions) -> Result<Vec<IpAddr>> {
+    if options.timeout.is_none() && options.servers.is_empty() {
+        Ok((host, 0)
+            .to_socket_addrs()?
+            .map(|socket_addr| socket_addr.ip())
+            .collect())
+    } else {
+        Ok(options.resolver()?.lookup_ip(host)?.into_iter().collect())
+    }
+}
+
+/// Find the hostname of `ip`, according to `options`.
+///
+/// Return `None` if `ip` has no hostname, or if the lookup fails.
+#[cfg(feature = "dynamic-completions")]
+pub fn reverse_lookup(ip: IpAddr, options: &DnsOptions) -> Option<String> {
+    let names = options.resolver().ok()?.reverse_lookup(ip).ok()?;
+    names
+        .iter()
+        .next()
+        .map(|name| name.to_utf8().trim_end_matches('.').to_owned())
+}
+
+/// Resolve hostnames, and remember their addresses for the rest of the run.
+#[derive(Debug, Default)]
+pub struct Resolver {
+    options: DnsOptions,
+    cache: Mutex<HashMap<String, Vec<IpAddr>>>,
+}
+
+impl Resolver {
+    /// Create a new resolver with the given `options`.
+    pub fn new(options: DnsOptions) -> Self {
+        Self {
+            options,
+            cache: Mutex::default(),
+        }
+    }
+
+    /// Forget all remembered addresses.
+    pub fn forget(&self) {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.clear();
+        }
+    }
+
+    fn cached(&self, host: &str) -> Option<Vec<IpAddr>> {
+        self.cache
+            .lock()
+            .ok()
+            .and_then(|cache| cache.get(host).cloned())
+    }
+
+    fn remember(&self, host: &str, ips: Vec<IpAddr>) {
+        if let Ok(mut cache) = self.cache.lock() {
+            cache.insert(host.to_owned(), ips);
+        }
+    }
+
+    /// Resolve all `hosts` concurrently, and remember their addresses.
+    ///
+    /// Ignore failures; [`Self::resolve`] tries again for these hosts.
+    pub fn prefetch<'a>(&self, hosts: impl IntoIterator<Item = &'a str>) {
+        let mut hosts = hosts
+            .into_iter()
+            .filter(|host| self.cached(host).is_none())
+            .collect::<Vec<_>>();
+        hosts.sort_unstable();
+        hosts.dedup();
+        for chunk in hosts.chunks(MAX_CONCURRENT_LOOKUPS) {
+            std::thread::scope(|scope| {
+                for host in chunk {
+                    scope.spawn(move || {
+                        if let Ok(ips) = lookup(host, &self.options) {
+                            self.remember(host, ips);
+                        }
+                    });
+                }
+            });
+        }
+    }
+
+    /// Resolve `host` to socket addresses with `port`.
+    ///
+    /// Return remembered addresses if `host` was resolved before.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if resolution failed or timed out.
+    pub fn resolve(&self, host: &str, port: u16) -> Result<Vec<SocketAddr>> {
+        let ips = if let Some(ips) = self.cached(host) {
+            ips
+        } else {
+            let ips = lookup(host, &self.options)?;
+            self.remember(host, ips.clone());
+            ips
+        };
+        Ok(ips
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    #[test]
+    fn server() {
+        assert_eq!(
+            parse_server("192.0.2.1").unwrap(),
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 53)
+        );
+        assert_eq!(
+            parse_server("192.0.2.1:5353").unwrap(),
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 5353)
+        );
+        assert_eq!(
+            parse_server("2001:db8::1").unwrap(),
+            SocketAddr::new(
+                IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+                53
+            )
+        );
+        assert_eq!(
+            parse_server("[2001:db8::1]:5353").unwrap(),
+            SocketAddr::new(
+                IpAddr::V6(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+                5353
+            )
+        );
+        assert!(parse_server("dns.example.com").is_err());
+    }
+
+    #[test]
+    fn resolve_cached() {
+        let resolver = Resolver::default();
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        resolver.remember("wol.invalid", vec![ip]);
+        resolver.prefetch(["wol.invalid"]);
+        assert_eq!(
+            resolver.resolve("wol.invalid", 9).unwrap(),
+            vec![SocketAddr::new(ip, 9)]
+        );
+    }
+}
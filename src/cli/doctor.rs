@@ -0,0 +1,416 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Diagnose the local environment for sending magic packets.
+
+use std::io::{ErrorKind, Result};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::process::ExitCode;
+
+use anstyle::{AnsiColor, Style};
+use clap::{ArgAction, Args};
+use if_addrs::{IfAddr, Ifv4Addr};
+
+use super::check::{self, WakeOn};
+use super::hint;
+use super::neighbours::run;
+
+/// The style of passed checks.
+const OK: Style = AnsiColor::Green.on_default();
+
+/// The style of warnings.
+const WARNING: Style = AnsiColor::Yellow.on_default().bold();
+
+/// The style of errors.
+const ERROR: Style = AnsiColor::Red.on_default().bold();
+
+/// Firewall services which may block magic packets.
+const FIREWALLS: [&str; 4] = ["firewalld", "ufw", "nftables", "iptables"];
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct DoctorArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+}
+
+/// How bad a finding is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Level {
+    /// Everything's fine.
+    Ok,
+    /// Something may keep magic packets from arriving.
+    Warning,
+    /// Something keeps magic packets from being sent.
+    Error,
+}
+
+/// A finding about the environment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Finding {
+    level: Level,
+    message: String,
+}
+
+impl Finding {
+    fn new(level: Level, message: impl Into<String>) -> Self {
+        Self {
+            level,
+            message: message.into(),
+        }
+    }
+}
+
+/// The addresses of an operational network interface.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct InterfaceAddresses {
+    name: String,
+    ipv4: Vec<Ifv4Addr>,
+    ipv6: Vec<Ipv6Addr>,
+}
+
+/// Collect the addresses of all operational network interfaces except
+/// loopback, by interface.
+fn interface_addresses() -> Result<Vec<InterfaceAddresses>> {
+    let mut interfaces: Vec<InterfaceAddresses> = Vec::new();
+    for interface in if_addrs::get_if_addrs()? {
+        if interface.is_loopback() || !interface.is_oper_up() {
+            continue;
+        }
+        if !interfaces
+            .iter()
+            .any(|candidate| candidate.name == interface.name)
+        {
+            interfaces.push(InterfaceAddresses {
+                name: interface.name.clone(),
+                ..InterfaceAddresses::default()
+            });
+        }
+        if let Some(addresses) = interfaces
+            .iter_mut()
+            .find(|candidate| candidate.name == interface.name)
+        {
+            match interface.addr {
+                IfAddr::V4(addr) => addresses.ipv4.push(addr),
+                IfAddr::V6(addr) => addresses.ipv6.push(addr.ip),
+            }
+        }
+    }
+    Ok(interfaces)
+}
+
+/// Check whether we may send to the limited broadcast address.
+///
+/// Connect a UDP socket to the broadcast address, which checks permission and
+/// routes without actually sending a packet.
+fn check_broadcast() -> Finding {
+    let destination = SocketAddr::new(Ipv4Addr::BROADCAST.into(), 9);
+    let result = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).and_then(|socket| {
+        socket.set_broadcast(true)?;
+        socket.connect(destination)
+    });
+    match result {
+        Ok(()) => Finding::new(
+            Level::Ok,
+            format!("broadcast: sending to {destination} is permitted"),
+        ),
+        Err(error) => Finding::new(
+            Level::Error,
+            format!(
+                "broadcast: cannot send to {destination}: {}",
+                hint::explain(error, destination)
+            ),
+        ),
+    }
+}
+
+/// Describe the IPv4 broadcast addresses of `interfaces`.
+fn check_interfaces(interfaces: &[InterfaceAddresses]) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for interface in interfaces {
+        for addr in &interface.ipv4 {
+            let address = format!("{}/{}", addr.ip, addr.prefixlen);
+            findings.push(match addr.broadcast {
+                Some(broadcast) => Finding::new(
+                    Level::Ok,
+                    format!(
+                        "{}: {address}, broadcast {broadcast}",
+                        interface.name
+                    ),
+                ),
+                None => Finding::new(
+                    Level::Warning,
+                    format!(
+                        "{}: {address} has no broadcast address; magic packets cannot be broadcast on this interface",
+                        interface.name
+                    ),
+                ),
+            });
+        }
+    }
+    if !interfaces
+        .iter()
+        .flat_map(|interface| &interface.ipv4)
+        .any(|addr| addr.broadcast.is_some())
+    {
+        findings.push(Finding::new(
+            Level::Error,
+            "interfaces: no network interface with an IPv4 broadcast address; connect to the network of the systems to wake up",
+        ));
+    }
+    findings
+}
+
+/// Check whether any of `interfaces` has IPv6 to send to `ff02::1`.
+fn check_ipv6(interfaces: &[InterfaceAddresses]) -> Finding {
+    let names = interfaces
+        .iter()
+        .filter(|interface| interface.ipv6.iter().any(Ipv6Addr::is_unicast_link_local))
+        .map(|interface| interface.name.as_str())
+        .collect::<Vec<_>>();
+    if names.is_empty() {
+        Finding::new(
+            Level::Warning,
+            "ipv6: no network interface with a link-local IPv6 address; use IPv4 broadcast addresses",
+        )
+    } else {
+        Finding::new(
+            Level::Ok,
+            format!("ipv6: ff02::1 available on {}", names.join(", ")),
+        )
+    }
+}
+
+/// Describe the Wake-on-LAN `settings` of network interfaces.
+///
+/// Skip interfaces without settings, and stop at the first interface if
+/// ethtool is missing.
+fn check_wake_on(settings: Vec<(String, Result<Option<WakeOn>>)>) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    for (interface, wake_on) in settings {
+        match wake_on {
+            Ok(Some(wake_on)) => {
+                let level = if wake_on.magic_packet_enabled() {
+                    Level::Ok
+                } else {
+                    Level::Warning
+                };
+                findings.push(Finding::new(level, wake_on.describe(&interface)));
+            }
+            Ok(None) => {}
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                findings.push(Finding::new(
+                    Level::Warning,
+                    format!("wake on LAN: {error}"),
+                ));
+                break;
+            }
+            Err(error) => {
+                findings.push(Finding::new(
+                    Level::Warning,
+                    format!("{interface}: {error}"),
+                ));
+            }
+        }
+    }
+    findings
+}
+
+/// Describe the `active` firewall services.
+fn check_firewalls(active: &[&str]) -> Finding {
+    if active.is_empty() {
+        Finding::new(
+            Level::Ok,
+            format!("firewall: none of {} is active", FIREWALLS.join(", ")),
+        )
+    } else {
+        Finding::new(
+            Level::Warning,
+            format!(
+                "firewall: {} active; make sure it permits outgoing UDP broadcasts, and incoming magic packets for wol listen",
+                active.join(", ")
+            ),
+        )
+    }
+}
+
+/// Find all active firewall services with systemd.
+fn active_firewalls() -> Vec<&'static str> {
+    FIREWALLS
+        .into_iter()
+        .filter(|unit| run("systemctl", &["is-active", "--quiet", unit]).is_ok())
+        .collect()
+}
+
+/// Format `finding` as a line of the report.
+fn format_finding(finding: &Finding) -> String {
+    let (style, label) = match finding.level {
+        Level::Ok => (OK, "ok"),
+        Level::Warning => (WARNING, "warning"),
+        Level::Error => (ERROR, "error"),
+    };
+    format!("{style}{label:7}{style:#}  {}", finding.message)
+}
+
+/// Check the local environment for sending magic packets, and print a report.
+///
+/// Exit with 1 if any check found an error.
+///
+/// # Errors
+///
+/// Return an error if listing network interfaces fails.
+pub fn doctor(_args: &DoctorArgs) -> Result<ExitCode> {
+    let interfaces = interface_addresses()?;
+    let mut findings = vec![check_broadcast()];
+    findings.extend(check_interfaces(&interfaces));
+    findings.push(check_ipv6(&interfaces));
+    if cfg!(target_os = "linux") {
+        findings.extend(check_wake_on(
+            interfaces
+                .iter()
+                .map(|interface| {
+                    let name = interface.name.clone();
+                    let wake_on = check::read_wake_on(&name);
+                    (name, wake_on)
+                })
+                .collect(),
+        ));
+        findings.push(check_firewalls(&active_firewalls()));
+    }
+    for finding in &findings {
+        anstream::println!("{}", format_finding(finding));
+    }
+    let problems = findings
+        .iter()
+        .filter(|finding| finding.level != Level::Ok)
+        .count();
+    match problems {
+        0 => anstream::println!("\nNo problems found"),
+        1 => anstream::println!("\nFound 1 possible problem"),
+        _ => anstream::println!("\nFound {problems} possible problems"),
+    }
+    Ok(
+        if findings.iter().any(|finding| finding.level == Level::Error) {
+            ExitCode::FAILURE
+        } else {
+            ExitCode::SUCCESS
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Error;
+
+    use super::*;
+
+    fn ipv4(ip: [u8; 4], prefixlen: u8, broadcast: Option<[u8; 4]>) -> Ifv4Addr {
+        Ifv4Addr {
+            ip: ip.into(),
+            netmask: Ipv4Addr::UNSPECIFIED,
+            prefixlen,
+            broadcast: broadcast.map(Ipv4Addr::from),
+        }
+    }
+
+    fn interfaces() -> Vec<InterfaceAddresses> {
+        vec![
+            InterfaceAddresses {
+                name: "eth0".to_owned(),
+                ipv4: vec![ipv4([192, 168, 1, 10], 24, Some([192, 168, 1, 255]))],
+                ipv6: vec!["fe80::1".parse().unwrap()],
+            },
+            InterfaceAddresses {
+                name: "wg0".to_owned(),
+                ipv4: vec![ipv4([10, 0, 0, 2], 32, None)],
+                ipv6: vec!["fd00::2".parse().unwrap()],
+            },
+        ]
+    }
+
+    fn messages(findings: &[Finding]) -> Vec<(Level, &str)> {
+        findings
+            .iter()
+            .map(|finding| (finding.level, finding.message.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn interfaces_and_ipv6() {
+        let interfaces = interfaces();
+        assert_eq!(
+            messages(&check_interfaces(&interfaces)),
+            [
+                (Level::Ok, "eth0: 192.168.1.10/24, broadcast 192.168.1.255"),
+                (
+                    Level::Warning,
+                    "wg0: 10.0.0.2/32 has no broadcast address; magic packets cannot be broadcast on this interface"
+                ),
+            ]
+        );
+        assert_eq!(
+            check_ipv6(&interfaces),
+            Finding::new(Level::Ok, "ipv6: ff02::1 available on eth0")
+        );
+
+        let (_, vpn_only) = interfaces.split_at(1);
+        let findings = check_interfaces(vpn_only);
+        assert_eq!(findings.last().unwrap().level, Level::Error);
+        assert_eq!(check_ipv6(vpn_only).level, Level::Warning);
+    }
+
+    #[test]
+    fn wake_on() {
+        let ethtool = |enabled: &str| {
+            WakeOn::parse(&format!(
+                "\tSupports Wake-on: pumbg\n\tWake-on: {enabled}\n"
+            ))
+        };
+        let settings = vec![
+            ("eth0".to_owned(), Ok(ethtool("g"))),
+            ("eth1".to_owned(), Ok(ethtool("d"))),
+            ("ifb0".to_owned(), Ok(None)),
+            (
+                "wlan0".to_owned(),
+                Err(Error::new(ErrorKind::NotFound, "ethtool not found")),
+            ),
+            ("wlan1".to_owned(), Ok(ethtool("g"))),
+        ];
+        assert_eq!(
+            messages(&check_wake_on(settings)),
+            [
+                (Level::Ok, "eth0: wake up on magic packets enabled"),
+                (
+                    Level::Warning,
+                    "eth1: wake up on magic packets disabled; enable with wol check --enable eth1"
+                ),
+                (Level::Warning, "wake on LAN: ethtool not found"),
+            ]
+        );
+    }
+
+    #[test]
+    fn firewalls() {
+        assert_eq!(check_firewalls(&[]).level, Level::Ok);
+        assert_eq!(
+            check_firewalls(&["ufw"]).message,
+            "firewall: ufw active; make sure it permits outgoing UDP broadcasts, and incoming magic packets for wol listen"
+        );
+    }
+
+    #[test]
+    fn format() {
+        assert_eq!(
+            format_finding(&Finding::new(Level::Warning, "ipv6: none")),
+            format!("{WARNING}warning{WARNING:#}  ipv6: none")
+        );
+        assert_eq!(
+            format_finding(&Finding::new(Level::Ok, "fine")),
+            format!("{OK}ok     {OK:#}  fine")
+        );
+    }
+}
@@ -0,0 +1,270 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Edit the hosts database or wakeup files safely.
+//!
+//! Like visudo edit a copy of the file, and only install the copy if it is
+//! valid.
+
+use std::fs::OpenOptions;
+use std::io::{BufRead, Error, ErrorKind, IsTerminal, Result, Write, stderr, stdin};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+use clap::{ArgAction, Args, ValueHint};
+
+use super::hosts::{Hosts, HostsArgs};
+use super::validate;
+use super::wake::EXIT_INVALID_FILE;
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct EditArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Edit FILE instead of the hosts database.
+    ///
+    /// Check FILE as hosts database if it ends with .toml,
+    /// and as wakeup file otherwise.
+    #[arg(value_name = "FILE", value_hint = ValueHint::FilePath, verbatim_doc_comment)]
+    file: Option<PathBuf>,
+    #[command(flatten)]
+    hosts: HostsArgs,
+}
+
+/// What kind of file we edit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    /// A hosts database.
+    Hosts,
+    /// A wakeup file.
+    WakeupFile,
+}
+
+impl Kind {
+    /// Guess the kind of file at `path` from its extension.
+    fn of(path: &Path) -> Self {
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            Self::Hosts
+        } else {
+            Self::WakeupFile
+        }
+    }
+
+    /// Check `contents` of a file of this kind, and print all errors with
+    /// `name`.
+    ///
+    /// Return whether `contents` is valid.
+    fn check(self, name: &str, contents: &str) -> bool {
+        match self {
+            Self::Hosts => match Hosts::parse(contents).and_then(|hosts| hosts.check()) {
+                Ok(()) => true,
+                Err(error) => {
+                    anstream::println!("{name}: {error}");
+                    false
+                }
+            },
+            Self::WakeupFile => validate::print_errors(name, contents) == 0,
+        }
+    }
+}
+
+/// The editor to use, as program and arguments.
+///
+/// Use `$VISUAL` or `$EDITOR`, and fall back to vi, or notepad on Windows.
+///
+/// # Errors
+///
+/// Return an error if the editor command is invalid.
+fn editor() -> Result<Vec<String>> {
+    let command = std::env::var("VISUAL")
+        .ok()
+        .filter(|command| !command.trim().is_empty())
+        .or_else(|| std::env::var("EDITOR").ok())
+        .filter(|command| !command.trim().is_empty())
+        .unwrap_or_else(|| if cfg!(windows) { "notepad" } else { "vi" }.to_owned());
+    shlex::split(&command)
+        .filter(|words| !words.is_empty())
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Invalid editor command: {command}"),
+            )
+        })
+}
+
+/// Edit `path` with `editor`.
+///
+/// # Errors
+///
+/// Return an error if the editor failed to start, or exited unsuccessfully.
+fn run_editor(editor: &[String], path: &Path) -> Result<()> {
+    let (program, args) = editor
+        .split_first()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Empty editor command"))?;
+    let status = Command::new(program)
+        .args(args)
+        .arg(path)
+        .status()
+        .map_err(|error| Error::new(error.kind(), format!("Failed to run {program}: {error}")))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::other(format!("{program} failed: {status}")))
+    }
+}
+
+/// The path of the copy to edit for `path`.
+///
+/// Put the copy next to `path`, like visudo, to keep it on the same file
+/// system and inherit the permissions of the directory.
+fn scratch_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map_or_else(|| "wol".into(), |name| name.to_string_lossy());
+    path.with_file_name(format!(".{name}.wol-edit"))
+}
+
+/// Write `contents` to `path`, only readable by the current user on Unix.
+fn write_private(path: &Path, contents: &str) -> Result<()> {
+    if let Some(directory) = path.parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+/// Ask whether to edit an invalid file again.
+///
+/// Write the question to `output` and read the answer from `input`.
+///
+/// # Errors
+///
+/// Return an error if reading or writing fails.
+fn edit_again(mut input: impl BufRead, mut output: impl Write) -> Result<bool> {
+    loop {
+        write!(output, "What now? [e]dit again, [q]uit without saving: ")?;
+        output.flush()?;
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            return Ok(false);
+        }
+        match answer.trim() {
+            "e" | "E" => return Ok(true),
+            "q" | "Q" => return Ok(false),
+            _ => {}
+        }
+    }
+}
+
+/// Edit `scratch`, a copy of `path`, until it is valid or the user gives up.
+///
+/// Return `true` if the user left a valid file.
+fn edit_until_valid(kind: Kind, path: &Path, scratch: &Path) -> Result<bool> {
+    let editor = editor()?;
+    let name = path.display().to_string();
+    loop {
+        run_editor(&editor, scratch)?;
+        let contents = std::fs::read_to_string(scratch)?;
+        if kind.check(&name, &contents) {
+            return Ok(true);
+        }
+        let interactive = stdin().is_terminal() && stderr().is_terminal();
+        if !interactive || !edit_again(stdin().lock(), stderr())? {
+            return Ok(false);
+        }
+    }
+}
+
+/// Edit the hosts database or a wakeup file, and install it if valid.
+///
+/// Exit with 4 if the user left an invalid file, which we did not install.
+///
+/// # Errors
+///
+/// Return an error if reading or writing files fails, or if the editor fails.
+pub fn edit(args: &EditArgs) -> Result<ExitCode> {
+    let (path, kind) = match &args.file {
+        Some(file) => (file.clone(), Kind::of(file)),
+        None => (args.hosts.path()?, Kind::Hosts),
+    };
+    let original = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+        Err(error) => {
+            return Err(Error::new(
+                error.kind(),
+                format!("Failed to read {}: {error}", path.display()),
+            ));
+        }
+    };
+    let scratch = scratch_path(&path);
+    write_private(&scratch, &original)?;
+    let valid = edit_until_valid(kind, &path, &scratch);
+    let contents = std::fs::read_to_string(&scratch);
+    std::fs::remove_file(&scratch)?;
+    if !valid? {
+        anstream::eprintln!("{} not changed", path.display());
+        return Ok(ExitCode::from(EXIT_INVALID_FILE));
+    }
+    let contents = contents?;
+    if contents == original {
+        anstream::eprintln!("{} not changed", path.display());
+    } else if kind == Kind::Hosts {
+        Hosts::parse(&contents)?.write(&path)?;
+    } else {
+        std::fs::write(&path, contents)?;
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kind() {
+        assert_eq!(Kind::of(Path::new("hosts.toml")), Kind::Hosts);
+        assert_eq!(Kind::of(Path::new("lab.wol")), Kind::WakeupFile);
+        assert_eq!(Kind::of(Path::new("lab")), Kind::WakeupFile);
+    }
+
+    #[test]
+    fn check() {
+        assert!(Kind::Hosts.check("hosts.toml", "[nas]\nmac = \"12:13:14:15:16:17\"\n"));
+        assert!(!Kind::Hosts.check("hosts.toml", "[nas\n"));
+        assert!(!Kind::Hosts.check("hosts.toml", "[nas]\nmac = \"foo\"\n"));
+        assert!(Kind::WakeupFile.check("lab", "# lab\n12:13:14:15:16:17 192.0.2.255\n"));
+        assert!(!Kind::WakeupFile.check("lab", "12:13:14:15:16\n"));
+    }
+
+    #[test]
+    fn scratch() {
+        assert_eq!(
+            scratch_path(Path::new("/home/foo/.config/wol/hosts.toml")),
+            Path::new("/home/foo/.config/wol/.hosts.toml.wol-edit")
+        );
+    }
+
+    #[test]
+    fn ask() {
+        let mut output = Vec::new();
+        assert!(edit_again("x\ne\n".as_bytes(), &mut output).unwrap());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "What now? [e]dit again, [q]uit without saving: ".repeat(2)
+        );
+        assert!(!edit_again("q\n".as_bytes(), Vec::new()).unwrap());
+        assert!(!edit_again("".as_bytes(), Vec::new()).unwrap());
+    }
+}
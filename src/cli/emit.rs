@@ -0,0 +1,95 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Write magic packets instead of sending them.
+
+use std::io::{Result, Write};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use clap::ValueEnum;
+use wol::{MacAddress, SecureOn};
+
+/// The format to write magic packets in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PacketFormat {
+    /// One line of lowercase hexadecimal digits per packet.
+    Hex,
+    /// One line of standard base64 per packet.
+    Base64,
+    /// The bytes of all packets, one after another.
+    Raw,
+}
+
+/// Write the magic packet for `mac_address` to `sink` in `format`.
+///
+/// Include `secure_on` in the packet, if any.
+///
+/// # Errors
+///
+/// Return any error from writing to `sink`.
+pub fn write_packet<W: Write>(
+    sink: &mut W,
+    format: PacketFormat,
+    mac_address: MacAddress,
+    secure_on: Option<SecureOn>,
+) -> Result<()> {
+    let mut packet = Vec::with_capacity(108);
+    wol::write_magic_packet(&mut packet, mac_address, secure_on)?;
+    match format {
+        PacketFormat::Hex => {
+            for byte in packet {
+                write!(sink, "{byte:02x}")?;
+            }
+            writeln!(sink)
+        }
+        PacketFormat::Base64 => writeln!(sink, "{}", STANDARD.encode(packet)),
+        PacketFormat::Raw => sink.write_all(&packet),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mac() -> MacAddress {
+        MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17])
+    }
+
+    #[test]
+    fn write_hex() {
+        let mut buffer = Vec::new();
+        write_packet(&mut buffer, PacketFormat::Hex, mac(), None).unwrap();
+        let hex = String::from_utf8(buffer).unwrap();
+        assert_eq!(hex.len(), 205);
+        assert!(hex.starts_with("ffffffffffff121314151617121314151617"));
+        assert!(hex.ends_with("121314151617\n"));
+    }
+
+    #[test]
+    fn write_base64() {
+        let mut buffer = Vec::new();
+        write_packet(
+            &mut buffer,
+            PacketFormat::Base64,
+            mac(),
+            Some(SecureOn::new([1, 2, 3, 4, 5, 6])),
+        )
+        .unwrap();
+        let line = String::from_utf8(buffer).unwrap();
+        let packet = STANDARD.decode(line.trim_end()).unwrap();
+        assert_eq!(packet.len(), 108);
+        assert_eq!(packet.get(102..), Some(&[1, 2, 3, 4, 5, 6][..]));
+    }
+
+    #[test]
+    fn write_raw() {
+        let mut buffer = Vec::new();
+        write_packet(&mut buffer, PacketFormat::Raw, mac(), None).unwrap();
+        write_packet(&mut buffer, PacketFormat::Raw, mac(), None).unwrap();
+        assert_eq!(buffer.len(), 204);
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Send magic packets in raw Ethernet frames, like etherwake.
+
+use std::io::{Error, ErrorKind, Result};
+
+use wol::{MacAddress, SecureOn};
+
+/// The `EtherType` of Wake-on-LAN frames.
+pub const ETHERTYPE_WOL: u16 = 0x0842;
+
+/// Build an Ethernet frame from `source` to `hardware_address`, with a magic
+/// packet for `hardware_address` and `secure_on`.
+///
+/// Like etherwake address the frame to the system to wake up; switches which
+/// no longer know the port of a sleeping system flood the frame to all ports.
+pub fn frame(
+    source: MacAddress,
+    hardware_address: MacAddress,
+    secure_on: Option<SecureOn>,
+) -> Vec<u8> {
+    let mut packet = [0; 102];
+    wol::fill_magic_packet(&mut packet, hardware_address);
+    let mut frame = Vec::with_capacity(14 + 108);
+    frame.extend_from_slice(hardware_address.as_ref());
+    frame.extend_from_slice(source.as_ref());
+    frame.extend_from_slice(&ETHERTYPE_WOL.to_be_bytes());
+    frame.extend_from_slice(&packet);
+    if let Some(secure_on) = secure_on {
+        frame.extend_from_slice(secure_on.as_ref());
+    }
+    frame
+}
+
+/// A raw socket to send Wake-on-LAN frames out of a network interface.
+pub struct RawSocket {
+    #[cfg(target_os = "linux")]
+    sender: Box<dyn pnet_datalink::DataLinkSender>,
+    source: MacAddress,
+}
+
+impl std::fmt::Debug for RawSocket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RawSocket")
+            .field("source", &self.source)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RawSocket {
+    /// Open a raw socket on `interface`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if `interface` does not exist, or if opening the socket
+    /// fails, typically because the process lacks `CAP_NET_RAW`.
+    #[cfg(target_os = "linux")]
+    pub fn open(interface: &str) -> Result<Self> {
+        let interface = pnet_datalink::interfaces()
+            .into_iter()
+            .find(|candidate| candidate.name == interface)
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::NotFound,
+                    format!("Interface {interface} not found"),
+                )
+            })?;
+        let source = interface
+            .mac
+            .filter(|mac| !mac.is_zero())
+            .map(|mac| MacAddress::new(mac.octets()))
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Interface {} has no hardware address", interface.name),
+                )
+            })?;
+        // Don't put the interface into promiscuous mode; we only send frames
+        let config = pnet_datalink::Config {
+            promiscuous: false,
+            ..pnet_datalink::Config::default()
+        };
+        match pnet_datalink::channel(&interface, config) {
+            Ok(pnet_datalink::Channel::Ethernet(sender, _)) => Ok(Self { sender, source }),
+            Ok(_) => Err(Error::new(
+                ErrorKind::Unsupported,
+                format!("Cannot send raw Ethernet frames on {}", interface.name),
+            )),
+            Err(error) if error.kind() == ErrorKind::PermissionDenied => Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "{error}; raw Ethernet frames require CAP_NET_RAW, run as root or grant wol the capability"
+                ),
+            )),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Open a raw socket on `interface`.
+    ///
+    /// # Errors
+    ///
+    /// Always return an error, because raw Ethernet frames are only supported
+    /// on Linux.
+    #[cfg(not(target_os = "linux"))]
+    pub fn open(interface: &str) -> Result<Self> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("Cannot send raw Ethernet frames on {interface}: only supported on Linux"),
+        ))
+    }
+
+    /// Send a Wake-on-LAN frame for `hardware_address` and `secure_on`.
+    ///
+    /// # Errors
+    ///
+    /// Return any error from sending the frame.
+    pub fn send_magic_packet(
+        &mut self,
+        hardware_address: MacAddress,
+        secure_on: Option<SecureOn>,
+    ) -> Result<()> {
+        let frame = frame(self.source, hardware_address, secure_on);
+        self.send(&frame)
+    }
+
+    #[cfg(target_os = "linux")]
+    fn send(&mut self, frame: &[u8]) -> Result<()> {
+        self.sender
+            .send_to(frame, None)
+            .unwrap_or_else(|| Err(Error::new(ErrorKind::WriteZero, "Failed to send frame")))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    #[allow(clippy::unused_self)]
+    fn send(&mut self, _frame: &[u8]) -> Result<()> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "Cannot send raw Ethernet frames: only supported on Linux",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn magic_packet_frame() {
+        let source = MacAddress::new([0x00, 0x1b, 0x21, 0x01, 0x02, 0x03]);
+        let target = MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]);
+        let frame = frame(source, target, None);
+        let (header, packet) = frame.split_at(14);
+        assert_eq!(
+            header,
+            [
+                0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x00, 0x1b, 0x21, 0x01, 0x02, 0x03, 0x08, 0x42
+            ]
+        );
+        assert_eq!(packet.len(), 102);
+        assert_eq!(wol::parse_magic_packet(packet), Some((target, None)));
+
+        let secure_on = SecureOn::new([1, 2, 3, 4, 5, 6]);
+        let frame = super::frame(source, target, Some(secure_on));
+        let (_, packet) = frame.split_at(14);
+        assert_eq!(packet.len(), 108);
+        assert_eq!(
+            wol::parse_magic_packet(packet),
+            Some((target, Some(secure_on)))
+        );
+    }
+}
@@ -0,0 +1,150 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Events of the HTTP API, for clients of the event stream.
+
+use std::io::{Result, Write};
+use std::sync::Mutex;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use serde_json::Value;
+
+/// An event, with its name and a JSON object as data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    name: &'static str,
+    data: Value,
+}
+
+impl Event {
+    /// Create an event `name` with `data`.
+    pub fn new(name: &'static str, data: Value) -> Self {
+        Self { name, data }
+    }
+
+    /// The name of this event.
+    #[cfg_attr(not(feature = "dbus"), allow(dead_code))]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The data of this event.
+    #[cfg_attr(not(feature = "dbus"), allow(dead_code))]
+    pub fn data(&self) -> &Value {
+        &self.data
+    }
+
+    /// Write this event to `writer` as server-sent event.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if writing fails.
+    pub fn write_to(&self, mut writer: impl Write) -> Result<()> {
+        // JSON without pretty-printing never contains newlines, so the data
+        // fits into a single data line
+        write!(writer, "event: {}\ndata: {}\n\n", self.name, self.data)
+    }
+}
+
+/// Broadcast events to all subscribers.
+#[derive(Debug, Default)]
+pub struct Events {
+    subscribers: Mutex<Vec<Sender<Event>>>,
+}
+
+impl Events {
+    /// Subscribe to all events published from now on.
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.push(sender);
+        }
+        receiver
+    }
+
+    /// Publish `event` to all subscribers.
+    ///
+    /// Forget subscribers which went away.
+    pub fn publish(&self, event: &Event) {
+        if let Ok(mut subscribers) = self.subscribers.lock() {
+            subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        }
+    }
+}
+
+/// Write all events from `events` to `writer` as server-sent events.
+///
+/// Write a comment after `keep_alive` without events, to keep proxies from
+/// closing the connection, and to notice clients which went away.
+///
+/// Return when `events` disconnects.
+///
+/// # Errors
+///
+/// Return an error if writing fails, typically because the client went away.
+pub fn stream(
+    events: &Receiver<Event>,
+    keep_alive: Duration,
+    mut writer: impl Write,
+) -> Result<()> {
+    loop {
+        match events.recv_timeout(keep_alive) {
+            Ok(event) => event.write_to(&mut writer)?,
+            Err(RecvTimeoutError::Timeout) => writer.write_all(b": keep-alive\n\n")?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        writer.flush()?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn broadcast() {
+        let events = Events::default();
+        let first = events.subscribe();
+        let second = events.subscribe();
+        drop(second);
+        let event = Event::new("wake", json!({ "mac": "12:13:14:15:16:17" }));
+        events.publish(&event);
+        assert_eq!(first.try_recv().unwrap(), event);
+        assert_eq!(events.subscribers.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn write_stream() {
+        let events = Events::default();
+        let receiver = events.subscribe();
+        events.publish(&Event::new("online", json!({ "name": "nas" })));
+        events.publish(&Event::new("packet", json!({ "port": 9 })));
+        drop(events);
+        let mut buffer = Vec::new();
+        stream(&receiver, Duration::from_secs(1), &mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "event: online\ndata: {\"name\":\"nas\"}\n\nevent: packet\ndata: {\"port\":9}\n\n"
+        );
+    }
+
+    #[test]
+    fn keep_alive() {
+        let (sender, receiver) = mpsc::channel();
+        let handle = std::thread::spawn(move || {
+            let mut buffer = Vec::new();
+            stream(&receiver, Duration::from_millis(10), &mut buffer).unwrap();
+            buffer
+        });
+        std::thread::sleep(Duration::from_millis(50));
+        drop(sender);
+        let buffer = String::from_utf8(handle.join().unwrap()).unwrap();
+        assert!(buffer.starts_with(": keep-alive\n\n"), "{buffer}");
+    }
+}
@@ -0,0 +1,281 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Export systems to other formats.
+
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Result, stdin};
+
+use clap::{ArgAction, Args, ValueEnum, ValueHint};
+use serde_json::{Map, Value};
+use wol::file::WakeUpTarget;
+
+use super::PathOrStdin;
+use super::hosts::{Host, Hosts, HostsArgs};
+use super::{fmt, input};
+
+/// The format to export systems to.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// The format of wakeup files.
+    #[default]
+    Plain,
+    /// The format of the hosts database.
+    Toml,
+    /// One JSON object per line, as for --stdin-format.
+    Json,
+    /// Comma-separated values with a header line, as for --stdin-format.
+    Csv,
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct ExportArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Export systems in FORMAT.
+    #[arg(long = "format", value_name = "FORMAT", value_enum, default_value_t)]
+    format: ExportFormat,
+    /// Export systems from wakeup FILE instead of the hosts
+    /// database.
+    ///
+    /// Use - to read from standard input; repeat to export
+    /// systems from multiple files.
+    #[arg(
+        short = 'f',
+        long = "file",
+        value_name = "FILE",
+        action = ArgAction::Append,
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    file: Vec<PathOrStdin>,
+    #[command(flatten)]
+    hosts: HostsArgs,
+}
+
+/// The columns of CSV output.
+const CSV_COLUMNS: [&str; 8] = [
+    "name", "mac", "host", "port", "passwd", "address", "after", "tags",
+];
+
+/// Quote `field` for CSV if necessary.
+fn quote_csv(field: &str) -> String {
+    if field.contains([',', '"', '\n']) || field.trim() != field {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+/// Get the fields of `host` by name, including its tags.
+fn fields(host: &Host) -> Vec<(&'static str, String)> {
+    let mut fields = input::target_fields(&host.target);
+    if !host.tags.is_empty() {
+        fields.push(("tags", host.tags.join(",")));
+    }
+    fields
+}
+
+/// Convert `host` to a JSON object.
+fn to_json(host: &Host) -> Value {
+    let object = fields(host)
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match key {
+                "port" => value
+                    .parse::<u16>()
+                    .map_or(Value::String(value), Value::from),
+                "after" | "tags" => value.split(',').collect(),
+                _ => Value::String(value),
+            };
+            (key.to_owned(), value)
+        })
+        .collect::<Map<_, _>>();
+    Value::Object(object)
+}
+
+/// Format all `hosts` in `format`.
+///
+/// # Errors
+///
+/// Return an error if a system cannot be represented in `format`, e.g. a
+/// system without name in the format of the hosts database.
+fn export_hosts(hosts: &[Host], format: ExportFormat) -> Result<String> {
+    let lines = match format {
+        ExportFormat::Plain => hosts.iter().map(|host| fmt::line(&host.target)).collect(),
+        ExportFormat::Toml => {
+            let mut database = Hosts::default();
+            for host in hosts {
+                let name = host.target.name().ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "{}: No name, required for the hosts database",
+                            host.target.hardware_address()
+                        ),
+                    )
+                })?;
+                let fields = fields(host);
+                let fields = fields
+                    .iter()
+                    .filter(|(key, _)| !["mac", "name"].contains(key))
+                    .map(|(key, value)| (*key, value.as_str()))
+                    .collect::<Vec<_>>();
+                database.add(name, host.target.hardware_address(), &fields)?;
+            }
+            return Ok(database.to_string());
+        }
+        ExportFormat::Json => hosts.iter().map(|host| to_json(host).to_string()).collect(),
+        ExportFormat::Csv => std::iter::once(CSV_COLUMNS.join(","))
+            .chain(hosts.iter().map(|host| {
+                let fields = fields(host);
+                CSV_COLUMNS
+                    .iter()
+                    .map(|column| {
+                        fields
+                            .iter()
+                            .find(|(key, _)| key == column)
+                            .map(|(_, value)| quote_csv(value))
+                            .unwrap_or_default()
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",")
+            }))
+            .collect::<Vec<_>>(),
+    };
+    Ok(lines.into_iter().map(|line| line + "\n").collect())
+}
+
+/// Export systems from the hosts database or from wakeup files.
+///
+/// # Errors
+///
+/// Return an error if reading the hosts database or a file fails, if any
+/// system is invalid, or if a system cannot be represented in the format.
+pub fn export(args: &ExportArgs) -> Result<()> {
+    let hosts = if args.file.is_empty() {
+        args.hosts.read()?.hosts()?
+    } else {
+        let mut hosts = Vec::new();
+        for file in &args.file {
+            let targets: Box<dyn Iterator<Item = Result<WakeUpTarget>>> = match file {
+                PathOrStdin::Stdin => Box::new(wol::file::from_reader(BufReader::new(stdin()))),
+                PathOrStdin::Path(path) => {
+                    Box::new(wol::file::from_reader(BufReader::new(File::open(path)?)))
+                }
+            };
+            for target in targets {
+                hosts.push(Host {
+                    target: target?,
+                    tags: Vec::new(),
+                });
+            }
+        }
+        hosts
+    };
+    anstream::print!("{}", export_hosts(&hosts, args.format)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn hosts() -> Vec<Host> {
+        vec![
+            Host {
+                target: WakeUpTarget::from_str(
+                    "12:13:14:15:16:17 192.168.1.255 9 keyring:nas name=nas address=nas.lan",
+                )
+                .unwrap(),
+                tags: vec!["lab".to_owned(), "storage".to_owned()],
+            },
+            Host {
+                target: WakeUpTarget::from_str("12:13:14:15:16:18 name=\"office pc\" after=nas")
+                    .unwrap(),
+                tags: Vec::new(),
+            },
+        ]
+    }
+
+    #[test]
+    fn plain() {
+        assert_eq!(
+            export_hosts(&hosts(), ExportFormat::Plain).unwrap(),
+            "12:13:14:15:16:17 192.168.1.255 9 keyring:nas name=nas address=nas.lan
+12:13:14:15:16:18 name=\"office pc\" after=nas
+"
+        );
+    }
+
+    #[test]
+    fn toml() {
+        let contents = export_hosts(&hosts(), ExportFormat::Toml).unwrap();
+        assert_eq!(
+            contents,
+            r#"[nas]
+mac = "12:13:14:15:16:17"
+host = "192.168.1.255"
+port = 9
+passwd = "keyring:nas"
+address = "nas.lan"
+tags = ["lab", "storage"]
+
+["office pc"]
+mac = "12:13:14:15:16:18"
+after = ["nas"]
+"#
+        );
+        assert_eq!(Hosts::parse(&contents).unwrap().hosts().unwrap(), hosts());
+
+        let unnamed = Host {
+            target: WakeUpTarget::from_str("12:13:14:15:16:19").unwrap(),
+            tags: Vec::new(),
+        };
+        let error = export_hosts(&[unnamed], ExportFormat::Toml).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "12:13:14:15:16:19: No name, required for the hosts database"
+        );
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(
+            export_hosts(&hosts(), ExportFormat::Json).unwrap(),
+            r#"{"address":"nas.lan","host":"192.168.1.255","mac":"12:13:14:15:16:17","name":"nas","passwd":"keyring:nas","port":9,"tags":["lab","storage"]}
+{"after":["nas"],"mac":"12:13:14:15:16:18","name":"office pc"}
+"#
+        );
+    }
+
+    #[test]
+    fn csv() {
+        let contents = export_hosts(&hosts(), ExportFormat::Csv).unwrap();
+        assert_eq!(
+            contents,
+            "name,mac,host,port,passwd,address,after,tags
+nas,12:13:14:15:16:17,192.168.1.255,9,keyring:nas,nas.lan,,\"lab,storage\"
+office pc,12:13:14:15:16:18,,,,,nas,
+"
+        );
+        let targets = input::parse_csv(&contents)
+            .into_iter()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(
+            targets,
+            hosts()
+                .into_iter()
+                .map(|host| host.target)
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,297 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Rewrite wakeup files into canonical form.
+
+use std::io::{Read, Result, stdin};
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use clap::{ArgAction, Args, ValueHint};
+use wol::file::{MagicPacketDestination, ParseLineError, WakeUpTarget, quote_field};
+
+use super::PathOrStdin;
+use super::wake::EXIT_INVALID_FILE;
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct FmtArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Do not rewrite files; list all files which are not in
+    /// canonical form, and exit with 1 if there are any.
+    #[arg(long = "check", verbatim_doc_comment)]
+    check: bool,
+    /// The wakeup files to rewrite in place.
+    ///
+    /// Use - to read from standard input and write to
+    /// standard output.
+    #[arg(
+        value_name = "FILE",
+        required = true,
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    files: Vec<PathOrStdin>,
+}
+
+/// The number of columns of a canonical line.
+const COLUMNS: usize = 7;
+
+/// Format `destination` in canonical form.
+///
+/// DNS names are case-insensitive, so use lowercase.
+fn destination(destination: &MagicPacketDestination) -> String {
+    match destination {
+        MagicPacketDestination::Dns(name) => name.to_lowercase(),
+        destination => destination.to_string(),
+    }
+}
+
+/// The fields of `target` in canonical form, one for every column.
+///
+/// Columns are the hardware address, the packet destination, the port, the
+/// SecureON token, and the name, address, and after attributes.  Missing
+/// fields are empty.
+fn columns(target: &WakeUpTarget) -> [String; COLUMNS] {
+    let attribute = |key: &str, value: Option<String>| {
+        value.map_or_else(String::new, |value| {
+            format!("{key}={}", quote_field(&value))
+        })
+    };
+    let secure_on = target
+        .secure_on()
+        .map(|secure_on| secure_on.to_string())
+        .or_else(|| {
+            target
+                .keyring_secure_on()
+                .map(|name| quote_field(&format!("keyring:{name}")).into_owned())
+        });
+    let after = Some(target.after().join(",")).filter(|after| !after.is_empty());
+    [
+        target.hardware_address().to_string(),
+        target
+            .packet_destination()
+            .map(|value| quote_field(&destination(value)).into_owned())
+            .unwrap_or_default(),
+        target
+            .port()
+            .map(|port| port.to_string())
+            .unwrap_or_default(),
+        secure_on.unwrap_or_default(),
+        attribute("name", target.name().map(str::to_owned)),
+        attribute("address", target.address().map(destination)),
+        attribute("after", after),
+    ]
+}
+
+/// Format `target` as a single line of a wakeup file in canonical form.
+pub fn line(target: &WakeUpTarget) -> String {
+    columns(target)
+        .into_iter()
+        .filter(|field| !field.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A system in a wakeup file, with the comments directly above it.
+struct Entry {
+    comments: Vec<String>,
+    target: WakeUpTarget,
+    columns: [String; COLUMNS],
+}
+
+/// Rewrite the `contents` of a wakeup file into canonical form.
+///
+/// Use uppercase hardware addresses and SecureON tokens with colons, lowercase
+/// DNS names, and align all fields in columns.  Sort systems by name and then
+/// by hardware address, and drop duplicate systems.
+///
+/// Keep comments directly above a system with the system; move all other
+/// comments to the top of the file.
+///
+/// # Errors
+///
+/// Return an error for the first invalid line in `contents`.
+fn format(contents: &str) -> std::result::Result<String, ParseLineError> {
+    let mut header = Vec::new();
+    let mut pending = Vec::new();
+    let mut entries: Vec<Entry> = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            header.append(&mut pending);
+        } else if line.starts_with('#') {
+            pending.push(line.to_owned());
+        } else {
+            let target = WakeUpTarget::from_str(line)
+                .map_err(|error| ParseLineError::new(index + 1, error))?;
+            let columns = columns(&target);
+            entries.push(Entry {
+                comments: std::mem::take(&mut pending),
+                target,
+                columns,
+            });
+        }
+    }
+    header.append(&mut pending);
+
+    entries.sort_by(|a, b| {
+        let key = |entry: &Entry| {
+            (
+                entry.target.name().is_none(),
+                entry.target.name().map(str::to_owned),
+                <[u8; 6]>::from(entry.target.hardware_address()),
+            )
+        };
+        key(a).cmp(&key(b))
+    });
+    let mut unique: Vec<Entry> = Vec::with_capacity(entries.len());
+    for mut entry in entries {
+        match unique
+            .iter_mut()
+            .find(|other| other.columns == entry.columns)
+        {
+            Some(other) => other.comments.append(&mut entry.comments),
+            None => unique.push(entry),
+        }
+    }
+
+    let mut widths = [0; COLUMNS];
+    for entry in &unique {
+        for (width, cell) in widths.iter_mut().zip(&entry.columns) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut lines = header;
+    if !lines.is_empty() && !unique.is_empty() {
+        lines.push(String::new());
+    }
+    for entry in unique {
+        lines.extend(entry.comments);
+        lines.push(
+            entry
+                .columns
+                .iter()
+                .zip(widths)
+                .filter(|(_, width)| 0 < *width)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+                .trim_end()
+                .to_owned(),
+        );
+    }
+    Ok(lines.into_iter().map(|line| line + "\n").collect())
+}
+
+/// Rewrite all files into canonical form, or check whether they are.
+///
+/// Exit with 4 if any file is invalid, with 1 if any file is not in canonical
+/// form with `--check`, and with 0 otherwise.
+///
+/// # Errors
+///
+/// Return an error if reading or writing a file fails.
+pub fn fmt(args: &FmtArgs) -> Result<ExitCode> {
+    let mut invalid = false;
+    let mut unformatted = false;
+    for file in &args.files {
+        let (name, contents) = match file {
+            PathOrStdin::Stdin => {
+                let mut contents = String::new();
+                stdin().read_to_string(&mut contents)?;
+                ("<stdin>".to_owned(), contents)
+            }
+            PathOrStdin::Path(path) => (path.display().to_string(), std::fs::read_to_string(path)?),
+        };
+        let formatted = match format(&contents) {
+            Ok(formatted) => formatted,
+            Err(error) => {
+                anstream::eprintln!("{name}: {error}");
+                invalid = true;
+                continue;
+            }
+        };
+        if args.check {
+            if formatted != contents {
+                anstream::println!("{name}");
+                unformatted = true;
+            }
+        } else {
+            match file {
+                PathOrStdin::Stdin => anstream::print!("{formatted}"),
+                PathOrStdin::Path(path) if formatted != contents => {
+                    std::fs::write(path, formatted)?;
+                }
+                PathOrStdin::Path(_) => {}
+            }
+        }
+    }
+    Ok(if invalid {
+        ExitCode::from(EXIT_INVALID_FILE)
+    } else if unformatted {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical() {
+        let contents = "# Lab systems
+
+# The file server
+12-13-14-15-16-1a   NAS.Lan 9 name=nas
+12:13:14:15:16:17 keyring:\"office pc\" name=office
+  12:13:14:15:16:20\t7 address=Router.lan name=router
+12:13:14:15:16:1A nas.lan 9   name=nas
+# Unnamed
+12:13:14:15:16:21 ff02::1%eth0 9 aa:bb:cc:dd:ee:ff after=nas,router
+";
+        let formatted = format(contents).unwrap();
+        assert_eq!(
+            formatted,
+            "# Lab systems
+
+# The file server
+12:13:14:15:16:1A nas.lan      9                     name=nas
+12:13:14:15:16:17                \"keyring:office pc\" name=office
+12:13:14:15:16:20              7                     name=router address=router.lan
+# Unnamed
+12:13:14:15:16:21 ff02::1%eth0 9 AA:BB:CC:DD:EE:FF                                  after=nas,router
+"
+        );
+        assert_eq!(format(&formatted).unwrap(), formatted);
+        // Formatting keeps all distinct systems
+        let distinct = |contents: &str| {
+            let mut columns = wol::file::from_lines(contents.lines())
+                .map(|target| columns(&target.unwrap()))
+                .collect::<Vec<_>>();
+            columns.sort();
+            columns.dedup();
+            columns
+        };
+        assert_eq!(distinct(&formatted), distinct(contents));
+    }
+
+    #[test]
+    fn invalid() {
+        let error = format("12:13:14:15:16:17\n\n12:13:14:15:16 nas.lan\n").unwrap_err();
+        assert_eq!(error.line_no(), 3);
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(format("").unwrap(), "");
+        assert_eq!(format("# Nothing\n\n").unwrap(), "# Nothing\n");
+    }
+}
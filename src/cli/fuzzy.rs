@@ -0,0 +1,249 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Find systems by fuzzy search.
+
+use std::io::{BufRead, Error, ErrorKind, IsTerminal, Result, Write, stderr, stdin};
+
+use wol::file::WakeUpTarget;
+
+use super::hosts::Host;
+
+/// The score of every matched character.
+const SCORE_MATCH: i64 = 16;
+
+/// The bonus for a match at the start of a word.
+const BONUS_WORD_START: i64 = 8;
+
+/// The bonus for a match right after the previous match.
+const BONUS_CONSECUTIVE: i64 = 8;
+
+/// The penalty for every character skipped before or between matches.
+const PENALTY_GAP: i64 = 1;
+
+/// Score how well `query` matches `candidate`.
+///
+/// Match all characters of `query` in order, ignoring case, and prefer
+/// consecutive matches, matches at the start of words, and matches close to
+/// the start of `candidate`.  Return `None` if `candidate` does not contain
+/// all characters of `query` in order.
+pub fn score(query: &str, candidate: &str) -> Option<i64> {
+    let mut query = query.chars().flat_map(char::to_lowercase).peekable();
+    let mut score = 0;
+    let mut previous: Option<char> = None;
+    let mut last_match: Option<usize> = None;
+    for (index, c) in candidate.chars().flat_map(char::to_lowercase).enumerate() {
+        let Some(&wanted) = query.peek() else {
+            break;
+        };
+        if c == wanted {
+            query.next();
+            score += SCORE_MATCH;
+            if previous.is_none_or(|previous| !previous.is_alphanumeric()) {
+                score += BONUS_WORD_START;
+            }
+            match last_match {
+                Some(last) if last + 1 == index => score += BONUS_CONSECUTIVE,
+                Some(last) => {
+                    score -= PENALTY_GAP * i64::try_from(index - last - 1).unwrap_or(i64::MAX);
+                }
+                // Prefer matches close to the start
+                None => score -= PENALTY_GAP * i64::try_from(index).unwrap_or(i64::MAX),
+            }
+            last_match = Some(index);
+        }
+        previous = Some(c);
+    }
+    query.peek().is_none().then_some(score)
+}
+
+/// Score how well `query` matches `host`.
+///
+/// Match against the name, the tags, and the destination of `host`, and take
+/// the best score.
+fn score_host(query: &str, host: &Host) -> Option<i64> {
+    let destination = host.target.packet_destination().map(ToString::to_string);
+    host.target
+        .name()
+        .into_iter()
+        .chain(host.tags.iter().map(String::as_str))
+        .chain(destination.as_deref())
+        .filter_map(|candidate| score(query, candidate))
+        .max()
+}
+
+/// Find all `hosts` which match `query`, best match first.
+///
+/// Return only the host whose name is `query`, if any.
+fn matches<'a>(query: &str, hosts: &'a [Host]) -> Vec<&'a Host> {
+    if let Some(exact) = hosts.iter().find(|host| {
+        host.target
+            .name()
+            .is_some_and(|name| name.eq_ignore_ascii_case(query))
+    }) {
+        return vec![exact];
+    }
+    let mut scored = hosts
+        .iter()
+        .filter_map(|host| score_host(query, host).map(|score| (score, host)))
+        .collect::<Vec<_>>();
+    // Stable sort keeps the order of the database among equal scores
+    scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+    scored.into_iter().map(|(_, host)| host).collect()
+}
+
+/// Describe `host` in a line of text, for a choice.
+fn describe(host: &Host) -> String {
+    let name = host.target.name().unwrap_or("-");
+    let hardware_address = host.target.hardware_address();
+    if host.tags.is_empty() {
+        format!("{name} ({hardware_address})")
+    } else {
+        format!("{name} ({hardware_address}) [{}]", host.tags.join(","))
+    }
+}
+
+/// Let the user choose among `hosts`.
+///
+/// Write a numbered list of `hosts` to `output`, and read the number of a
+/// host, or `a` for all hosts, from `input`.
+///
+/// # Errors
+///
+/// Return an error if reading or writing fails, or if the answer is invalid.
+fn choose<'a>(
+    hosts: &[&'a Host],
+    mut input: impl BufRead,
+    mut output: impl Write,
+) -> Result<Vec<&'a Host>> {
+    for (number, host) in (1..).zip(hosts) {
+        writeln!(output, "{number:>3}) {}", describe(host))?;
+    }
+    write!(
+        output,
+        "Wake up which system? [1-{}, a for all] ",
+        hosts.len()
+    )?;
+    output.flush()?;
+    let mut answer = String::new();
+    input.read_line(&mut answer)?;
+    let answer = answer.trim();
+    if answer == "a" {
+        return Ok(hosts.to_vec());
+    }
+    answer
+        .parse::<usize>()
+        .ok()
+        .and_then(|number| number.checked_sub(1))
+        .and_then(|index| hosts.get(index))
+        .map(|host| vec![*host])
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("Invalid choice: {answer}")))
+}
+
+/// Pick systems from `hosts` which match `query`.
+///
+/// If more than one system matches ask the user which to pick, or fail if
+/// not running in a terminal.
+///
+/// # Errors
+///
+/// Return an error if no system matches, or if multiple systems match and
+/// the user made no valid choice.
+pub fn pick(query: &str, hosts: &[Host]) -> Result<Vec<WakeUpTarget>> {
+    let found = matches(query, hosts);
+    let picked = match found.as_slice() {
+        [] => {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("No system matches {query}"),
+            ));
+        }
+        [host] => vec![*host],
+        _ if stdin().is_terminal() && stderr().is_terminal() => {
+            choose(&found, stdin().lock(), stderr())?
+        }
+        _ => {
+            let names = found
+                .iter()
+                .map(|host| host.target.name().unwrap_or("-"))
+                .collect::<Vec<_>>();
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("{query} matches multiple systems: {}", names.join(", ")),
+            ));
+        }
+    };
+    Ok(picked.into_iter().map(|host| host.target.clone()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn hosts() -> Vec<Host> {
+        [
+            ("12:13:14:15:16:17 name=office-desktop", vec!["office"]),
+            ("12:13:14:15:16:18 name=nas", vec![]),
+            ("12:13:14:15:16:19 nas.lan name=storage", vec!["lab"]),
+            ("12:13:14:15:16:20 name=laptop", vec!["office"]),
+        ]
+        .into_iter()
+        .map(|(line, tags)| Host {
+            target: WakeUpTarget::from_str(line).unwrap(),
+            tags: tags.into_iter().map(str::to_owned).collect(),
+        })
+        .collect()
+    }
+
+    fn names(hosts: &[&Host]) -> Vec<String> {
+        hosts
+            .iter()
+            .map(|host| host.target.name().unwrap_or("-").to_owned())
+            .collect()
+    }
+
+    #[test]
+    fn scores() {
+        assert_eq!(score("dsk", "desktop"), Some(16 * 3 + 8 - 1 + 8));
+        assert_eq!(score("DESK", "desktop"), Some(16 * 4 + 8 + 8 * 3));
+        assert!(score("desk", "desktop") > score("desk", "office-desktop"));
+        assert!(score("od", "office-desktop") > score("od", "cloud"));
+        assert_eq!(score("dekstop", "desktop"), None);
+        assert_eq!(score("", "desktop"), Some(0));
+    }
+
+    #[test]
+    fn matching() {
+        let hosts = hosts();
+        assert_eq!(names(&matches("nas", &hosts)), ["nas"]);
+        assert_eq!(names(&matches("lan", &hosts)), ["storage"]);
+        assert_eq!(names(&matches("dsk", &hosts)), ["office-desktop"]);
+        assert_eq!(names(&matches("off", &hosts)), ["office-desktop", "laptop"]);
+        assert!(matches("xyz", &hosts).is_empty());
+    }
+
+    #[test]
+    fn choices() {
+        let hosts = hosts();
+        let found = matches("off", &hosts);
+        let mut output = Vec::new();
+        let chosen = choose(&found, "2\n".as_bytes(), &mut output).unwrap();
+        assert_eq!(names(&chosen), ["laptop"]);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "  1) office-desktop (12:13:14:15:16:17) [office]
+  2) laptop (12:13:14:15:16:20) [office]
+Wake up which system? [1-2, a for all] "
+        );
+        let chosen = choose(&found, "a\n".as_bytes(), std::io::sink()).unwrap();
+        assert_eq!(names(&chosen), ["office-desktop", "laptop"]);
+        let error = choose(&found, "3\n".as_bytes(), std::io::sink()).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid choice: 3");
+        assert!(choose(&found, "0\n".as_bytes(), std::io::sink()).is_err());
+    }
+}
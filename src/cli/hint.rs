@@ -0,0 +1,88 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Hints for common errors when sending magic packets.
+
+use std::io::{Error, ErrorKind};
+use std::net::{IpAddr, SocketAddr};
+
+/// Whether `ip` is a broadcast or multicast address.
+fn is_broadcast(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_broadcast() || ip.is_multicast(),
+        IpAddr::V6(ip) => ip.is_multicast(),
+    }
+}
+
+/// A hint about what to do about `error` when sending to `destination`.
+fn hint(error: &Error, destination: SocketAddr) -> Option<String> {
+    let ip = destination.ip();
+    let hint = match error.kind() {
+        ErrorKind::PermissionDenied if is_broadcast(ip) => {
+            "a firewall may block broadcasts; check your firewall rules, or try --all-interfaces"
+                .to_owned()
+        }
+        ErrorKind::PermissionDenied => format!(
+            "a firewall may block packets to {ip}, or your router may block directed broadcasts; \
+             try --all-interfaces"
+        ),
+        ErrorKind::NetworkUnreachable | ErrorKind::NetworkDown => {
+            "the network may not be up yet, e.g. right after resume; try again later, or use --retries"
+                .to_owned()
+        }
+        ErrorKind::HostUnreachable => format!(
+            "no route to {ip}; check the destination, or try a broadcast address on the local network"
+        ),
+        ErrorKind::AddrNotAvailable => {
+            "the local address is not available; check --bind and --interface".to_owned()
+        }
+        _ => return None,
+    };
+    Some(hint)
+}
+
+/// Add a hint about what to do to `error` from sending to `destination`.
+///
+/// Keep the kind of `error`, and return `error` as is if there's no hint.
+pub fn explain(error: Error, destination: SocketAddr) -> Error {
+    match hint(&error, destination) {
+        Some(hint) => Error::new(error.kind(), format!("{error}; {hint}")),
+        None => error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn explain_permission_denied() {
+        let error = explain(
+            Error::from(ErrorKind::PermissionDenied),
+            SocketAddr::new(Ipv4Addr::BROADCAST.into(), 9),
+        );
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+        assert!(error.to_string().contains("firewall may block broadcasts"));
+
+        let error = explain(
+            Error::from(ErrorKind::PermissionDenied),
+            SocketAddr::new(Ipv4Addr::new(192, 168, 1, 255).into(), 9),
+        );
+        assert!(error.to_string().contains("directed broadcasts"));
+    }
+
+    #[test]
+    fn explain_other() {
+        let error = explain(
+            Error::new(ErrorKind::InvalidData, "Foo"),
+            SocketAddr::new(Ipv4Addr::BROADCAST.into(), 9),
+        );
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(error.to_string(), "Foo");
+    }
+}
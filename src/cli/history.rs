@@ -0,0 +1,514 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Remember when systems were last woken up, and who woke them up.
+
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::{ArgAction, Args, ValueHint};
+use serde_json::json;
+use toml_edit::{DocumentMut, Item, Value};
+use wol::MacAddress;
+use wol::file::WakeUpTarget;
+
+use super::listen::format_time;
+use super::report::{OutputFormat, TargetResult};
+use super::target::Selector;
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct HistoryArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Show only the last COUNT wake attempts.
+    #[arg(short = 'n', long = "limit", value_name = "COUNT")]
+    limit: Option<usize>,
+    /// Print wake attempts in FORMAT.
+    ///
+    /// With json print the JSON object of every wake attempt
+    /// from the audit log, one per line.
+    #[arg(
+        long = "output",
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t,
+        verbatim_doc_comment
+    )]
+    output: OutputFormat,
+    /// Show only wake attempts for NAME, by name, hardware
+    /// address, destination, or pattern.
+    #[arg(value_name = "NAME", verbatim_doc_comment)]
+    #[cfg_attr(
+        feature = "dynamic-completions",
+        arg(add = clap_complete::ArgValueCandidates::new(super::complete::systems))
+    )]
+    name: Option<Selector>,
+    #[command(flatten)]
+    wake_log: WakeLogArgs,
+}
+
+/// Arguments to locate the audit log of wake attempts.
+#[derive(Args, Debug, Clone, Default)]
+pub struct WakeLogArgs {
+    /// Record wake attempts in the audit log FILE.
+    ///
+    /// Defaults to /var/log/wol/wakes.jsonl if /var/log/wol
+    /// exists, to share one audit log between all users of a
+    /// host, and to wol/audit.jsonl in the state directory,
+    /// usually ~/.local/state, otherwise.
+    #[arg(
+        long = "wake-log",
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    wake_log: Option<PathBuf>,
+}
+
+impl WakeLogArgs {
+    /// The path of the audit log, with the state directory `state_dir`.
+    pub fn path(&self, state_dir: Option<&Path>) -> Option<PathBuf> {
+        self.wake_log
+            .clone()
+            .or_else(|| {
+                Some(PathBuf::from(SYSTEM_WAKE_LOG))
+                    .filter(|path| cfg!(unix) && path.parent().is_some_and(Path::is_dir))
+            })
+            .or_else(|| state_dir.map(|dir| dir.join(AUDIT_LOG_FILE)))
+    }
+}
+
+/// The state directory of wol.
+///
+/// Use `wol` in `$XDG_STATE_HOME` or `~/.local/state`, or in `%LOCALAPPDATA%`
+/// on Windows.
+pub fn state_dir() -> Option<PathBuf> {
+    let state_dir = if cfg!(windows) {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_STATE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME")
+                    .map(|home| PathBuf::from(home).join(".local").join("state"))
+            })
+    };
+    state_dir.map(|dir| dir.join("wol"))
+}
+
+/// The state directory of wol for the user with the home directory `home`.
+///
+/// Use `wol` in `~/.local/state`, for daemons which switched to another user,
+/// and cannot rely on the environment.
+pub fn state_dir_of(home: &Path) -> PathBuf {
+    home.join(".local").join("state").join("wol")
+}
+
+/// The name of the wake-up history in the state directory.
+const HISTORY_FILE: &str = "history.toml";
+
+/// The name of the audit log in the state directory.
+const AUDIT_LOG_FILE: &str = "audit.jsonl";
+
+/// The audit log of all users, if its directory exists.
+const SYSTEM_WAKE_LOG: &str = "/var/log/wol/wakes.jsonl";
+
+/// The default path of the wake-up history, `history.toml` in the state
+/// directory.
+pub fn default_path() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join(HISTORY_FILE))
+}
+
+/// When systems were last woken up.
+///
+/// A TOML file which maps hardware addresses to the seconds since the epoch of
+/// the last magic packet sent to the system.
+#[derive(Debug, Default, Clone)]
+pub struct History(DocumentMut);
+
+impl History {
+    /// Read the history at `path`.
+    ///
+    /// Return an empty history if `path` does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if `path` could not be read or is not valid TOML.
+    pub fn read(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => contents
+                .parse::<DocumentMut>()
+                .map(Self)
+                .map_err(|error| Error::new(ErrorKind::InvalidData, error)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error),
+        }
+        .map_err(|error| {
+            Error::new(
+                error.kind(),
+                format!("Failed to read history from {}: {error}", path.display()),
+            )
+        })
+    }
+
+    /// Write the history to `path`, creating all parent directories.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if writing fails.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        path.parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| std::fs::write(path, self.0.to_string()))
+            .map_err(|error| {
+                Error::new(
+                    error.kind(),
+                    format!("Failed to write history to {}: {error}", path.display()),
+                )
+            })
+    }
+
+    /// When `hardware_address` was last woken up, if ever.
+    pub fn last_wake(&self, hardware_address: MacAddress) -> Option<SystemTime> {
+        let seconds = self
+            .0
+            .get(&hardware_address.to_string())
+            .and_then(Item::as_integer)?;
+        u64::try_from(seconds)
+            .ok()
+            .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds))
+    }
+
+    /// Remember that `hardware_address` was woken up at `time`.
+    pub fn record(&mut self, hardware_address: MacAddress, time: SystemTime) {
+        let seconds = time
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .and_then(|elapsed| i64::try_from(elapsed.as_secs()).ok())
+            .unwrap_or_default();
+        self.0.insert(
+            &hardware_address.to_string(),
+            Item::Value(Value::from(seconds)),
+        );
+    }
+}
+
+/// Remember in `state_dir` that all `hardware_addresses` were woken up just
+/// now.
+///
+/// # Errors
+///
+/// Return an error if reading or writing the history fails.
+pub fn record_now(state_dir: &Path, hardware_addresses: &[MacAddress]) -> Result<()> {
+    let path = state_dir.join(HISTORY_FILE);
+    let mut history = History::read(&path)?;
+    let now = SystemTime::now();
+    for hardware_address in hardware_addresses {
+        history.record(*hardware_address, now);
+    }
+    history.write(&path)
+}
+
+/// The user who invoked wol.
+///
+/// Take the real user of the process, which users cannot forge, unlike the
+/// environment.  Only if that's root prefer the user who ran sudo, to tell who
+/// woke up a system from a shared host.
+#[cfg(unix)]
+#[allow(
+    clippy::unnecessary_wraps,
+    reason = "Other platforms may not know the user"
+)]
+fn invoking_user() -> Option<String> {
+    use nix::unistd::{Uid, User, geteuid, getuid};
+
+    let uid = getuid();
+    let uid = if uid.is_root() && geteuid().is_root() {
+        std::env::var("SUDO_UID")
+            .ok()
+            .and_then(|uid| uid.parse().ok())
+            .map_or(uid, Uid::from_raw)
+    } else {
+        uid
+    };
+    let user = User::from_uid(uid).ok().flatten();
+    Some(user.map_or_else(|| uid.to_string(), |user| user.name))
+}
+
+/// The user who invoked wol.
+#[cfg(not(unix))]
+fn invoking_user() -> Option<String> {
+    std::env::var_os("USERNAME")
+        .filter(|user| !user.is_empty())
+        .map(|user| user.to_string_lossy().into_owned())
+}
+
+/// Append the wake attempt `result` of `user` at `time` to the audit log at
+/// `path`, creating all parent directories.
+///
+/// The audit log has one JSON object per line, with the fields of JSON output
+/// of wake up, plus the time and the user.
+///
+/// # Errors
+///
+/// Return an error if writing fails.
+fn append_audit(
+    path: &Path,
+    result: &TargetResult,
+    time: SystemTime,
+    user: Option<&str>,
+) -> Result<()> {
+    let mut entry = result.to_json();
+    if let Some(object) = entry.as_object_mut() {
+        object.insert("time".to_owned(), json!(format_time(time)));
+        object.insert("user".to_owned(), json!(user));
+    }
+    path.parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            // Write the whole line at once, so that concurrent wol processes
+            // don't interleave their lines
+            file.write_all(format!("{entry}\n").as_bytes())
+        })
+        .map_err(|error| {
+            Error::new(
+                error.kind(),
+                format!("Failed to write audit log to {}: {error}", path.display()),
+            )
+        })
+}
+
+/// Append the wake attempt `result` to the audit log at `path`, now, for the
+/// invoking user.
+///
+/// # Errors
+///
+/// Return an error if writing fails.
+pub fn audit_now(path: &Path, result: &TargetResult) -> Result<()> {
+    append_audit(path, result, SystemTime::now(), invoking_user().as_deref())
+}
+
+/// Read all entries of the audit log at `path`.
+///
+/// Return no entries if `path` does not exist.
+///
+/// # Errors
+///
+/// Return an error if reading fails, or if a line is no valid JSON.
+fn read_audit(path: &Path) -> Result<Vec<serde_json::Value>> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+    let mut entries = Vec::new();
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        entries.push(serde_json::from_str(&line).map_err(|error| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{}:{}: {error}", path.display(), index + 1),
+            )
+        })?);
+    }
+    Ok(entries)
+}
+
+/// Get the string `key` of `entry`, if any.
+fn field<'a>(entry: &'a serde_json::Value, key: &str) -> Option<&'a str> {
+    entry.get(key).and_then(serde_json::Value::as_str)
+}
+
+/// Whether `selector` selects the system of the audit log `entry`.
+fn selects(selector: &Selector, entry: &serde_json::Value) -> bool {
+    field(entry, "mac")
+        .and_then(|mac| MacAddress::from_str(mac).ok())
+        .is_some_and(|hardware_address| {
+            let target = WakeUpTarget::new(hardware_address)
+                .with_name(field(entry, "name").map(str::to_owned))
+                .with_packet_destination(field(entry, "destination").map(|destination| {
+                    wol::file::MagicPacketDestination::from(destination.to_owned())
+                }));
+            selector.matches(&target)
+        })
+}
+
+/// Format audit log `entries` as a table with aligned columns.
+fn table(entries: &[serde_json::Value]) -> Vec<String> {
+    let header = ["TIME", "USER", "NAME", "MAC", "DESTINATION", "RESULT"].map(str::to_owned);
+    let lines = std::iter::once(header)
+        .chain(entries.iter().map(|entry| {
+            let cell = |key| field(entry, key).unwrap_or("-").to_owned();
+            let result = match field(entry, "error") {
+                Some(error) => format!("{} ({error})", cell("result")),
+                None => cell("result"),
+            };
+            [
+                cell("time"),
+                cell("user"),
+                cell("name"),
+                cell("mac"),
+                cell("destination"),
+                result,
+            ]
+        }))
+        .collect::<Vec<_>>();
+    let mut widths = [0; 6];
+    for cells in &lines {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    lines
+        .iter()
+        .map(|cells| {
+            cells
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_owned()
+        })
+        .collect()
+}
+
+/// Show wake attempts from the audit log.
+///
+/// # Errors
+///
+/// Return an error if there's no audit log, or if reading it fails.
+pub fn history(args: &HistoryArgs) -> Result<()> {
+    let path = args.wake_log.path(state_dir().as_deref()).ok_or_else(|| {
+        Error::new(
+            ErrorKind::NotFound,
+            "No audit log, neither XDG_STATE_HOME nor HOME are set, use --wake-log to give one",
+        )
+    })?;
+    let mut entries = read_audit(&path)?;
+    if let Some(selector) = &args.name {
+        entries.retain(|entry| selects(selector, entry));
+    }
+    if let Some(limit) = args.limit {
+        entries.drain(..entries.len().saturating_sub(limit));
+    }
+    match args.output {
+        OutputFormat::Text => {
+            for line in table(&entries) {
+                anstream::println!("{line}");
+            }
+        }
+        OutputFormat::Json => {
+            for entry in &entries {
+                anstream::println!("{entry}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Describe how long ago `time` was, relative to `now`.
+pub fn ago(time: SystemTime, now: SystemTime) -> String {
+    let seconds = now.duration_since(time).unwrap_or_default().as_secs();
+    match seconds {
+        0..60 => "just now".to_owned(),
+        60..3600 => format!("{}m ago", seconds / 60),
+        3600..86400 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_and_last_wake() {
+        let mac = MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]);
+        let mut history = History::default();
+        assert_eq!(history.last_wake(mac), None);
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        history.record(mac, time);
+        assert_eq!(
+            history.0.to_string(),
+            "\"12:13:14:15:16:17\" = 1700000000\n"
+        );
+        assert_eq!(history.last_wake(mac), Some(time));
+    }
+
+    #[test]
+    fn audit_log() {
+        use std::io::Error;
+
+        use crate::cli::report::Failure;
+
+        let path = std::env::temp_dir()
+            .join(format!("wol-audit-{}", std::process::id()))
+            .join("audit.jsonl");
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let result = |name, failure| TargetResult {
+            hardware_address: MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]),
+            name: Some(name),
+            destination: Some("192.168.1.255".to_owned()),
+            port: 9,
+            duration: Duration::from_millis(5),
+            failure,
+        };
+        append_audit(&path, &result("nas", None), time, Some("alice")).unwrap();
+        let failure = Failure::Send(Error::from(ErrorKind::PermissionDenied));
+        append_audit(&path, &result("finance", Some(failure)), time, None).unwrap();
+        let entries = read_audit(&path);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        let entries = entries.unwrap();
+        let [nas, finance] = entries.as_slice() else {
+            panic!("Expected two entries, got {entries:?}");
+        };
+        assert_eq!(field(nas, "user"), Some("alice"));
+        assert_eq!(field(nas, "time"), Some("2023-11-14T22:13:20.000Z"));
+
+        let selector = Selector::from_str("fin*").unwrap();
+        let selected = entries
+            .iter()
+            .filter(|entry| selects(&selector, entry))
+            .collect::<Vec<_>>();
+        assert_eq!(selected, [finance]);
+        assert_eq!(
+            table(&entries),
+            [
+                "TIME                      USER   NAME     MAC                DESTINATION    RESULT",
+                "2023-11-14T22:13:20.000Z  alice  nas      12:13:14:15:16:17  192.168.1.255  ok",
+                "2023-11-14T22:13:20.000Z  -      finance  12:13:14:15:16:17  192.168.1.255  wake-failed (permission denied)",
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_audit_log() {
+        let path = std::env::temp_dir().join("wol-does-not-exist.jsonl");
+        assert!(read_audit(&path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn ago_units() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let ago = |seconds| ago(now - Duration::from_secs(seconds), now);
+        assert_eq!(ago(5), "just now");
+        assert_eq!(ago(150), "2m ago");
+        assert_eq!(ago(7200), "2h ago");
+        assert_eq!(ago(3 * 86400 + 5), "3d ago");
+    }
+}
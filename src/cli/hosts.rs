@@ -0,0 +1,673 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! The hosts database.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::{Args, ValueHint};
+use toml_edit::{Array, DocumentMut, Item, Table, Value};
+use wol::MacAddress;
+use wol::file::WakeUpTarget;
+
+use super::input;
+use super::target::Selector;
+
+/// The default path of the hosts database.
+///
+/// Use `wol/hosts.toml` in `$XDG_CONFIG_HOME` or `~/.config`, or in
+/// `%APPDATA%` on Windows.
+pub fn default_path() -> Option<PathBuf> {
+    let config_dir = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+    config_dir.map(|dir| dir.join("wol").join("hosts.toml"))
+}
+
+/// Arguments to locate the hosts database.
+#[derive(Args, Debug, Clone)]
+pub struct HostsArgs {
+    /// Use the hosts database at FILE.
+    ///
+    /// Defaults to wol/hosts.toml in the XDG configuration
+    /// directory, usually ~/.config, or in the application data
+    /// directory on Windows.
+    #[arg(
+        long = "hosts",
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    hosts: Option<PathBuf>,
+}
+
+impl HostsArgs {
+    /// The path of the hosts database.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if no path was given and there's no default path.
+    pub fn path(&self) -> Result<PathBuf> {
+        self.hosts.clone().or_else(default_path).ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotFound,
+                "No hosts database, use --hosts to give one",
+            )
+        })
+    }
+
+    /// Whether `--hosts` gave the hosts database explicitly.
+    pub fn is_explicit(&self) -> bool {
+        self.hosts.is_some()
+    }
+
+    /// Read the hosts database.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if the database could not be read or is invalid.
+    pub fn read(&self) -> Result<Hosts> {
+        Hosts::read(&self.path()?)
+    }
+}
+
+/// The keys of a system in the hosts database.
+const KEYS: [&str; 7] = ["mac", "host", "port", "passwd", "address", "after", "tags"];
+
+/// Convert the `value` of `key` to a TOML value.
+fn toml_value(key: &str, value: &str) -> Result<Value> {
+    match key {
+        "port" => u16::from_str(value)
+            .map(|port| Value::from(i64::from(port)))
+            .map_err(|error| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("Invalid port {value}: {error}"),
+                )
+            }),
+        "after" | "tags" => Ok(Value::Array(
+            value.split(',').map(str::trim).collect::<Array>(),
+        )),
+        _ if KEYS.contains(&key) => Ok(Value::from(value)),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Unknown key {key}, expected one of {}", KEYS.join(", ")),
+        )),
+    }
+}
+
+/// The table of groups in the hosts database.
+const GROUPS: &str = "groups";
+
+/// Fail unless `name` is a valid name for a system.
+///
+/// Refuse names which look like hardware addresses or patterns, because
+/// `wol` could not wake up systems by these names.
+fn validate_name(name: &str) -> Result<()> {
+    match Selector::from_str(name) {
+        Ok(Selector::Name(_)) if !name.contains(['@', ',']) && name != GROUPS => Ok(()),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid name {name}"),
+        )),
+    }
+}
+
+/// Convert a TOML `value` to a field value.
+fn toml_field(value: &Value) -> String {
+    match value {
+        Value::String(value) => value.value().clone(),
+        Value::Array(values) => values.iter().map(toml_field).collect::<Vec<_>>().join(","),
+        value => value.to_string().trim().to_owned(),
+    }
+}
+
+/// A system in the hosts database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Host {
+    /// The system, with its name.
+    pub target: WakeUpTarget,
+    /// The tags of the system.
+    pub tags: Vec<String>,
+}
+
+/// A database of named systems.
+///
+/// The database is a TOML file with a table for every system; the name of the
+/// table is the name of the system, and its keys are the fields of
+/// `--stdin-format`, e.g.
+///
+/// ```toml
+/// [office-pc]
+/// mac = "12:13:14:15:16:17"
+/// host = "192.168.1.255"
+/// port = 9
+/// tags = ["office"]
+/// ```
+///
+/// The `groups` table is not a system; instead it lists the members of
+/// groups, in addition to all systems tagged with the group.  A member is
+/// either the name of a system, or `@` and the name of another group, e.g.
+///
+/// ```toml
+/// [groups]
+/// lab = ["@office", "@renderfarm", "nas"]
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct Hosts(DocumentMut);
+
+impl Hosts {
+    /// Parse a hosts database from `contents`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if `contents` is not valid TOML.
+    pub fn parse(contents: &str) -> Result<Self> {
+        contents
+            .parse::<DocumentMut>()
+            .map(Self)
+            .map_err(|error| Error::new(ErrorKind::InvalidData, error))
+    }
+
+    /// Read the hosts database at `path`.
+    ///
+    /// Return an empty database if `path` does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if `path` could not be read or is not valid TOML.
+    pub fn read(path: &Path) -> Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => Self::parse(&contents),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error),
+        }
+        .map_err(|error| {
+            Error::new(
+                error.kind(),
+                format!("Failed to read hosts from {}: {error}", path.display()),
+            )
+        })
+    }
+
+    /// Write this database to `path`.
+    ///
+    /// Create all parent directories of `path`.  On Unix make the file only
+    /// readable for the current user, because it may contain passwords.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if writing fails.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let write = || {
+            if let Some(directory) = path.parent() {
+                std::fs::create_dir_all(directory)?;
+            }
+            let mut options = OpenOptions::new();
+            options.write(true).create(true).truncate(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            options.open(path)?.write_all(self.to_string().as_bytes())
+        };
+        write().map_err(|error| {
+            Error::new(
+                error.kind(),
+                format!("Failed to write hosts to {}: {error}", path.display()),
+            )
+        })
+    }
+
+    /// Get the system `name` from `item`.
+    fn host(name: &str, item: &Item) -> Result<Host> {
+        let invalid =
+            |message: String| Error::new(ErrorKind::InvalidData, format!("Host {name}: {message}"));
+        let table = item
+            .as_table_like()
+            .ok_or_else(|| invalid("Expected a table".to_owned()))?;
+        let fields = table
+            .iter()
+            .filter_map(|(key, item)| {
+                Item::as_value(item).map(|value| (key.to_owned(), toml_field(value)))
+            })
+            .collect::<HashMap<_, _>>();
+        let target = input::target_from_fields(&fields)
+            .map(|target| target.with_name(Some(name.to_owned())))
+            .map_err(invalid)?;
+        let tags = fields
+            .get("tags")
+            .map(|tags| {
+                tags.split(',')
+                    .map(str::trim)
+                    .filter(|tag| !tag.is_empty())
+                    .map(str::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Ok(Host { target, tags })
+    }
+
+    /// Get all systems in this database, with their names and tags.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if any system is invalid.
+    pub fn hosts(&self) -> Result<Vec<Host>> {
+        self.0
+            .iter()
+            .filter(|(name, _)| *name != GROUPS)
+            .map(|(name, item)| Self::host(name, item))
+            .collect()
+    }
+
+    /// Add the names of all members of `group` in `hosts` to `names`.
+    ///
+    /// Expand nested groups, and skip all groups in `visited`, to break
+    /// cycles.
+    fn collect_members(
+        &self,
+        group: &str,
+        hosts: &[Host],
+        visited: &mut HashSet<String>,
+        names: &mut HashSet<String>,
+    ) -> Result<()> {
+        if !visited.insert(group.to_owned()) {
+            return Ok(());
+        }
+        let invalid = |message: String| {
+            Error::new(ErrorKind::InvalidData, format!("Group {group}: {message}"))
+        };
+        names.extend(
+            hosts
+                .iter()
+                .filter(|host| host.tags.iter().any(|tag| tag == group))
+                .filter_map(|host| host.target.name().map(str::to_owned)),
+        );
+        let Some(members) = self.0.get(GROUPS).and_then(|groups| groups.get(group)) else {
+            return Ok(());
+        };
+        let members = members
+            .as_array()
+            .ok_or_else(|| invalid("Expected an array of names".to_owned()))?;
+        for member in members {
+            let member = member
+                .as_str()
+                .ok_or_else(|| invalid(format!("Expected a name, got {member}")))?;
+            match member.strip_prefix('@') {
+                Some(nested) => self.collect_members(nested, hosts, visited, names)?,
+                None if hosts.iter().any(|host| host.target.name() == Some(member)) => {
+                    names.insert(member.to_owned());
+                }
+                None => return Err(invalid(format!("No system named {member}"))),
+            }
+        }
+        Ok(())
+    }
+
+    /// Get all systems in `group`, in the order of this database.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if any system or group is invalid, or if `group` has
+    /// no members.
+    pub fn group(&self, group: &str) -> Result<Vec<WakeUpTarget>> {
+        let hosts = self.hosts()?;
+        let mut names = HashSet::new();
+        self.collect_members(group, &hosts, &mut HashSet::new(), &mut names)?;
+        if names.is_empty() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("No system in group {group}"),
+            ));
+        }
+        Ok(hosts
+            .into_iter()
+            .map(|host| host.target)
+            .filter(|target| target.name().is_some_and(|name| names.contains(name)))
+            .collect())
+    }
+
+    /// Check all systems and groups in this database.
+    ///
+    /// # Errors
+    ///
+    /// Return an error for the first invalid system or group.
+    pub fn check(&self) -> Result<()> {
+        let hosts = self.hosts()?;
+        let Some(groups) = self.0.get(GROUPS) else {
+            return Ok(());
+        };
+        let groups = groups.as_table_like().ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Expected a table of groups in [{GROUPS}]"),
+            )
+        })?;
+        for (group, _) in groups.iter() {
+            self.collect_members(group, &hosts, &mut HashSet::new(), &mut HashSet::new())?;
+        }
+        Ok(())
+    }
+
+    /// Get the names of all groups and tags in this database, in sorted order.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if any system is invalid.
+    #[cfg(feature = "dynamic-completions")]
+    pub fn groups(&self) -> Result<Vec<String>> {
+        let mut groups = self
+            .hosts()?
+            .into_iter()
+            .flat_map(|host| host.tags)
+            .chain(
+                self.0
+                    .get(GROUPS)
+                    .and_then(Item::as_table_like)
+                    .into_iter()
+                    .flat_map(|groups| groups.iter().map(|(group, _)| group.to_owned())),
+            )
+            .collect::<Vec<_>>();
+        groups.sort();
+        groups.dedup();
+        Ok(groups)
+    }
+
+    /// Get all systems in this database, with their names.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if any system is invalid.
+    pub fn targets(&self) -> Result<Vec<WakeUpTarget>> {
+        self.hosts()
+            .map(|hosts| hosts.into_iter().map(|host| host.target).collect())
+    }
+
+    /// Get the table of the system `name`.
+    fn table_mut(&mut self, name: &str) -> Result<&mut Table> {
+        self.0
+            .get_mut(name)
+            .and_then(Item::as_table_mut)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("No system named {name}")))
+    }
+
+    /// Add a system `name` with `hardware_address` and further `fields`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if `name` is invalid or exists already, or if any
+    /// field is invalid.
+    pub fn add(
+        &mut self,
+        name: &str,
+        hardware_address: MacAddress,
+        fields: &[(&str, &str)],
+    ) -> Result<()> {
+        validate_name(name)?;
+        if self.0.contains_key(name) {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("System {name} exists already"),
+            ));
+        }
+        let mut table = Table::new();
+        table.insert("mac", Item::Value(hardware_address.to_string().into()));
+        for (key, value) in fields {
+            table.insert(key, Item::Value(toml_value(key, value)?));
+        }
+        let item = Item::Table(table);
+        Self::host(name, &item)?;
+        self.0.insert(name, item);
+        Ok(())
+    }
+
+    /// Remove the system `name`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if there's no system `name`.
+    pub fn remove(&mut self, name: &str) -> Result<()> {
+        self.0
+            .remove(name)
+            .map(|_| ())
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("No system named {name}")))
+    }
+
+    /// Rename the system `from` to `to`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if there's no system `from`, or if `to` is invalid or
+    /// exists already.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<()> {
+        validate_name(to)?;
+        if self.0.contains_key(to) {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("System {to} exists already"),
+            ));
+        }
+        let item = self
+            .0
+            .remove(from)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("No system named {from}")))?;
+        self.0.insert(to, item);
+        Ok(())
+    }
+
+    /// Set `key` of the system `name` to `value`, or remove `key` if `value`
+    /// is empty.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if there's no system `name`, if `key` is unknown, or if
+    /// `value` is invalid.
+    pub fn set(&mut self, name: &str, key: &str, value: &str) -> Result<()> {
+        let table = self.table_mut(name)?;
+        let mut changed = table.clone();
+        if value.is_empty() && KEYS.contains(&key) {
+            changed.remove(key);
+        } else {
+            changed.insert(key, Item::Value(toml_value(key, value)?));
+        }
+        let changed = Item::Table(changed);
+        Self::host(name, &changed)?;
+        *table = changed.into_table().unwrap_or_default();
+        Ok(())
+    }
+}
+
+impl Display for Hosts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use wol::MacAddress;
+
+    use super::*;
+
+    #[test]
+    fn targets() {
+        let hosts = Hosts::parse(
+            r#"
+# The big one
+[office-pc]
+mac = "12:13:14:15:16:17"
+host = "192.168.1.255"
+port = 9
+
+[nas]
+mac = "12-13-14-15-16-18"
+passwd = "keyring:nas"
+after = ["office-pc"]
+tags = ["storage", "lab"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            hosts.targets().unwrap(),
+            vec![
+                WakeUpTarget::new(MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]))
+                    .with_ip_packet_destination(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 255)))
+                    .with_port(Some(9))
+                    .with_name(Some("office-pc".to_owned())),
+                WakeUpTarget::new(MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x18]))
+                    .with_keyring_secure_on(Some("nas".to_owned()))
+                    .with_after(vec!["office-pc".to_owned()])
+                    .with_name(Some("nas".to_owned())),
+            ]
+        );
+        let tags = hosts
+            .hosts()
+            .unwrap()
+            .into_iter()
+            .map(|host| host.tags)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tags,
+            vec![vec![], vec!["storage".to_owned(), "lab".to_owned()]]
+        );
+    }
+
+    #[test]
+    fn invalid_targets() {
+        let error = Hosts::parse("[nas]\nhost = \"nas.lan\"\n")
+            .unwrap()
+            .targets()
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Host nas: Missing mac");
+        let error = Hosts::parse("nas = 42\n").unwrap().targets().unwrap_err();
+        assert_eq!(error.to_string(), "Host nas: Expected a table");
+        assert!(Hosts::parse("[nas").is_err());
+    }
+
+    #[test]
+    fn edit() {
+        let mut hosts =
+            Hosts::parse("# Lab\n[nas]\nmac = \"12:13:14:15:16:18\" # Rack 3\n").unwrap();
+        let mac = MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]);
+        hosts
+            .add(
+                "office-pc",
+                mac,
+                &[("host", "192.168.1.255"), ("port", "9")],
+            )
+            .unwrap();
+        hosts.set("nas", "after", "office-pc").unwrap();
+        hosts.set("office-pc", "port", "").unwrap();
+        hosts.rename("office-pc", "desktop").unwrap();
+        assert_eq!(
+            hosts.0.to_string(),
+            "# Lab\n[nas]\nmac = \"12:13:14:15:16:18\" # Rack 3\nafter = [\"office-pc\"]\n\n[desktop]\nmac = \"12:13:14:15:16:17\"\nhost = \"192.168.1.255\"\n"
+        );
+        hosts.remove("desktop").unwrap();
+        assert_eq!(hosts.targets().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn edit_invalid() {
+        let mut hosts = Hosts::parse("[nas]\nmac = \"12:13:14:15:16:18\"\n").unwrap();
+        let mac = MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]);
+        assert!(hosts.add("nas", mac, &[]).is_err());
+        assert!(hosts.add("web-*", mac, &[]).is_err());
+        assert!(hosts.add("12:13:14:15:16:17", mac, &[]).is_err());
+        assert!(hosts.add("pc", mac, &[("port", "foo")]).is_err());
+        assert!(hosts.add("pc", mac, &[("passwd", "foo")]).is_err());
+        assert!(hosts.set("nas", "mac", "").is_err());
+        assert!(hosts.set("nas", "color", "red").is_err());
+        assert!(hosts.set("pc", "port", "9").is_err());
+        assert!(hosts.rename("pc", "desktop").is_err());
+        assert!(hosts.remove("pc").is_err());
+        assert_eq!(hosts.targets().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn nested_groups() {
+        let hosts = Hosts::parse(
+            r#"
+[groups]
+lab = ["@renderfarm", "nas", "@lab"]
+renderfarm = ["render-3"]
+
+[render-1]
+mac = "12:13:14:15:16:01"
+tags = ["renderfarm"]
+
+[nas]
+mac = "12:13:14:15:16:02"
+
+[render-2]
+mac = "12:13:14:15:16:03"
+tags = ["renderfarm", "lab"]
+
+[render-3]
+mac = "12:13:14:15:16:04"
+
+[office-pc]
+mac = "12:13:14:15:16:05"
+"#,
+        )
+        .unwrap();
+        let names = |group| {
+            hosts
+                .group(group)
+                .unwrap()
+                .into_iter()
+                .filter_map(|target| target.name().map(str::to_owned))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names("renderfarm"), ["render-1", "render-2", "render-3"]);
+        assert_eq!(names("lab"), ["render-1", "nas", "render-2", "render-3"]);
+        assert_eq!(hosts.targets().unwrap().len(), 5);
+        assert_eq!(
+            hosts.group("office").unwrap_err().to_string(),
+            "No system in group office"
+        );
+    }
+
+    #[test]
+    fn invalid_groups() {
+        let hosts = Hosts::parse("[groups]\nlab = [\"nas\"]\noffice = \"pc\"\n").unwrap();
+        assert_eq!(
+            hosts.group("lab").unwrap_err().to_string(),
+            "Group lab: No system named nas"
+        );
+        assert_eq!(
+            hosts.group("office").unwrap_err().to_string(),
+            "Group office: Expected an array of names"
+        );
+        assert_eq!(
+            hosts.check().unwrap_err().to_string(),
+            "Group lab: No system named nas"
+        );
+        assert!(
+            Hosts::parse("[groups]\nlab = [\"nas\"]\n[nas]\nmac = \"12:13:14:15:16:02\"\n")
+                .unwrap()
+                .check()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn read_missing() {
+        let hosts = Hosts::read(Path::new("/does/not/exist/hosts.toml")).unwrap();
+        assert!(hosts.targets().unwrap().is_empty());
+    }
+}
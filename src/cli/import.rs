@@ -0,0 +1,743 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Import systems from other inventories.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{Error, ErrorKind, Read, Result, stdin};
+use std::net::IpAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use clap::{ArgAction, Args, ValueEnum, ValueHint};
+use wol::MacAddress;
+use wol::file::{MagicPacketDestination, ParseLineError, WakeUpTarget};
+
+use super::PathOrStdin;
+use super::hosts::{Hosts, HostsArgs};
+use super::{fmt, input};
+
+/// The format of an inventory to import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ImportFormat {
+    /// An ethers(5) file, e.g. /etc/ethers.
+    Ethers,
+    /// A dnsmasq leases file.
+    Dnsmasq,
+    /// An ISC dhcpd leases or configuration file.
+    Dhcpd,
+    /// Comma-separated values with a header line, as for --stdin-format.
+    Csv,
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct ImportArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// The format of the inventory.
+    #[arg(long = "from", value_name = "FORMAT", value_enum)]
+    from: ImportFormat,
+    /// Import into the wakeup FILE instead of the hosts database.
+    #[arg(
+        short = 'f',
+        long = "file",
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath
+    )]
+    file: Option<PathBuf>,
+    /// The inventory to import.
+    ///
+    /// Use - to read from standard input.
+    #[arg(value_name = "PATH", value_hint = ValueHint::FilePath, verbatim_doc_comment)]
+    path: PathOrStdin,
+    #[command(flatten)]
+    hosts: HostsArgs,
+}
+
+/// Create an error for invalid data at the 1-based `line_no`.
+fn invalid_line(line_no: usize, message: impl std::fmt::Display) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Line {line_no}: {message}"))
+}
+
+/// Parse a hardware address in line `line_no`.
+fn hardware_address(line_no: usize, value: &str) -> Result<MacAddress> {
+    MacAddress::from_str(value).map_err(|error| {
+        invalid_line(
+            line_no,
+            format!("Invalid hardware address {value}: {error}"),
+        )
+    })
+}
+
+/// Parse systems from an ethers(5) file.
+///
+/// Each line has a hardware address, and a host name or an IP address.
+fn parse_ethers(contents: &str) -> Result<Vec<WakeUpTarget>> {
+    let mut targets = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        let line = line.split_once('#').map_or(line, |(line, _)| line);
+        match line.split_ascii_whitespace().collect::<Vec<_>>().as_slice() {
+            [] => {}
+            [mac, host] => {
+                let target = WakeUpTarget::new(hardware_address(index + 1, mac)?);
+                targets.push(match IpAddr::from_str(host) {
+                    Ok(ip) => target.with_address(Some(MagicPacketDestination::Ip(ip))),
+                    Err(_) => target.with_name(Some((*host).to_owned())),
+                });
+            }
+            _ => return Err(invalid_line(index + 1, "Expected MAC and host")),
+        }
+    }
+    Ok(targets)
+}
+
+/// Parse systems from a dnsmasq leases file.
+///
+/// Each line has the expiry time, the hardware address, the IP address, the
+/// host name or `*`, and the client ID of a lease.  Skip the DUID line of
+/// leases for IPv6.
+fn parse_dnsmasq(contents: &str) -> Result<Vec<WakeUpTarget>> {
+    let mut targets = Vec::new();
+    for (index, line) in contents.lines().enumerate() {
+        match line.split_ascii_whitespace().collect::<Vec<_>>().as_slice() {
+            [] | ["duid", ..] => {}
+            [_, mac, address, name, ..] => targets.push(
+                WakeUpTarget::new(hardware_address(index + 1, mac)?)
+                    .with_name(Some(*name).filter(|name| *name != "*").map(str::to_owned))
+                    .with_address(Some(MagicPacketDestination::from((*address).to_owned()))),
+            ),
+            _ => {
+                return Err(invalid_line(
+                    index + 1,
+                    "Expected expiry time, MAC, IP address, and host name",
+                ));
+            }
+        }
+    }
+    Ok(targets)
+}
+
+/// A token in an ISC dhcpd file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// A word or a quoted string, and its 1-based line number.
+    Word(String, usize),
+    /// The start of a block.
+    Open,
+    /// The end of a block.
+    Close,
+    /// The end of a statement.
+    End,
+}
+
+/// Split the contents of an ISC dhcpd file into tokens.
+///
+/// Skip comments from `#` to the end of the line.
+fn dhcpd_tokens(contents: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut word: Option<String> = None;
+    let mut line_no = 1;
+    let mut chars = contents.chars();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '{' => Some(Token::Open),
+            '}' => Some(Token::Close),
+            ';' => Some(Token::End),
+            '#' => {
+                chars.by_ref().take_while(|c| *c != '\n').for_each(drop);
+                tokens.extend(word.take().map(|word| Token::Word(word, line_no)));
+                line_no += 1;
+                continue;
+            }
+            '"' => {
+                let mut escaped = false;
+                let quoted = word.get_or_insert_default();
+                for c in chars.by_ref() {
+                    match c {
+                        '\\' if !escaped => escaped = true,
+                        '"' if !escaped => break,
+                        c => {
+                            escaped = false;
+                            quoted.push(c);
+                        }
+                    }
+                }
+                continue;
+            }
+            c if c.is_whitespace() => None,
+            c => {
+                word.get_or_insert_default().push(c);
+                continue;
+            }
+        };
+        tokens.extend(word.take().map(|word| Token::Word(word, line_no)));
+        tokens.extend(token);
+        if c == '\n' {
+            line_no += 1;
+        }
+    }
+    tokens.extend(word.map(|word| Token::Word(word, line_no)));
+    tokens
+}
+
+/// A host declaration or a lease in an ISC dhcpd file.
+#[derive(Debug, Default)]
+struct Declaration {
+    name: Option<String>,
+    address: Option<String>,
+    hardware_address: Option<MacAddress>,
+}
+
+/// Parse systems from an ISC dhcpd leases or configuration file.
+///
+/// Take systems from `lease` blocks with their client host names, and from
+/// `host` declarations with their fixed addresses.  For multiple leases of the
+/// same system, keep the last one.
+fn parse_dhcpd(contents: &str) -> Result<Vec<WakeUpTarget>> {
+    let mut targets = Vec::new();
+    let mut blocks: Vec<Option<Declaration>> = Vec::new();
+    let mut statement: Vec<(String, usize)> = Vec::new();
+    for token in dhcpd_tokens(contents) {
+        match token {
+            Token::Word(word, line_no) => statement.push((word, line_no)),
+            Token::Open => {
+                let words = statement.iter().map(|(word, _)| word.as_str());
+                blocks.push(match words.collect::<Vec<_>>().as_slice() {
+                    ["host", name, ..] => Some(Declaration {
+                        name: Some((*name).to_owned()),
+                        ..Declaration::default()
+                    }),
+                    ["lease", address, ..] => Some(Declaration {
+                        address: Some((*address).to_owned()),
+                        ..Declaration::default()
+                    }),
+                    _ => None,
+                });
+                statement.clear();
+            }
+            Token::End => {
+                if let Some(Some(declaration)) = blocks.last_mut() {
+                    match statement.as_slice() {
+                        [(hardware, _), (ethernet, _), (mac, line_no)]
+                            if hardware == "hardware" && ethernet == "ethernet" =>
+                        {
+                            declaration.hardware_address = Some(hardware_address(*line_no, mac)?);
+                        }
+                        [(key, _), (name, _)] if key == "client-hostname" => {
+                            declaration.name.get_or_insert_with(|| name.clone());
+                        }
+                        [(key, _), (address, _), ..] if key == "fixed-address" => {
+                            declaration.address = Some(address.trim_end_matches(',').to_owned());
+                        }
+                        _ => {}
+                    }
+                }
+                statement.clear();
+            }
+            Token::Close => {
+                if let Some(Some(declaration)) = blocks.pop() {
+                    if let Some(hardware_address) = declaration.hardware_address {
+                        targets.push(
+                            WakeUpTarget::new(hardware_address)
+                                .with_name(declaration.name)
+                                .with_address(
+                                    declaration.address.map(MagicPacketDestination::from),
+                                ),
+                        );
+                    }
+                }
+                statement.clear();
+            }
+        }
+    }
+    // Keep the last lease of every system
+    let mut seen = HashSet::new();
+    targets.reverse();
+    targets.retain(|target| seen.insert(<[u8; 6]>::from(target.hardware_address())));
+    targets.reverse();
+    Ok(targets)
+}
+
+/// Parse systems from `contents` in `format`.
+///
+/// # Errors
+///
+/// Return an error if `contents` is invalid.
+fn parse(format: ImportFormat, contents: &str) -> Result<Vec<WakeUpTarget>> {
+    match format {
+        ImportFormat::Ethers => parse_ethers(contents),
+        ImportFormat::Dnsmasq => parse_dnsmasq(contents),
+        ImportFormat::Dhcpd => parse_dhcpd(contents),
+        ImportFormat::Csv => input::parse_csv(contents).into_iter().collect(),
+    }
+}
+
+/// A change to import a system.
+enum Change<'a> {
+    /// Add a new system.
+    Add(&'a WakeUpTarget),
+    /// Add `fields` to the existing system at the given index, to give the
+    /// merged system.
+    Update(usize, &'a WakeUpTarget, &'a [(&'static str, String)]),
+}
+
+/// The result of an import.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Summary {
+    added: usize,
+    updated: usize,
+    conflicts: Vec<String>,
+}
+
+/// How to merge an imported system.
+enum Merge {
+    /// An existing system has all fields of the imported system.
+    Unchanged,
+    /// Add the imported system.
+    Add,
+    /// Add the given fields to the existing system at the given index.
+    Update(usize, Vec<(&'static str, String)>),
+}
+
+/// Merge `target` into `existing` systems.
+///
+/// Merge a system with the same hardware address by adding all fields the
+/// existing system lacks.
+///
+/// # Errors
+///
+/// Return a message about the conflict if an existing system has the same
+/// hardware address but different fields, or the same name but a different
+/// hardware address.
+fn merge(existing: &[WakeUpTarget], target: &WakeUpTarget) -> std::result::Result<Merge, String> {
+    let mac = target.hardware_address();
+    let label = target.name().map_or_else(|| mac.to_string(), str::to_owned);
+    let Some((index, other)) = existing
+        .iter()
+        .enumerate()
+        .find(|(_, other)| other.hardware_address() == mac)
+    else {
+        let same_name = target
+            .name()
+            .and_then(|name| existing.iter().find(|other| other.name() == Some(name)));
+        return match same_name {
+            Some(other) => Err(format!(
+                "{label}: Hardware address {mac} in import, but {} already",
+                other.hardware_address()
+            )),
+            None => Ok(Merge::Add),
+        };
+    };
+    let current = input::target_fields(other);
+    let mut missing = Vec::new();
+    for (key, value) in input::target_fields(target) {
+        match current.iter().find(|(other_key, _)| *other_key == key) {
+            None => missing.push((key, value)),
+            Some((_, other_value)) if *other_value == value => {}
+            // Do not include values, lest we leak passwords
+            Some(_) => return Err(format!("{label}: Different {key} in import")),
+        }
+    }
+    Ok(if missing.is_empty() {
+        Merge::Unchanged
+    } else {
+        Merge::Update(index, missing)
+    })
+}
+
+/// Import all `imported` systems into `existing` systems.
+///
+/// Call `apply` for every change; record the error of `apply` as a conflict,
+/// and skip the system.
+fn import_targets(
+    mut existing: Vec<WakeUpTarget>,
+    imported: Vec<WakeUpTarget>,
+    mut apply: impl FnMut(Change<'_>) -> Result<()>,
+) -> Summary {
+    let mut summary = Summary::default();
+    for target in imported {
+        let result = match merge(&existing, &target) {
+            Ok(Merge::Unchanged) => Ok(()),
+            Ok(Merge::Add) => apply(Change::Add(&target)).map(|()| {
+                existing.push(target);
+                summary.added += 1;
+            }),
+            Ok(Merge::Update(index, missing)) => {
+                let fields = existing
+                    .get(index)
+                    .map(input::target_fields)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .chain(missing.iter().cloned())
+                    .map(|(key, value)| (key.to_owned(), value))
+                    .collect::<HashMap<_, _>>();
+                input::target_from_fields(&fields)
+                    .map_err(|message| Error::new(ErrorKind::InvalidData, message))
+                    .and_then(|merged| {
+                        apply(Change::Update(index, &merged, &missing))?;
+                        if let Some(other) = existing.get_mut(index) {
+                            *other = merged;
+                        }
+                        summary.updated += 1;
+                        Ok(())
+                    })
+            }
+            Err(message) => Err(Error::new(ErrorKind::AlreadyExists, message)),
+        };
+        if let Err(error) = result {
+            summary.conflicts.push(error.to_string());
+        }
+    }
+    summary
+}
+
+/// Import `imported` systems into the hosts database `hosts`.
+///
+/// # Errors
+///
+/// Return an error if the hosts database is invalid.
+fn import_into_hosts(hosts: &mut Hosts, imported: Vec<WakeUpTarget>) -> Result<Summary> {
+    let existing = hosts
+        .hosts()?
+        .into_iter()
+        .map(|host| host.target)
+        .collect::<Vec<_>>();
+    let mut names = existing
+        .iter()
+        .map(|target| target.name().unwrap_or_default().to_owned())
+        .collect::<Vec<_>>();
+    Ok(import_targets(existing, imported, |change| match change {
+        Change::Add(target) => {
+            let name = target.name().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "{}: No name, required for the hosts database",
+                        target.hardware_address()
+                    ),
+                )
+            })?;
+            let fields = input::target_fields(target);
+            let fields = fields
+                .iter()
+                .filter(|(key, _)| !["mac", "name"].contains(key))
+                .map(|(key, value)| (*key, value.as_str()))
+                .collect::<Vec<_>>();
+            hosts.add(name, target.hardware_address(), &fields)?;
+            names.push(name.to_owned());
+            Ok(())
+        }
+        Change::Update(index, _, missing) => {
+            let name = names.get(index).map(String::as_str).unwrap_or_default();
+            for (key, value) in missing {
+                hosts.set(name, key, value)?;
+            }
+            Ok(())
+        }
+    }))
+}
+
+/// Import `imported` systems into the `contents` of a wakeup file.
+///
+/// Append new systems, and replace updated systems in place.
+///
+/// # Errors
+///
+/// Return an error if `contents` is invalid.
+fn import_into_file(
+    contents: &str,
+    imported: Vec<WakeUpTarget>,
+) -> std::result::Result<(String, Summary), ParseLineError> {
+    let mut lines = contents.lines().map(str::to_owned).collect::<Vec<_>>();
+    let mut existing = Vec::new();
+    let mut line_indices = Vec::new();
+    for (index, line) in lines.iter().enumerate() {
+        if !line.trim().is_empty() && !line.trim().starts_with('#') {
+            existing.push(
+                WakeUpTarget::from_str(line)
+                    .map_err(|error| ParseLineError::new(index + 1, error))?,
+            );
+            line_indices.push(index);
+        }
+    }
+    let summary = import_targets(existing, imported, |change| {
+        match change {
+            Change::Add(target) => {
+                line_indices.push(lines.len());
+                lines.push(fmt::line(target));
+            }
+            Change::Update(index, merged, _) => {
+                if let Some(line) = line_indices
+                    .get(index)
+                    .and_then(|line_index| lines.get_mut(*line_index))
+                {
+                    *line = fmt::line(merged);
+                }
+            }
+        }
+        Ok(())
+    });
+    let contents = lines.into_iter().map(|line| line + "\n").collect();
+    Ok((contents, summary))
+}
+
+/// Import systems into the hosts database or a wakeup file.
+///
+/// Exit with 1 if any system conflicted with an existing system, and with 0
+/// otherwise.
+///
+/// # Errors
+///
+/// Return an error if the inventory is invalid, or if reading or writing the
+/// hosts database or the wakeup file fails.
+pub fn import(args: &ImportArgs) -> Result<ExitCode> {
+    let contents = match &args.path {
+        PathOrStdin::Stdin => {
+            let mut contents = String::new();
+            stdin().read_to_string(&mut contents)?;
+            contents
+        }
+        PathOrStdin::Path(path) => std::fs::read_to_string(path)?,
+    };
+    let imported = parse(args.from, &contents)?;
+    let summary = if let Some(path) = &args.file {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == ErrorKind::NotFound => String::new(),
+            Err(error) => return Err(error),
+        };
+        let (contents, summary) = import_into_file(&contents, imported).map_err(|error| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("{}: {error}", path.display()),
+            )
+        })?;
+        std::fs::write(path, contents)?;
+        summary
+    } else {
+        let path = args.hosts.path()?;
+        let mut hosts = Hosts::read(&path)?;
+        let summary = import_into_hosts(&mut hosts, imported)?;
+        hosts.write(&path)?;
+        summary
+    };
+    for conflict in &summary.conflicts {
+        anstream::eprintln!("{conflict}");
+    }
+    anstream::eprintln!(
+        "Added {}, updated {}, and skipped {} conflicting systems",
+        summary.added,
+        summary.updated,
+        summary.conflicts.len()
+    );
+    Ok(if summary.conflicts.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(targets: &[WakeUpTarget]) -> Vec<(String, Option<&str>, Option<String>)> {
+        targets
+            .iter()
+            .map(|target| {
+                (
+                    target.hardware_address().to_string(),
+                    target.name(),
+                    target.address().map(ToString::to_string),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ethers() {
+        let targets = parse_ethers(
+            "# Lab\n12:13:14:15:16:17 nas.lan\n\n12-13-14-15-16-18\t192.168.1.10 # router\n",
+        )
+        .unwrap();
+        assert_eq!(
+            names(&targets),
+            [
+                ("12:13:14:15:16:17".to_owned(), Some("nas.lan"), None),
+                (
+                    "12:13:14:15:16:18".to_owned(),
+                    None,
+                    Some("192.168.1.10".to_owned())
+                ),
+            ]
+        );
+        let error = parse_ethers("12:13:14:15:16:17\n").unwrap_err();
+        assert_eq!(error.to_string(), "Line 1: Expected MAC and host");
+    }
+
+    #[test]
+    fn dnsmasq() {
+        let targets = parse_dnsmasq(
+            "1700000000 12:13:14:15:16:17 192.168.1.10 nas 01:12:13:14:15:16:17
+1700000000 12:13:14:15:16:18 192.168.1.11 * *
+duid 00:01:00:01:2c:2a:5e:5f:12:13:14:15:16:17
+",
+        )
+        .unwrap();
+        assert_eq!(
+            names(&targets),
+            [
+                (
+                    "12:13:14:15:16:17".to_owned(),
+                    Some("nas"),
+                    Some("192.168.1.10".to_owned())
+                ),
+                (
+                    "12:13:14:15:16:18".to_owned(),
+                    None,
+                    Some("192.168.1.11".to_owned())
+                ),
+            ]
+        );
+        assert!(parse_dnsmasq("1700000000 foo 192.168.1.10 nas\n").is_err());
+    }
+
+    #[test]
+    fn dhcpd() {
+        let targets = parse_dhcpd(
+            r#"# dhcpd.conf
+subnet 192.168.1.0 netmask 255.255.255.0 {
+  host nas {
+    hardware ethernet 12:13:14:15:16:17;
+    fixed-address 192.168.1.10, nas.lan;
+  }
+}
+lease 192.168.1.20 {
+  starts 4 2023/11/16 12:00:00;
+  hardware ethernet 12:13:14:15:16:18;
+  client-hostname "office pc";
+}
+lease 192.168.1.21 {
+  hardware ethernet 12:13:14:15:16:18;
+  client-hostname "office pc"; # Renewed
+}
+lease 192.168.1.30 {
+  binding state free;
+}
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            names(&targets),
+            [
+                (
+                    "12:13:14:15:16:17".to_owned(),
+                    Some("nas"),
+                    Some("192.168.1.10".to_owned())
+                ),
+                (
+                    "12:13:14:15:16:18".to_owned(),
+                    Some("office pc"),
+                    Some("192.168.1.21".to_owned())
+                ),
+            ]
+        );
+        let error = parse_dhcpd("host nas {\n  hardware ethernet 12:13;\n}\n").unwrap_err();
+        assert!(error.to_string().starts_with("Line 2: "), "{error}");
+    }
+
+    fn imported() -> Vec<WakeUpTarget> {
+        parse_dnsmasq(
+            "0 12:13:14:15:16:17 192.168.1.10 nas
+0 12:13:14:15:16:18 192.168.1.11 office
+0 12:13:14:15:16:19 192.168.1.12 router
+0 12:13:14:15:16:20 192.168.1.13 printer
+0 12:13:14:15:16:21 192.168.1.14 *
+",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn into_hosts() {
+        let mut hosts = Hosts::parse(
+            r#"# Lab
+[nas]
+mac = "12:13:14:15:16:17"
+host = "192.168.1.255"
+
+[office]
+mac = "12:13:14:15:16:18"
+address = "office.lan"
+
+[router]
+mac = "12:13:14:15:16:10"
+"#,
+        )
+        .unwrap();
+        let summary = import_into_hosts(&mut hosts, imported()).unwrap();
+        assert_eq!(
+            summary,
+            Summary {
+                added: 1,
+                updated: 1,
+                conflicts: vec![
+                    "office: Different address in import".to_owned(),
+                    "router: Hardware address 12:13:14:15:16:19 in import, but 12:13:14:15:16:10 already".to_owned(),
+                    "12:13:14:15:16:21: No name, required for the hosts database".to_owned(),
+                ]
+            }
+        );
+        let targets = hosts.targets().unwrap();
+        assert_eq!(
+            names(&targets),
+            [
+                (
+                    "12:13:14:15:16:17".to_owned(),
+                    Some("nas"),
+                    Some("192.168.1.10".to_owned())
+                ),
+                (
+                    "12:13:14:15:16:18".to_owned(),
+                    Some("office"),
+                    Some("office.lan".to_owned())
+                ),
+                ("12:13:14:15:16:10".to_owned(), Some("router"), None),
+                (
+                    "12:13:14:15:16:20".to_owned(),
+                    Some("printer"),
+                    Some("192.168.1.13".to_owned())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn into_file() {
+        let (contents, summary) = import_into_file(
+            "# Lab\n12:13:14:15:16:17 192.168.1.255 name=nas\n12:13:14:15:16:10 name=router\n",
+            imported(),
+        )
+        .unwrap();
+        assert_eq!(
+            contents,
+            "# Lab
+12:13:14:15:16:17 192.168.1.255 name=nas address=192.168.1.10
+12:13:14:15:16:10 name=router
+12:13:14:15:16:18 name=office address=192.168.1.11
+12:13:14:15:16:20 name=printer address=192.168.1.13
+12:13:14:15:16:21 address=192.168.1.14
+"
+        );
+        assert_eq!((summary.added, summary.updated), (3, 1));
+        assert_eq!(summary.conflicts.len(), 1);
+        let error = import_into_file("12:13\n", Vec::new()).unwrap_err();
+        assert_eq!(error.line_no(), 1);
+    }
+}
@@ -0,0 +1,343 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Read targets in structured formats.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Error, ErrorKind, Result};
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde_json::Value;
+use wol::file::{MagicPacketDestination, WakeUpTarget};
+use wol::{MacAddress, SecureOn};
+
+/// The format of targets on standard input.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StdinFormat {
+    /// The format of wakeup files.
+    #[default]
+    Plain,
+    /// JSON objects, one after another or in an array.
+    Json,
+    /// Comma-separated values with a header line.
+    Csv,
+}
+
+/// The prefix of a SecureON password which refers to a keyring entry.
+const KEYRING_PREFIX: &str = "keyring:";
+
+/// Create a target from `fields` by name.
+///
+/// Ignore unknown and empty fields.
+///
+/// # Errors
+///
+/// Return an error message if `fields` lack a hardware address, or have
+/// invalid values.
+pub fn target_from_fields(
+    fields: &HashMap<String, String>,
+) -> std::result::Result<WakeUpTarget, String> {
+    let get = |key: &str| {
+        fields
+            .get(key)
+            .map(|value| value.trim())
+            .filter(|value| !value.is_empty())
+    };
+    let hardware_address = get("mac").ok_or("Missing mac")?;
+    let hardware_address = MacAddress::from_str(hardware_address)
+        .map_err(|error| format!("Invalid hardware address {hardware_address}: {error}"))?;
+    let port = get("port")
+        .map(|port| u16::from_str(port).map_err(|error| format!("Invalid port {port}: {error}")))
+        .transpose()?;
+    let target = WakeUpTarget::new(hardware_address)
+        .with_packet_destination(
+            get("host").map(|host| MagicPacketDestination::from(host.to_owned())),
+        )
+        .with_port(port)
+        .with_name(get("name").map(str::to_owned))
+        .with_address(
+            get("address").map(|address| MagicPacketDestination::from(address.to_owned())),
+        )
+        .with_after(
+            get("after")
+                .map(|after| {
+                    after
+                        .split(',')
+                        .map(|name| name.trim().to_owned())
+                        .collect()
+                })
+                .unwrap_or_default(),
+        );
+    match get("passwd") {
+        Some(passwd) => match passwd.strip_prefix(KEYRING_PREFIX) {
+            Some(name) => Ok(target.with_keyring_secure_on(Some(name.to_owned()))),
+            // Do not include the password in the error message, lest we leak it
+            None => SecureOn::from_str(passwd)
+                .map(|secure_on| target.with_secure_on(Some(secure_on)))
+                .map_err(|error| format!("Invalid SecureON password: {error}")),
+        },
+        None => Ok(target),
+    }
+}
+
+/// Get the fields of `target` by name.
+///
+/// This is the inverse of [`target_from_fields`]; omit all fields `target`
+/// does not have.
+pub fn target_fields(target: &WakeUpTarget) -> Vec<(&'static str, String)> {
+    let passwd = target
+        .secure_on()
+        .map(|secure_on| secure_on.to_string())
+        .or_else(|| {
+            target
+                .keyring_secure_on()
+                .map(|name| format!("{KEYRING_PREFIX}{name}"))
+        });
+    [
+        ("mac", Some(target.hardware_address().to_string())),
+        ("name", target.name().map(str::to_owned)),
+        ("host", target.packet_destination().map(ToString::to_string)),
+        ("port", target.port().map(|port| port.to_string())),
+        ("passwd", passwd),
+        ("address", target.address().map(ToString::to_string)),
+        (
+            "after",
+            Some(target.after().join(",")).filter(|after| !after.is_empty()),
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| value.map(|value| (key, value)))
+    .collect()
+}
+
+/// Convert a JSON `value` to a field value, or `None` for `null`.
+fn json_field(value: Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(value) => Some(value),
+        Value::Array(values) => Some(
+            values
+                .into_iter()
+                .filter_map(json_field)
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+        value => Some(value.to_string()),
+    }
+}
+
+/// Parse targets from JSON `contents`.
+///
+/// Accept a sequence of JSON objects, e.g. one per line, or arrays of
+/// objects; each object has the fields of a target.
+fn parse_json(contents: &str) -> Vec<Result<WakeUpTarget>> {
+    let mut targets = Vec::new();
+    let values = serde_json::Deserializer::from_str(contents).into_iter::<Value>();
+    for value in values {
+        let objects = match value {
+            Ok(Value::Array(values)) => values,
+            Ok(value) => vec![value],
+            Err(error) => {
+                targets.push(Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Invalid JSON: {error}"),
+                )));
+                break;
+            }
+        };
+        for object in objects {
+            let index = targets.len() + 1;
+            let target = match object {
+                Value::Object(object) => target_from_fields(
+                    &object
+                        .into_iter()
+                        .filter_map(|(key, value)| json_field(value).map(|value| (key, value)))
+                        .collect(),
+                ),
+                _ => Err("Expected an object".to_owned()),
+            };
+            targets.push(target.map_err(|message| {
+                Error::new(ErrorKind::InvalidData, format!("Target {index}: {message}"))
+            }));
+        }
+    }
+    targets
+}
+
+/// Split a `line` of comma-separated values into fields.
+///
+/// Double quotes enclose fields with commas; two double quotes within double
+/// quotes stand for a literal double quote.
+fn split_csv_line(line: &str) -> std::result::Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (false, ',') => fields.push(std::mem::take(&mut field)),
+            (false, '"') if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (_, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unbalanced quotes".to_owned());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+/// Parse targets from comma-separated values in `contents`.
+///
+/// The first line names the fields of all following lines; ignore empty
+/// lines.
+pub fn parse_csv(contents: &str) -> Vec<Result<WakeUpTarget>> {
+    let mut lines = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+    let invalid = |index: usize, message: String| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Line {}: {message}", index + 1),
+        )
+    };
+    let header = match lines.next() {
+        Some((index, line)) => match split_csv_line(line) {
+            Ok(header) => header
+                .into_iter()
+                .map(|name| name.trim().to_lowercase())
+                .collect::<Vec<_>>(),
+            Err(message) => return vec![Err(invalid(index, message))],
+        },
+        None => return Vec::new(),
+    };
+    lines
+        .map(|(index, line)| {
+            let fields = split_csv_line(line).map_err(|message| invalid(index, message))?;
+            if fields.len() != header.len() {
+                return Err(invalid(
+                    index,
+                    format!("Expected {} fields, got {}", header.len(), fields.len()),
+                ));
+            }
+            target_from_fields(&header.iter().cloned().zip(fields).collect())
+                .map_err(|message| invalid(index, message))
+        })
+        .collect()
+}
+
+/// Read targets in `format` from `reader`.
+///
+/// # Errors
+///
+/// Return an error if reading fails; the iterator yields errors for invalid
+/// targets.
+pub fn read_targets<R: BufRead + 'static>(
+    mut reader: R,
+    format: StdinFormat,
+) -> Result<Box<dyn Iterator<Item = Result<WakeUpTarget>>>> {
+    if format == StdinFormat::Plain {
+        return Ok(Box::new(wol::file::from_reader(reader)));
+    }
+    let mut contents = String::new();
+    reader.read_to_string(&mut contents)?;
+    let targets = match format {
+        StdinFormat::Json => parse_json(&contents),
+        StdinFormat::Csv | StdinFormat::Plain => parse_csv(&contents),
+    };
+    Ok(Box::new(targets.into_iter()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+
+    fn mac() -> MacAddress {
+        MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17])
+    }
+
+    #[test]
+    fn json_lines_and_arrays() {
+        let targets = parse_json(
+            r#"{"mac": "12:13:14:15:16:17", "host": "192.168.1.255", "port": 9, "vendor": "acme"}
+[{"mac": "12-13-14-15-16-17", "name": "nas", "after": ["router", "switch"], "passwd": null}]"#,
+        )
+        .into_iter()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+        assert_eq!(
+            targets,
+            vec![
+                WakeUpTarget::new(mac())
+                    .with_ip_packet_destination(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 255)))
+                    .with_port(Some(9)),
+                WakeUpTarget::new(mac())
+                    .with_name(Some("nas".to_owned()))
+                    .with_after(vec!["router".to_owned(), "switch".to_owned()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn json_invalid() {
+        let targets = parse_json(r#"{"host": "nas"} 42 {"mac": "#);
+        let errors = targets
+            .into_iter()
+            .map(|target| target.unwrap_err().to_string())
+            .collect::<Vec<_>>();
+        let [missing_mac, not_an_object, invalid_json] = errors.as_slice() else {
+            panic!("Unexpected errors: {errors:?}");
+        };
+        assert_eq!(missing_mac, "Target 1: Missing mac");
+        assert_eq!(not_an_object, "Target 2: Expected an object");
+        assert!(invalid_json.starts_with("Invalid JSON"));
+    }
+
+    #[test]
+    fn csv() {
+        let targets = parse_csv(
+            "MAC,host,passwd,name\n\n12:13:14:15:16:17,,keyring:nas,\"rack 3, \"\"big\"\" nas\"\n",
+        )
+        .into_iter()
+        .collect::<Result<Vec<_>>>()
+        .unwrap();
+        assert_eq!(
+            targets,
+            vec![
+                WakeUpTarget::new(mac())
+                    .with_keyring_secure_on(Some("nas".to_owned()))
+                    .with_name(Some("rack 3, \"big\" nas".to_owned()))
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_invalid() {
+        let targets = parse_csv("mac,port\n12:13:14:15:16:17\n12:13:14:15:16:17,foo\n\"foo");
+        let errors = targets
+            .into_iter()
+            .map(|target| target.unwrap_err().to_string())
+            .collect::<Vec<_>>();
+        let [missing_field, invalid_port, unbalanced_quotes] = errors.as_slice() else {
+            panic!("Unexpected errors: {errors:?}");
+        };
+        assert_eq!(missing_field, "Line 2: Expected 2 fields, got 1");
+        assert!(invalid_port.starts_with("Line 3: Invalid port foo"));
+        assert_eq!(unbalanced_quotes, "Line 4: Unbalanced quotes");
+    }
+}
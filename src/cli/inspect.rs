@@ -0,0 +1,348 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Find magic packets in capture files.
+
+use std::io::{Error, Read, Result, stdin};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use clap::{ArgAction, Args, ValueHint};
+use wol::MacAddress;
+
+use super::PathOrStdin;
+use super::listen::format_time;
+use super::pcap::{CapturedPacket, read_capture};
+
+/// BSD loopback encapsulation.
+const LINKTYPE_NULL: u32 = 0;
+/// Ethernet frames.
+const LINKTYPE_ETHERNET: u32 = 1;
+/// Raw IPv4 or IPv6 packets.
+const LINKTYPE_RAW: u32 = 101;
+/// OpenBSD loopback encapsulation.
+const LINKTYPE_LOOP: u32 = 108;
+/// Linux "cooked" capture, version 1.
+const LINKTYPE_LINUX_SLL: u32 = 113;
+/// Raw IPv4 packets.
+const LINKTYPE_IPV4: u32 = 228;
+/// Raw IPv6 packets.
+const LINKTYPE_IPV6: u32 = 229;
+/// Linux "cooked" capture, version 2.
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+/// The `EtherType` of IPv4.
+const ETHERTYPE_IPV4: u16 = 0x0800;
+/// The `EtherType` of IPv6.
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+/// The `EtherType` of 802.1Q VLAN tags.
+const ETHERTYPE_VLAN: u16 = 0x8100;
+/// The `EtherType` of 802.1ad service VLAN tags.
+const ETHERTYPE_QINQ: u16 = 0x88a8;
+
+/// The IP protocol number of UDP.
+const UDP: u8 = 17;
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct InspectArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// The pcap or pcapng file to inspect.
+    ///
+    /// Use - to read from standard input.
+    #[arg(
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    file: PathOrStdin,
+}
+
+/// A decoded frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Frame<'a> {
+    /// The sender of the frame.
+    source: String,
+    /// The receiver of the frame.
+    destination: String,
+    /// The innermost protocol we decoded.
+    protocol: String,
+    /// The payload of the innermost protocol.
+    payload: &'a [u8],
+}
+
+fn be_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes([
+        *data.get(offset)?,
+        *data.get(offset + 1)?,
+    ]))
+}
+
+fn mac_address(data: &[u8]) -> Option<String> {
+    Some(MacAddress::new(<[u8; 6]>::try_from(data.get(..6)?).ok()?).to_string())
+}
+
+/// Decode a raw IPv4 or IPv6 packet, and a UDP datagram inside.
+fn decode_ip(data: &[u8]) -> Option<Frame<'_>> {
+    let (source, destination, protocol, payload) = match data.first()? >> 4 {
+        4 => {
+            let header_length = usize::from(data.first()? & 0x0f) * 4;
+            let total_length = usize::from(be_u16(data, 2)?).min(data.len());
+            let source = <[u8; 4]>::try_from(data.get(12..16)?).ok()?;
+            let destination = <[u8; 4]>::try_from(data.get(16..20)?).ok()?;
+            (
+                IpAddr::from(Ipv4Addr::from(source)),
+                IpAddr::from(Ipv4Addr::from(destination)),
+                *data.get(9)?,
+                data.get(header_length..total_length)?,
+            )
+        }
+        6 => {
+            let total_length = (40 + usize::from(be_u16(data, 4)?)).min(data.len());
+            let source = <[u8; 16]>::try_from(data.get(8..24)?).ok()?;
+            let destination = <[u8; 16]>::try_from(data.get(24..40)?).ok()?;
+            (
+                IpAddr::from(Ipv6Addr::from(source)),
+                IpAddr::from(Ipv6Addr::from(destination)),
+                *data.get(6)?,
+                data.get(40..total_length)?,
+            )
+        }
+        _ => return None,
+    };
+    if protocol == UDP {
+        let length = usize::from(be_u16(payload, 4)?).min(payload.len());
+        Some(Frame {
+            source: SocketAddr::new(source, be_u16(payload, 0)?).to_string(),
+            destination: SocketAddr::new(destination, be_u16(payload, 2)?).to_string(),
+            protocol: "UDP".to_owned(),
+            payload: payload.get(8..length)?,
+        })
+    } else {
+        Some(Frame {
+            source: source.to_string(),
+            destination: destination.to_string(),
+            protocol: format!("IP protocol {protocol}"),
+            payload,
+        })
+    }
+}
+
+/// Decode the `payload` of an Ethernet frame of `ethertype` between the
+/// hardware addresses `source` and `destination`.
+///
+/// Use the whole payload for unknown `ethertype`s, e.g. for magic packets
+/// sent directly over Ethernet with `EtherType` 0x0842.
+fn decode_ethertype(
+    source: String,
+    destination: String,
+    ethertype: u16,
+    payload: &[u8],
+) -> Frame<'_> {
+    match ethertype {
+        ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => decode_ip(payload),
+        _ => None,
+    }
+    .unwrap_or_else(|| Frame {
+        source,
+        destination,
+        protocol: format!("EtherType {ethertype:#06x}"),
+        payload,
+    })
+}
+
+/// Decode a frame of `link_type`.
+///
+/// Return `None` if we do not understand `link_type`, or if `data` is
+/// truncated.
+fn decode(link_type: u32, data: &[u8]) -> Option<Frame<'_>> {
+    match link_type {
+        LINKTYPE_ETHERNET => {
+            let destination = mac_address(data)?;
+            let source = mac_address(data.get(6..)?)?;
+            let mut offset = 12;
+            // Skip over VLAN tags
+            while matches!(be_u16(data, offset)?, ETHERTYPE_VLAN | ETHERTYPE_QINQ) {
+                offset += 4;
+            }
+            let ethertype = be_u16(data, offset)?;
+            Some(decode_ethertype(
+                source,
+                destination,
+                ethertype,
+                data.get(offset + 2..)?,
+            ))
+        }
+        LINKTYPE_LINUX_SLL => {
+            let source = mac_address(data.get(6..)?).unwrap_or_default();
+            let ethertype = be_u16(data, 14)?;
+            Some(decode_ethertype(
+                source,
+                String::new(),
+                ethertype,
+                data.get(16..)?,
+            ))
+        }
+        LINKTYPE_LINUX_SLL2 => {
+            let source = mac_address(data.get(12..)?).unwrap_or_default();
+            let ethertype = be_u16(data, 0)?;
+            Some(decode_ethertype(
+                source,
+                String::new(),
+                ethertype,
+                data.get(20..)?,
+            ))
+        }
+        LINKTYPE_NULL | LINKTYPE_LOOP => decode_ip(data.get(4..)?),
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => decode_ip(data),
+        _ => None,
+    }
+}
+
+/// Describe the magic packet in `packet`, if any, in a line of text.
+///
+/// Look for a magic packet in the innermost payload we can decode, and then
+/// anywhere in the packet.  Do not include the SecureON token, lest we leak
+/// it.
+fn describe(packet: &CapturedPacket) -> Option<String> {
+    let frame = decode(packet.link_type, &packet.data);
+    let (hardware_address, secure_on) = frame
+        .as_ref()
+        .and_then(|frame| wol::parse_magic_packet(frame.payload))
+        .or_else(|| wol::parse_magic_packet(&packet.data))?;
+    let time = packet.time.map_or_else(|| "-".to_owned(), format_time);
+    let summary = frame.map_or_else(
+        || format!("Link type {}", packet.link_type),
+        |frame| {
+            if frame.destination.is_empty() {
+                format!("{} ({})", frame.source, frame.protocol)
+            } else {
+                format!(
+                    "{} -> {} ({})",
+                    frame.source, frame.destination, frame.protocol
+                )
+            }
+        },
+    );
+    let secure_on = if secure_on.is_some() {
+        " with SecureON"
+    } else {
+        ""
+    };
+    Some(format!("{time} {summary}: {hardware_address}{secure_on}"))
+}
+
+/// Print all magic packets in a capture file.
+///
+/// # Errors
+///
+/// Return an error if reading the file fails, or if it is no valid capture
+/// file.
+pub fn inspect(args: &InspectArgs) -> Result<()> {
+    let (name, data) = match &args.file {
+        PathOrStdin::Stdin => {
+            let mut data = Vec::new();
+            stdin().read_to_end(&mut data)?;
+            ("<stdin>".to_owned(), data)
+        }
+        PathOrStdin::Path(path) => (path.display().to_string(), std::fs::read(path)?),
+    };
+    let packets = read_capture(&data)
+        .map_err(|error| Error::new(error.kind(), format!("{name}: {error}")))?;
+    let mut found = 0;
+    for line in packets.iter().filter_map(describe) {
+        anstream::println!("{line}");
+        found += 1;
+    }
+    anstream::eprintln!("Found {found} magic packets in {} packets", packets.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    fn magic_packet(secure_on: Option<[u8; 6]>) -> Vec<u8> {
+        let mut payload = Vec::new();
+        wol::write_magic_packet(
+            &mut payload,
+            MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]),
+            secure_on.map(wol::SecureOn::new),
+        )
+        .unwrap();
+        payload
+    }
+
+    #[test]
+    fn ethernet_frame() {
+        let mut data = vec![0xff; 6];
+        data.extend_from_slice(&[0x02, 0, 0, 0, 0, 1]);
+        // A VLAN tag
+        data.extend_from_slice(&[0x81, 0, 0, 42]);
+        data.extend_from_slice(&[0x08, 0x42]);
+        data.extend_from_slice(&magic_packet(Some([1, 2, 3, 4, 5, 6])));
+        let packet = CapturedPacket {
+            time: Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000)),
+            link_type: LINKTYPE_ETHERNET,
+            data,
+        };
+        assert_eq!(
+            describe(&packet).unwrap(),
+            "2023-11-14T22:13:20.000Z 02:00:00:00:00:01 -> FF:FF:FF:FF:FF:FF (EtherType 0x0842): 12:13:14:15:16:17 with SecureON"
+        );
+    }
+
+    #[test]
+    fn udp_datagram() {
+        let mut data = vec![0xff; 6];
+        data.extend_from_slice(&[0x02, 0, 0, 0, 0, 1, 0x08, 0x00]);
+        let payload = magic_packet(Some([1, 2, 3, 4, 5, 6]));
+        let total_length = u16::try_from(28 + payload.len()).unwrap();
+        data.extend_from_slice(&[0x45, 0]);
+        data.extend_from_slice(&total_length.to_be_bytes());
+        data.extend_from_slice(&[0, 0, 0x40, 0, 64, UDP, 0, 0, 192, 168, 1, 10]);
+        data.extend_from_slice(&[192, 168, 1, 255, 0x9c, 0x40, 0, 9]);
+        data.extend_from_slice(&(total_length - 20).to_be_bytes());
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&payload);
+        // Ethernet padding after the IP packet
+        data.extend_from_slice(&[0; 4]);
+        let packet = CapturedPacket {
+            time: None,
+            link_type: LINKTYPE_ETHERNET,
+            data,
+        };
+        assert_eq!(
+            describe(&packet).unwrap(),
+            "- 192.168.1.10:40000 -> 192.168.1.255:9 (UDP): 12:13:14:15:16:17 with SecureON"
+        );
+    }
+
+    #[test]
+    fn embedded_and_unknown() {
+        let mut data = b"GET /wake HTTP/1.1\r\n\r\n".to_vec();
+        data.extend_from_slice(&magic_packet(None));
+        data.extend_from_slice(b"trailing");
+        let packet = CapturedPacket {
+            time: None,
+            link_type: 4242,
+            data,
+        };
+        assert_eq!(
+            describe(&packet).unwrap(),
+            "- Link type 4242: 12:13:14:15:16:17"
+        );
+        let packet = CapturedPacket {
+            time: None,
+            link_type: LINKTYPE_RAW,
+            data: b"hello".to_vec(),
+        };
+        assert_eq!(describe(&packet), None);
+    }
+}
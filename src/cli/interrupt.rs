@@ -0,0 +1,118 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Interrupt runs gracefully, on signals or after a deadline.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use signal_hook::consts::{SIGINT, SIGTERM};
+use signal_hook::flag;
+
+/// The exit code of an interrupted run, as for a shell killed by `SIGINT`.
+pub const EXIT_INTERRUPTED: u8 = 130;
+
+/// The exit code of a run which exceeded its deadline, as for `timeout(1)`.
+pub const EXIT_TIMED_OUT: u8 = 124;
+
+/// How often to check for interrupts while sleeping.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Whether the user asked to stop with `SIGINT` or `SIGTERM`, or the run
+/// exceeded its deadline.
+#[derive(Debug, Clone, Default)]
+pub struct Interrupt {
+    signalled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl Interrupt {
+    /// Catch `SIGINT` and `SIGTERM`.
+    ///
+    /// The first signal only sets the interrupt flag; a second signal
+    /// terminates the process immediately.
+    pub fn register() -> Self {
+        let interrupt = Self::default();
+        for signal in [SIGINT, SIGTERM] {
+            // Register the shutdown first, so that it only fires on the second signal
+            let result = flag::register_conditional_shutdown(
+                signal,
+                i32::from(EXIT_INTERRUPTED),
+                interrupt.signalled.clone(),
+            )
+            .and_then(|_| flag::register(signal, interrupt.signalled.clone()));
+            if let Err(error) = result {
+                tracing::warn!("Failed to catch signal {signal}: {error}");
+            }
+        }
+        interrupt
+    }
+
+    /// Also interrupt the run after `timeout`, if any.
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.deadline = timeout.map(|timeout| Instant::now() + timeout);
+        self
+    }
+
+    /// Whether a signal interrupted the run.
+    pub fn signalled(&self) -> bool {
+        self.signalled.load(Ordering::Relaxed)
+    }
+
+    /// Whether the run exceeded its deadline.
+    pub fn timed_out(&self) -> bool {
+        self.deadline
+            .is_some_and(|deadline| deadline <= Instant::now())
+    }
+
+    /// Whether the run was interrupted, by a signal or the deadline.
+    pub fn is_set(&self) -> bool {
+        self.signalled() || self.timed_out()
+    }
+
+    /// Sleep for `duration`, unless interrupted before.
+    ///
+    /// Return `false` if interrupted.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        while !self.is_set() {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return true;
+            }
+            sleep(remaining.min(POLL_INTERVAL));
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleep_interrupted() {
+        let interrupt = Interrupt::default();
+        assert!(interrupt.sleep(Duration::from_millis(1)));
+        interrupt.signalled.store(true, Ordering::Relaxed);
+        let start = Instant::now();
+        assert!(!interrupt.sleep(Duration::from_secs(10)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn sleep_timed_out() {
+        let interrupt = Interrupt::default().with_timeout(Some(Duration::from_millis(100)));
+        assert!(!interrupt.timed_out());
+        let start = Instant::now();
+        assert!(!interrupt.sleep(Duration::from_secs(10)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(interrupt.timed_out());
+        assert!(!interrupt.signalled());
+    }
+}
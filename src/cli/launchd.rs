@@ -0,0 +1,221 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Integrate daemons with launchd on macOS.
+//!
+//! Generate property lists for launch daemons which run daemons under launchd,
+//! or wake up systems on a schedule.
+
+use std::ffi::OsStr;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+use super::schedule::Schedule;
+
+/// Escape `s` for XML text.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A property list for a launch daemon with `label`, which runs `command` in
+/// `directory`, with further `keys` for launchd.
+fn plist<S: AsRef<OsStr>>(
+    label: &str,
+    command: &[S],
+    directory: &Path,
+    keys: &str,
+) -> Result<String> {
+    fn utf8(s: &OsStr) -> Result<String> {
+        s.to_str().map(escape).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("Property lists need UTF-8, got {}", s.to_string_lossy()),
+            )
+        })
+    }
+
+    let arguments = command
+        .iter()
+        .map(|word| utf8(word.as_ref()).map(|word| format!("        <string>{word}</string>\n")))
+        .collect::<Result<String>>()?;
+    let directory = utf8(directory.as_os_str())?;
+    Ok(format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{label}</string>
+    <key>ProgramArguments</key>
+    <array>
+{arguments}    </array>
+    <key>WorkingDirectory</key>
+    <string>{directory}</string>
+{keys}</dict>
+</plist>
+"#,
+        label = escape(label),
+    ))
+}
+
+/// A property list for a launch daemon with `label`, which runs `command` in
+/// `directory`.
+///
+/// launchd starts the daemon at boot, and restarts it if it fails.
+///
+/// # Errors
+///
+/// Return an error if `command` or `directory` are not valid UTF-8.
+pub fn daemon_plist<S: AsRef<OsStr>>(
+    label: &str,
+    command: &[S],
+    directory: &Path,
+) -> Result<String> {
+    plist(
+        label,
+        command,
+        directory,
+        "    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+",
+    )
+}
+
+/// A property list for a launch daemon with `label`, which runs `command` in
+/// `directory` on `schedule`.
+///
+/// # Errors
+///
+/// Return an error if `command` or `directory` are not valid UTF-8.
+pub fn calendar_plist<S: AsRef<OsStr>>(
+    label: &str,
+    command: &[S],
+    directory: &Path,
+    schedule: &Schedule,
+) -> Result<String> {
+    let time = format!(
+        "            <key>Hour</key>
+            <integer>{}</integer>
+            <key>Minute</key>
+            <integer>{}</integer>
+",
+        schedule.hour(),
+        schedule.minute()
+    );
+    let intervals = if schedule.weekdays().is_empty() {
+        format!("        <dict>\n{time}        </dict>\n")
+    } else {
+        schedule
+            .weekdays()
+            .iter()
+            .map(|weekday| {
+                // launchd counts weekdays from Sunday
+                let weekday = (weekday + 1) % 7;
+                format!(
+                    "        <dict>
+            <key>Weekday</key>
+            <integer>{weekday}</integer>
+{time}        </dict>
+"
+                )
+            })
+            .collect::<Vec<_>>()
+            .concat()
+    };
+    plist(
+        label,
+        command,
+        directory,
+        &format!("    <key>StartCalendarInterval</key>\n    <array>\n{intervals}    </array>\n"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plist() {
+        let plist = daemon_plist(
+            "de.swsnr.wol.serve",
+            &[
+                "/usr/local/bin/wol",
+                "serve",
+                "--hosts",
+                "/etc/wol/<my> & hosts.toml",
+            ],
+            Path::new("/var/lib/wol"),
+        )
+        .unwrap();
+        assert!(plist.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n"));
+        assert!(plist.contains(
+            "    <key>Label</key>
+    <string>de.swsnr.wol.serve</string>
+    <key>ProgramArguments</key>
+    <array>
+        <string>/usr/local/bin/wol</string>
+        <string>serve</string>
+        <string>--hosts</string>
+        <string>/etc/wol/&lt;my&gt; &amp; hosts.toml</string>
+    </array>
+    <key>WorkingDirectory</key>
+    <string>/var/lib/wol</string>
+"
+        ));
+        assert!(plist.ends_with("</dict>\n</plist>\n"));
+    }
+
+    #[test]
+    fn calendar() {
+        use std::str::FromStr;
+
+        let plist = calendar_plist(
+            "de.swsnr.wol.wake",
+            &["/usr/local/bin/wol", "wake", "nas"],
+            Path::new("/var/lib/wol"),
+            &Schedule::from_str("Sat..Sun 9:00").unwrap(),
+        )
+        .unwrap();
+        assert!(plist.ends_with(
+            "    <key>StartCalendarInterval</key>
+    <array>
+        <dict>
+            <key>Weekday</key>
+            <integer>6</integer>
+            <key>Hour</key>
+            <integer>9</integer>
+            <key>Minute</key>
+            <integer>0</integer>
+        </dict>
+        <dict>
+            <key>Weekday</key>
+            <integer>0</integer>
+            <key>Hour</key>
+            <integer>9</integer>
+            <key>Minute</key>
+            <integer>0</integer>
+        </dict>
+    </array>
+</dict>
+</plist>
+"
+        ));
+    }
+}
@@ -0,0 +1,248 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! List known systems.
+
+use std::fs::File;
+use std::io::{BufReader, Result, stdin};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::{ArgAction, Args, ValueHint};
+use serde_json::json;
+use wol::file::WakeUpTarget;
+
+use super::PathOrStdin;
+use super::history::{self, History};
+use super::hosts::{Host, HostsArgs};
+use super::oui::{self, Vendors};
+use super::report::OutputFormat;
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct ListArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Also list systems from wakeup FILE.
+    ///
+    /// Use - to read from standard input; repeat to list
+    /// systems from multiple files.
+    #[arg(
+        short = 'f',
+        long = "file",
+        value_name = "FILE",
+        action = ArgAction::Append,
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    file: Vec<PathOrStdin>,
+    /// Print systems in FORMAT.
+    ///
+    /// With json print one JSON object per line for every
+    /// system, with its name, MAC address, vendor, destination,
+    /// port, tags, and the time of the last wake up in seconds
+    /// since the epoch.
+    #[arg(
+        long = "output",
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t,
+        verbatim_doc_comment
+    )]
+    output: OutputFormat,
+    #[command(flatten)]
+    hosts: HostsArgs,
+}
+
+/// A listed system.
+struct Row {
+    name: Option<String>,
+    mac: String,
+    vendor: Option<String>,
+    destination: Option<String>,
+    port: Option<u16>,
+    tags: Vec<String>,
+    last_wake: Option<SystemTime>,
+}
+
+impl Row {
+    fn new(
+        target: &WakeUpTarget,
+        tags: Vec<String>,
+        history: &History,
+        vendors: Option<&Vendors>,
+    ) -> Self {
+        Self {
+            name: target.name().map(str::to_owned),
+            mac: target.hardware_address().to_string(),
+            vendor: vendors
+                .and_then(|vendors| vendors.get(target.hardware_address()))
+                .map(str::to_owned),
+            destination: target.packet_destination().map(ToString::to_string),
+            port: target.port(),
+            tags,
+            last_wake: history.last_wake(target.hardware_address()),
+        }
+    }
+
+    /// The cells of this row in a table, at `now`.
+    fn cells(&self, now: SystemTime) -> [String; 6] {
+        let or_dash = |value: Option<String>| value.unwrap_or_else(|| "-".to_owned());
+        [
+            or_dash(self.name.clone()),
+            match &self.vendor {
+                Some(vendor) => format!("{} ({vendor})", self.mac),
+                None => self.mac.clone(),
+            },
+            or_dash(self.destination.clone()),
+            or_dash(self.port.map(|port| port.to_string())),
+            or_dash(Some(self.tags.join(",")).filter(|tags| !tags.is_empty())),
+            or_dash(self.last_wake.map(|time| history::ago(time, now))),
+        ]
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        let last_wake = self
+            .last_wake
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|elapsed| elapsed.as_secs());
+        json!({
+            "name": self.name,
+            "mac": self.mac,
+            "vendor": self.vendor,
+            "destination": self.destination,
+            "port": self.port,
+            "tags": self.tags,
+            "last_wake": last_wake,
+        })
+    }
+}
+
+/// Format `rows` as a table with aligned columns, at `now`.
+fn table(rows: &[Row], now: SystemTime) -> Vec<String> {
+    let header = ["NAME", "MAC", "DESTINATION", "PORT", "TAGS", "LAST WAKE"].map(str::to_owned);
+    let lines = std::iter::once(header)
+        .chain(rows.iter().map(|row| row.cells(now)))
+        .collect::<Vec<_>>();
+    let mut widths = [0; 6];
+    for cells in &lines {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    lines
+        .iter()
+        .map(|cells| {
+            cells
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_owned()
+        })
+        .collect()
+}
+
+/// List systems from the hosts database and all files.
+///
+/// # Errors
+///
+/// Return an error if reading the hosts database, a file, or the history
+/// fails, or if any system is invalid.
+pub fn list(args: &ListArgs) -> Result<()> {
+    let history = history::default_path()
+        .map(|path| History::read(&path))
+        .transpose()?
+        .unwrap_or_default();
+    let vendors = oui::load();
+    let mut rows = args
+        .hosts
+        .read()?
+        .hosts()?
+        .into_iter()
+        .map(|Host { target, tags }| Row::new(&target, tags, &history, vendors.as_ref()))
+        .collect::<Vec<_>>();
+    for file in &args.file {
+        let targets: Box<dyn Iterator<Item = Result<WakeUpTarget>>> = match file {
+            PathOrStdin::Stdin => Box::new(wol::file::from_reader(BufReader::new(stdin()))),
+            PathOrStdin::Path(path) => {
+                Box::new(wol::file::from_reader(BufReader::new(File::open(path)?)))
+            }
+        };
+        for target in targets {
+            rows.push(Row::new(&target?, Vec::new(), &history, vendors.as_ref()));
+        }
+    }
+    match args.output {
+        OutputFormat::Text => {
+            for line in table(&rows, SystemTime::now()) {
+                anstream::println!("{line}");
+            }
+        }
+        OutputFormat::Json => {
+            for row in &rows {
+                anstream::println!("{}", row.to_json());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use wol::MacAddress;
+
+    use super::*;
+
+    #[test]
+    fn aligned_table() {
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let mac = MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]);
+        let mut history = History::default();
+        history.record(mac, now - Duration::from_secs(7200));
+        let rows = [
+            Row::new(
+                &WakeUpTarget::new(mac)
+                    .with_name(Some("office-pc".to_owned()))
+                    .with_dns_packet_destination("office.lan".to_owned())
+                    .with_port(Some(9)),
+                vec!["lab".to_owned(), "office".to_owned()],
+                &history,
+                None,
+            ),
+            Row::new(
+                &WakeUpTarget::new(MacAddress::new([0x00, 0x1b, 0x21, 0x15, 0x16, 0x18])),
+                Vec::new(),
+                &history,
+                Some(&Vendors::parse("001B21 ACME\n")),
+            ),
+        ];
+        assert_eq!(
+            table(&rows, now),
+            vec![
+                "NAME       MAC                       DESTINATION  PORT  TAGS        LAST WAKE",
+                "office-pc  12:13:14:15:16:17         office.lan   9     lab,office  2h ago",
+                "-          00:1B:21:15:16:18 (ACME)  -            -     -           -",
+            ]
+        );
+        assert_eq!(
+            rows.first().map(Row::to_json),
+            Some(json!({
+                "name": "office-pc",
+                "mac": "12:13:14:15:16:17",
+                "vendor": null,
+                "destination": "office.lan",
+                "port": 9,
+                "tags": ["lab", "office"],
+                "last_wake": 1_699_992_800,
+            }))
+        );
+    }
+}
@@ -0,0 +1,368 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Receive and display magic packets.
+
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use clap::{ArgAction, Args, ValueHint};
+use serde_json::json;
+use socket2::{Domain, Protocol, Socket, Type};
+use wol::{MacAddress, SecureOn};
+
+use super::interrupt::Interrupt;
+use super::pcap::PcapWriter;
+use super::report::OutputFormat;
+use super::sandbox::{Access, SandboxArgs};
+use super::systemd;
+
+/// How often to check for interrupts while waiting for packets.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct ListenArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Listen for magic packets on UDP PORT.
+    ///
+    /// Repeat to listen on multiple ports.
+    #[arg(
+        short = 'p',
+        long = "port",
+        value_name = "PORT",
+        action = ArgAction::Append,
+        default_value = "9",
+        verbatim_doc_comment
+    )]
+    ports: Vec<u16>,
+    /// Print received packets in FORMAT.
+    ///
+    /// With json print one JSON object per line for every
+    /// packet, with its time, source, port, size, and the
+    /// MAC address of the magic packet, if any.
+    #[arg(
+        long = "output",
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t,
+        verbatim_doc_comment
+    )]
+    output: OutputFormat,
+    /// Also write all received packets to FILE in pcap format,
+    /// for analysis with e.g. Wireshark.
+    ///
+    /// Overwrite FILE if it exists.
+    #[arg(
+        long = "write-pcap",
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    write_pcap: Option<PathBuf>,
+    #[command(flatten)]
+    sandbox: SandboxArgs,
+}
+
+/// A received UDP packet.
+#[derive(Debug, Clone)]
+pub struct Event {
+    /// When the packet arrived.
+    pub time: SystemTime,
+    /// The address which sent the packet.
+    pub source: SocketAddr,
+    /// The local port which received the packet.
+    pub port: u16,
+    /// The payload of the packet.
+    pub payload: Vec<u8>,
+}
+
+impl Event {
+    /// The hardware address and SecureON token of the magic packet in this
+    /// event, if any.
+    pub fn magic_packet(&self) -> Option<(MacAddress, Option<SecureOn>)> {
+        wol::parse_magic_packet(&self.payload)
+    }
+
+    /// Describe this event in a line of text.
+    ///
+    /// Do not include the SecureON token, lest we leak it.
+    pub fn describe(&self) -> String {
+        let packet = match self.magic_packet() {
+            Some((hardware_address, None)) => hardware_address.to_string(),
+            Some((hardware_address, Some(_))) => format!("{hardware_address} with SecureON"),
+            None => format!("No magic packet, {} bytes", self.payload.len()),
+        };
+        format!(
+            "{} {} -> {}: {packet}",
+            format_time(self.time),
+            self.source,
+            self.port
+        )
+    }
+
+    /// Convert this event to a JSON object.
+    ///
+    /// Only tell whether the packet has a SecureON token, but do not include
+    /// the token itself.
+    pub fn to_json(&self) -> serde_json::Value {
+        let magic_packet = self.magic_packet();
+        json!({
+            "time": format_time(self.time),
+            "source": self.source.to_string(),
+            "port": self.port,
+            "size": self.payload.len(),
+            "mac": magic_packet.map(|(hardware_address, _)| hardware_address.to_string()),
+            "secure_on": magic_packet.is_some_and(|(_, secure_on)| secure_on.is_some()),
+        })
+    }
+
+    /// The local address which received this event.
+    ///
+    /// We do not know the exact local address, so use the unspecified address
+    /// of the family of the source address.
+    fn destination(&self) -> SocketAddr {
+        let ip = match self.source.ip() {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        SocketAddr::new(ip, self.port)
+    }
+}
+
+/// Format `time` as RFC 3339 timestamp in UTC, with milliseconds.
+pub fn format_time(time: SystemTime) -> String {
+    let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = elapsed.as_secs();
+    // Convert days since the epoch to a civil date, after
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = seconds / 86400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{:03}Z",
+        seconds % 86400 / 3600,
+        seconds % 3600 / 60,
+        seconds % 60,
+        elapsed.subsec_millis()
+    )
+}
+
+/// Bind a socket to receive packets on `port`.
+///
+/// Receive both IPv6 and IPv4 packets if possible, and otherwise only IPv4
+/// packets.
+pub fn bind(port: u16) -> Result<UdpSocket> {
+    let dual_stack = || -> Result<UdpSocket> {
+        let socket = Socket::new(Domain::IPV6, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.set_only_v6(false)?;
+        socket.bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)).into())?;
+        Ok(socket.into())
+    };
+    dual_stack()
+        .or_else(|_| UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port)))
+        .map_err(|error| {
+            Error::new(
+                error.kind(),
+                format!("Failed to listen on port {port}: {error}"),
+            )
+        })
+}
+
+/// Receive packets on `socket` and send them to `events`, until interrupted.
+pub fn receive(
+    socket: &UdpSocket,
+    port: u16,
+    interrupt: &Interrupt,
+    events: &mpsc::Sender<Result<Event>>,
+) {
+    let mut buffer = vec![0; usize::from(u16::MAX)];
+    while !interrupt.is_set() {
+        let event = match socket.recv_from(&mut buffer) {
+            Ok((size, source)) => Ok(Event {
+                time: SystemTime::now(),
+                // Show IPv4 addresses as such, not as IPv4-mapped IPv6 addresses
+                source: SocketAddr::new(source.ip().to_canonical(), source.port()),
+                port,
+                payload: buffer.get(..size).unwrap_or_default().to_vec(),
+            }),
+            Err(error)
+                if matches!(
+                    error.kind(),
+                    ErrorKind::WouldBlock | ErrorKind::TimedOut | ErrorKind::Interrupted
+                ) =>
+            {
+                continue;
+            }
+            Err(error) => Err(error),
+        };
+        let failed = event.is_err();
+        if events.send(event).is_err() || failed {
+            break;
+        }
+    }
+}
+
+/// Listen for magic packets and print every received packet, until
+/// interrupted.
+///
+/// # Errors
+///
+/// Return an error if binding a port, receiving packets, or writing the pcap
+/// file fails.
+pub fn listen(args: &ListenArgs) -> Result<ExitCode> {
+    let interrupt = Interrupt::register();
+    let mut pcap = args
+        .write_pcap
+        .as_ref()
+        .map(|path| PcapWriter::new(BufWriter::new(File::create(path)?)))
+        .transpose()?;
+    let sockets = args
+        .ports
+        .iter()
+        .map(|port| {
+            let socket = bind(*port)?;
+            socket.set_read_timeout(Some(POLL_INTERVAL))?;
+            Ok((*port, socket))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    args.sandbox.apply(&Access::default())?;
+    let (sender, events) = mpsc::channel();
+    for (port, socket) in sockets {
+        tracing::info!("Listening on port {port}");
+        let sender = sender.clone();
+        let interrupt = interrupt.clone();
+        std::thread::spawn(move || receive(&socket, port, &interrupt, &sender));
+    }
+    drop(sender);
+    let ports = args
+        .ports
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>();
+    systemd::notify_ready(&format!("Listening on ports {}", ports.join(", ")));
+    loop {
+        match events.recv_timeout(POLL_INTERVAL) {
+            Ok(event) => {
+                let event = event?;
+                match args.output {
+                    OutputFormat::Text => anstream::println!("{}", event.describe()),
+                    OutputFormat::Json => anstream::println!("{}", event.to_json()),
+                }
+                if let Some(pcap) = pcap.as_mut() {
+                    pcap.write_packet(
+                        event.time,
+                        event.source,
+                        event.destination(),
+                        &event.payload,
+                    )?;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) if !interrupt.is_set() => {}
+            Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => break,
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn time() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_042);
+        assert_eq!(format_time(time), "2023-11-14T22:13:20.042Z");
+        assert_eq!(format_time(UNIX_EPOCH), "1970-01-01T00:00:00.000Z");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400);
+        assert_eq!(format_time(leap_day), "2000-02-29T00:00:00.000Z");
+    }
+
+    #[test]
+    fn describe() {
+        let mac_address = MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]);
+        let mut payload = Vec::new();
+        wol::write_magic_packet(&mut payload, mac_address, None).unwrap();
+        let mut event = Event {
+            time: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            source: SocketAddr::new(IpAddr::from([192, 168, 1, 10]), 40000),
+            port: 9,
+            payload,
+        };
+        assert_eq!(
+            event.describe(),
+            "2023-11-14T22:13:20.000Z 192.168.1.10:40000 -> 9: 12:13:14:15:16:17"
+        );
+        event.payload.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+        assert_eq!(
+            event.describe(),
+            "2023-11-14T22:13:20.000Z 192.168.1.10:40000 -> 9: 12:13:14:15:16:17 with SecureON"
+        );
+        event.payload = b"hello".to_vec();
+        assert_eq!(
+            event.describe(),
+            "2023-11-14T22:13:20.000Z 192.168.1.10:40000 -> 9: No magic packet, 5 bytes"
+        );
+    }
+
+    #[test]
+    fn json() {
+        let mac_address = MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]);
+        let mut payload = Vec::new();
+        wol::write_magic_packet(&mut payload, mac_address, None).unwrap();
+        payload.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+        let mut event = Event {
+            time: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            source: SocketAddr::new(IpAddr::from([192, 168, 1, 10]), 40000),
+            port: 9,
+            payload,
+        };
+        assert_eq!(
+            event.to_json().to_string(),
+            r#"{"mac":"12:13:14:15:16:17","port":9,"secure_on":true,"size":108,"source":"192.168.1.10:40000","time":"2023-11-14T22:13:20.000Z"}"#
+        );
+        event.payload = b"hello".to_vec();
+        assert_eq!(
+            event.to_json().to_string(),
+            r#"{"mac":null,"port":9,"secure_on":false,"size":5,"source":"192.168.1.10:40000","time":"2023-11-14T22:13:20.000Z"}"#
+        );
+    }
+
+    #[test]
+    fn receive_packets() {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        socket.set_read_timeout(Some(POLL_INTERVAL)).unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let mac_address = MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]);
+        wol::send_magic_packet(mac_address, None, (Ipv4Addr::LOCALHOST, port).into()).unwrap();
+        // Stop receiving after the deadline
+        let interrupt = Interrupt::default().with_timeout(Some(Duration::from_millis(500)));
+        let (sender, events) = mpsc::channel();
+        let thread = std::thread::spawn(move || receive(&socket, port, &interrupt, &sender));
+        let event = events.recv().unwrap().unwrap();
+        assert_eq!(event.port, port);
+        assert_eq!(event.magic_packet(), Some((mac_address, None)));
+        thread.join().unwrap();
+    }
+}
@@ -0,0 +1,251 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Log what wol does.
+
+use std::fs::{File, OpenOptions};
+use std::io::{IsTerminal, Result};
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use clap::{Args, ValueEnum};
+use tracing::Subscriber;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{Layer, fmt};
+
+/// The most verbose level to log.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, ValueEnum)]
+pub enum LogLevel {
+    /// Do not log at all.
+    #[default]
+    Off,
+    /// Log errors only.
+    Error,
+    /// Log warnings and errors.
+    Warn,
+    /// Log informational messages, warnings and errors.
+    Info,
+    /// Log debug messages as well.
+    Debug,
+    /// Log everything.
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(value: LogLevel) -> Self {
+        match value {
+            LogLevel::Off => LevelFilter::OFF,
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// The format of log records.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+/// Arguments to configure logging.
+#[derive(Args, Debug, Clone)]
+pub struct LogArgs {
+    /// Log records at LEVEL and above.
+    #[arg(
+        id = "log-level",
+        long = "log-level",
+        value_name = "LEVEL",
+        value_enum,
+        default_value_t = LogLevel::Off,
+        global = true
+    )]
+    level: LogLevel,
+    /// The format of log records.
+    #[arg(
+        id = "log-format",
+        long = "log-format",
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t = LogFormat::Text,
+        global = true
+    )]
+    format: LogFormat,
+    /// Append log records to FILE instead of standard error.
+    #[arg(id = "log-file", long = "log-file", value_name = "FILE", global = true)]
+    file: Option<PathBuf>,
+    /// Log to the systemd journal instead.
+    #[cfg(feature = "journald")]
+    #[arg(
+        id = "log-journald",
+        long = "log-journald",
+        global = true,
+        conflicts_with = "log-file"
+    )]
+    #[cfg_attr(feature = "syslog", arg(conflicts_with = "log-syslog"))]
+    journald: bool,
+    /// Log to syslog instead.
+    #[cfg(feature = "syslog")]
+    #[arg(
+        id = "log-syslog",
+        long = "log-syslog",
+        global = true,
+        conflicts_with = "log-file"
+    )]
+    syslog: bool,
+}
+
+/// Format log records for a `writer`.
+fn fmt_layer<S, W>(format: LogFormat, writer: W, ansi: bool) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = fmt::layer().with_writer(writer).with_ansi(ansi);
+    match format {
+        LogFormat::Text => layer.boxed(),
+        LogFormat::Json => layer.json().boxed(),
+    }
+}
+
+impl LogArgs {
+    /// Create the layer which writes log records to the selected sink.
+    fn layer<S>(&self) -> Result<Box<dyn Layer<S> + Send + Sync>>
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        #[cfg(feature = "journald")]
+        if self.journald {
+            return Ok(tracing_journald::layer()?.boxed());
+        }
+        #[cfg(feature = "syslog")]
+        if self.syslog {
+            return Ok(system_log::SyslogLayer::connect()?.boxed());
+        }
+        match &self.file {
+            Some(path) => {
+                let file = OpenOptions::new().create(true).append(true).open(path)?;
+                Ok(fmt_layer(self.format, Mutex::<File>::new(file), false))
+            }
+            None => Ok(fmt_layer(
+                self.format,
+                std::io::stderr,
+                std::io::stderr().is_terminal(),
+            )),
+        }
+    }
+
+    /// Install the global logger.
+    ///
+    /// Do nothing if logging is off.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if the log file or the system log could not be opened.
+    pub fn init(&self) -> Result<()> {
+        if self.level == LogLevel::Off {
+            return Ok(());
+        }
+        tracing_subscriber::registry()
+            .with(self.layer()?.with_filter(LevelFilter::from(self.level)))
+            .try_init()
+            .map_err(std::io::Error::other)
+    }
+}
+
+#[cfg(feature = "syslog")]
+mod system_log {
+    use std::io::{Error, Result};
+    use std::sync::Mutex;
+
+    use syslog::{Facility, Formatter3164, Logger, LoggerBackend};
+    use tracing::field::{Field, Visit};
+    use tracing::{Event, Level, Subscriber};
+    use tracing_subscriber::Layer;
+    use tracing_subscriber::layer::Context;
+
+    /// Collect the message and fields of an event.
+    #[derive(Default)]
+    struct Line {
+        message: String,
+        fields: Vec<String>,
+    }
+
+    impl Line {
+        fn into_string(self) -> String {
+            std::iter::once(self.message)
+                .chain(self.fields)
+                .collect::<Vec<_>>()
+                .join(" ")
+        }
+    }
+
+    impl Visit for Line {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            if field.name() == "message" {
+                value.clone_into(&mut self.message);
+            } else {
+                self.fields.push(format!("{}={value}", field.name()));
+            }
+        }
+
+        // tracing passes all values we do not record otherwise as Debug
+        #[allow(clippy::use_debug)]
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            if field.name() == "message" {
+                self.message = format!("{value:?}");
+            } else {
+                self.fields.push(format!("{}={value:?}", field.name()));
+            }
+        }
+    }
+
+    /// A layer which sends events to syslog.
+    pub struct SyslogLayer(Mutex<Logger<LoggerBackend, Formatter3164>>);
+
+    impl SyslogLayer {
+        /// Connect to the local syslog daemon.
+        pub fn connect() -> Result<Self> {
+            let formatter = Formatter3164 {
+                facility: Facility::LOG_USER,
+                hostname: None,
+                process: env!("CARGO_BIN_NAME").to_owned(),
+                pid: std::process::id(),
+            };
+            let logger = syslog::unix(formatter)
+                .map_err(|error| Error::other(format!("Failed to connect to syslog: {error}")))?;
+            Ok(Self(Mutex::new(logger)))
+        }
+    }
+
+    impl<S: Subscriber> Layer<S> for SyslogLayer {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            let mut line = Line::default();
+            event.record(&mut line);
+            let line = line.into_string();
+            if let Ok(mut logger) = self.0.lock() {
+                let result = match *event.metadata().level() {
+                    Level::ERROR => logger.err(line),
+                    Level::WARN => logger.warning(line),
+                    Level::INFO => logger.info(line),
+                    _ => logger.debug(line),
+                };
+                if let Err(error) = result {
+                    eprintln!("Failed to log to syslog: {error}");
+                }
+            }
+        }
+    }
+}
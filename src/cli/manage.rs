@@ -0,0 +1,189 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Commands to manage the hosts database.
+
+use std::io::Result;
+
+use clap::{ArgAction, Args};
+use wol::MacAddress;
+
+use super::hosts::{Hosts, HostsArgs};
+
+/// Read the hosts database, apply `change`, and write it back.
+fn edit(hosts: &HostsArgs, change: impl FnOnce(&mut Hosts) -> Result<()>) -> Result<()> {
+    let path = hosts.path()?;
+    let mut database = Hosts::read(&path)?;
+    change(&mut database)?;
+    database.write(&path)
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct AddArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// The name of the new system.
+    #[arg(value_name = "NAME")]
+    name: String,
+    /// The hardware address of the new system.
+    #[arg(value_name = "MAC")]
+    #[cfg_attr(
+        feature = "dynamic-completions",
+        arg(add = clap_complete::ArgValueCandidates::new(super::complete::neighbours))
+    )]
+    hardware_address: MacAddress,
+    /// Send the magic packet to HOST.
+    #[arg(long = "host", value_name = "HOST")]
+    host: Option<String>,
+    /// Send the magic packet to PORT.
+    #[arg(long = "port", value_name = "PORT")]
+    port: Option<u16>,
+    /// Include the SecureON password PASSWD.
+    ///
+    /// Use keyring:NAME to read the password from the keyring
+    /// entry NAME when waking up the system.
+    #[arg(long = "passwd", value_name = "PASSWD", verbatim_doc_comment)]
+    passwd: Option<String>,
+    /// Wait until ADDRESS is online after waking up the system.
+    #[arg(long = "address", value_name = "ADDRESS")]
+    address: Option<String>,
+    /// Tag the system with TAG, to wake it up with @TAG.
+    ///
+    /// Repeat or separate multiple tags with commas.
+    #[arg(
+        long = "tag",
+        value_name = "TAG",
+        value_delimiter = ',',
+        action = ArgAction::Append,
+        verbatim_doc_comment
+    )]
+    tags: Vec<String>,
+    #[command(flatten)]
+    hosts: HostsArgs,
+}
+
+/// Add a system to the hosts database.
+///
+/// # Errors
+///
+/// Return an error if the system is invalid or exists already, or if reading
+/// or writing the hosts database fails.
+pub fn add(args: &AddArgs) -> Result<()> {
+    let port = args.port.map(|port| port.to_string());
+    let tags = Some(args.tags.join(",")).filter(|tags| !tags.is_empty());
+    let fields = [
+        ("host", args.host.as_deref()),
+        ("port", port.as_deref()),
+        ("passwd", args.passwd.as_deref()),
+        ("address", args.address.as_deref()),
+        ("tags", tags.as_deref()),
+    ]
+    .into_iter()
+    .filter_map(|(key, value)| value.map(|value| (key, value)))
+    .collect::<Vec<_>>();
+    edit(&args.hosts, |hosts| {
+        hosts.add(&args.name, args.hardware_address, &fields)
+    })
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct RemoveArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// The name of the system to remove.
+    #[arg(value_name = "NAME")]
+    #[cfg_attr(
+        feature = "dynamic-completions",
+        arg(add = clap_complete::ArgValueCandidates::new(super::complete::systems))
+    )]
+    name: String,
+    #[command(flatten)]
+    hosts: HostsArgs,
+}
+
+/// Remove a system from the hosts database.
+///
+/// # Errors
+///
+/// Return an error if there's no such system, or if reading or writing the
+/// hosts database fails.
+pub fn remove(args: &RemoveArgs) -> Result<()> {
+    edit(&args.hosts, |hosts| hosts.remove(&args.name))
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct RenameArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// The current name of the system.
+    #[arg(value_name = "NAME")]
+    #[cfg_attr(
+        feature = "dynamic-completions",
+        arg(add = clap_complete::ArgValueCandidates::new(super::complete::systems))
+    )]
+    from: String,
+    /// The new name of the system.
+    #[arg(value_name = "NEW_NAME")]
+    to: String,
+    #[command(flatten)]
+    hosts: HostsArgs,
+}
+
+/// Rename a system in the hosts database.
+///
+/// # Errors
+///
+/// Return an error if there's no such system, if the new name is invalid or
+/// exists already, or if reading or writing the hosts database fails.
+pub fn rename(args: &RenameArgs) -> Result<()> {
+    edit(&args.hosts, |hosts| hosts.rename(&args.from, &args.to))
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct SetArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// The name of the system.
+    #[arg(value_name = "NAME")]
+    #[cfg_attr(
+        feature = "dynamic-completions",
+        arg(add = clap_complete::ArgValueCandidates::new(super::complete::systems))
+    )]
+    name: String,
+    /// The key to set.
+    ///
+    /// One of mac, host, port, passwd, address, after, or tags.
+    #[arg(value_name = "KEY", verbatim_doc_comment)]
+    key: String,
+    /// The new value of KEY.
+    ///
+    /// Separate multiple values for "after" and "tags" with
+    /// commas.  An empty VALUE removes KEY.
+    #[arg(value_name = "VALUE", verbatim_doc_comment)]
+    value: String,
+    #[command(flatten)]
+    hosts: HostsArgs,
+}
+
+/// Change a system in the hosts database.
+///
+/// # Errors
+///
+/// Return an error if there's no such system, if the key or value is invalid,
+/// or if reading or writing the hosts database fails.
+pub fn set(args: &SetArgs) -> Result<()> {
+    edit(&args.hosts, |hosts| {
+        hosts.set(&args.name, &args.key, &args.value)
+    })
+}
@@ -0,0 +1,154 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Metrics of the HTTP API, in the Prometheus text format.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use serde_json::{Map, Value};
+
+/// A counter of the HTTP API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Counter {
+    /// Handled requests, by status.
+    Requests,
+    /// Attempts to wake up systems, by result.
+    WakeAttempts,
+    /// Requests refused by a rate limit, by limit.
+    RateLimited,
+}
+
+impl Counter {
+    /// All counters.
+    const ALL: [Self; 3] = [Self::Requests, Self::WakeAttempts, Self::RateLimited];
+
+    /// The name of this counter.
+    fn name(self) -> &'static str {
+        match self {
+            Self::Requests => "wol_http_requests_total",
+            Self::WakeAttempts => "wol_wake_attempts_total",
+            Self::RateLimited => "wol_rate_limited_total",
+        }
+    }
+
+    /// What this counter counts.
+    fn help(self) -> &'static str {
+        match self {
+            Self::Requests => "Handled HTTP requests, by status.",
+            Self::WakeAttempts => "Attempts to wake up systems, by result.",
+            Self::RateLimited => "Requests refused by a rate limit, by limit.",
+        }
+    }
+
+    /// The name of the label of this counter.
+    fn label(self) -> &'static str {
+        match self {
+            Self::Requests => "status",
+            Self::WakeAttempts => "result",
+            Self::RateLimited => "limit",
+        }
+    }
+}
+
+/// Counters of the HTTP API, by label value.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: Mutex<BTreeMap<(Counter, String), u64>>,
+}
+
+impl Metrics {
+    /// Increment `counter` for the label `value`.
+    ///
+    /// `value` must not need escaping, i.e. contain no `"`, `\`, or line
+    /// breaks.
+    pub fn increment(&self, counter: Counter, value: &str) {
+        if let Ok(mut counters) = self.counters.lock() {
+            *counters.entry((counter, value.to_owned())).or_default() += 1;
+        }
+    }
+
+    /// All counters as JSON object, by name and label value.
+    pub fn to_json(&self) -> Value {
+        let mut json = Map::new();
+        if let Ok(counters) = self.counters.lock() {
+            for ((counter, value), count) in counters.iter() {
+                if let Some(samples) = json
+                    .entry(counter.name())
+                    .or_insert_with(|| Value::Object(Map::new()))
+                    .as_object_mut()
+                {
+                    samples.insert(value.clone(), Value::from(*count));
+                }
+            }
+        }
+        Value::Object(json)
+    }
+
+    /// Render all counters in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let counters = self
+            .counters
+            .lock()
+            .map(|counters| counters.clone())
+            .unwrap_or_default();
+        Counter::ALL
+            .into_iter()
+            .flat_map(|counter| {
+                let samples = counters
+                    .iter()
+                    .filter(move |((c, _), _)| *c == counter)
+                    .map(move |((_, value), count)| {
+                        format!(
+                            "{}{{{}=\"{value}\"}} {count}\n",
+                            counter.name(),
+                            counter.label()
+                        )
+                    });
+                [
+                    format!("# HELP {} {}\n", counter.name(), counter.help()),
+                    format!("# TYPE {} counter\n", counter.name()),
+                ]
+                .into_iter()
+                .chain(samples)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let metrics = Metrics::default();
+        metrics.increment(Counter::Requests, "200");
+        metrics.increment(Counter::Requests, "429");
+        metrics.increment(Counter::Requests, "200");
+        metrics.increment(Counter::RateLimited, "client");
+        assert_eq!(
+            metrics.render(),
+            "# HELP wol_http_requests_total Handled HTTP requests, by status.
+# TYPE wol_http_requests_total counter
+wol_http_requests_total{status=\"200\"} 2
+wol_http_requests_total{status=\"429\"} 1
+# HELP wol_wake_attempts_total Attempts to wake up systems, by result.
+# TYPE wol_wake_attempts_total counter
+# HELP wol_rate_limited_total Requests refused by a rate limit, by limit.
+# TYPE wol_rate_limited_total counter
+wol_rate_limited_total{limit=\"client\"} 1
+"
+        );
+        assert_eq!(
+            metrics.to_json(),
+            serde_json::json!({
+                "wol_http_requests_total": { "200": 2, "429": 1 },
+                "wol_rate_limited_total": { "client": 1 },
+            })
+        );
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Read the neighbour table of the system.
+
+use std::io::{Error, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, UdpSocket};
+use std::process::Command;
+
+use wol::MacAddress;
+
+/// The port to send probes to.
+///
+/// Use the discard port; we only need the system to answer ARP or NDP
+/// requests, not the probe itself.
+const DISCARD_PORT: u16 = 9;
+
+/// Parse a hardware address with one or two hex digits per byte, separated
+/// by colons or dashes.
+fn parse_hardware_address(s: &str) -> Option<MacAddress> {
+    let mut bytes = [0; 6];
+    let mut parts = s.split([':', '-']);
+    for byte in &mut bytes {
+        let part = parts.next().filter(|part| (1..=2).contains(&part.len()))?;
+        *byte = u8::from_str_radix(part, 16).ok()?;
+    }
+    parts.next().is_none().then(|| MacAddress::new(bytes))
+}
+
+/// Parse a neighbour table.
+///
+/// Understand `/proc/net/arp` and the output of `ip neighbour` on Linux,
+/// the output of `arp -an` and `ndp -an` on BSD and macOS, and the output of
+/// `arp -a` and `netsh` on Windows, by taking the first IP address and the
+/// first hardware address of every line.  Skip incomplete and broadcast
+/// entries.
+fn parse_neighbours(contents: &str) -> Vec<(IpAddr, MacAddress)> {
+    contents
+        .lines()
+        .filter_map(|line| {
+            let mut tokens = line
+                .split_whitespace()
+                .map(|token| token.trim_matches(['(', ')']));
+            // Strip the zone of link-local IPv6 addresses
+            let ip = tokens.find_map(|token| {
+                token
+                    .split_once('%')
+                    .map_or(token, |(ip, _)| ip)
+                    .parse::<IpAddr>()
+                    .ok()
+            })?;
+            let hardware_address = tokens.find_map(parse_hardware_address)?;
+            let bytes = <[u8; 6]>::from(hardware_address);
+            (bytes != [0; 6] && bytes != [0xff; 6]).then_some((ip, hardware_address))
+        })
+        .collect()
+}
+
+/// Run `program` with `args`, and return its standard output.
+///
+/// # Errors
+///
+/// Return an error if `program` fails to start or fails.
+pub fn run(program: &str, args: &[&str]) -> Result<String> {
+    let output = Command::new(program).args(args).output()?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(Error::other(format!(
+            "{program} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+/// Read the IPv4 and IPv6 neighbour table of the system.
+///
+/// Ignore failures to read the IPv6 neighbour table, which needs additional
+/// tools on some systems.
+///
+/// # Errors
+///
+/// Return an error if reading the IPv4 neighbour table fails.
+pub fn read_neighbours() -> Result<Vec<(IpAddr, MacAddress)>> {
+    let (mut contents, ipv6) = if cfg!(target_os = "linux") {
+        (
+            std::fs::read_to_string("/proc/net/arp")?,
+            run("ip", &["-6", "neighbour", "show"]),
+        )
+    } else if cfg!(windows) {
+        (
+            run("arp", &["-a"])?,
+            run("netsh", &["interface", "ipv6", "show", "neighbors"]),
+        )
+    } else {
+        (run("arp", &["-an"])?, run("ndp", &["-an"]))
+    };
+    match ipv6 {
+        Ok(ipv6) => contents.push_str(&ipv6),
+        Err(error) => tracing::debug!("Failed to read IPv6 neighbours: {error}"),
+    }
+    Ok(parse_neighbours(&contents))
+}
+
+/// Probe all `ips`, to make the system resolve their hardware addresses with
+/// ARP or NDP, and add them to the neighbour table.
+///
+/// # Errors
+///
+/// Return an error if binding a socket to send probes fails.  Ignore failures
+/// to send a probe.
+pub fn probe(ips: impl IntoIterator<Item = IpAddr>) -> Result<()> {
+    let ips = ips.into_iter().collect::<Vec<_>>();
+    let bind = |unspecified: IpAddr| {
+        ips.iter()
+            .any(|ip| ip.is_ipv4() == unspecified.is_ipv4())
+            .then(|| UdpSocket::bind((unspecified, 0)))
+            .transpose()
+    };
+    let ipv4 = bind(Ipv4Addr::UNSPECIFIED.into())?;
+    let ipv6 = bind(Ipv6Addr::UNSPECIFIED.into())?;
+    for ip in ips {
+        let socket = if ip.is_ipv4() { &ipv4 } else { &ipv6 };
+        if let Some(socket) = socket {
+            if let Err(error) = socket.send_to(&[], (ip, DISCARD_PORT)) {
+                tracing::debug!("Failed to probe {ip}: {error}");
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    #[test]
+    fn ipv4() {
+        let linux = "IP address       HW type     Flags       HW address            Mask     Device
+192.168.1.1      0x1         0x2         00:1B:21:15:16:17     *        eth0
+192.168.1.23     0x1         0x0         00:00:00:00:00:00     *        eth0
+";
+        let macos = "? (192.168.1.1) at 00:1B:21:15:16:17 on en0 ifscope [ethernet]
+? (192.168.1.23) at (incomplete) on en0 ifscope [ethernet]
+? (192.168.1.42) at 2:0:c:d:e:f on en0 ifscope [ethernet]
+? (192.168.1.255) at ff:ff:ff:ff:ff:ff on en0 ifscope [ethernet]
+";
+        let windows = "Interface: 192.168.1.10 --- 0xb
+  Internet Address      Physical Address      Type
+  192.168.1.1           00-1B-21-15-16-17     dynamic
+  192.168.1.255         ff-ff-ff-ff-ff-ff     static
+";
+        let gateway = (
+            IpAddr::from(Ipv4Addr::new(192, 168, 1, 1)),
+            MacAddress::new([0x00, 0x1b, 0x21, 0x15, 0x16, 0x17]),
+        );
+        assert_eq!(parse_neighbours(linux), [gateway]);
+        assert_eq!(
+            parse_neighbours(macos),
+            [
+                gateway,
+                (
+                    IpAddr::from(Ipv4Addr::new(192, 168, 1, 42)),
+                    MacAddress::new([0x02, 0, 0x0c, 0x0d, 0x0e, 0x0f])
+                )
+            ]
+        );
+        assert_eq!(parse_neighbours(windows), [gateway]);
+    }
+
+    #[test]
+    fn ipv6() {
+        let linux = "fe80::1 dev eth0 lladdr 00:1b:21:15:16:17 router REACHABLE
+2001:db8::23 dev eth0 FAILED
+";
+        let macos =
+            "Neighbor                        Linklayer Address  Netif Expire    St Flgs Prbs
+fe80::1%en0                     0:1b:21:15:16:17   en0 23h59m58s S  R
+";
+        let router = (
+            IpAddr::from(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1)),
+            MacAddress::new([0x00, 0x1b, 0x21, 0x15, 0x16, 0x17]),
+        );
+        assert_eq!(parse_neighbours(linux), [router]);
+        assert_eq!(parse_neighbours(macos), [router]);
+    }
+}
@@ -0,0 +1,277 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Check whether systems are online.
+
+use std::fmt::Display;
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, SocketAddr, TcpStream, UdpSocket};
+use std::str::FromStr;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+/// How long to wait for a single probe to respond.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long to wait between probes.
+const PROBE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// ICMP echo request and reply types for IPv4.
+const ICMPV4_ECHO_REQUEST: u8 = 8;
+const ICMPV4_ECHO_REPLY: u8 = 0;
+
+/// ICMP echo request and reply types for IPv6.
+const ICMPV6_ECHO_REQUEST: u8 = 128;
+const ICMPV6_ECHO_REPLY: u8 = 129;
+
+/// How to check whether a system is online.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Probe {
+    /// Send ICMP echo requests.
+    Ping,
+    /// Connect to the given TCP port.
+    Tcp(u16),
+}
+
+impl Display for Probe {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Probe::Ping => write!(f, "ping"),
+            Probe::Tcp(port) => write!(f, "tcp:{port}"),
+        }
+    }
+}
+
+impl FromStr for Probe {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s == "ping" {
+            Ok(Self::Ping)
+        } else if let Some(port) = s.strip_prefix("tcp:") {
+            u16::from_str(port)
+                .map(Self::Tcp)
+                .map_err(|error| format!("Invalid port {port}: {error}"))
+        } else {
+            Err(format!("Invalid probe {s}, expected ping or tcp:PORT"))
+        }
+    }
+}
+
+/// A probe, ready to check a system.
+enum Prober {
+    Ping(UdpSocket),
+    Tcp,
+}
+
+/// Compute the internet checksum of `data`.
+fn checksum(data: &[u8]) -> u16 {
+    let sum = data
+        .chunks(2)
+        .map(|chunk| match chunk {
+            [high, low] => u32::from(u16::from_be_bytes([*high, *low])),
+            [high] => u32::from(u16::from_be_bytes([*high, 0])),
+            _ => 0,
+        })
+        .fold(0_u32, |sum, word| {
+            let sum = sum + word;
+            (sum & 0xffff) + (sum >> 16)
+        });
+    // The fold keeps the sum within 16 bits.
+    !u16::try_from(sum).unwrap_or(u16::MAX)
+}
+
+/// Build an ICMP echo request for `ip` with the given `sequence` number.
+fn echo_request(ip: IpAddr, sequence: u16) -> [u8; 8] {
+    let request_type = if ip.is_ipv4() {
+        ICMPV4_ECHO_REQUEST
+    } else {
+        ICMPV6_ECHO_REQUEST
+    };
+    let [sequence_high, sequence_low] = sequence.to_be_bytes();
+    let mut request = [request_type, 0, 0, 0, 0, 0, sequence_high, sequence_low];
+    // The kernel computes the checksum of ICMPv6 packets.
+    if ip.is_ipv4() {
+        let [checksum_high, checksum_low] = checksum(&request).to_be_bytes();
+        request[2] = checksum_high;
+        request[3] = checksum_low;
+    }
+    request
+}
+
+/// Whether `packet` is an ICMP echo reply.
+///
+/// Some systems include the IPv4 header in packets received from ICMP
+/// sockets; skip it if present.
+fn is_echo_reply(ip: IpAddr, packet: &[u8]) -> bool {
+    match ip {
+        IpAddr::V4(_) => {
+            let icmp = match packet.first() {
+                Some(first) if first >> 4 == 4 => packet.get(usize::from(first & 0x0f) * 4..),
+                _ => Some(packet),
+            };
+            icmp.and_then(<[u8]>::first) == Some(&ICMPV4_ECHO_REPLY)
+        }
+        IpAddr::V6(_) => packet.first() == Some(&ICMPV6_ECHO_REPLY),
+    }
+}
+
+/// Open an unprivileged ICMP socket to ping `address`.
+fn ping_socket(address: SocketAddr) -> Result<UdpSocket> {
+    let (domain, protocol) = if address.is_ipv4() {
+        (Domain::IPV4, Protocol::ICMPV4)
+    } else {
+        (Domain::IPV6, Protocol::ICMPV6)
+    };
+    let socket = Socket::new(domain, Type::DGRAM, Some(protocol)).map_err(|error| {
+        Error::new(
+            error.kind(),
+            format!("Cannot ping, use a TCP probe instead: {error}"),
+        )
+    })?;
+    socket.connect(&address.into())?;
+    let socket = UdpSocket::from(socket);
+    socket.set_read_timeout(Some(PROBE_TIMEOUT))?;
+    Ok(socket)
+}
+
+/// Ping `ip` once and return whether it replied.
+fn ping(socket: &UdpSocket, ip: IpAddr, sequence: u16) -> Result<bool> {
+    if let Err(error) = socket.send(&echo_request(ip, sequence)) {
+        // Sending fails while the system is not yet reachable, e.g. when
+        // neighbour discovery fails.
+        return if error.kind() == ErrorKind::PermissionDenied {
+            Err(error)
+        } else {
+            Ok(false)
+        };
+    }
+    let deadline = Instant::now() + PROBE_TIMEOUT;
+    let mut buffer = [0; 1500];
+    while Instant::now() < deadline {
+        match socket.recv(&mut buffer) {
+            Ok(size) => {
+                if is_echo_reply(ip, buffer.get(..size).unwrap_or_default()) {
+                    return Ok(true);
+                }
+            }
+            // Timeouts, or errors like host unreachable only tell us that the
+            // system is not online yet.
+            Err(_) => return Ok(false),
+        }
+    }
+    Ok(false)
+}
+
+/// Try to connect to `address` once and return whether the system responded.
+fn connect(address: &SocketAddr) -> bool {
+    match TcpStream::connect_timeout(address, PROBE_TIMEOUT) {
+        // A refused connection still tells us that the system is up.
+        Ok(_) => true,
+        Err(error) => error.kind() == ErrorKind::ConnectionRefused,
+    }
+}
+
+/// Wait until `address` responds to `probe`, for at most `timeout`.
+///
+/// Ignore the port of `address`.
+///
+/// Return how long it took for the system to respond.
+///
+/// # Errors
+///
+/// Return an error with [`ErrorKind::TimedOut`] if the system did not respond
+/// within `timeout`, with [`ErrorKind::Interrupted`] if `interrupted` returned
+/// `true` before, or any error from setting up the probe.
+pub fn wait_online(
+    probe: Probe,
+    mut address: SocketAddr,
+    timeout: Duration,
+    interrupted: impl Fn() -> bool,
+) -> Result<Duration> {
+    let prober = match probe {
+        Probe::Ping => {
+            address.set_port(0);
+            Prober::Ping(ping_socket(address)?)
+        }
+        Probe::Tcp(port) => {
+            address.set_port(port);
+            Prober::Tcp
+        }
+    };
+    let start = Instant::now();
+    let mut sequence: u16 = 0;
+    loop {
+        if interrupted() {
+            return Err(Error::new(
+                ErrorKind::Interrupted,
+                format!("Interrupted while waiting for {}", address.ip()),
+            ));
+        }
+        let attempt = Instant::now();
+        let online = match &prober {
+            Prober::Ping(socket) => ping(socket, address.ip(), sequence)?,
+            Prober::Tcp => connect(&address),
+        };
+        if online {
+            return Ok(start.elapsed());
+        }
+        if timeout <= start.elapsed() {
+            return Err(Error::new(
+                ErrorKind::TimedOut,
+                format!("{} not online after {}s", address.ip(), timeout.as_secs()),
+            ));
+        }
+        sequence = sequence.wrapping_add(1);
+        sleep(PROBE_INTERVAL.saturating_sub(attempt.elapsed()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    #[test]
+    fn probe() {
+        assert_eq!(Probe::from_str("ping").unwrap(), Probe::Ping);
+        assert_eq!(Probe::from_str("tcp:22").unwrap(), Probe::Tcp(22));
+        assert_eq!(Probe::Tcp(22).to_string(), "tcp:22");
+        assert!(Probe::from_str("tcp:").is_err());
+        assert!(Probe::from_str("tcp:70000").is_err());
+        assert!(Probe::from_str("udp:22").is_err());
+    }
+
+    #[test]
+    fn echo_request_checksum() {
+        let request = echo_request(IpAddr::V4(Ipv4Addr::LOCALHOST), 1);
+        assert_eq!(request, [8, 0, 0xf7, 0xfe, 0, 0, 0, 1]);
+        assert_eq!(checksum(&request), 0);
+    }
+
+    #[test]
+    fn echo_reply() {
+        let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        assert!(is_echo_reply(ip, &[0, 0, 0, 0, 0, 0, 0, 1]));
+        assert!(!is_echo_reply(ip, &[8, 0, 0, 0, 0, 0, 0, 1]));
+        let mut with_header = vec![0x45; 1];
+        with_header.extend([0; 19]);
+        with_header.extend([0, 0, 0, 0, 0, 0, 0, 1]);
+        assert!(is_echo_reply(ip, &with_header));
+    }
+
+    #[test]
+    fn tcp_refused_is_online() {
+        let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let address = listener.local_addr().unwrap();
+        assert!(connect(&address));
+        drop(listener);
+        assert!(connect(&address));
+    }
+}
@@ -0,0 +1,147 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Vendors of hardware addresses.
+
+use std::collections::HashMap;
+
+use wol::MacAddress;
+
+/// Databases of hardware address prefixes and their vendors, in order of
+/// preference.
+const VENDOR_DATABASES: [&str; 5] = [
+    "/usr/share/ieee-data/oui.txt",
+    "/usr/share/hwdata/oui.txt",
+    "/usr/share/misc/oui.txt",
+    "/usr/share/wireshark/manuf",
+    "/usr/share/nmap/nmap-mac-prefixes",
+];
+
+/// Vendors of hardware address prefixes.
+#[derive(Debug, Clone, Default)]
+pub struct Vendors {
+    prefixes: HashMap<[u8; 3], String>,
+}
+
+impl Vendors {
+    /// Parse a database of hardware address prefixes and vendors.
+    ///
+    /// Understand the IEEE OUI list, the manuf file of Wireshark, and the MAC
+    /// prefixes of nmap.
+    pub fn parse(contents: &str) -> Self {
+        let mut prefixes = HashMap::new();
+        for line in contents.lines() {
+            let Some((prefix, vendor)) = line.trim().split_once(char::is_whitespace) else {
+                continue;
+            };
+            let prefix = prefix.replace([':', '-'], "");
+            if prefix.len() != 6 {
+                continue;
+            }
+            let Ok(prefix) = u32::from_str_radix(&prefix, 16) else {
+                continue;
+            };
+            let [_, prefix @ ..] = prefix.to_be_bytes();
+            // Skip the "(hex)" marker of the IEEE list, and prefer the long names
+            // of Wireshark over its short names
+            let vendor = vendor.trim().trim_start_matches("(hex)");
+            let vendor = vendor.rsplit('\t').next().unwrap_or(vendor).trim();
+            if !vendor.is_empty() {
+                prefixes.entry(prefix).or_insert_with(|| vendor.to_owned());
+            }
+        }
+        Self { prefixes }
+    }
+
+    /// Read the first vendor database of the system which exists.
+    ///
+    /// Return an empty database if the system has none.
+    pub fn read() -> Self {
+        VENDOR_DATABASES
+            .iter()
+            .find_map(|path| std::fs::read_to_string(path).ok())
+            .map(|contents| Self::parse(&contents))
+            .unwrap_or_default()
+    }
+
+    /// Get the vendor of `hardware_address`.
+    ///
+    /// Return "locally administered" for locally administered addresses, which
+    /// have no vendor, and `None` if the vendor is unknown.
+    pub fn get(&self, hardware_address: MacAddress) -> Option<&str> {
+        let [first, second, third, ..] = <[u8; 6]>::from(hardware_address);
+        if first & 0x02 == 0 {
+            self.prefixes
+                .get(&[first, second, third])
+                .map(String::as_str)
+        } else {
+            Some("locally administered")
+        }
+    }
+}
+
+/// Read the vendor database of the system, if the `oui` feature is enabled.
+pub fn load() -> Option<Vendors> {
+    cfg!(feature = "oui").then(Vendors::read)
+}
+
+/// Format `hardware_address` with its vendor from `vendors`, if known.
+pub fn with_vendor(hardware_address: MacAddress, vendors: Option<&Vendors>) -> String {
+    match vendors.and_then(|vendors| vendors.get(hardware_address)) {
+        Some(vendor) => format!("{hardware_address} ({vendor})"),
+        None => hardware_address.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn databases() {
+        let ieee = "OUI/MA-L                                                    Organization
+company_id                                                  Organization
+                                                            Address
+
+00-1B-21   (hex)\t\tACME Corporation
+001B21     (base 16)\t\tACME Corporation
+\t\t\t\tSpringfield
+";
+        let manuf = "# Wireshark manuf
+00:1B:21\tAcme\tACME Corporation
+00:50:C2:00:00:00/36\tTTI\tTTI Ltd
+";
+        let nmap = "001B21 ACME\n";
+        for (contents, name) in [
+            (ieee, "ACME Corporation"),
+            (manuf, "ACME Corporation"),
+            (nmap, "ACME"),
+        ] {
+            let vendors = Vendors::parse(contents);
+            assert_eq!(vendors.prefixes.len(), 1, "{contents}");
+            assert_eq!(
+                vendors.get(MacAddress::new([0x00, 0x1b, 0x21, 0x15, 0x16, 0x17])),
+                Some(name)
+            );
+        }
+    }
+
+    #[test]
+    fn lookup() {
+        let vendors = Vendors::parse("001B21 ACME\n");
+        let acme = MacAddress::new([0x00, 0x1b, 0x21, 0x15, 0x16, 0x17]);
+        let unknown = MacAddress::new([0x10, 0, 0, 0, 0, 1]);
+        let local = MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]);
+        assert_eq!(vendors.get(unknown), None);
+        assert_eq!(vendors.get(local), Some("locally administered"));
+        assert_eq!(
+            with_vendor(acme, Some(&vendors)),
+            "00:1B:21:15:16:17 (ACME)"
+        );
+        assert_eq!(with_vendor(unknown, Some(&vendors)), "10:00:00:00:00:01");
+        assert_eq!(with_vendor(acme, None), "00:1B:21:15:16:17");
+    }
+}
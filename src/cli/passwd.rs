@@ -0,0 +1,122 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Map hardware addresses to SecureON passwords.
+
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::str::FromStr;
+
+use wol::{MacAddress, SecureOn};
+
+/// SecureON passwords by hardware address.
+#[derive(Debug, Default, Clone)]
+pub struct PasswdMap(HashMap<[u8; 6], SecureOn>);
+
+impl PasswdMap {
+    /// Parse a map from `contents`.
+    ///
+    /// Each line has a hardware address and a SecureON password, separated
+    /// by whitespace; ignore empty lines and lines starting with `#`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if a line is invalid.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut map = HashMap::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |message: String| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Line {}: {message}", index + 1),
+                )
+            };
+            let mut fields = line.split_whitespace();
+            let (Some(hardware_address), Some(secure_on), None) =
+                (fields.next(), fields.next(), fields.next())
+            else {
+                return Err(invalid(
+                    "Expected hardware address and SecureON password".to_owned(),
+                ));
+            };
+            let hardware_address = MacAddress::from_str(hardware_address).map_err(|error| {
+                invalid(format!(
+                    "Invalid hardware address {hardware_address}: {error}"
+                ))
+            })?;
+            // Do not include the password in the error message, lest we leak it
+            let secure_on = SecureOn::from_str(secure_on)
+                .map_err(|error| invalid(format!("Invalid SecureON password: {error}")))?;
+            map.insert(hardware_address.into(), secure_on);
+        }
+        Ok(Self(map))
+    }
+
+    /// Read a map from the file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if the file could not be read, or is invalid.
+    pub fn read(path: &Path) -> Result<Self> {
+        std::fs::read_to_string(path)
+            .and_then(|contents| Self::parse(&contents))
+            .map_err(|error| {
+                Error::new(
+                    error.kind(),
+                    format!(
+                        "Failed to read SecureON passwords from {}: {error}",
+                        path.display()
+                    ),
+                )
+            })
+    }
+
+    /// Get the SecureON password for `hardware_address`.
+    pub fn get(&self, hardware_address: MacAddress) -> Option<SecureOn> {
+        self.0.get(&<[u8; 6]>::from(hardware_address)).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let map = PasswdMap::parse(
+            "# Rack 3\n\n12:13:14:15:16:17 cd-23-ae-9d-3f-c2\n  12-23-24-25-26-27\t01:02:03:04:05:06\n",
+        )
+        .unwrap();
+        assert_eq!(
+            map.get(MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17])),
+            Some(SecureOn::new([0xcd, 0x23, 0xae, 0x9d, 0x3f, 0xc2]))
+        );
+        assert_eq!(
+            map.get(MacAddress::new([0x12, 0x23, 0x24, 0x25, 0x26, 0x27])),
+            Some(SecureOn::new([1, 2, 3, 4, 5, 6]))
+        );
+        assert_eq!(
+            map.get(MacAddress::new([0x12, 0x33, 0x34, 0x35, 0x36, 0x37])),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(PasswdMap::parse("12:13:14:15:16:17").is_err());
+        assert!(PasswdMap::parse("12:13:14:15:16:17 cd-23-ae-9d-3f-c2 foo").is_err());
+        assert!(PasswdMap::parse("12:13:14:15:16 cd-23-ae-9d-3f-c2").is_err());
+        let error = PasswdMap::parse("\n12:13:14:15:16:17 cd-23-ae-9d-3f-zz").unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().starts_with("Line 2:"));
+        assert!(!error.to_string().contains("cd-23"));
+    }
+}
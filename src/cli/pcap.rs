@@ -0,0 +1,499 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Read and write capture files.
+//!
+//! See <https://www.tcpdump.org/manpages/pcap-savefile.5.html> for the pcap
+//! format, and <https://www.ietf.org/archive/id/draft-ietf-opsawg-pcapng-02.html>
+//! for the pcapng format.
+
+use std::io::{Error, ErrorKind, Result, Write};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The magic number of pcap files with microsecond timestamps.
+const MAGIC: u32 = 0xa1b2_c3d4;
+
+/// The link type of raw IPv4 or IPv6 packets.
+const LINKTYPE_RAW: u32 = 101;
+
+/// The maximum number of bytes of a packet.
+const SNAPLEN: u32 = 65535;
+
+/// The IP protocol number of UDP.
+const UDP: u8 = 17;
+
+/// The TTL or hop limit of written IP packets.
+const TTL: u8 = 64;
+
+/// Add `bytes` as big-endian 16-bit words to `sum`.
+fn add_words(bytes: &[u8], sum: u32) -> u32 {
+    bytes.chunks(2).fold(sum, |sum, chunk| {
+        let word = match chunk {
+            [high, low] => u16::from_be_bytes([*high, *low]),
+            [high] => u16::from_be_bytes([*high, 0]),
+            _ => 0,
+        };
+        sum + u32::from(word)
+    })
+}
+
+/// The internet checksum of `bytes`, after adding the words in `sum`.
+fn checksum(bytes: &[u8], sum: u32) -> u16 {
+    let mut sum = add_words(bytes, sum);
+    while 0xffff < sum {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !u16::try_from(sum).unwrap_or(u16::MAX)
+}
+
+/// Build a raw IP packet with a UDP datagram from `source` to `destination`
+/// with `payload`.
+fn ip_packet(source: SocketAddr, destination: SocketAddr, payload: &[u8]) -> Vec<u8> {
+    let udp_length = u16::try_from(8 + payload.len()).unwrap_or(u16::MAX);
+    let mut udp = Vec::with_capacity(usize::from(udp_length));
+    udp.extend_from_slice(&source.port().to_be_bytes());
+    udp.extend_from_slice(&destination.port().to_be_bytes());
+    udp.extend_from_slice(&udp_length.to_be_bytes());
+    udp.extend_from_slice(&[0, 0]);
+    udp.extend_from_slice(payload);
+    let (mut packet, pseudo_header) = match (source.ip(), destination.ip()) {
+        (IpAddr::V4(source), IpAddr::V4(destination)) => {
+            let total_length = udp_length.saturating_add(20);
+            let mut header = vec![0x45, 0];
+            header.extend_from_slice(&total_length.to_be_bytes());
+            header.extend_from_slice(&[0, 0, 0x40, 0, TTL, UDP, 0, 0]);
+            header.extend_from_slice(&source.octets());
+            header.extend_from_slice(&destination.octets());
+            let ip_checksum = checksum(&header, 0).to_be_bytes();
+            header.splice(10..12, ip_checksum);
+            let mut pseudo_header = Vec::with_capacity(12);
+            pseudo_header.extend_from_slice(&source.octets());
+            pseudo_header.extend_from_slice(&destination.octets());
+            pseudo_header.extend_from_slice(&[0, UDP]);
+            pseudo_header.extend_from_slice(&udp_length.to_be_bytes());
+            (header, pseudo_header)
+        }
+        (source, destination) => {
+            let to_ipv6 = |ip: IpAddr| match ip {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                IpAddr::V6(ip) => ip,
+            };
+            let (source, destination) = (to_ipv6(source), to_ipv6(destination));
+            let mut header = vec![0x60, 0, 0, 0];
+            header.extend_from_slice(&udp_length.to_be_bytes());
+            header.extend_from_slice(&[UDP, TTL]);
+            header.extend_from_slice(&source.octets());
+            header.extend_from_slice(&destination.octets());
+            let mut pseudo_header = Vec::with_capacity(40);
+            pseudo_header.extend_from_slice(&source.octets());
+            pseudo_header.extend_from_slice(&destination.octets());
+            pseudo_header.extend_from_slice(&u32::from(udp_length).to_be_bytes());
+            pseudo_header.extend_from_slice(&[0, 0, 0, UDP]);
+            (header, pseudo_header)
+        }
+    };
+    // A checksum of zero means "no checksum", so send all ones instead
+    let udp_checksum = match checksum(&udp, add_words(&pseudo_header, 0)) {
+        0 => u16::MAX,
+        udp_checksum => udp_checksum,
+    };
+    udp.splice(6..8, udp_checksum.to_be_bytes());
+    packet.append(&mut udp);
+    packet
+}
+
+/// Write UDP packets to a pcap file.
+#[derive(Debug)]
+pub struct PcapWriter<W: Write> {
+    sink: W,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Create a new writer, and write the file header to `sink`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if writing the header fails.
+    pub fn new(mut sink: W) -> Result<Self> {
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&MAGIC.to_le_bytes());
+        header.extend_from_slice(&2_u16.to_le_bytes());
+        header.extend_from_slice(&4_u16.to_le_bytes());
+        // Time zone offset and timestamp accuracy
+        header.extend_from_slice(&[0; 8]);
+        header.extend_from_slice(&SNAPLEN.to_le_bytes());
+        header.extend_from_slice(&LINKTYPE_RAW.to_le_bytes());
+        sink.write_all(&header)?;
+        sink.flush()?;
+        Ok(Self { sink })
+    }
+
+    /// Write a UDP packet with `payload` from `source` to `destination`,
+    /// received at `time`.
+    ///
+    /// Flush the packet right away, to make it available to readers of the
+    /// file while listening.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if writing fails.
+    pub fn write_packet(
+        &mut self,
+        time: SystemTime,
+        source: SocketAddr,
+        destination: SocketAddr,
+        payload: &[u8],
+    ) -> Result<()> {
+        let packet = ip_packet(source, destination, payload);
+        let elapsed = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let length = u32::try_from(packet.len()).unwrap_or(u32::MAX);
+        let mut header = Vec::with_capacity(16);
+        header.extend_from_slice(
+            &u32::try_from(elapsed.as_secs())
+                .unwrap_or(u32::MAX)
+                .to_le_bytes(),
+        );
+        header.extend_from_slice(&elapsed.subsec_micros().to_le_bytes());
+        header.extend_from_slice(&length.to_le_bytes());
+        header.extend_from_slice(&length.to_le_bytes());
+        self.sink.write_all(&header)?;
+        self.sink.write_all(&packet)?;
+        self.sink.flush()
+    }
+}
+
+/// A packet read from a capture file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedPacket {
+    /// When the packet was captured, if known.
+    pub time: Option<SystemTime>,
+    /// The link type of the packet, see <https://www.tcpdump.org/linktypes.html>.
+    pub link_type: u32,
+    /// The captured bytes of the packet.
+    pub data: Vec<u8>,
+}
+
+fn truncated() -> Error {
+    Error::new(ErrorKind::InvalidData, "Truncated capture file")
+}
+
+/// Fields of a capture file, in the byte order of the file.
+#[derive(Debug, Clone, Copy)]
+struct Fields<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl<'a> Fields<'a> {
+    fn bytes(self, offset: usize, length: usize) -> Result<&'a [u8]> {
+        self.data
+            .get(offset..offset.saturating_add(length))
+            .ok_or_else(truncated)
+    }
+
+    fn u16(self, offset: usize) -> Result<u16> {
+        let bytes = <[u8; 2]>::try_from(self.bytes(offset, 2)?).map_err(|_| truncated())?;
+        Ok(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(self, offset: usize) -> Result<u32> {
+        let bytes = <[u8; 4]>::try_from(self.bytes(offset, 4)?).map_err(|_| truncated())?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn usize(self, offset: usize) -> Result<usize> {
+        usize::try_from(self.u32(offset)?).map_err(|_| truncated())
+    }
+}
+
+/// Convert a timestamp of `ticks` in `resolution` to a time.
+///
+/// `resolution` is a negative power of ten, or of two if the highest bit is
+/// set, as in the `if_tsresol` option of pcapng.
+fn timestamp(ticks: u64, resolution: u8) -> Option<SystemTime> {
+    let exponent = u32::from(resolution & 0x7f);
+    let units = if resolution & 0x80 == 0 {
+        10_u64.checked_pow(exponent)?
+    } else {
+        1_u64.checked_shl(exponent)?
+    };
+    let nanos = u128::from(ticks % units) * 1_000_000_000 / u128::from(units);
+    UNIX_EPOCH.checked_add(Duration::new(ticks / units, u32::try_from(nanos).ok()?))
+}
+
+/// Read all packets from the contents of a pcap file.
+fn read_pcap(data: &[u8]) -> Result<Vec<CapturedPacket>> {
+    let (big_endian, resolution) = match data.get(..4) {
+        Some([0xd4, 0xc3, 0xb2, 0xa1]) => (false, 6),
+        Some([0x4d, 0x3c, 0xb2, 0xa1]) => (false, 9),
+        Some([0xa1, 0xb2, 0xc3, 0xd4]) => (true, 6),
+        Some([0xa1, 0xb2, 0x3c, 0x4d]) => (true, 9),
+        _ => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Not a pcap or pcapng file",
+            ));
+        }
+    };
+    let fields = Fields { data, big_endian };
+    // The upper bits hold information about frame check sequences
+    let link_type = fields.u32(20)? & 0x0fff_ffff;
+    let mut packets = Vec::new();
+    let mut offset = 24;
+    while offset < data.len() {
+        let ticks = u64::from(fields.u32(offset)?) * 10_u64.pow(u32::from(resolution))
+            + u64::from(fields.u32(offset + 4)?);
+        let length = fields.usize(offset + 8)?;
+        packets.push(CapturedPacket {
+            time: timestamp(ticks, resolution),
+            link_type,
+            data: fields.bytes(offset + 16, length)?.to_vec(),
+        });
+        offset += 16 + length;
+    }
+    Ok(packets)
+}
+
+/// Read the `if_tsresol` option from the `options` of an interface
+/// description block, and default to microseconds.
+fn timestamp_resolution(options: Fields<'_>) -> u8 {
+    let mut offset = 0;
+    while let (Ok(code), Ok(length)) = (options.u16(offset), options.u16(offset + 2)) {
+        match code {
+            0 => break,
+            9 => {
+                return options
+                    .bytes(offset + 4, 1)
+                    .ok()
+                    .and_then(|value| value.first().copied())
+                    .unwrap_or(6);
+            }
+            _ => offset += 4 + usize::from(length).next_multiple_of(4),
+        }
+    }
+    6
+}
+
+/// Read all packets from the contents of a pcapng file.
+fn read_pcapng(data: &[u8]) -> Result<Vec<CapturedPacket>> {
+    let invalid = || Error::new(ErrorKind::InvalidData, "Invalid pcapng file");
+    let mut fields = Fields {
+        data,
+        big_endian: false,
+    };
+    // The link type and timestamp resolution of every interface
+    let mut interfaces: Vec<(u32, u8)> = Vec::new();
+    let mut packets = Vec::new();
+    let mut offset = 0;
+    while offset < data.len() {
+        if fields.bytes(offset, 4)? == [0x0a, 0x0d, 0x0d, 0x0a] {
+            // A new section, which determines the byte order of all its blocks
+            fields.big_endian = match fields.bytes(offset + 8, 4)? {
+                [0x1a, 0x2b, 0x3c, 0x4d] => true,
+                [0x4d, 0x3c, 0x2b, 0x1a] => false,
+                _ => return Err(invalid()),
+            };
+            interfaces.clear();
+        }
+        let block_type = fields.u32(offset)?;
+        let length = fields.usize(offset + 4)?;
+        let body = Fields {
+            data: fields.bytes(offset + 8, length.checked_sub(12).ok_or_else(invalid)?)?,
+            big_endian: fields.big_endian,
+        };
+        match block_type {
+            // Interface description block
+            1 => interfaces.push((
+                u32::from(body.u16(0)?),
+                timestamp_resolution(Fields {
+                    data: body.data.get(8..).unwrap_or_default(),
+                    ..body
+                }),
+            )),
+            // Enhanced packet block
+            6 => {
+                let (link_type, resolution) =
+                    *interfaces.get(body.usize(0)?).ok_or_else(invalid)?;
+                let ticks = (u64::from(body.u32(4)?) << 32) | u64::from(body.u32(8)?);
+                packets.push(CapturedPacket {
+                    time: timestamp(ticks, resolution),
+                    link_type,
+                    data: body.bytes(20, body.usize(12)?)?.to_vec(),
+                });
+            }
+            // Simple packet block, without timestamp
+            3 => {
+                let (link_type, _) = *interfaces.first().ok_or_else(invalid)?;
+                let length = body.usize(0)?.min(body.data.len().saturating_sub(4));
+                packets.push(CapturedPacket {
+                    time: None,
+                    link_type,
+                    data: body.bytes(4, length)?.to_vec(),
+                });
+            }
+            _ => {}
+        }
+        offset += length;
+    }
+    Ok(packets)
+}
+
+/// Read all packets from the contents of a pcap or pcapng file.
+///
+/// # Errors
+///
+/// Return an error if `data` is neither a pcap nor a pcapng file, or if it is
+/// truncated.
+pub fn read_capture(data: &[u8]) -> Result<Vec<CapturedPacket>> {
+    if data.starts_with(&[0x0a, 0x0d, 0x0d, 0x0a]) {
+        read_pcapng(data)
+    } else {
+        read_pcap(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn header_and_ipv4_packet() {
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        let time = UNIX_EPOCH + Duration::from_micros(1_700_000_000_000_042);
+        let source = SocketAddr::from((Ipv4Addr::new(192, 168, 1, 10), 40000));
+        let destination = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 9));
+        writer
+            .write_packet(time, source, destination, b"hello")
+            .unwrap();
+        let contents = writer.sink;
+        let (header, record) = contents.split_at(24);
+        assert_eq!(
+            header,
+            [
+                0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 101,
+                0, 0, 0
+            ]
+        );
+        let (record_header, packet) = record.split_at(16);
+        assert_eq!(
+            record_header,
+            [
+                0x00, 0xf1, 0x53, 0x65, 42, 0, 0, 0, 33, 0, 0, 0, 33, 0, 0, 0
+            ]
+        );
+        let (ip_header, udp) = packet.split_at(20);
+        // The checksum of a valid header is zero
+        assert_eq!(checksum(ip_header, 0), 0);
+        assert_eq!(udp.get(..6), Some([0x9c, 0x40, 0, 9, 0, 13].as_slice()));
+        assert_eq!(udp.get(8..), Some(b"hello".as_slice()));
+        // The checksum over the pseudo header and the datagram is zero
+        let mut pseudo_header = Vec::new();
+        pseudo_header.extend_from_slice(ip_header.get(12..20).unwrap_or_default());
+        pseudo_header.extend_from_slice(&[0, UDP, 0, 13]);
+        assert_eq!(checksum(udp, add_words(&pseudo_header, 0)), 0);
+    }
+
+    #[test]
+    fn ipv6_packet() {
+        let source = SocketAddr::from((Ipv6Addr::LOCALHOST, 40000));
+        let destination = SocketAddr::from((Ipv6Addr::UNSPECIFIED, 9));
+        let packet = ip_packet(source, destination, b"hello");
+        assert_eq!(packet.len(), 40 + 8 + 5);
+        let (ip_header, udp) = packet.split_at(40);
+        assert_eq!(ip_header.first(), Some(&0x60));
+        assert_eq!(ip_header.get(4..8), Some([0, 13, UDP, TTL].as_slice()));
+        // The checksum over the pseudo header and the datagram is zero
+        let mut pseudo_header = Vec::new();
+        pseudo_header.extend_from_slice(ip_header.get(8..40).unwrap_or_default());
+        pseudo_header.extend_from_slice(&[0, 0, 0, 13, 0, 0, 0, UDP]);
+        assert_eq!(checksum(udp, add_words(&pseudo_header, 0)), 0);
+    }
+
+    #[test]
+    fn read_written_pcap() {
+        let mut writer = PcapWriter::new(Vec::new()).unwrap();
+        let time = UNIX_EPOCH + Duration::from_micros(1_700_000_000_000_042);
+        let source = SocketAddr::from((Ipv4Addr::new(192, 168, 1, 10), 40000));
+        let destination = SocketAddr::from((Ipv4Addr::UNSPECIFIED, 9));
+        writer
+            .write_packet(time, source, destination, b"hello")
+            .unwrap();
+        writer
+            .write_packet(time, destination, source, b"world")
+            .unwrap();
+        let contents = writer.sink;
+        assert_eq!(
+            read_capture(&contents).unwrap(),
+            vec![
+                CapturedPacket {
+                    time: Some(time),
+                    link_type: LINKTYPE_RAW,
+                    data: ip_packet(source, destination, b"hello"),
+                },
+                CapturedPacket {
+                    time: Some(time),
+                    link_type: LINKTYPE_RAW,
+                    data: ip_packet(destination, source, b"world"),
+                }
+            ]
+        );
+        let (truncated, _) = contents.split_at(50);
+        assert_eq!(
+            read_capture(truncated).unwrap_err().to_string(),
+            "Truncated capture file"
+        );
+        assert_eq!(
+            read_capture(b"hello world").unwrap_err().to_string(),
+            "Not a pcap or pcapng file"
+        );
+    }
+
+    #[test]
+    fn read_pcapng() {
+        let data = [
+            // Big-endian section header block
+            0x0a, 0x0d, 0x0d, 0x0a, 0, 0, 0, 28, 0x1a, 0x2b, 0x3c, 0x4d, 0, 1, 0, 0, 0xff, 0xff,
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 28, //
+            // Ethernet interface with millisecond timestamps
+            0, 0, 0, 1, 0, 0, 0, 32, 0, 1, 0, 0, 0, 0, 0xff, 0xff, 0, 9, 0, 1, 3, 0, 0, 0, 0, 0, 0,
+            0, 0, 0, 0, 32, //
+            // Enhanced packet block
+            0, 0, 0, 6, 0, 0, 0, 40, 0, 0, 0, 0, 0, 0, 0x01, 0x8b, 0xcf, 0xe5, 0x68, 0x2a, 0, 0, 0,
+            5, 0, 0, 0, 5, b'h', b'e', b'l', b'l', b'o', 0, 0, 0, 0, 0, 0, 40, //
+            // Unknown block
+            0, 0, 0x0b, 0xad, 0, 0, 0, 12, 0, 0, 0, 12, //
+            // Simple packet block
+            0, 0, 0, 3, 0, 0, 0, 24, 0, 0, 0, 5, b'w', b'o', b'r', b'l', b'd', 0, 0, 0, 0, 0, 0, 24,
+        ];
+        assert_eq!(
+            read_capture(&data).unwrap(),
+            vec![
+                CapturedPacket {
+                    time: Some(UNIX_EPOCH + Duration::from_millis(1_700_000_000_042)),
+                    link_type: 1,
+                    data: b"hello".to_vec(),
+                },
+                CapturedPacket {
+                    time: None,
+                    link_type: 1,
+                    data: b"world".to_vec(),
+                }
+            ]
+        );
+    }
+}
@@ -0,0 +1,155 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Rate limits for the HTTP API.
+
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A token bucket.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    /// The tokens left in this bucket.
+    tokens: f64,
+    /// When we last refilled this bucket.
+    updated: Instant,
+}
+
+/// Limit the rate of events per key, with a token bucket for every key.
+///
+/// Every bucket holds at most as many tokens as the rate per minute, and
+/// refills continuously at this rate; every event takes one token.
+#[derive(Debug)]
+pub struct RateLimit<K> {
+    /// Events per minute, or 0 for no limit.
+    per_minute: u32,
+    buckets: Mutex<HashMap<K, Bucket>>,
+}
+
+impl<K: Eq + Hash + Clone> RateLimit<K> {
+    /// Allow `per_minute` events per minute for every key, or any number of
+    /// events if `per_minute` is 0.
+    pub fn per_minute(per_minute: u32) -> Self {
+        Self {
+            per_minute,
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// The tokens in `bucket` at `now`, after refilling it.
+    fn refill(&self, bucket: Bucket, now: Instant) -> f64 {
+        let capacity = f64::from(self.per_minute);
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * capacity / 60.0).min(capacity)
+    }
+
+    /// Take one token for every key in `keys` at `now`.
+    ///
+    /// Take nothing if any key lacks tokens; instead return how long to wait
+    /// until all keys have enough tokens again.  Count repeated keys as many
+    /// times as they occur.
+    pub fn acquire(&self, keys: &[K], now: Instant) -> Result<(), Duration> {
+        if self.per_minute == 0 {
+            return Ok(());
+        }
+        let Ok(mut buckets) = self.buckets.lock() else {
+            // Don't let a panicked thread lock out everyone
+            return Ok(());
+        };
+        let capacity = f64::from(self.per_minute);
+        let mut wanted = HashMap::<&K, f64>::new();
+        for key in keys {
+            *wanted.entry(key).or_default() += 1.0;
+        }
+        let mut retry_after = Duration::ZERO;
+        for (key, wanted) in &wanted {
+            let tokens = buckets
+                .get(*key)
+                .map_or(capacity, |bucket| self.refill(*bucket, now));
+            if tokens < *wanted {
+                let wait = Duration::from_secs_f64((wanted - tokens) * 60.0 / capacity);
+                retry_after = retry_after.max(wait);
+            }
+        }
+        if retry_after > Duration::ZERO {
+            return Err(retry_after);
+        }
+        for (key, wanted) in wanted {
+            let tokens = buckets
+                .get(key)
+                .map_or(capacity, |bucket| self.refill(*bucket, now));
+            buckets.insert(
+                key.clone(),
+                Bucket {
+                    tokens: tokens - wanted,
+                    updated: now,
+                },
+            );
+        }
+        // Forget full buckets, lest we remember every client forever
+        buckets.retain(|_, bucket| self.refill(*bucket, now) < capacity);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit() {
+        let limit = RateLimit::per_minute(2);
+        let start = Instant::now();
+        assert_eq!(limit.acquire(&["a"], start), Ok(()));
+        assert_eq!(limit.acquire(&["a", "b"], start), Ok(()));
+        assert_eq!(limit.acquire(&["a"], start), Err(Duration::from_secs(30)));
+        // Don't take anything if any key lacks tokens
+        assert_eq!(
+            limit.acquire(&["b", "a"], start),
+            Err(Duration::from_secs(30))
+        );
+        assert_eq!(limit.acquire(&["b"], start), Ok(()));
+        assert_eq!(
+            limit.acquire(&["a"], start + Duration::from_secs(15)),
+            Err(Duration::from_secs(15))
+        );
+        assert_eq!(
+            limit.acquire(&["a"], start + Duration::from_secs(30)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn repeated_keys() {
+        let limit = RateLimit::per_minute(3);
+        let start = Instant::now();
+        assert_eq!(
+            limit.acquire(&[(), (), (), ()], start),
+            Err(Duration::from_secs(20))
+        );
+        assert_eq!(limit.acquire(&[(), (), ()], start), Ok(()));
+    }
+
+    #[test]
+    fn forget_full_buckets() {
+        let limit = RateLimit::per_minute(60);
+        let start = Instant::now();
+        limit.acquire(&[1, 2], start).unwrap();
+        limit
+            .acquire(&[1], start + Duration::from_millis(1500))
+            .unwrap();
+        assert_eq!(limit.buckets.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn unlimited() {
+        let limit = RateLimit::per_minute(0);
+        let keys = vec!["a"; 1000];
+        assert_eq!(limit.acquire(&keys, Instant::now()), Ok(()));
+    }
+}
@@ -0,0 +1,716 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Wake up systems over the internet through a relay.
+//!
+//! A relay runs on the network of the systems to wake up, receives wake
+//! requests over UDP, and sends magic packets to the broadcast address of its
+//! own network, so that only the port of the relay needs forwarding, instead
+//! of forwarding magic packets into the broadcast domain.
+//!
+//! Requests carry a timestamp, a random nonce, and the hardware address and
+//! SecureON token of the system, encrypted with XChaCha20-Poly1305 under a
+//! key which client and relay share, so that nobody on the path learns the
+//! SecureON token.  The relay drops requests which fail to decrypt without
+//! answer, refuses requests more than 30 seconds off its clock, and sends no
+//! magic packet for a nonce it already saw, so that nobody can forge or replay
+//! requests.  It answers every authentic request with the nonce and the
+//! encrypted outcome.
+//!
+//! Requests and answers use separate keys, derived from the shared key with
+//! HMAC-SHA256 over `wol relay request` and `wol relay answer` respectively.
+//!
+//! A request has the following layout, with all numbers in big endian:
+//!
+//! - `WOLR`, and version 2 as single byte
+//! - the timestamp in milliseconds since the Unix epoch, as 8 bytes
+//! - the nonce, as 24 bytes
+//! - the ciphertext and tag of the hardware address as 6 bytes, and the
+//!   length of the SecureON token, either 0 or 6, as single byte followed by
+//!   the token, with all preceding bytes as associated data
+//!
+//! An answer has `WOLR` and version 2, the nonce, and the ciphertext and tag of
+//! the outcome as single byte, with all preceding bytes as associated data.
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::io::{Error, ErrorKind, Result};
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chacha20poly1305::aead::{Aead, Payload};
+use chacha20poly1305::{KeyInit, XChaCha20Poly1305, XNonce};
+use clap::{ArgAction, Args, Subcommand, ValueHint};
+use hmac_sha256::HMAC;
+use wol::{MacAddress, SecureOn};
+
+use super::listen;
+use super::sandbox::{Access, SandboxArgs};
+use super::systemd;
+
+/// The magic bytes and version of every message.
+const MAGIC: &[u8; 5] = b"WOLR\x02";
+
+/// The default port of relays.
+pub const DEFAULT_PORT: u16 = 4009;
+
+/// How far the timestamp of a request may be off the clock of the relay.
+const MAX_SKEW: Duration = Duration::from_secs(30);
+
+/// How long to wait for an answer from the relay, before sending the request
+/// again.
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How often to send a request before giving up.
+const ATTEMPTS: u32 = 3;
+
+/// The size of nonces.
+const NONCE_SIZE: usize = 24;
+
+/// The size of the authentication tag of every message.
+const TAG_SIZE: usize = 16;
+
+/// The size of the unencrypted header of requests.
+const REQUEST_HEADER_SIZE: usize = MAGIC.len() + 8 + NONCE_SIZE;
+
+/// The size of the unencrypted header of answers.
+const ANSWER_HEADER_SIZE: usize = MAGIC.len() + NONCE_SIZE;
+
+/// The size of the largest message.
+const MAX_MESSAGE_SIZE: usize = REQUEST_HEADER_SIZE + 6 + 1 + 6 + TAG_SIZE;
+
+/// What to derive the key for requests for.
+const REQUEST_CONTEXT: &[u8] = b"wol relay request";
+
+/// What to derive the key for answers for.
+const ANSWER_CONTEXT: &[u8] = b"wol relay answer";
+
+/// A key which client and relay share.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Key(Vec<u8>);
+
+impl Key {
+    /// Read a key from the first line of the file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if reading the file fails, or if its first line is
+    /// empty.
+    pub fn read(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|error| {
+            Error::new(
+                error.kind(),
+                format!("Failed to read relay key from {}: {error}", path.display()),
+            )
+        })?;
+        Self::from_str(contents.lines().next().unwrap_or_default())
+    }
+
+    /// The cipher with the key derived from this key for `context`.
+    fn cipher(&self, context: &[u8]) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&HMAC::mac(context, &self.0).into())
+    }
+}
+
+impl FromStr for Key {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let key = s.trim();
+        if key.is_empty() {
+            Err(Error::new(ErrorKind::InvalidInput, "Empty relay key"))
+        } else {
+            Ok(Self(key.as_bytes().to_vec()))
+        }
+    }
+}
+
+impl Debug for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Keep the key out of debug logs
+        f.write_str("Key(..)")
+    }
+}
+
+/// Encrypt `plaintext` with `cipher`, and append it to `header`.
+///
+/// `header` ends with the nonce, and goes along as associated data.
+fn seal(mut header: Vec<u8>, plaintext: &[u8], cipher: &XChaCha20Poly1305) -> Result<Vec<u8>> {
+    let nonce = header
+        .last_chunk::<NONCE_SIZE>()
+        .map(|nonce| XNonce::from(*nonce))
+        .ok_or_else(|| Error::other("Missing nonce in relay message"))?;
+    let ciphertext = cipher
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: plaintext,
+                aad: &header,
+            },
+        )
+        .map_err(|_| Error::other("Failed to encrypt relay message"))?;
+    header.extend_from_slice(&ciphertext);
+    Ok(header)
+}
+
+/// Split `message` after `header_size` bytes, and decrypt the rest with
+/// `cipher`.
+///
+/// Return the header and the plaintext, or `None` if decrypting fails.
+fn open<'a>(
+    message: &'a [u8],
+    header_size: usize,
+    cipher: &XChaCha20Poly1305,
+) -> Option<(&'a [u8], Vec<u8>)> {
+    let (header, ciphertext) = message.split_at_checked(header_size)?;
+    let nonce = XNonce::from(*header.last_chunk::<NONCE_SIZE>()?);
+    let plaintext = cipher
+        .decrypt(
+            &nonce,
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )
+        .ok()?;
+    Some((header, plaintext))
+}
+
+/// Take `N` bytes off the front of `bytes`.
+fn take<const N: usize>(bytes: &mut &[u8]) -> Option<[u8; N]> {
+    let (head, tail) = bytes.split_first_chunk::<N>()?;
+    *bytes = tail;
+    Some(*head)
+}
+
+/// A request to wake up a system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Request {
+    /// When the client made this request, in milliseconds since the epoch.
+    timestamp: u64,
+    nonce: [u8; NONCE_SIZE],
+    hardware_address: MacAddress,
+    secure_on: Option<SecureOn>,
+}
+
+impl Request {
+    /// A new request for `hardware_address` and `secure_on`, made now.
+    fn new(hardware_address: MacAddress, secure_on: Option<SecureOn>) -> Result<Self> {
+        let mut nonce = [0; NONCE_SIZE];
+        getrandom::getrandom(&mut nonce).map_err(Error::other)?;
+        Ok(Self {
+            timestamp: millis(SystemTime::now()),
+            nonce,
+            hardware_address,
+            secure_on,
+        })
+    }
+
+    /// Encode this request, encrypted with `key`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if encrypting fails.
+    fn encode(&self, key: &Key) -> Result<Vec<u8>> {
+        let mut header = Vec::with_capacity(MAX_MESSAGE_SIZE);
+        header.extend_from_slice(MAGIC);
+        header.extend_from_slice(&self.timestamp.to_be_bytes());
+        header.extend_from_slice(&self.nonce);
+        let mut plaintext = self.hardware_address.as_ref().to_vec();
+        match self.secure_on {
+            Some(secure_on) => {
+                plaintext.push(6);
+                plaintext.extend_from_slice(secure_on.as_ref());
+            }
+            None => plaintext.push(0),
+        }
+        seal(header, &plaintext, &key.cipher(REQUEST_CONTEXT))
+    }
+
+    /// Decode a request encrypted with `key`.
+    ///
+    /// Return `None` if `message` is no request, or fails to decrypt.
+    fn decode(message: &[u8], key: &Key) -> Option<Self> {
+        let (mut header, plaintext) =
+            open(message, REQUEST_HEADER_SIZE, &key.cipher(REQUEST_CONTEXT))?;
+        if take::<5>(&mut header)? != *MAGIC {
+            return None;
+        }
+        let timestamp = u64::from_be_bytes(take(&mut header)?);
+        let nonce = take(&mut header)?;
+        let mut plaintext = plaintext.as_slice();
+        let hardware_address = MacAddress::from(take::<6>(&mut plaintext)?);
+        let secure_on = match take::<1>(&mut plaintext)? {
+            [0] => None,
+            [6] => Some(SecureOn::from(take::<6>(&mut plaintext)?)),
+            _ => return None,
+        };
+        plaintext.is_empty().then_some(Self {
+            timestamp,
+            nonce,
+            hardware_address,
+            secure_on,
+        })
+    }
+}
+
+/// The outcome of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    /// The relay sent the magic packet.
+    Sent,
+    /// Sending the magic packet failed.
+    Failed,
+    /// The timestamp of the request was too far off the clock of the relay.
+    Expired,
+}
+
+impl Outcome {
+    fn to_byte(self) -> u8 {
+        match self {
+            Self::Sent => 0,
+            Self::Failed => 1,
+            Self::Expired => 2,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Self::Sent),
+            1 => Some(Self::Failed),
+            2 => Some(Self::Expired),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Sent => write!(f, "sent"),
+            Self::Failed => write!(f, "failed"),
+            Self::Expired => write!(f, "expired"),
+        }
+    }
+}
+
+/// Encode the answer to the request with `nonce`, encrypted with `key`.
+///
+/// # Errors
+///
+/// Return an error if encrypting fails.
+fn encode_answer(nonce: &[u8; NONCE_SIZE], outcome: Outcome, key: &Key) -> Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(ANSWER_HEADER_SIZE + 1 + TAG_SIZE);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(nonce);
+    seal(header, &[outcome.to_byte()], &key.cipher(ANSWER_CONTEXT))
+}
+
+/// Decode an answer encrypted with `key` to the request with `nonce`.
+///
+/// Return `None` if `message` is no such answer, or fails to decrypt.
+fn decode_answer(message: &[u8], nonce: &[u8; NONCE_SIZE], key: &Key) -> Option<Outcome> {
+    let (mut header, plaintext) = open(message, ANSWER_HEADER_SIZE, &key.cipher(ANSWER_CONTEXT))?;
+    if take::<5>(&mut header)? != *MAGIC || take::<NONCE_SIZE>(&mut header)? != *nonce {
+        return None;
+    }
+    match plaintext.as_slice() {
+        [outcome] => Outcome::from_byte(*outcome),
+        _ => None,
+    }
+}
+
+/// `time` in milliseconds since the Unix epoch.
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |duration| {
+        u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+    })
+}
+
+/// Requests which a relay recently saw, to refuse replays.
+#[derive(Debug, Default)]
+struct Seen {
+    /// The timestamp and outcome of every request by nonce.
+    requests: HashMap<[u8; NONCE_SIZE], (u64, Outcome)>,
+}
+
+impl Seen {
+    /// Handle `request` at `now` with `send`, unless it expired or we saw it
+    /// before.
+    ///
+    /// Return the outcome of the request, or of the first request with the
+    /// same nonce.
+    fn handle(
+        &mut self,
+        request: &Request,
+        now: u64,
+        send: impl FnOnce(&Request) -> Result<()>,
+    ) -> Outcome {
+        let max_skew = u64::try_from(MAX_SKEW.as_millis()).unwrap_or(u64::MAX);
+        // Forget requests which expired anyway; they do not get past the
+        // timestamp check below
+        self.requests
+            .retain(|_, (timestamp, _)| now.abs_diff(*timestamp) <= max_skew);
+        if max_skew < now.abs_diff(request.timestamp) {
+            return Outcome::Expired;
+        }
+        if let Some((_, outcome)) = self.requests.get(&request.nonce) {
+            return *outcome;
+        }
+        let outcome = match send(request) {
+            Ok(()) => Outcome::Sent,
+            Err(error) => {
+                tracing::error!(
+                    mac = %request.hardware_address,
+                    "Failed to send magic packet: {error}"
+                );
+                Outcome::Failed
+            }
+        };
+        self.requests
+            .insert(request.nonce, (request.timestamp, outcome));
+        outcome
+    }
+}
+
+/// Wake up `hardware_address` with `secure_on` through the relay at `relay`,
+/// with `key`.
+///
+/// Send the request again if the relay does not answer in time.
+///
+/// # Errors
+///
+/// Return an error if resolving `relay` fails, if the relay does not answer,
+/// or if it failed to send the magic packet, or refused the request.
+pub fn wake(
+    relay: &str,
+    key: &Key,
+    hardware_address: MacAddress,
+    secure_on: Option<SecureOn>,
+) -> Result<()> {
+    let address = relay.to_socket_addrs()?.next().ok_or_else(|| {
+        Error::new(
+            ErrorKind::HostUnreachable,
+            format!("Relay {relay} not reachable"),
+        )
+    })?;
+    let socket = UdpSocket::bind(if address.is_ipv4() {
+        SocketAddr::from(([0; 4], 0))
+    } else {
+        SocketAddr::from(([0; 16], 0))
+    })?;
+    socket.connect(address)?;
+    socket.set_read_timeout(Some(ANSWER_TIMEOUT))?;
+    let request = Request::new(hardware_address, secure_on)?;
+    let message = request.encode(key)?;
+    let mut buffer = [0; MAX_MESSAGE_SIZE];
+    for _ in 0..ATTEMPTS {
+        socket.send(&message)?;
+        loop {
+            match socket.recv(&mut buffer) {
+                Ok(size) => {
+                    let answer = buffer
+                        .get(..size)
+                        .and_then(|answer| decode_answer(answer, &request.nonce, key));
+                    match answer {
+                        Some(Outcome::Sent) => return Ok(()),
+                        Some(Outcome::Failed) => {
+                            return Err(Error::other(format!(
+                                "Relay {relay} failed to send magic packet"
+                            )));
+                        }
+                        Some(Outcome::Expired) => {
+                            return Err(Error::new(
+                                ErrorKind::InvalidInput,
+                                format!(
+                                    "Relay {relay} refused expired request, check the clocks of both systems"
+                                ),
+                            ));
+                        }
+                        // Not an answer to our request; keep waiting
+                        None => {}
+                    }
+                }
+                Err(error)
+                    if matches!(error.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                {
+                    break;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+    Err(Error::new(
+        ErrorKind::TimedOut,
+        format!("Relay {relay} did not answer; check the address, the key, and the firewall"),
+    ))
+}
+
+/// Arguments for relays.
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct RelayArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    #[command(subcommand)]
+    command: RelayCommand,
+}
+
+/// Commands for relays.
+#[derive(Subcommand, Debug, Clone)]
+enum RelayCommand {
+    /// Relay authentic wake requests from wol wake --relay.
+    ///
+    /// Receive encrypted wake requests on PORT, decrypt them
+    /// with the key, check their timestamp and nonce, and send
+    /// magic packets for authentic requests to the broadcast
+    /// address.
+    #[command(verbatim_doc_comment)]
+    Serve(RelayServeArgs),
+}
+
+/// Arguments for `wol relay serve`.
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct RelayServeArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Receive wake requests on PORT.
+    #[arg(
+        long = "port",
+        value_name = "PORT",
+        default_value_t = DEFAULT_PORT,
+        verbatim_doc_comment
+    )]
+    port: u16,
+    /// Read the key to decrypt requests with from the first
+    /// line of FILE.
+    #[arg(
+        long = "key-file",
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    key_file: PathBuf,
+    /// Send magic packets to ADDR.
+    #[arg(
+        long = "broadcast",
+        value_name = "ADDR",
+        default_value = "255.255.255.255:9",
+        verbatim_doc_comment
+    )]
+    broadcast: SocketAddr,
+    #[command(flatten)]
+    sandbox: SandboxArgs,
+}
+
+/// Relay wake requests, until killed.
+fn serve(args: &RelayServeArgs) -> Result<()> {
+    let key = Key::read(&args.key_file)?;
+    let socket = listen::bind(args.port)?;
+    args.sandbox.apply(&Access::default())?;
+    anstream::println!("Relaying on port {} to {}", args.port, args.broadcast);
+    systemd::notify_ready(&format!("Relaying on port {}", args.port));
+    let mut seen = Seen::default();
+    let mut buffer = [0; MAX_MESSAGE_SIZE + 1];
+    loop {
+        let (size, peer) = socket.recv_from(&mut buffer)?;
+        let Some(request) = buffer
+            .get(..size)
+            .and_then(|message| Request::decode(message, &key))
+        else {
+            tracing::warn!(%peer, "Dropping request which fails to decrypt");
+            continue;
+        };
+        let outcome = seen.handle(&request, millis(SystemTime::now()), |request| {
+            wol::send_magic_packet(request.hardware_address, request.secure_on, args.broadcast)
+        });
+        tracing::info!(
+            %peer,
+            mac = %request.hardware_address,
+            %outcome,
+            "Relayed wake request"
+        );
+        let answer = encode_answer(&request.nonce, outcome, &key)?;
+        if let Err(error) = socket.send_to(&answer, peer) {
+            tracing::warn!(%peer, "Failed to answer wake request: {error}");
+        }
+    }
+}
+
+/// Run a relay command.
+///
+/// # Errors
+///
+/// Return an error if the command fails.
+pub fn relay(args: &RelayArgs) -> Result<ExitCode> {
+    match &args.command {
+        RelayCommand::Serve(args) => serve(args).map(|()| ExitCode::SUCCESS),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    fn key(s: &str) -> Key {
+        Key::from_str(s).unwrap()
+    }
+
+    fn request() -> Request {
+        Request::new(
+            MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]),
+            Some(SecureOn::from([1, 2, 3, 4, 5, 6])),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn keys() {
+        assert_eq!(key(" secret\n"), key("secret"));
+        assert_eq!(
+            Key::from_str("  ").unwrap_err().to_string(),
+            "Empty relay key"
+        );
+        assert_eq!(format!("{:?}", key("secret")), "Key(..)");
+    }
+
+    #[test]
+    fn requests() {
+        let request = request();
+        let message = request.encode(&key("secret")).unwrap();
+        assert_eq!(message.len(), MAX_MESSAGE_SIZE);
+        // Keep the hardware address and the SecureON token off the wire
+        for secret in [[0x12, 0x13, 0x14, 0x15, 0x16, 0x17], [1, 2, 3, 4, 5, 6]] {
+            assert!(!message.windows(6).any(|window| window == secret));
+        }
+        assert_eq!(
+            Request::decode(&message, &key("secret")),
+            Some(request.clone())
+        );
+        assert_eq!(Request::decode(&message, &key("other")), None);
+        for i in 0..message.len() {
+            let mut tampered = message.clone();
+            if let Some(byte) = tampered.get_mut(i) {
+                *byte ^= 1;
+            }
+            assert_eq!(Request::decode(&tampered, &key("secret")), None);
+        }
+        assert_eq!(
+            Request::decode(message.get(1..).unwrap_or_default(), &key("secret")),
+            None
+        );
+        assert_eq!(Request::decode(&[], &key("secret")), None);
+
+        let request = Request {
+            secure_on: None,
+            ..request
+        };
+        let message = request.encode(&key("secret")).unwrap();
+        assert_eq!(Request::decode(&message, &key("secret")), Some(request));
+    }
+
+    #[test]
+    fn answers() {
+        let nonce = [7; NONCE_SIZE];
+        let message = encode_answer(&nonce, Outcome::Expired, &key("secret")).unwrap();
+        assert_eq!(
+            decode_answer(&message, &nonce, &key("secret")),
+            Some(Outcome::Expired)
+        );
+        assert_eq!(decode_answer(&message, &nonce, &key("other")), None);
+        // Answers do not pass for requests, nor the other way around
+        assert_eq!(Request::decode(&message, &key("secret")), None);
+        let request = request();
+        let message = request.encode(&key("secret")).unwrap();
+        assert_eq!(
+            decode_answer(&message, &request.nonce, &key("secret")),
+            None
+        );
+        assert_eq!(
+            decode_answer(&message, &[8; NONCE_SIZE], &key("secret")),
+            None
+        );
+    }
+
+    #[test]
+    fn replays() {
+        let request = request();
+        let count = Cell::new(0);
+        let send = |_: &Request| {
+            count.set(count.get() + 1);
+            Ok(())
+        };
+        let mut seen = Seen::default();
+        let now = request.timestamp;
+        assert_eq!(seen.handle(&request, now, send), Outcome::Sent);
+        // Answer replays like the original request, but do not send again
+        assert_eq!(seen.handle(&request, now + 1000, send), Outcome::Sent);
+        assert_eq!(count.get(), 1);
+        // Refuse requests too far off our clock
+        assert_eq!(seen.handle(&request, now + 31_000, send), Outcome::Expired);
+        let old = Request {
+            nonce: [0; NONCE_SIZE],
+            ..request.clone()
+        };
+        assert_eq!(seen.handle(&old, now + 31_000, send), Outcome::Expired);
+        assert_eq!(
+            seen.handle(&old, now - 31_000, |_| Ok(())),
+            Outcome::Expired
+        );
+        assert_eq!(count.get(), 1);
+        assert!(seen.requests.is_empty());
+
+        let failed = Request {
+            nonce: [1; NONCE_SIZE],
+            ..request
+        };
+        assert_eq!(
+            seen.handle(&failed, now, |_| Err(Error::other("Network unreachable"))),
+            Outcome::Failed
+        );
+        assert_eq!(seen.handle(&failed, now, send), Outcome::Failed);
+    }
+
+    #[test]
+    fn relay() {
+        let key = key("secret");
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        let server_key = key.clone();
+        std::thread::spawn(move || {
+            let mut seen = Seen::default();
+            let mut buffer = [0; MAX_MESSAGE_SIZE];
+            let mut drop_first = true;
+            loop {
+                let (size, peer) = socket.recv_from(&mut buffer).unwrap();
+                // Lose the first request, to make the client send it again
+                if drop_first {
+                    drop_first = false;
+                    continue;
+                }
+                let request = Request::decode(buffer.get(..size).unwrap(), &server_key).unwrap();
+                let outcome = seen.handle(&request, millis(SystemTime::now()), |_| Ok(()));
+                let answer = encode_answer(&request.nonce, outcome, &server_key).unwrap();
+                socket.send_to(&answer, peer).unwrap();
+            }
+        });
+        wake(
+            &address.to_string(),
+            &key,
+            MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]),
+            None,
+        )
+        .unwrap();
+    }
+}
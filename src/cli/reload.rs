@@ -0,0 +1,178 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Keep the hosts database of daemons up to date.
+//!
+//! Poll the hosts database for changes, and reload it as soon as it changes,
+//! but only if it is valid; otherwise keep the last valid database, lest a
+//! typo in the middle of an edit takes down a daemon.  Polling the size and
+//! modification time works alike on all systems, and also catches editors
+//! which replace the file instead of writing to it.
+
+use std::io::Result;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+
+use anstyle::{AnsiColor, Style};
+
+use super::hosts::Hosts;
+
+const WARNING: Style = AnsiColor::Yellow.on_default().bold();
+
+/// How often to check the hosts database for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The modification time and size of a file, if it exists.
+type Stamp = Option<(SystemTime, u64)>;
+
+/// The stamp of the file at `path`.
+fn stamp(path: &Path) -> Stamp {
+    let metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Read and check the hosts database at `path`.
+fn read_valid(path: &Path) -> Result<Hosts> {
+    let hosts = Hosts::read(path)?;
+    hosts.check().map_err(|error| {
+        std::io::Error::new(
+            error.kind(),
+            format!("Invalid hosts database {}: {error}", path.display()),
+        )
+    })?;
+    Ok(hosts)
+}
+
+/// The hosts database at a path, as it was when last valid.
+#[derive(Debug)]
+pub struct LiveHosts {
+    path: PathBuf,
+    /// The stamp of the file when we last looked at it, and the last valid
+    /// database.
+    state: RwLock<(Stamp, Arc<Hosts>)>,
+}
+
+impl LiveHosts {
+    /// Load the hosts database at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if the database could not be read or is invalid.
+    pub fn load(path: PathBuf) -> Result<Self> {
+        let stamp = stamp(&path);
+        let hosts = read_valid(&path)?;
+        Ok(Self {
+            path,
+            state: RwLock::new((stamp, Arc::new(hosts))),
+        })
+    }
+
+    /// The path of the hosts database.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The last valid hosts database.
+    pub fn get(&self) -> Arc<Hosts> {
+        self.state
+            .read()
+            .map(|state| Arc::clone(&state.1))
+            .unwrap_or_default()
+    }
+
+    /// Reload the hosts database if it changed since we last looked at it.
+    ///
+    /// Return whether the database changed.  Only look at every change once,
+    /// lest we complain about an invalid database over and over again.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if the database changed, but could not be read or is
+    /// invalid; keep the last valid database in this case.
+    pub fn refresh(&self) -> Result<bool> {
+        let stamp = stamp(&self.path);
+        if let Ok(mut state) = self.state.write() {
+            if state.0 == stamp {
+                return Ok(false);
+            }
+            state.0 = stamp;
+        }
+        self.reload().map(|()| true)
+    }
+
+    /// Reload the hosts database.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if the database could not be read or is invalid; keep
+    /// the last valid database in this case.
+    pub fn reload(&self) -> Result<()> {
+        let hosts = read_valid(&self.path)?;
+        if let Ok(mut state) = self.state.write() {
+            state.1 = Arc::new(hosts);
+        }
+        Ok(())
+    }
+}
+
+/// Reload `hosts` whenever it changes, forever.
+pub fn watch(hosts: &LiveHosts) {
+    loop {
+        std::thread::sleep(POLL_INTERVAL);
+        match hosts.refresh() {
+            Ok(true) => tracing::info!("Reloaded hosts database {}", hosts.path().display()),
+            Ok(false) => {}
+            Err(error) => anstream::eprintln!(
+                "{WARNING}warning{WARNING:#}: Keeping the previous hosts database: {error}"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refresh() {
+        let path = std::env::temp_dir().join(format!("wol-live-hosts-{}.toml", std::process::id()));
+        std::fs::write(&path, "[nas]\nmac = \"12:13:14:15:16:17\"\n").unwrap();
+        let hosts = LiveHosts::load(path.clone()).unwrap();
+        let names = |hosts: &LiveHosts| {
+            hosts
+                .get()
+                .hosts()
+                .unwrap()
+                .into_iter()
+                .filter_map(|host| host.target.name().map(ToOwned::to_owned))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&hosts), ["nas"]);
+        assert!(!hosts.refresh().unwrap());
+
+        std::fs::write(
+            &path,
+            "[nas]\nmac = \"12:13:14:15:16:17\"\n\n[ceo]\nmac = \"12:13:14:15:16:18\"\n",
+        )
+        .unwrap();
+        assert!(hosts.refresh().unwrap());
+        assert_eq!(names(&hosts), ["nas", "ceo"]);
+
+        // Keep the last valid database, and complain only once
+        std::fs::write(&path, "[nas]\nmac = \"not a mac\"\n").unwrap();
+        assert!(hosts.refresh().is_err());
+        assert!(!hosts.refresh().unwrap());
+        assert_eq!(names(&hosts), ["nas", "ceo"]);
+        std::fs::write(&path, "[nas\n").unwrap();
+        assert!(hosts.refresh().is_err());
+        assert_eq!(names(&hosts), ["nas", "ceo"]);
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(hosts.refresh().unwrap());
+        assert!(names(&hosts).is_empty());
+    }
+}
@@ -0,0 +1,642 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Report results of waking up systems.
+
+use std::convert::Infallible;
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, Instant};
+
+use anstyle::{AnsiColor, Style};
+use clap::ValueEnum;
+use indicatif::{ProgressBar, ProgressStyle};
+use serde_json::{Value, json};
+use wol::MacAddress;
+
+use super::command;
+
+/// The format of results.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable text.
+    #[default]
+    Text,
+    /// One JSON object per line for every system, and a summary.
+    Json,
+}
+
+/// The format of errors.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ErrorFormat {
+    /// Human-readable text.
+    #[default]
+    Text,
+    /// One JSON object per line for every error.
+    Json,
+}
+
+/// When to color output.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    /// Color output on terminals, unless `NO_COLOR` is set.
+    #[default]
+    Auto,
+    /// Always color output.
+    Always,
+    /// Never color output.
+    Never,
+}
+
+impl ColorChoice {
+    /// Use this choice for all output.
+    pub fn apply(self) {
+        let choice = match self {
+            ColorChoice::Auto => anstream::ColorChoice::Auto,
+            ColorChoice::Always => anstream::ColorChoice::Always,
+            ColorChoice::Never => anstream::ColorChoice::Never,
+        };
+        choice.write_global();
+    }
+}
+
+/// The style of successes.
+const SUCCESS: Style = AnsiColor::Green.on_default();
+
+/// The style of failures.
+const FAILURE: Style = AnsiColor::Red.on_default();
+
+/// The style of warnings.
+const WARNING: Style = AnsiColor::Yellow.on_default().bold();
+
+/// What failed for a system.
+#[derive(Debug)]
+pub enum Failure {
+    /// Resolving the destination of magic packets failed.
+    Resolve(Error),
+    /// Sending magic packets failed.
+    Send(Error),
+    /// The system did not come online.
+    Online(Error),
+    /// The command to run for the system failed.
+    Command(Error),
+}
+
+impl Failure {
+    /// The result of a system with this failure.
+    pub fn result(&self) -> &'static str {
+        match self {
+            Failure::Resolve(_) | Failure::Send(_) => "wake-failed",
+            Failure::Online(_) => "not-online",
+            Failure::Command(_) => "command-failed",
+        }
+    }
+
+    fn phase(&self) -> &'static str {
+        match self {
+            Failure::Resolve(_) => "resolve",
+            Failure::Send(_) => "send",
+            Failure::Online(_) => "online",
+            Failure::Command(_) => "command",
+        }
+    }
+
+    fn error(&self) -> &Error {
+        match self {
+            Failure::Resolve(error)
+            | Failure::Send(error)
+            | Failure::Online(error)
+            | Failure::Command(error) => error,
+        }
+    }
+}
+
+/// A stable code for the kind of `error`.
+fn error_code(error: &Error) -> &'static str {
+    match error.kind() {
+        ErrorKind::NotFound => "not-found",
+        ErrorKind::PermissionDenied => "permission-denied",
+        ErrorKind::ConnectionRefused => "connection-refused",
+        ErrorKind::HostUnreachable => "host-unreachable",
+        ErrorKind::NetworkUnreachable => "network-unreachable",
+        ErrorKind::AddrInUse => "address-in-use",
+        ErrorKind::AddrNotAvailable => "address-not-available",
+        ErrorKind::NetworkDown => "network-down",
+        ErrorKind::InvalidInput => "invalid-input",
+        ErrorKind::InvalidData => "invalid-data",
+        ErrorKind::Interrupted => "interrupted",
+        ErrorKind::TimedOut => "timed-out",
+        ErrorKind::Unsupported => "unsupported",
+        _ => "other",
+    }
+}
+
+/// A JSON object for `error` of the system with `hardware_address` in `phase`.
+fn error_json(error: &Error, hardware_address: Option<MacAddress>, phase: &str) -> Value {
+    json!({
+        "code": error_code(error),
+        "mac": hardware_address.map(|address| address.to_string()),
+        "phase": phase,
+        "message": error.to_string(),
+    })
+}
+
+/// The result of waking up a single system.
+#[derive(Debug)]
+pub struct TargetResult<'a> {
+    /// The hardware address of the system.
+    pub hardware_address: MacAddress,
+    /// The name of the system.
+    pub name: Option<&'a str>,
+    /// Where magic packets went, or `None` for all interfaces.
+    pub destination: Option<String>,
+    /// The port magic packets went to.
+    pub port: u16,
+    /// How long waking up the system took.
+    pub duration: Duration,
+    /// What failed, if anything.
+    pub failure: Option<Failure>,
+}
+
+impl TargetResult<'_> {
+    /// This result as JSON object.
+    pub fn to_json(&self) -> Value {
+        let duration_ms = u64::try_from(self.duration.as_millis()).unwrap_or(u64::MAX);
+        json!({
+            "mac": self.hardware_address.to_string(),
+            "name": self.name,
+            "destination": self.destination,
+            "port": self.port,
+            "result": self.failure.as_ref().map_or("ok", Failure::result),
+            "error": self.failure.as_ref().map(|failure| failure.error().to_string()),
+            "duration_ms": duration_ms,
+        })
+    }
+}
+
+/// The value of the placeholder `key` in a --format template.
+///
+/// Use the `result` of waking up `hardware_address`, or if `None` assume the
+/// system was skipped.  Return `-` for values a system does not have, to keep
+/// output lines easy to split at whitespace, and `None` for unknown keys.
+fn template_value(
+    hardware_address: MacAddress,
+    result: Option<&TargetResult>,
+    key: &str,
+) -> Option<String> {
+    let value = match (key, result) {
+        ("mac", _) => Some(hardware_address.to_string()),
+        ("status", None) => Some("skipped".to_owned()),
+        ("status", Some(result)) => Some(
+            result
+                .failure
+                .as_ref()
+                .map_or("ok", Failure::result)
+                .to_owned(),
+        ),
+        ("name", result) => result.and_then(|result| result.name).map(str::to_owned),
+        ("dest", result) => result.and_then(|result| result.destination.clone()),
+        ("port", result) => result.map(|result| result.port.to_string()),
+        ("error", result) => result
+            .and_then(|result| result.failure.as_ref())
+            .map(|failure| failure.error().to_string()),
+        ("duration", result) => result.map(|result| result.duration.as_millis().to_string()),
+        _ => return None,
+    };
+    Some(value.unwrap_or_else(|| "-".to_owned()))
+}
+
+/// Collect and print results of waking up systems.
+#[derive(Debug)]
+pub struct Report {
+    format: OutputFormat,
+    quiet: bool,
+    error_format: ErrorFormat,
+    start: Instant,
+    total: usize,
+    failed: Vec<MacAddress>,
+    skipped: Vec<MacAddress>,
+    template: Option<String>,
+    progress: Option<ProgressBar>,
+}
+
+impl Report {
+    /// Create a new report which prints results in `format`.
+    ///
+    /// If `quiet` omit the summary in text format.
+    pub fn new(format: OutputFormat, quiet: bool) -> Self {
+        Self {
+            format,
+            quiet,
+            error_format: ErrorFormat::Text,
+            start: Instant::now(),
+            total: 0,
+            failed: Vec::new(),
+            skipped: Vec::new(),
+            template: None,
+            progress: None,
+        }
+    }
+
+    /// Print errors in `error_format`.
+    #[must_use]
+    pub fn with_error_format(mut self, error_format: ErrorFormat) -> Self {
+        self.error_format = error_format;
+        self
+    }
+
+    /// Print a line from `template` for every system instead, if any.
+    ///
+    /// See [`template_value`] for the placeholders in `template`.
+    #[must_use]
+    pub fn with_template(mut self, template: Option<String>) -> Self {
+        self.template = template;
+        self
+    }
+
+    /// Print the line from `template` for `hardware_address`.
+    fn print_template(
+        &self,
+        template: &str,
+        hardware_address: MacAddress,
+        result: Option<&TargetResult>,
+    ) {
+        let Ok(line) = command::expand(template, &mut |key| {
+            Ok::<_, Infallible>(template_value(hardware_address, result, key))
+        });
+        self.println(&line);
+    }
+
+    /// Show a progress bar for `len` systems instead of a line for each system.
+    #[must_use]
+    pub fn with_progress(mut self, len: usize) -> Self {
+        let style = ProgressStyle::with_template("{bar:40} {pos}/{len} ETA {eta} {msg}")
+            .unwrap_or_else(|_| ProgressStyle::default_bar());
+        self.progress =
+            Some(ProgressBar::new(u64::try_from(len).unwrap_or(u64::MAX)).with_style(style));
+        self
+    }
+
+    /// Print `line`, without disturbing the progress bar.
+    pub fn println(&self, line: &str) {
+        match &self.progress {
+            Some(progress) => progress.suspend(|| anstream::println!("{line}")),
+            None => anstream::println!("{line}"),
+        }
+    }
+
+    /// Print `line` as success.
+    pub fn success(&self, line: &str) {
+        self.println(&format!("{SUCCESS}{line}{SUCCESS:#}"));
+    }
+
+    /// Print `line` to stderr, without disturbing the progress bar.
+    fn eprintln(&self, line: &str) {
+        match &self.progress {
+            Some(progress) => progress.suspend(|| anstream::eprintln!("{line}")),
+            None => anstream::eprintln!("{line}"),
+        }
+    }
+
+    /// Print `line` as failure to stderr.
+    fn failure(&self, line: &str) {
+        self.eprintln(&format!("{FAILURE}{line}{FAILURE:#}"));
+    }
+
+    /// Print `message` as warning to stderr.
+    pub fn warning(&self, message: &str) {
+        self.eprintln(&format!("{WARNING}warning{WARNING:#}: {message}"));
+    }
+
+    /// Print an `error` in `phase` which does not concern a single system.
+    pub fn error(&self, error: &Error, phase: &str) {
+        match self.error_format {
+            ErrorFormat::Text => self.failure(&error.to_string()),
+            ErrorFormat::Json => self.eprintln(&error_json(error, None, phase).to_string()),
+        }
+    }
+
+    /// Print an error about an invalid wakeup file.
+    pub fn invalid_file(&self, error: &Error) {
+        self.error(error, "parse");
+    }
+
+    /// Print the failure of the system with `hardware_address`.
+    fn print_failure(&self, hardware_address: MacAddress, failure: &Failure) {
+        match (self.error_format, failure) {
+            (ErrorFormat::Json, failure) => self.eprintln(
+                &error_json(failure.error(), Some(hardware_address), failure.phase()).to_string(),
+            ),
+            (ErrorFormat::Text, Failure::Resolve(error) | Failure::Send(error)) => {
+                self.failure(&format!("Failed to wake up {hardware_address}: {error}"));
+            }
+            (ErrorFormat::Text, Failure::Online(error)) => {
+                self.failure(&format!("{hardware_address} not online: {error}"));
+            }
+            (ErrorFormat::Text, Failure::Command(error)) => {
+                self.failure(&format!(
+                    "Failed to run command for {hardware_address}: {error}"
+                ));
+            }
+        }
+    }
+
+    /// Announce that waking up `hardware_address` begins.
+    pub fn begin(&self, hardware_address: MacAddress) {
+        match &self.progress {
+            Some(progress) => progress.set_message(format!("Waking up {hardware_address}...")),
+            None => println!("Waking up {hardware_address}..."),
+        }
+    }
+
+    /// Add and print the `result` of waking up a system.
+    pub fn add(&mut self, result: &TargetResult) {
+        self.total += 1;
+        if let Some(failure) = &result.failure {
+            tracing::error!(
+                mac = %result.hardware_address,
+                phase = failure.phase(),
+                code = error_code(failure.error()),
+                "{}",
+                failure.error()
+            );
+            self.failed.push(result.hardware_address);
+        } else {
+            tracing::info!(
+                mac = %result.hardware_address,
+                duration_ms = u64::try_from(result.duration.as_millis()).unwrap_or(u64::MAX),
+                "Woke up"
+            );
+        }
+        match self.format {
+            OutputFormat::Text => {
+                if let Some(template) = &self.template {
+                    self.print_template(template, result.hardware_address, Some(result));
+                }
+                if let Some(failure) = &result.failure {
+                    self.print_failure(result.hardware_address, failure);
+                }
+            }
+            OutputFormat::Json => {
+                self.println(&result.to_json().to_string());
+                if let (ErrorFormat::Json, Some(failure)) = (self.error_format, &result.failure) {
+                    self.print_failure(result.hardware_address, failure);
+                }
+            }
+        }
+        if let Some(progress) = &self.progress {
+            progress.inc(1);
+        }
+    }
+
+    /// Add a system which was skipped.
+    pub fn skip(&mut self, hardware_address: MacAddress) {
+        self.skipped.push(hardware_address);
+        if let Some(template) = &self.template {
+            self.print_template(template, hardware_address, None);
+        } else if self.format == OutputFormat::Json {
+            self.println(
+                &json!({
+                    "mac": hardware_address.to_string(),
+                    "result": "skipped",
+                })
+                .to_string(),
+            );
+        }
+    }
+
+    /// Print that the run stopped early for `reason`.
+    pub fn stopped(&self, reason: &str) {
+        if self.format == OutputFormat::Text {
+            self.failure(reason);
+        }
+    }
+
+    /// The number of all systems.
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// The number of systems which failed.
+    pub fn failed(&self) -> usize {
+        self.failed.len()
+    }
+
+    fn summary_json(&self, elapsed: Duration) -> Value {
+        json!({
+            "summary": {
+                "total": self.total,
+                "succeeded": self.total - self.failed(),
+                "failed": self.failed(),
+                "failed_macs": self.failed.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                "skipped": self.skipped.len(),
+                "skipped_macs": self.skipped.iter().map(ToString::to_string).collect::<Vec<_>>(),
+                "elapsed_ms": u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX),
+            }
+        })
+    }
+
+    fn summary_text(&self, elapsed: Duration) -> String {
+        let summary = format!(
+            "Woke up {} of {} systems in {:.1}s",
+            self.total - self.failed(),
+            self.total + self.skipped.len(),
+            elapsed.as_secs_f64()
+        );
+        std::iter::once(summary)
+            .chain(
+                [("failed", &self.failed), ("skipped", &self.skipped)]
+                    .into_iter()
+                    .filter(|(_, macs)| !macs.is_empty())
+                    .map(|(label, macs)| {
+                        let macs = macs.iter().map(ToString::to_string).collect::<Vec<_>>();
+                        format!("{label}: {}", macs.join(", "))
+                    }),
+            )
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Print a summary of all results.
+    ///
+    /// In text format only print a summary for more than one system.
+    pub fn finish(&self) {
+        if let Some(progress) = &self.progress {
+            progress.finish_and_clear();
+        }
+        let elapsed = self.start.elapsed();
+        match self.format {
+            OutputFormat::Text => {
+                if !self.quiet && 1 < self.total + self.skipped.len() {
+                    let summary = self.summary_text(elapsed);
+                    if self.failed.is_empty() && self.skipped.is_empty() {
+                        self.success(&summary);
+                    } else {
+                        self.println(&format!("{FAILURE}{summary}{FAILURE:#}"));
+                    }
+                }
+            }
+            OutputFormat::Json => self.println(&self.summary_json(elapsed).to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::ErrorKind;
+
+    use super::*;
+
+    #[test]
+    fn target_result_json() {
+        let result = TargetResult {
+            hardware_address: MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]),
+            name: Some("nas"),
+            destination: Some("192.0.2.255".into()),
+            port: 9,
+            duration: Duration::from_millis(42),
+            failure: Some(Failure::Online(Error::new(
+                ErrorKind::TimedOut,
+                "192.0.2.4 not online after 120s",
+            ))),
+        };
+        assert_eq!(
+            result.to_json(),
+            json!({
+                "mac": "12:13:14:15:16:17",
+                "name": "nas",
+                "destination": "192.0.2.255",
+                "port": 9,
+                "result": "not-online",
+                "error": "192.0.2.4 not online after 120s",
+                "duration_ms": 42,
+            })
+        );
+    }
+
+    #[test]
+    fn summary() {
+        let mut report = Report::new(OutputFormat::Text, false);
+        report.total = 3;
+        assert_eq!(
+            report.summary_text(Duration::from_millis(3250)),
+            "Woke up 3 of 3 systems in 3.2s"
+        );
+        report.failed = vec![
+            MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]),
+            MacAddress::from([0xaa, 0xbb, 0xcc, 0xdd, 0xee, 0xff]),
+        ];
+        assert_eq!(
+            report.summary_text(Duration::from_millis(3250)),
+            "Woke up 1 of 3 systems in 3.2s, failed: 12:13:14:15:16:17, AA:BB:CC:DD:EE:FF"
+        );
+        assert_eq!(
+            report.summary_json(Duration::from_millis(3250)),
+            json!({
+                "summary": {
+                    "total": 3,
+                    "succeeded": 1,
+                    "failed": 2,
+                    "failed_macs": ["12:13:14:15:16:17", "AA:BB:CC:DD:EE:FF"],
+                    "skipped": 0,
+                    "skipped_macs": [],
+                    "elapsed_ms": 3250,
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn template_values() {
+        let result = TargetResult {
+            hardware_address: MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]),
+            name: Some("nas"),
+            destination: Some("192.168.1.255".to_owned()),
+            port: 9,
+            duration: Duration::from_millis(12),
+            failure: None,
+        };
+        let value = |key| template_value(result.hardware_address, Some(&result), key);
+        assert_eq!(value("mac").as_deref(), Some("12:13:14:15:16:17"));
+        assert_eq!(value("name").as_deref(), Some("nas"));
+        assert_eq!(value("dest").as_deref(), Some("192.168.1.255"));
+        assert_eq!(value("port").as_deref(), Some("9"));
+        assert_eq!(value("status").as_deref(), Some("ok"));
+        assert_eq!(value("error").as_deref(), Some("-"));
+        assert_eq!(value("duration").as_deref(), Some("12"));
+        assert_eq!(value("foo"), None);
+        assert_eq!(
+            template_value(result.hardware_address, None, "status").as_deref(),
+            Some("skipped")
+        );
+        assert_eq!(
+            template_value(result.hardware_address, None, "port").as_deref(),
+            Some("-")
+        );
+    }
+
+    #[test]
+    fn summary_skipped() {
+        let mut report = Report::new(OutputFormat::Text, false);
+        report.total = 1;
+        report.skipped = vec![MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17])];
+        assert_eq!(
+            report.summary_text(Duration::from_secs(1)),
+            "Woke up 1 of 2 systems in 1.0s, skipped: 12:13:14:15:16:17"
+        );
+    }
+
+    #[test]
+    fn error_json_object() {
+        let error = Error::new(ErrorKind::HostUnreachable, "Host nas not reachable");
+        assert_eq!(
+            error_json(
+                &error,
+                Some(MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17])),
+                "resolve"
+            ),
+            json!({
+                "code": "host-unreachable",
+                "mac": "12:13:14:15:16:17",
+                "phase": "resolve",
+                "message": "Host nas not reachable",
+            })
+        );
+        let error = Error::new(ErrorKind::InvalidData, "Line 1: Field 1: Invalid MAC");
+        assert_eq!(
+            error_json(&error, None, "parse"),
+            json!({
+                "code": "invalid-data",
+                "mac": null,
+                "phase": "parse",
+                "message": "Line 1: Field 1: Invalid MAC",
+            })
+        );
+    }
+
+    #[test]
+    fn target_result_json_ok() {
+        let result = TargetResult {
+            hardware_address: MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]),
+            name: None,
+            destination: None,
+            port: 40000,
+            duration: Duration::from_millis(1),
+            failure: None,
+        };
+        assert_eq!(
+            result.to_json(),
+            json!({
+                "mac": "12:13:14:15:16:17",
+                "name": null,
+                "destination": null,
+                "port": 40000,
+                "result": "ok",
+                "error": null,
+                "duration_ms": 1,
+            })
+        );
+    }
+}
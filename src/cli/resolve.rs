@@ -0,0 +1,122 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Find the hardware address of a system.
+
+use std::io::{Error, ErrorKind, Result};
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::{ArgAction, Args, ValueHint};
+use wol::MacAddress;
+
+use super::dns::Resolver;
+use super::neighbours::{probe, read_neighbours};
+use super::wake::parse_millis;
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct ResolveArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Probe the system if it is not in the neighbour table,
+    /// to make it answer ARP or NDP requests.
+    #[arg(long = "probe", verbatim_doc_comment)]
+    probe: bool,
+    /// With --probe wait MSECS milliseconds for the system to
+    /// answer.
+    #[arg(
+        long = "wait",
+        value_name = "MSECS",
+        default_value = "1000",
+        value_parser = parse_millis,
+        verbatim_doc_comment
+    )]
+    wait: Duration,
+    /// The IP address or hostname of the system.
+    #[arg(value_name = "HOST", value_hint = ValueHint::Hostname)]
+    host: String,
+}
+
+/// Find the hardware addresses of `ips` in `neighbours`.
+///
+/// Return hardware addresses in the order of `ips`, without duplicates.
+fn lookup(ips: &[IpAddr], neighbours: &[(IpAddr, MacAddress)]) -> Vec<MacAddress> {
+    let mut hardware_addresses = Vec::new();
+    for ip in ips {
+        for (_, hardware_address) in neighbours.iter().filter(|(other, _)| other == ip) {
+            if !hardware_addresses.contains(hardware_address) {
+                hardware_addresses.push(*hardware_address);
+            }
+        }
+    }
+    hardware_addresses
+}
+
+/// Print the hardware address of a system.
+///
+/// # Errors
+///
+/// Return an error if resolving the hostname, probing, or reading the
+/// neighbour table fails, or if the neighbour table has no hardware address
+/// for the system.
+pub fn resolve(args: &ResolveArgs) -> Result<()> {
+    let ips = match IpAddr::from_str(&args.host) {
+        Ok(ip) => vec![ip],
+        Err(_) => Resolver::default()
+            .resolve(&args.host, 0)?
+            .into_iter()
+            .map(|socket_addr| socket_addr.ip())
+            .collect(),
+    };
+    let mut hardware_addresses = lookup(&ips, &read_neighbours()?);
+    if hardware_addresses.is_empty() && args.probe {
+        probe(ips.iter().copied())?;
+        std::thread::sleep(args.wait);
+        hardware_addresses = lookup(&ips, &read_neighbours()?);
+    }
+    if hardware_addresses.is_empty() {
+        let hint = if args.probe { "" } else { "; try --probe" };
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "No hardware address for {} in the neighbour table{hint}",
+                args.host
+            ),
+        ));
+    }
+    for hardware_address in hardware_addresses {
+        anstream::println!("{hardware_address}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    #[test]
+    fn lookup_neighbours() {
+        let ipv4 = IpAddr::from(Ipv4Addr::new(192, 168, 1, 23));
+        let ipv6 = IpAddr::from(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 0x23));
+        let nas = MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]);
+        let router = MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x18]);
+        let neighbours = [
+            (IpAddr::from(Ipv4Addr::new(192, 168, 1, 1)), router),
+            (ipv6, nas),
+            (ipv4, nas),
+        ];
+        assert_eq!(lookup(&[ipv4, ipv6], &neighbours), [nas]);
+        assert_eq!(
+            lookup(&[IpAddr::from(Ipv4Addr::new(192, 168, 1, 42))], &neighbours),
+            []
+        );
+    }
+}
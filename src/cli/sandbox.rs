@@ -0,0 +1,471 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Drop privileges of daemons, and confine them.
+//!
+//! After binding their sockets daemons switch to an unprivileged user with
+//! `--user`.  On Linux they also confine themselves, unless disabled with
+//! `--no-sandbox`: Landlock limits file access to system directories and the
+//! files of the daemon, and a seccomp filter permits only the system calls
+//! which our daemons need, to e.g. keep them from running programs, tracing
+//! other processes, or mounting file systems.
+//!
+//! On kernels without Landlock daemons run without restrictions on file
+//! access.
+
+use std::io::Result;
+#[cfg(any(unix, target_os = "linux"))]
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use clap::Args;
+
+/// Options to drop privileges and confine daemons.
+#[derive(Args, Debug, Clone, Default)]
+pub struct SandboxArgs {
+    /// Switch to USER after binding sockets.
+    ///
+    /// Requires root privileges.  Read API tokens, TLS keys,
+    /// and other files before switching, but re-read API tokens
+    /// as USER.
+    #[cfg(all(unix, not(target_vendor = "apple")))]
+    #[arg(long = "user", value_name = "USER", verbatim_doc_comment)]
+    user: Option<String>,
+    /// Do not confine this daemon with Landlock and seccomp.
+    #[cfg(target_os = "linux")]
+    #[arg(long = "no-sandbox", verbatim_doc_comment)]
+    no_sandbox: bool,
+}
+
+impl SandboxArgs {
+    /// Switch to the user given with `--user`, if any, and confine this
+    /// process to `access`, unless disabled.
+    ///
+    /// Call before spawning any thread.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if switching users or confining this process fails.
+    pub fn apply(&self, access: &Access) -> Result<()> {
+        self.switch_user()?;
+        self.confine(access)
+    }
+
+    /// Switch to the user given with `--user`, if any.
+    ///
+    /// Return the home directory of the user, if we switched, for daemons to
+    /// find their state in.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if switching users fails.
+    #[cfg(all(unix, not(target_vendor = "apple")))]
+    pub fn switch_user(&self) -> Result<Option<PathBuf>> {
+        self.user.as_deref().map(switch_user).transpose()
+    }
+
+    /// Switch to the user given with `--user`, if any.
+    ///
+    /// There is no `--user` on this system, so never switch users.
+    #[cfg(not(all(unix, not(target_vendor = "apple"))))]
+    #[allow(clippy::unused_self, clippy::unnecessary_wraps)]
+    pub fn switch_user(&self) -> Result<Option<PathBuf>> {
+        Ok(None)
+    }
+
+    /// Confine this process to `access`, unless disabled.
+    ///
+    /// Call after switching users, and before spawning any thread.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if confining this process fails.
+    #[cfg_attr(
+        not(target_os = "linux"),
+        allow(clippy::unused_self, clippy::unnecessary_wraps, unused_variables)
+    )]
+    pub fn confine(&self, access: &Access) -> Result<()> {
+        #[cfg(target_os = "linux")]
+        if !self.no_sandbox {
+            if !landlock(access)? {
+                tracing::warn!("Landlock not available, not restricting file access");
+            }
+            #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+            seccomp()?;
+        }
+        Ok(())
+    }
+}
+
+/// Files and directories a confined daemon may access, besides system
+/// directories.
+#[derive(Debug, Default)]
+pub struct Access {
+    read: Vec<PathBuf>,
+    write: Vec<PathBuf>,
+}
+
+impl Access {
+    /// Also permit reading `path`, and everything beneath.
+    pub fn read(&mut self, path: &Path) {
+        self.read
+            .push(std::path::absolute(path).unwrap_or_else(|_| path.to_owned()));
+    }
+
+    /// Also permit reading and writing `path`, and creating and removing
+    /// files beneath.
+    pub fn write(&mut self, path: &Path) {
+        self.write
+            .push(std::path::absolute(path).unwrap_or_else(|_| path.to_owned()));
+    }
+}
+
+/// Switch to the user `name`, with its primary group.
+///
+/// Return the home directory of the user.
+///
+/// # Errors
+///
+/// Return an error if there's no such user, or if switching fails.
+#[cfg(all(unix, not(target_vendor = "apple")))]
+fn switch_user(name: &str) -> Result<PathBuf> {
+    use nix::unistd::{User, setgid, setgroups, setuid};
+
+    let fail = |what: &str, error: nix::Error| {
+        let error = Error::from(error);
+        Error::new(
+            error.kind(),
+            format!("Failed to switch to user {name}: {what}: {error}"),
+        )
+    };
+    let user = User::from_name(name)
+        .map_err(|error| fail("getpwnam_r", error))?
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("No user {name}")))?;
+    // Drop supplementary groups first, while we still may
+    setgroups(&[user.gid]).map_err(|error| fail("setgroups", error))?;
+    setgid(user.gid).map_err(|error| fail("setgid", error))?;
+    setuid(user.uid).map_err(|error| fail("setuid", error))?;
+    if !user.uid.is_root() && setuid(nix::unistd::Uid::from_raw(0)).is_ok() {
+        return Err(Error::new(
+            ErrorKind::PermissionDenied,
+            format!("Failed to switch to user {name}: Could regain root privileges"),
+        ));
+    }
+    tracing::info!("Switched to user {name} ({}:{})", user.uid, user.gid);
+    Ok(user.dir)
+}
+
+/// System directories which all daemons may read, for configuration, shared
+/// libraries, and data, e.g. the OUI database.
+#[cfg(target_os = "linux")]
+const SYSTEM_DIRECTORIES: [&str; 8] = [
+    "/etc", "/usr", "/lib", "/lib64", "/proc", "/sys", "/dev", "/run",
+];
+
+/// Restrict file access of this process to system directories and `access`
+/// with Landlock.
+///
+/// Return `false` if the kernel does not support Landlock.  Skip paths which
+/// do not exist.
+///
+/// # Errors
+///
+/// Return an error if Landlock fails.
+#[cfg(target_os = "linux")]
+fn landlock(access: &Access) -> Result<bool> {
+    use landlock::{
+        ABI, Access as _, AccessFs, BitFlags, PathBeneath, PathFd, Ruleset, RulesetAttr,
+        RulesetCreatedAttr, RulesetStatus,
+    };
+
+    let abi = ABI::V1;
+    let fail = |error: landlock::RulesetError| {
+        Error::other(format!(
+            "Failed to restrict file access with Landlock: {error}"
+        ))
+    };
+    let read = AccessFs::ReadFile | AccessFs::ReadDir;
+    let write = read
+        | AccessFs::WriteFile
+        | AccessFs::RemoveDir
+        | AccessFs::RemoveFile
+        | AccessFs::MakeDir
+        | AccessFs::MakeReg;
+    let rules = SYSTEM_DIRECTORIES
+        .iter()
+        .map(|directory| (Path::new(directory), read))
+        .chain(access.read.iter().map(|path| (path.as_path(), read)))
+        .chain(access.write.iter().map(|path| (path.as_path(), write)))
+        .chain([(
+            Path::new("/dev/null"),
+            BitFlags::from(AccessFs::ReadFile) | AccessFs::WriteFile,
+        )]);
+    let mut ruleset = Ruleset::default()
+        .handle_access(AccessFs::from_all(abi))
+        .and_then(Ruleset::create)
+        .map_err(fail)?;
+    for (path, allowed_access) in rules {
+        let Ok(file) = PathFd::new(path) else {
+            continue;
+        };
+        let allowed_access = if std::fs::metadata(path)?.is_dir() {
+            allowed_access
+        } else {
+            allowed_access & AccessFs::from_file(abi)
+        };
+        ruleset = ruleset
+            .add_rule(PathBeneath::new(file, allowed_access))
+            .map_err(fail)?;
+    }
+    let status = ruleset.restrict_self().map_err(fail)?;
+    Ok(status.ruleset != RulesetStatus::NotEnforced)
+}
+
+/// System calls which our daemons need, on all architectures.
+///
+/// Besides reading and writing files and sockets, memory, threads, and
+/// signals, permit creating sockets to send magic packets and talk to the
+/// resolver, the journal, systemd, or D-Bus, and accessing files for the
+/// hosts database, the token file, and logs.
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+const ALLOWED_SYSCALLS: &[libc::c_long] = &[
+    // Files
+    libc::SYS_openat,
+    libc::SYS_close,
+    libc::SYS_read,
+    libc::SYS_readv,
+    libc::SYS_pread64,
+    libc::SYS_write,
+    libc::SYS_writev,
+    libc::SYS_pwrite64,
+    libc::SYS_lseek,
+    libc::SYS_fstat,
+    libc::SYS_newfstatat,
+    libc::SYS_statx,
+    libc::SYS_statfs,
+    libc::SYS_fstatfs,
+    libc::SYS_faccessat,
+    libc::SYS_faccessat2,
+    libc::SYS_readlinkat,
+    libc::SYS_getdents64,
+    libc::SYS_getcwd,
+    libc::SYS_fcntl,
+    libc::SYS_ioctl,
+    libc::SYS_flock,
+    libc::SYS_fsync,
+    libc::SYS_fdatasync,
+    libc::SYS_ftruncate,
+    libc::SYS_mkdirat,
+    libc::SYS_unlinkat,
+    libc::SYS_renameat,
+    libc::SYS_renameat2,
+    libc::SYS_dup,
+    libc::SYS_dup3,
+    libc::SYS_pipe2,
+    libc::SYS_eventfd2,
+    libc::SYS_memfd_create,
+    libc::SYS_inotify_init1,
+    libc::SYS_inotify_add_watch,
+    libc::SYS_inotify_rm_watch,
+    // Sockets
+    libc::SYS_socket,
+    libc::SYS_socketpair,
+    libc::SYS_bind,
+    libc::SYS_listen,
+    libc::SYS_accept,
+    libc::SYS_accept4,
+    libc::SYS_connect,
+    libc::SYS_shutdown,
+    libc::SYS_getsockname,
+    libc::SYS_getpeername,
+    libc::SYS_getsockopt,
+    libc::SYS_setsockopt,
+    libc::SYS_sendto,
+    libc::SYS_sendmsg,
+    libc::SYS_sendmmsg,
+    libc::SYS_recvfrom,
+    libc::SYS_recvmsg,
+    libc::SYS_recvmmsg,
+    // Waiting for events
+    libc::SYS_ppoll,
+    libc::SYS_pselect6,
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_ctl,
+    libc::SYS_epoll_pwait,
+    libc::SYS_epoll_pwait2,
+    libc::SYS_futex,
+    libc::SYS_nanosleep,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_getres,
+    libc::SYS_gettimeofday,
+    libc::SYS_timerfd_create,
+    libc::SYS_timerfd_settime,
+    libc::SYS_sched_yield,
+    libc::SYS_restart_syscall,
+    // Memory
+    libc::SYS_brk,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    // Threads and processes
+    libc::SYS_clone,
+    libc::SYS_clone3,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    libc::SYS_wait4,
+    libc::SYS_set_robust_list,
+    libc::SYS_get_robust_list,
+    libc::SYS_set_tid_address,
+    libc::SYS_rseq,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_getpid,
+    libc::SYS_gettid,
+    libc::SYS_getppid,
+    libc::SYS_getuid,
+    libc::SYS_geteuid,
+    libc::SYS_getgid,
+    libc::SYS_getegid,
+    libc::SYS_getresuid,
+    libc::SYS_getresgid,
+    libc::SYS_getrandom,
+    libc::SYS_getrlimit,
+    libc::SYS_prlimit64,
+    libc::SYS_uname,
+    libc::SYS_prctl,
+    // Signals
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack,
+    libc::SYS_tgkill,
+];
+
+/// Legacy system calls which our daemons need, on architectures which have
+/// them.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+const ALLOWED_LEGACY_SYSCALLS: &[libc::c_long] = &[
+    libc::SYS_open,
+    libc::SYS_stat,
+    libc::SYS_lstat,
+    libc::SYS_access,
+    libc::SYS_readlink,
+    libc::SYS_mkdir,
+    libc::SYS_unlink,
+    libc::SYS_rename,
+    libc::SYS_poll,
+    libc::SYS_select,
+    libc::SYS_epoll_wait,
+    libc::SYS_pipe,
+    libc::SYS_dup2,
+    libc::SYS_arch_prctl,
+    libc::SYS_time,
+];
+
+/// Legacy system calls which our daemons need, on architectures which have
+/// them.
+#[cfg(all(target_os = "linux", target_arch = "aarch64"))]
+const ALLOWED_LEGACY_SYSCALLS: &[libc::c_long] = &[];
+
+/// A seccomp filter which permits [`ALLOWED_SYSCALLS`] and
+/// [`ALLOWED_LEGACY_SYSCALLS`], and refuses all other system calls with
+/// `EPERM`.
+///
+/// # Errors
+///
+/// Return an error if compiling the filter fails.
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+fn seccomp_filter() -> Result<seccompiler::BpfProgram> {
+    use seccompiler::{SeccompAction, SeccompFilter, TargetArch};
+
+    let fail = |error: seccompiler::BackendError| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid seccomp filter: {error}"),
+        )
+    };
+    let arch = if cfg!(target_arch = "x86_64") {
+        TargetArch::x86_64
+    } else {
+        TargetArch::aarch64
+    };
+    let errno =
+        u32::try_from(libc::EPERM).map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+    let rules = ALLOWED_SYSCALLS
+        .iter()
+        .chain(ALLOWED_LEGACY_SYSCALLS)
+        .map(|syscall| (*syscall, Vec::new()))
+        .collect();
+    SeccompFilter::new(
+        rules,
+        SeccompAction::Errno(errno),
+        SeccompAction::Allow,
+        arch,
+    )
+    .and_then(seccompiler::BpfProgram::try_from)
+    .map_err(fail)
+}
+
+/// Permit only system calls which our daemons need with seccomp.
+///
+/// # Errors
+///
+/// Return an error if seccomp fails.
+#[cfg(all(
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+fn seccomp() -> Result<()> {
+    seccompiler::apply_filter_all_threads(&seccomp_filter()?)
+        .map_err(|error| Error::other(format!("Failed to install seccomp filter: {error}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn absolute_paths() {
+        let mut access = Access::default();
+        access.read(Path::new("hosts.toml"));
+        access.write(Path::new("/var/log/wol"));
+        assert_eq!(
+            access.read,
+            [std::env::current_dir().unwrap().join("hosts.toml")]
+        );
+        assert_eq!(access.write, [PathBuf::from("/var/log/wol")]);
+    }
+
+    #[cfg(all(
+        target_os = "linux",
+        any(target_arch = "x86_64", target_arch = "aarch64")
+    ))]
+    #[test]
+    fn filter() {
+        let filter = seccomp_filter().unwrap();
+        assert!(!filter.is_empty());
+        let forbidden = [
+            libc::SYS_execve,
+            libc::SYS_ptrace,
+            libc::SYS_setuid,
+            libc::SYS_mount,
+        ];
+        assert!(
+            forbidden
+                .iter()
+                .all(|syscall| !ALLOWED_SYSCALLS.contains(syscall)
+                    && !ALLOWED_LEGACY_SYSCALLS.contains(syscall))
+        );
+    }
+}
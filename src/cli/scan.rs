@@ -0,0 +1,275 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Discover systems on a subnet.
+
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr};
+use std::time::Duration;
+
+use clap::{ArgAction, Args};
+use wol::MacAddress;
+use wol::file::MagicPacketDestination;
+
+use super::hosts::{Host, HostsArgs};
+use super::neighbours::{probe, read_neighbours};
+use super::oui::{self, Vendors};
+use super::subnet::Ipv4Subnet;
+use super::wake::parse_millis;
+
+/// The smallest prefix length we scan, to avoid flooding large networks.
+const MIN_PREFIX_LEN: u8 = 16;
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct ScanArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Wait MSECS milliseconds for systems to respond.
+    #[arg(
+        long = "wait",
+        value_name = "MSECS",
+        default_value = "2000",
+        value_parser = parse_millis
+    )]
+    wait: Duration,
+    /// Add all responding systems which are not in the hosts
+    /// database to the hosts database.
+    ///
+    /// Name new systems after their IP address, e.g.
+    /// host-192-168-1-23, with their IP address as address.
+    #[arg(long = "save", verbatim_doc_comment)]
+    save: bool,
+    /// The IPv4 subnet to scan, e.g. 192.168.1.0/24.
+    ///
+    /// The prefix length must be at least 16.
+    #[arg(value_name = "SUBNET", verbatim_doc_comment)]
+    subnet: Ipv4Subnet,
+    #[command(flatten)]
+    hosts: HostsArgs,
+}
+
+/// A system found in a subnet.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Row {
+    ip: Ipv4Addr,
+    hardware_address: MacAddress,
+    vendor: String,
+    /// The name of the system in the hosts database.
+    name: Option<String>,
+    /// Whether the system answered.
+    responding: bool,
+}
+
+/// Find all systems in `subnet`.
+///
+/// Report all `neighbours` in `subnet` as responding, and all `hosts` whose
+/// address is in `subnet` but which are not among `neighbours` as silent.
+fn rows(
+    subnet: Ipv4Subnet,
+    neighbours: &[(IpAddr, MacAddress)],
+    hosts: &[Host],
+    vendors: Option<&Vendors>,
+) -> Vec<Row> {
+    let vendor = |hardware_address: MacAddress| {
+        vendors
+            .and_then(|vendors| vendors.get(hardware_address))
+            .unwrap_or("-")
+            .to_owned()
+    };
+    let name = |hardware_address: MacAddress| {
+        hosts
+            .iter()
+            .find(|host| host.target.hardware_address() == hardware_address)
+            .and_then(|host| host.target.name())
+            .map(str::to_owned)
+    };
+    let mut rows: Vec<Row> = Vec::new();
+    for (ip, hardware_address) in neighbours {
+        let IpAddr::V4(ip) = *ip else {
+            continue;
+        };
+        if subnet.contains(ip) && !rows.iter().any(|row| row.ip == ip) {
+            rows.push(Row {
+                ip,
+                hardware_address: *hardware_address,
+                vendor: vendor(*hardware_address),
+                name: name(*hardware_address),
+                responding: true,
+            });
+        }
+    }
+    for host in hosts {
+        let hardware_address = host.target.hardware_address();
+        if let Some(MagicPacketDestination::Ip(IpAddr::V4(ip))) = host.target.address() {
+            if subnet.contains(*ip)
+                && !rows
+                    .iter()
+                    .any(|row| row.ip == *ip || row.hardware_address == hardware_address)
+            {
+                rows.push(Row {
+                    ip: *ip,
+                    hardware_address,
+                    vendor: vendor(hardware_address),
+                    name: host.target.name().map(str::to_owned),
+                    responding: false,
+                });
+            }
+        }
+    }
+    rows.sort_by_key(|row| row.ip);
+    rows
+}
+
+/// The name of a new system at `ip`.
+fn new_name(ip: Ipv4Addr) -> String {
+    format!("host-{}", ip.to_string().replace('.', "-"))
+}
+
+/// Format `rows` as a table with aligned columns.
+fn table(rows: &[Row]) -> Vec<String> {
+    let header = ["IP", "MAC", "VENDOR", "NAME", "STATUS"].map(str::to_owned);
+    let lines = std::iter::once(header)
+        .chain(rows.iter().map(|row| {
+            [
+                row.ip.to_string(),
+                row.hardware_address.to_string(),
+                row.vendor.clone(),
+                row.name.clone().unwrap_or_else(|| "-".to_owned()),
+                if row.responding {
+                    "responding"
+                } else {
+                    "silent"
+                }
+                .to_owned(),
+            ]
+        }))
+        .collect::<Vec<_>>();
+    let mut widths = [0; 5];
+    for cells in &lines {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    lines
+        .iter()
+        .map(|cells| {
+            cells
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_owned()
+        })
+        .collect()
+}
+
+/// Scan a subnet for systems, and print all systems found.
+///
+/// # Errors
+///
+/// Return an error if the subnet is too large, if probing fails, if reading
+/// the neighbour table or the hosts database fails, or if saving new systems
+/// fails.
+pub fn scan(args: &ScanArgs) -> Result<()> {
+    if args.subnet.prefix_len() < MIN_PREFIX_LEN {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "Subnet {} too large, use a prefix length of at least {MIN_PREFIX_LEN}",
+                args.subnet
+            ),
+        ));
+    }
+    let path = args.hosts.path()?;
+    let mut database = args.hosts.read()?;
+    tracing::info!("Probing {}", args.subnet);
+    probe(args.subnet.hosts().map(IpAddr::from))?;
+    std::thread::sleep(args.wait);
+    let neighbours = read_neighbours()
+        .map_err(|error| Error::new(error.kind(), format!("Failed to read neighbours: {error}")))?;
+    let mut rows = rows(
+        args.subnet,
+        &neighbours,
+        &database.hosts()?,
+        oui::load().as_ref(),
+    );
+    if args.save {
+        let mut added = 0;
+        for row in &mut rows {
+            if row.name.is_none() {
+                let name = new_name(row.ip);
+                database.add(
+                    &name,
+                    row.hardware_address,
+                    &[("address", &row.ip.to_string())],
+                )?;
+                row.name = Some(name);
+                added += 1;
+            }
+        }
+        if 0 < added {
+            database.write(&path)?;
+        }
+        anstream::eprintln!("Added {added} systems to {}", path.display());
+    }
+    for line in table(&rows) {
+        anstream::println!("{line}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use wol::file::WakeUpTarget;
+
+    use super::*;
+
+    #[test]
+    fn scan_rows() {
+        let subnet = Ipv4Subnet::from_str("192.168.1.0/24").unwrap();
+        let neighbours = [
+            (
+                IpAddr::from(Ipv4Addr::new(192, 168, 1, 23)),
+                MacAddress::new([0x00, 0x1b, 0x21, 0x15, 0x16, 0x17]),
+            ),
+            (
+                IpAddr::from(Ipv4Addr::new(192, 168, 1, 1)),
+                MacAddress::new([0x00, 0x1b, 0x21, 0x15, 0x16, 0x18]),
+            ),
+            (
+                IpAddr::from(Ipv4Addr::new(10, 0, 0, 1)),
+                MacAddress::new([0x00, 0x1b, 0x21, 0x15, 0x16, 0x19]),
+            ),
+        ];
+        let hosts = [
+            "00:1B:21:15:16:17 name=nas address=192.168.1.10",
+            "00:1B:21:15:16:20 name=desktop address=192.168.1.42",
+            "00:1B:21:15:16:21 name=laptop address=10.0.0.2",
+        ]
+        .map(|line| Host {
+            target: WakeUpTarget::from_str(line).unwrap(),
+            tags: Vec::new(),
+        });
+        let vendors = Vendors::parse("001B21 ACME\n");
+        let rows = rows(subnet, &neighbours, &hosts, Some(&vendors));
+        assert_eq!(
+            table(&rows),
+            [
+                "IP            MAC                VENDOR  NAME     STATUS",
+                "192.168.1.1   00:1B:21:15:16:18  ACME    -        responding",
+                "192.168.1.23  00:1B:21:15:16:17  ACME    nas      responding",
+                "192.168.1.42  00:1B:21:15:16:20  ACME    desktop  silent",
+            ]
+        );
+        assert_eq!(new_name(Ipv4Addr::new(192, 168, 1, 1)), "host-192-168-1-1");
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Weekly schedules to wake up systems, for systemd timers and launchd.
+
+use std::str::FromStr;
+
+/// Abbreviated names of weekdays, from Monday.
+pub const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// A weekly schedule, e.g. `Mon..Fri 07:30`, or `07:30` for every day.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    /// Weekdays, from Monday as 0; empty for every day.
+    weekdays: Vec<u8>,
+    hour: u8,
+    minute: u8,
+}
+
+impl Schedule {
+    /// The weekdays of this schedule, from Monday as 0.
+    ///
+    /// Empty if this schedule applies to every day.
+    pub fn weekdays(&self) -> &[u8] {
+        &self.weekdays
+    }
+
+    /// The hour of this schedule.
+    pub fn hour(&self) -> u8 {
+        self.hour
+    }
+
+    /// The minute of this schedule.
+    pub fn minute(&self) -> u8 {
+        self.minute
+    }
+
+    /// This schedule as calendar event for `OnCalendar=` of systemd timers.
+    pub fn on_calendar(&self) -> String {
+        let time = format!("*-*-* {:02}:{:02}:00", self.hour, self.minute);
+        if self.weekdays.is_empty() {
+            time
+        } else {
+            let weekdays = self
+                .weekdays
+                .iter()
+                .filter_map(|weekday| WEEKDAYS.get(usize::from(*weekday)).copied())
+                .collect::<Vec<_>>();
+            format!("{} {time}", weekdays.join(","))
+        }
+    }
+}
+
+/// Parse the abbreviated name of a weekday.
+fn weekday(name: &str) -> Result<u8, String> {
+    WEEKDAYS
+        .iter()
+        .position(|weekday| weekday.eq_ignore_ascii_case(name.trim()))
+        .and_then(|index| u8::try_from(index).ok())
+        .ok_or_else(|| {
+            format!(
+                "Invalid weekday {name}, expected one of {}",
+                WEEKDAYS.join(", ")
+            )
+        })
+}
+
+impl FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (weekdays, time) = match s.trim().rsplit_once(' ') {
+            Some((weekdays, time)) => (Some(weekdays), time),
+            None => (None, s.trim()),
+        };
+        let (hour, minute) = time
+            .split_once(':')
+            .ok_or_else(|| format!("Expected time HH:MM, got {time}"))?;
+        let hour = u8::from_str(hour)
+            .ok()
+            .filter(|hour| *hour < 24)
+            .ok_or_else(|| format!("Invalid hour {hour}"))?;
+        let minute = u8::from_str(minute)
+            .ok()
+            .filter(|minute| *minute < 60)
+            .ok_or_else(|| format!("Invalid minute {minute}"))?;
+        let mut days = Vec::new();
+        for item in weekdays.iter().flat_map(|weekdays| weekdays.split(',')) {
+            let (first, last) = match item.split_once("..") {
+                Some((first, last)) => (weekday(first)?, weekday(last)?),
+                None => (weekday(item)?, weekday(item)?),
+            };
+            if last < first {
+                return Err(format!("Invalid range of weekdays {item}"));
+            }
+            days.extend(first..=last);
+        }
+        days.sort_unstable();
+        days.dedup();
+        Ok(Self {
+            weekdays: days,
+            hour,
+            minute,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::Schedule;
+
+    #[test]
+    fn parse() {
+        let schedule = Schedule::from_str("7:05").unwrap();
+        assert!(schedule.weekdays().is_empty());
+        assert_eq!((schedule.hour(), schedule.minute()), (7, 5));
+        assert_eq!(schedule.on_calendar(), "*-*-* 07:05:00");
+
+        let schedule = Schedule::from_str("sun,Mon..Wed,tue 23:59").unwrap();
+        assert_eq!(schedule.weekdays(), [0, 1, 2, 6]);
+        assert_eq!(schedule.on_calendar(), "Mon,Tue,Wed,Sun *-*-* 23:59:00");
+    }
+
+    #[test]
+    fn invalid() {
+        for (schedule, error) in [
+            ("7", "Expected time HH:MM, got 7"),
+            ("24:00", "Invalid hour 24"),
+            ("07:60", "Invalid minute 60"),
+            (
+                "Foo 07:30",
+                "Invalid weekday Foo, expected one of Mon, Tue, Wed, Thu, Fri, Sat, Sun",
+            ),
+            ("Fri..Mon 07:30", "Invalid range of weekdays Fri..Mon"),
+        ] {
+            assert_eq!(
+                Schedule::from_str(schedule).unwrap_err(),
+                error,
+                "{schedule}"
+            );
+        }
+    }
+}
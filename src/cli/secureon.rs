@@ -0,0 +1,99 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Generate random SecureON tokens.
+
+use std::io::{Error, Result};
+
+use clap::{ArgAction, Args};
+use wol::file::WakeUpTarget;
+use wol::{MacAddress, SecureOn};
+
+use super::fmt;
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct GenSecureOnArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Generate COUNT tokens.
+    #[arg(
+        short = 'n',
+        long = "count",
+        value_name = "COUNT",
+        default_value = "1",
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with = "hardware_addresses"
+    )]
+    count: u32,
+    /// Generate a token for each MAC, and print a line of a
+    /// wakeup file with MAC and the token.
+    #[arg(value_name = "MAC", verbatim_doc_comment)]
+    hardware_addresses: Vec<MacAddress>,
+}
+
+/// Generate a random SecureON token from the random number generator of the
+/// operating system.
+///
+/// # Errors
+///
+/// Return an error if the operating system has no random numbers for us.
+fn generate() -> Result<SecureOn> {
+    let mut token = [0; 6];
+    getrandom::getrandom(&mut token).map_err(Error::other)?;
+    Ok(SecureOn::new(token))
+}
+
+/// Format a line of a wakeup file to wake up `hardware_address` with
+/// `secure_on`.
+fn wakeup_line(hardware_address: MacAddress, secure_on: SecureOn) -> String {
+    fmt::line(&WakeUpTarget::new(hardware_address).with_secure_on(Some(secure_on)))
+}
+
+/// Generate random SecureON tokens, and print them.
+///
+/// # Errors
+///
+/// Return an error if generating a token fails.
+pub fn gen_secureon(args: &GenSecureOnArgs) -> Result<()> {
+    if args.hardware_addresses.is_empty() {
+        for _ in 0..args.count {
+            anstream::println!("{}", generate()?);
+        }
+    } else {
+        for hardware_address in &args.hardware_addresses {
+            anstream::println!("{}", wakeup_line(*hardware_address, generate()?));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn random_tokens() {
+        let tokens = (0..4).map(|_| generate().unwrap()).collect::<Vec<_>>();
+        for (index, token) in tokens.iter().enumerate() {
+            assert!(!tokens.iter().skip(index + 1).any(|other| other == token));
+        }
+    }
+
+    #[test]
+    fn wakeup_file() {
+        let hardware_address = MacAddress::from_str("12:13:14:15:16:17").unwrap();
+        let secure_on = SecureOn::from_str("cd-23-ae-9d-3f-c2").unwrap();
+        let line = wakeup_line(hardware_address, secure_on);
+        assert_eq!(line, "12:13:14:15:16:17 CD:23:AE:9D:3F:C2");
+        let target = WakeUpTarget::from_str(&line).unwrap();
+        assert_eq!(target.hardware_address(), hardware_address);
+        assert_eq!(target.secure_on(), Some(secure_on));
+    }
+}
@@ -0,0 +1,1818 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Wake up systems over a small HTTP API.
+//!
+//! Serve a few JSON endpoints backed by the hosts database, for home
+//! automation and other tools which speak HTTP but not Wake-on-LAN:
+//!
+//! - `GET /hosts` lists all systems in the hosts database.
+//! - `GET /status` probes all systems in the hosts database.
+//! - `POST /wake/{name|mac}` wakes up systems by name, pattern, hardware
+//!   address, or `@GROUP`.
+//! - `GET /events` streams wake attempts, whether woken up systems came
+//!   online, and received magic packets as server-sent events.
+//! - `GET /metrics` exposes counters of requests, wake attempts, and rate
+//!   limits for Prometheus.
+//! - `GET /openapi.json` describes this API as OpenAPI document.
+//!
+//! With `--web-ui` also serve a single page at `/`, which shows all systems
+//! with their status, and a button to wake up each.
+//!
+//! With API tokens every request needs a token, either as bearer token in
+//! `Authorization`, or in `X-API-Key`.  Tokens with `wake` permission may only
+//! wake up systems; tokens with `admin` permission may do everything.  Tokens
+//! may also be limited to some systems, which they alone may wake up.
+//!
+//! With `--audit-log` append every wake request to an audit log, with the
+//! client, the requested systems, and the outcome.
+//!
+//! Rate limits for each client, each system, and all magic packets keep
+//! misbehaving clients from flooding the network; requests over a limit get
+//! 429 responses.
+//!
+//! Reload the hosts database as soon as it changes, but keep the last valid
+//! database if it becomes invalid.  On `SIGHUP` also re-read the configuration
+//! files and the token file, reopen the audit log, and forget resolved
+//! addresses.
+
+use std::ffi::OsString;
+use std::io::{BufRead, BufReader, Error, ErrorKind, Read, Result, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+#[cfg(unix)]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock, mpsc};
+use std::time::{Duration, Instant, SystemTime};
+
+use anstyle::{AnsiColor, Style};
+use clap::{ArgAction, Args, ValueHint};
+use serde_json::{Value, json};
+use wol::MacAddress;
+use wol::file::{MagicPacketDestination, WakeUpTarget};
+
+use super::argfile;
+use super::audit::{self, AuditLog};
+use super::config;
+#[cfg(unix)]
+use super::control;
+#[cfg(feature = "dbus")]
+use super::dbus;
+use super::dns::Resolver;
+use super::events::{self, Event, Events};
+use super::history::{self, WakeLogArgs};
+use super::hosts::{Host, HostsArgs};
+use super::interrupt::Interrupt;
+use super::launchd;
+use super::listen;
+use super::metrics::{Counter, Metrics};
+use super::online::{self, Probe};
+use super::ratelimit::RateLimit;
+use super::reload::{self, LiveHosts};
+use super::report::{Failure, TargetResult};
+use super::sandbox::{Access, SandboxArgs};
+use super::status::{self, Status};
+use super::systemd;
+use super::target::Selector;
+#[cfg(feature = "tls")]
+use super::tls;
+use super::tokens::{Grant, Permission, Tokens};
+use super::wake::parse_secs;
+#[cfg(all(windows, feature = "windows-service"))]
+use super::windows;
+
+/// The style of warnings.
+const WARNING: Style = AnsiColor::Yellow.on_default().bold();
+
+/// The description of services which run the HTTP API.
+const DESCRIPTION: &str = "Wake-on-LAN HTTP API";
+
+/// The label of launch daemons which run the HTTP API.
+const LAUNCHD_LABEL: &str = "de.swsnr.wol.serve";
+
+/// The OpenAPI document of the HTTP API, without version.
+const OPENAPI: &str = include_str!("openapi.json");
+
+/// The page of the web UI.
+const WEB_UI: &str = include_str!("serve.html");
+
+/// The maximum size of the request line and headers of a request.
+const MAX_REQUEST_SIZE: u64 = 16 * 1024;
+
+/// How long to wait for a client to send a request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The port to send magic packets to for systems without port.
+const DEFAULT_PORT: u16 = 9;
+
+/// How long to wait for events before sending a keep-alive comment.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Parse the arguments of `wol serve` again, with the current configuration.
+pub type Reread = fn() -> Result<ServeArgs>;
+
+/// How often to check for `SIGHUP`.
+#[cfg(unix)]
+const HANGUP_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Command line flags are naturally bools
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct ServeArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Listen for HTTP requests on ADDRESS.
+    ///
+    /// Use 0.0.0.0:8080 to serve other systems on the network.
+    /// Ignored if systemd passes stream sockets to listen on.
+    #[arg(
+        short = 'l',
+        long = "listen",
+        value_name = "ADDRESS",
+        default_value = "127.0.0.1:8080",
+        verbatim_doc_comment
+    )]
+    listen: SocketAddr,
+    /// Check whether systems are online with PROBE for
+    /// GET /status and --wait-online.
+    ///
+    /// PROBE is either ping to send ICMP echo requests, or
+    /// tcp:PORT to connect to PORT, e.g. tcp:22 for SSH.
+    #[arg(
+        long = "probe",
+        value_name = "PROBE",
+        default_value = "ping",
+        verbatim_doc_comment
+    )]
+    probe: Probe,
+    /// Require API tokens from FILE.
+    ///
+    /// Each line has a token and its permission, separated by
+    /// whitespace: wake to only wake up systems, or admin for
+    /// all requests.  Clients send the token as bearer token in
+    /// the Authorization header, or in the X-API-Key header.
+    ///
+    /// Optionally name a token with name=NAME after its
+    /// permission, to tell clients apart in the audit log, and
+    /// list the systems a token may wake up: names, patterns,
+    /// hardware addresses, or @GROUP.  Such a token may not wake
+    /// up any other system.
+    #[arg(
+        long = "token-file",
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    token_file: Option<PathBuf>,
+    /// Serve a web page at / which lists all systems with their
+    /// status, and wakes them up at the push of a button.
+    #[arg(long = "web-ui", verbatim_doc_comment)]
+    web_ui: bool,
+    /// Append every wake request to the audit log FILE.
+    ///
+    /// Write one JSON object per line, with the time, the
+    /// address of the client, the name of its token, the
+    /// requested systems, the status of the response, and the
+    /// results for all systems, or the error.
+    #[arg(
+        long = "audit-log",
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    audit_log: Option<PathBuf>,
+    /// Send every wake request to the systemd journal instead,
+    /// with the fields of --audit-log as structured fields.
+    #[cfg(feature = "journald")]
+    #[arg(
+        long = "audit-journald",
+        conflicts_with = "audit_log",
+        verbatim_doc_comment
+    )]
+    audit_journald: bool,
+    /// Wait until woken up systems are online.
+    ///
+    /// After waking up a system, probe it with --probe until it
+    /// responds, and tell clients of GET /events whether it came
+    /// online within the timeout given by --online-timeout.
+    #[arg(long = "wait-online", verbatim_doc_comment)]
+    wait_online: bool,
+    /// Wait at most SECS seconds for systems to come online.
+    #[arg(
+        long = "online-timeout",
+        value_name = "SECS",
+        default_value = "120",
+        value_parser = parse_secs,
+        requires = "wait_online",
+        verbatim_doc_comment
+    )]
+    online_timeout: Duration,
+    /// Listen for magic packets on UDP PORT, and tell clients of
+    /// GET /events about every received packet.
+    ///
+    /// Repeat to listen on multiple ports.  Also listen on all
+    /// datagram sockets which systemd passes.
+    #[arg(
+        long = "watch-port",
+        value_name = "PORT",
+        action = ArgAction::Append,
+        verbatim_doc_comment
+    )]
+    watch_ports: Vec<u16>,
+    /// Allow each client at most N requests per minute.
+    ///
+    /// Use 0 for no limit.
+    #[arg(
+        long = "client-rate",
+        value_name = "N",
+        default_value = "60",
+        verbatim_doc_comment
+    )]
+    client_rate: u32,
+    /// Send at most N magic packets per minute to each system.
+    ///
+    /// Use 0 for no limit.
+    #[arg(
+        long = "host-rate",
+        value_name = "N",
+        default_value = "10",
+        verbatim_doc_comment
+    )]
+    host_rate: u32,
+    /// Send at most N magic packets per minute in total.
+    ///
+    /// Use 0 for no limit.
+    #[arg(
+        long = "global-rate",
+        value_name = "N",
+        default_value = "120",
+        verbatim_doc_comment
+    )]
+    global_rate: u32,
+    /// Accept commands from wol ctl on the Unix socket PATH.
+    ///
+    /// Only the owner of the socket may send commands.
+    #[cfg(unix)]
+    #[arg(
+        long = "control-socket",
+        value_name = "PATH",
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    control_socket: Option<PathBuf>,
+    /// Also serve org.swsnr.wol on the D-Bus BUS.
+    ///
+    /// Everyone whom the bus permits to talk to org.swsnr.wol
+    /// may wake up systems, without API tokens.
+    #[cfg(feature = "dbus")]
+    #[arg(long = "dbus", value_name = "BUS", verbatim_doc_comment)]
+    dbus: Option<dbus::Bus>,
+    /// Print a systemd service unit which runs wol serve with
+    /// all other arguments, and exit.
+    ///
+    /// The unit runs in the current directory, and uses the
+    /// current hosts database.
+    #[arg(long = "print-systemd-unit", verbatim_doc_comment)]
+    print_systemd_unit: bool,
+    /// Print a launchd property list for a launch daemon which
+    /// runs wol serve with all other arguments, and exit.
+    ///
+    /// The daemon runs in the current directory, and uses the
+    /// current hosts database.
+    #[arg(
+        long = "print-launchd-plist",
+        conflicts_with = "print_systemd_unit",
+        verbatim_doc_comment
+    )]
+    print_launchd_plist: bool,
+    /// Install a Windows service which runs wol serve with all
+    /// other arguments at boot, start it, and exit.
+    ///
+    /// The service uses the current hosts database.  Give all
+    /// other files with absolute paths, as the service runs in
+    /// the system directory.
+    #[cfg(all(windows, feature = "windows-service"))]
+    #[arg(
+        long = "install-service",
+        conflicts_with = "run_as_service",
+        verbatim_doc_comment
+    )]
+    install_service: bool,
+    /// Run under the Windows service control manager.
+    ///
+    /// The service which --install-service installs uses this flag.
+    #[cfg(all(windows, feature = "windows-service"))]
+    #[arg(long = "run-as-service", verbatim_doc_comment)]
+    run_as_service: bool,
+    /// Serve over TLS with the certificate chain in the PEM
+    /// file CERT.
+    #[cfg(feature = "tls")]
+    #[arg(
+        long = "tls-cert",
+        value_name = "CERT",
+        requires = "tls_key",
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    tls_cert: Option<PathBuf>,
+    /// The private key for --tls-cert, as PEM file.
+    #[cfg(feature = "tls")]
+    #[arg(
+        long = "tls-key",
+        value_name = "KEY",
+        requires = "tls_cert",
+        value_hint = ValueHint::FilePath
+    )]
+    tls_key: Option<PathBuf>,
+    /// Require clients to present a certificate issued by a
+    /// certificate authority in the PEM file CA.
+    #[cfg(feature = "tls")]
+    #[arg(
+        long = "tls-client-ca",
+        value_name = "CA",
+        requires = "tls_cert",
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    tls_client_ca: Option<PathBuf>,
+    #[command(flatten)]
+    sandbox: SandboxArgs,
+    #[command(flatten)]
+    hosts: HostsArgs,
+    #[command(flatten)]
+    wake_log: WakeLogArgs,
+}
+
+/// An HTTP request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Request {
+    method: String,
+    path: String,
+    /// All headers, with lowercase names.
+    headers: Vec<(String, String)>,
+}
+
+/// Read a request from `reader`.
+///
+/// Read the request line and all headers, and skip the body.
+///
+/// # Errors
+///
+/// Return an error if reading fails, or if the request is malformed.
+fn read_request(reader: impl BufRead) -> Result<Request> {
+    let invalid = |message: &str| Error::new(ErrorKind::InvalidData, message.to_owned());
+    let mut reader = reader.take(MAX_REQUEST_SIZE);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(invalid("Malformed request line"));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(invalid("Unsupported HTTP version"));
+    }
+    let mut request = Request {
+        method: method.to_owned(),
+        path: path.to_owned(),
+        headers: Vec::new(),
+    };
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(invalid("Incomplete request"));
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header
+            .split_once(':')
+            .ok_or_else(|| invalid("Malformed header"))?;
+        request
+            .headers
+            .push((name.trim().to_ascii_lowercase(), value.trim().to_owned()));
+    }
+    let length = request
+        .header("content-length")
+        .map(u64::from_str)
+        .transpose()
+        .map_err(|_| invalid("Invalid Content-Length"))?
+        .unwrap_or(0);
+    std::io::copy(&mut reader.into_inner().take(length), &mut std::io::sink())?;
+    Ok(request)
+}
+
+impl Request {
+    /// The value of the header `name`, in lowercase.
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The API token of this request, if any.
+    ///
+    /// Take a bearer token from `Authorization`, or else a token from
+    /// `X-API-Key`.
+    fn token(&self) -> Option<&str> {
+        self.header("authorization")
+            .and_then(|value| value.split_once(' '))
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+            .map(|(_, token)| token.trim())
+            .or_else(|| self.header("x-api-key"))
+    }
+
+    /// The permission this request needs, or `None` if anyone may make it.
+    fn permission(&self) -> Option<Permission> {
+        if self.method == "GET" && (self.path == "/" || self.path == "/openapi.json") {
+            // The web UI asks for a token by itself
+            None
+        } else if self.method == "POST" && self.path.starts_with("/wake/") {
+            Some(Permission::Wake)
+        } else {
+            Some(Permission::Admin)
+        }
+    }
+}
+
+/// Decode percent-encoded bytes in `s`.
+///
+/// Return `None` if `s` has invalid escapes, or does not decode to UTF-8.
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let (hex, tail) = tail.split_at_checked(2)?;
+            bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            rest = tail;
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// The body of a response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Body {
+    Json(Value),
+    Html(&'static str),
+    /// Metrics in the Prometheus text format.
+    Metrics(String),
+    /// A stream of server-sent events, without end.
+    Events,
+}
+
+/// An HTTP response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Response {
+    status: u16,
+    body: Body,
+    /// How long to wait before retrying, for 429 responses.
+    retry_after: Option<Duration>,
+}
+
+impl Response {
+    /// A response with `status` and `body`.
+    fn new(status: u16, body: Body) -> Self {
+        Self {
+            status,
+            body,
+            retry_after: None,
+        }
+    }
+
+    /// A successful response with `body`.
+    fn ok(body: Value) -> Self {
+        Self::new(200, Body::Json(body))
+    }
+
+    /// An error response with `status` and `message`.
+    fn error(status: u16, message: &str) -> Self {
+        Self::new(status, Body::Json(json!({ "error": message })))
+    }
+
+    /// A 429 response for exceeding `limit`, to retry after `retry_after`.
+    fn too_many_requests(limit: &str, retry_after: Duration) -> Self {
+        Self {
+            retry_after: Some(retry_after),
+            ..Self::error(429, &format!("Too many requests, exceeded {limit}"))
+        }
+    }
+
+    /// The reason phrase of the status of this response.
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            401 => "Unauthorized",
+            403 => "Forbidden",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            429 => "Too Many Requests",
+            _ => "Internal Server Error",
+        }
+    }
+
+    /// Write this response to `writer`.
+    fn write_to(&self, mut writer: impl Write) -> Result<()> {
+        let (content_type, body) = match &self.body {
+            Body::Json(value) => ("application/json", value.to_string()),
+            Body::Html(page) => ("text/html; charset=utf-8", (*page).to_owned()),
+            Body::Metrics(metrics) => ("text/plain; version=0.0.4", metrics.clone()),
+            Body::Events => {
+                // Events follow until the connection closes
+                return writer.write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+                );
+            }
+        };
+        let authenticate = if self.status == 401 {
+            "WWW-Authenticate: Bearer\r\n"
+        } else {
+            ""
+        };
+        // Round up, lest clients retry too early
+        let retry_after = self.retry_after.map_or_else(String::new, |retry_after| {
+            let seconds = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            format!("Retry-After: {seconds}\r\n")
+        });
+        let response = format!(
+            "HTTP/1.1 {} {}\r\n{authenticate}{retry_after}Content-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            self.status,
+            self.reason(),
+            body.len()
+        );
+        writer.write_all(response.as_bytes())
+    }
+}
+
+impl From<&Error> for Response {
+    fn from(error: &Error) -> Self {
+        let status = match error.kind() {
+            ErrorKind::NotFound => 404,
+            ErrorKind::InvalidInput => 400,
+            ErrorKind::PermissionDenied => 403,
+            _ => 500,
+        };
+        Self::error(status, &error.to_string())
+    }
+}
+
+/// An operation of the HTTP API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operation {
+    Hosts,
+    Status,
+    Events,
+    Metrics,
+    Wake,
+}
+
+/// All operations of the HTTP API, by method and path as in the OpenAPI
+/// document.
+///
+/// `{name}` at the end of a path matches the rest of the requested path.
+const OPERATIONS: [(&str, &str, Operation); 5] = [
+    ("GET", "/hosts", Operation::Hosts),
+    ("GET", "/status", Operation::Status),
+    ("GET", "/events", Operation::Events),
+    ("GET", "/metrics", Operation::Metrics),
+    ("POST", "/wake/{name}", Operation::Wake),
+];
+
+/// Match `path` against the path of an operation.
+///
+/// Return the value of `{name}` if `template` has one, or an empty string
+/// otherwise, or `None` if `path` does not match `template`.
+fn match_path<'a>(template: &str, path: &'a str) -> Option<&'a str> {
+    match template.strip_suffix("{name}") {
+        Some(prefix) => path.strip_prefix(prefix),
+        None => Some("").filter(|_| template == path),
+    }
+}
+
+/// The OpenAPI document of the HTTP API.
+fn openapi() -> Result<Value> {
+    let mut document = serde_json::from_str::<Value>(OPENAPI)?;
+    if let Some(info) = document.get_mut("info").and_then(Value::as_object_mut) {
+        info.insert("version".to_owned(), env!("CARGO_PKG_VERSION").into());
+    }
+    Ok(document)
+}
+
+/// A system in the hosts database as JSON object.
+fn host_json(host: &Host) -> Value {
+    json!({
+        "name": host.target.name(),
+        "mac": host.target.hardware_address().to_string(),
+        "destination": host.target.packet_destination().map(ToString::to_string),
+        "port": host.target.port(),
+        "tags": host.tags,
+    })
+}
+
+/// The status of `target` as JSON object.
+fn status_json(target: &WakeUpTarget, status: &Status) -> Value {
+    let (state, reason) = match status {
+        Status::Up => ("up", None),
+        Status::Down => ("down", None),
+        Status::Unknown(reason) => ("unknown", Some(reason)),
+    };
+    json!({
+        "name": target.name(),
+        "mac": target.hardware_address().to_string(),
+        "status": state,
+        "reason": reason,
+    })
+}
+
+/// Open the audit log for `args`, if any.
+///
+/// # Errors
+///
+/// Return an error if opening the audit log fails.
+fn audit_log(args: &ServeArgs) -> Result<Option<AuditLog>> {
+    #[cfg(feature = "journald")]
+    if args.audit_journald {
+        return AuditLog::journald().map(Some);
+    }
+    args.audit_log.as_deref().map(AuditLog::open).transpose()
+}
+
+/// Serve requests for the hosts database.
+#[derive(Debug)]
+struct Server {
+    /// The last valid hosts database.
+    hosts: LiveHosts,
+    probe: Probe,
+    resolver: Resolver,
+    /// The tokens to require, if any.
+    tokens: RwLock<Option<Tokens>>,
+    /// Whether to serve the web UI.
+    web_ui: bool,
+    /// How long to wait for woken up systems to come online, if at all.
+    wait_online: Option<Duration>,
+    /// When this server started.
+    started: Instant,
+    /// Events for clients of `GET /events`.
+    events: Arc<Events>,
+    /// Counters for `GET /metrics`.
+    metrics: Metrics,
+    /// The audit log of wake requests, if any.
+    audit: RwLock<Option<AuditLog>>,
+    /// The state directory for the wake-up history, if any.
+    state_dir: Option<PathBuf>,
+    /// The audit log of wake attempts, if any.
+    wake_log: Option<PathBuf>,
+    /// Requests per client.
+    client_limit: RateLimit<IpAddr>,
+    /// Magic packets per system.
+    host_limit: RateLimit<MacAddress>,
+    /// Magic packets in total.
+    global_limit: RateLimit<()>,
+    /// The TLS configuration, if serving over TLS.
+    #[cfg(feature = "tls")]
+    tls: Option<std::sync::Arc<rustls::ServerConfig>>,
+}
+
+impl Server {
+    /// Create a server for `args`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if reading the tokens, or the TLS certificate and key
+    /// fails.
+    fn new(args: &ServeArgs) -> Result<Self> {
+        Ok(Self {
+            hosts: LiveHosts::load(args.hosts.path()?)?,
+            probe: args.probe,
+            resolver: Resolver::default(),
+            tokens: RwLock::new(args.token_file.as_deref().map(Tokens::read).transpose()?),
+            web_ui: args.web_ui,
+            wait_online: args.wait_online.then_some(args.online_timeout),
+            started: Instant::now(),
+            events: Arc::default(),
+            metrics: Metrics::default(),
+            audit: RwLock::new(audit_log(args)?),
+            state_dir: history::state_dir(),
+            wake_log: args.wake_log.path(history::state_dir().as_deref()),
+            client_limit: RateLimit::per_minute(args.client_rate),
+            host_limit: RateLimit::per_minute(args.host_rate),
+            global_limit: RateLimit::per_minute(args.global_rate),
+            #[cfg(feature = "tls")]
+            tls: match (&args.tls_cert, &args.tls_key) {
+                (Some(certificate), Some(key)) => Some(tls::server_config(
+                    certificate,
+                    key,
+                    args.tls_client_ca.as_deref(),
+                )?),
+                _ => None,
+            },
+        })
+    }
+
+    /// The URL scheme of this server.
+    #[cfg_attr(not(feature = "tls"), allow(clippy::unused_self))]
+    fn scheme(&self) -> &'static str {
+        #[cfg(feature = "tls")]
+        if self.tls.is_some() {
+            return "https";
+        }
+        "http"
+    }
+
+    /// Reload the hosts database, re-read the token file and reopen the audit
+    /// log for `args`, and forget all resolved addresses.
+    ///
+    /// Keep the current hosts database, tokens and audit log if reading any
+    /// fails; requests in flight finish with whatever they started with.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if reading the hosts database or the tokens, or
+    /// opening the audit log fails.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    fn reload(&self, args: &ServeArgs) -> Result<()> {
+        let tokens = args.token_file.as_deref().map(Tokens::read).transpose()?;
+        let audit = audit_log(args)?;
+        self.hosts.reload()?;
+        if let Ok(mut current) = self.tokens.write() {
+            *current = tokens;
+        }
+        if let Ok(mut current) = self.audit.write() {
+            *current = audit;
+        }
+        self.resolver.forget();
+        Ok(())
+    }
+
+    /// Whether this server requires API tokens.
+    fn has_tokens(&self) -> bool {
+        self.tokens.read().is_ok_and(|tokens| tokens.is_some())
+    }
+
+    /// What the token of `request` grants, if any.
+    fn grant(&self, request: &Request) -> Option<Grant> {
+        let tokens = self.tokens.read().ok()?;
+        request
+            .token()
+            .and_then(|token| tokens.as_ref()?.grant(token))
+            .cloned()
+    }
+
+    /// Check whether `request` has a token with the permission it needs.
+    ///
+    /// Return what the token grants, or `None` if the request needs no token,
+    /// or an error response if the token is missing or lacks permission.
+    fn authorize(&self, request: &Request) -> std::result::Result<Option<Grant>, Response> {
+        let Some(required) = request.permission().filter(|_| self.has_tokens()) else {
+            return Ok(None);
+        };
+        match self.grant(request) {
+            None => Err(Response::error(401, "Missing or invalid API token")),
+            Some(grant) if grant.permission < required => Err(Response::error(
+                403,
+                &format!("API token lacks {required} permission"),
+            )),
+            Some(grant) => Ok(Some(grant)),
+        }
+    }
+
+    /// `GET /hosts`: list all systems.
+    fn hosts(&self) -> Result<Response> {
+        let hosts = self.hosts.get().hosts()?;
+        Ok(Response::ok(hosts.iter().map(host_json).collect()))
+    }
+
+    /// `GET /status`: probe all systems.
+    fn status(&self) -> Result<Response> {
+        let targets = self.hosts.get().targets()?;
+        let statuses = status::check_all(&targets, self.probe, &self.resolver);
+        Ok(Response::ok(
+            targets
+                .iter()
+                .zip(&statuses)
+                .map(|(target, status)| status_json(target, status))
+                .collect(),
+        ))
+    }
+
+    /// The systems to wake up for `name` in the path of a request.
+    ///
+    /// Wake up a hardware address which is not in the hosts database with the
+    /// default broadcast address.
+    fn targets(&self, name: &str) -> Result<Vec<WakeUpTarget>> {
+        let hosts = self.hosts.get();
+        if let Some(group) = name.strip_prefix('@') {
+            return hosts.group(group);
+        }
+        let selector =
+            Selector::from_str(name).map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+        let targets = hosts
+            .targets()?
+            .into_iter()
+            .filter(|target| selector.matches(target))
+            .collect::<Vec<_>>();
+        match selector {
+            _ if !targets.is_empty() => Ok(targets),
+            Selector::HardwareAddress(hardware_address) => {
+                Ok(vec![WakeUpTarget::new(hardware_address)])
+            }
+            _ => Err(Error::new(
+                ErrorKind::NotFound,
+                format!("No system matches {selector}"),
+            )),
+        }
+    }
+
+    /// Send a magic packet to `target` at `destination` and `port`.
+    fn send(
+        &self,
+        target: &WakeUpTarget,
+        destination: &MagicPacketDestination,
+        port: u16,
+    ) -> std::result::Result<(), Failure> {
+        let secure_on = match target.keyring_secure_on() {
+            Some(name) => Some(super::wake::secure_on_from_keyring(name).map_err(Failure::Send)?),
+            None => target.secure_on(),
+        };
+        let mut socket_addr =
+            status::resolve(destination, &self.resolver).map_err(Failure::Resolve)?;
+        socket_addr.set_port(port);
+        wol::send_magic_packet(target.hardware_address(), secure_on, socket_addr)
+            .map_err(Failure::Send)
+    }
+
+    /// Wake up `target`, and record the attempt in the audit log.
+    ///
+    /// Return the result as JSON object, and whether waking up succeeded.
+    fn wake_target(&self, target: &WakeUpTarget) -> (Value, bool) {
+        let start = Instant::now();
+        let destination = target
+            .packet_destination()
+            .cloned()
+            .unwrap_or(MagicPacketDestination::Ip(IpAddr::V4(Ipv4Addr::BROADCAST)));
+        let port = target.port().unwrap_or(DEFAULT_PORT);
+        let failure = self.send(target, &destination, port).err();
+        let result = TargetResult {
+            hardware_address: target.hardware_address(),
+            name: target.name(),
+            destination: Some(destination.to_string()),
+            port,
+            duration: start.elapsed(),
+            failure,
+        };
+        // Like for wol wake, history and audit log are no reason to fail
+        if let Some(state_dir) = self.state_dir.as_ref().filter(|_| result.failure.is_none()) {
+            if let Err(error) = history::record_now(state_dir, &[target.hardware_address()]) {
+                tracing::warn!("Failed to record wake-up history: {error}");
+            }
+        }
+        if let Some(wake_log) = &self.wake_log {
+            if let Err(error) = history::audit_now(wake_log, &result) {
+                tracing::warn!("Failed to record wake attempt in audit log: {error}");
+            }
+        }
+        self.metrics.increment(
+            Counter::WakeAttempts,
+            result.failure.as_ref().map_or("ok", Failure::result),
+        );
+        let json = result.to_json();
+        self.events.publish(&Event::new("wake", json.clone()));
+        if let (None, Some(timeout)) = (&result.failure, self.wait_online) {
+            self.verify(target, port, timeout);
+        }
+        (json, result.failure.is_none())
+    }
+
+    /// Wait in the background until `target` is online, for at most
+    /// `timeout`, and publish the outcome as `online` event.
+    ///
+    /// `port` is the port magic packets went to.
+    fn verify(&self, target: &WakeUpTarget, port: u16, timeout: Duration) {
+        let address = status::probe_address(target).cloned();
+        let socket_addr = address
+            .as_ref()
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "No address to probe"))
+            .and_then(|address| status::resolve(address, &self.resolver));
+        let hardware_address = target.hardware_address();
+        let name = target.name().map(ToOwned::to_owned);
+        let probe = self.probe;
+        let events = Arc::clone(&self.events);
+        std::thread::spawn(move || {
+            let start = Instant::now();
+            let failure = socket_addr
+                .and_then(|socket_addr| online::wait_online(probe, socket_addr, timeout, || false))
+                .err()
+                .map(Failure::Online);
+            let result = TargetResult {
+                hardware_address,
+                name: name.as_deref(),
+                destination: address.map(|address| address.to_string()),
+                port,
+                duration: start.elapsed(),
+                failure,
+            };
+            events.publish(&Event::new("online", result.to_json()));
+        });
+    }
+
+    /// `POST /wake/{name}`: wake up all systems for `name`.
+    ///
+    /// With `grant` refuse to wake up any system if the grant does not permit
+    /// waking up all systems.  Respond with 500 if waking up any system
+    /// failed.
+    fn wake(&self, name: &str, grant: Option<&Grant>) -> Result<Response> {
+        let name = percent_decode(name)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, "Invalid escape in name"))?;
+        let targets = self.targets(&name)?;
+        if let Some(grant) = grant.filter(|grant| !grant.scopes.is_empty()) {
+            let hosts = self.hosts.get();
+            if let Some(target) = targets
+                .iter()
+                .find(|target| !grant.may_wake(target, &hosts))
+            {
+                return Err(Error::new(
+                    ErrorKind::PermissionDenied,
+                    format!(
+                        "API token may not wake up {}",
+                        target.name().map_or_else(
+                            || target.hardware_address().to_string(),
+                            ToOwned::to_owned
+                        )
+                    ),
+                ));
+            }
+        }
+        let now = Instant::now();
+        let addresses = targets
+            .iter()
+            .map(WakeUpTarget::hardware_address)
+            .collect::<Vec<_>>();
+        if let Err(retry_after) = self.host_limit.acquire(&addresses, now) {
+            self.metrics.increment(Counter::RateLimited, "host");
+            return Ok(Response::too_many_requests(
+                "rate limit for systems",
+                retry_after,
+            ));
+        }
+        if let Err(retry_after) = self.global_limit.acquire(&vec![(); targets.len()], now) {
+            self.metrics.increment(Counter::RateLimited, "global");
+            return Ok(Response::too_many_requests(
+                "global rate limit",
+                retry_after,
+            ));
+        }
+        let mut all_sent = true;
+        let results = targets
+            .iter()
+            .map(|target| {
+                let (result, sent) = self.wake_target(target);
+                all_sent &= sent;
+                result
+            })
+            .collect::<Vec<_>>();
+        Ok(Response::new(
+            if all_sent { 200 } else { 500 },
+            Body::Json(Value::Array(results)),
+        ))
+    }
+
+    /// Append the wake `request` from `peer` and its `response` to the audit
+    /// log, if any.
+    ///
+    /// Ignore all requests other than `POST /wake/{name}`.
+    fn audit(&self, request: &Request, peer: SocketAddr, response: &Response) {
+        let Ok(audit) = self.audit.read() else {
+            return;
+        };
+        let Some(audit) = audit.as_ref() else {
+            return;
+        };
+        let path = request.path.split('?').next().unwrap_or_default();
+        let Some(name) = path
+            .strip_prefix("/wake/")
+            .filter(|_| request.method == "POST")
+        else {
+            return;
+        };
+        let name = percent_decode(name).unwrap_or_else(|| name.to_owned());
+        let grant = self.grant(request);
+        let client = grant.as_ref().and_then(|grant| grant.name.as_deref());
+        let body = match &response.body {
+            Body::Json(value) => value,
+            _ => &Value::Null,
+        };
+        let entry = audit::Entry {
+            time: SystemTime::now(),
+            peer,
+            client,
+            name: &name,
+            status: response.status,
+            response: body,
+        };
+        if let Err(error) = audit.write(&entry) {
+            tracing::error!("Failed to write audit log: {error}");
+        }
+    }
+
+    /// Describe the state of this server.
+    fn state(&self) -> Value {
+        json!({
+            "pid": std::process::id(),
+            "uptime_secs": self.started.elapsed().as_secs(),
+            "hosts": self.hosts.path().display().to_string(),
+            "systems": self.hosts.get().targets().map(|targets| targets.len()).ok(),
+            "tokens": self.has_tokens(),
+            "audit_log": self.audit.read().is_ok_and(|audit| audit.is_some()),
+            "metrics": self.metrics.to_json(),
+        })
+    }
+
+    /// Answer the control `request`, with arguments from `reread` to reload.
+    #[cfg(unix)]
+    fn control(&self, request: &control::Request, reread: Reread) -> Value {
+        let error = |error: Error| json!({ "error": error.to_string() });
+        match request {
+            control::Request::Reload => reread()
+                .and_then(|args| self.reload(&args))
+                .map_or_else(error, |()| json!({ "result": "ok" })),
+            control::Request::Wake(name) => {
+                self.wake(name, None)
+                    .map_or_else(error, |response| match response.body {
+                        Body::Json(value) => value,
+                        _ => Value::Null,
+                    })
+            }
+            control::Request::State => self.state(),
+        }
+    }
+
+    /// Respond to `request`.
+    fn respond(&self, request: &Request) -> Response {
+        let grant = match self.authorize(request) {
+            Ok(grant) => grant,
+            Err(response) => return response,
+        };
+        let path = request.path.split('?').next().unwrap_or_default();
+        // Serve the web UI and the OpenAPI document outside of the API
+        match (request.method.as_str(), path) {
+            ("GET", "/") if self.web_ui => return Response::new(200, Body::Html(WEB_UI)),
+            ("GET", "/openapi.json") => {
+                return openapi().map_or_else(|error| Response::from(&error), Response::ok);
+            }
+            (_, "/openapi.json") => return Response::error(405, "Method not allowed"),
+            _ => {}
+        }
+        let mut operations = OPERATIONS
+            .iter()
+            .filter_map(|(method, template, operation)| {
+                match_path(template, path).map(|name| (*method, *operation, name))
+            })
+            .peekable();
+        if operations.peek().is_none() {
+            return Response::error(404, "Not found");
+        }
+        let result = match operations.find(|(method, ..)| *method == request.method) {
+            Some((_, Operation::Hosts, _)) => self.hosts(),
+            Some((_, Operation::Status, _)) => self.status(),
+            Some((_, Operation::Events, _)) => Ok(Response::new(200, Body::Events)),
+            Some((_, Operation::Metrics, _)) => {
+                Ok(Response::new(200, Body::Metrics(self.metrics.render())))
+            }
+            Some((_, Operation::Wake, name)) => self.wake(name, grant.as_ref()),
+            None => Ok(Response::error(405, "Method not allowed")),
+        };
+        result.unwrap_or_else(|error| Response::from(&error))
+    }
+
+    /// Handle a single request from `peer` on `stream`.
+    fn handle(&self, mut stream: impl Read + Write, peer: SocketAddr) -> Result<()> {
+        let response = match read_request(BufReader::new(&mut stream)) {
+            Ok(request) => {
+                let response = match self.client_limit.acquire(&[peer.ip()], Instant::now()) {
+                    Ok(()) => self.respond(&request),
+                    Err(retry_after) => {
+                        self.metrics.increment(Counter::RateLimited, "client");
+                        Response::too_many_requests("rate limit for clients", retry_after)
+                    }
+                };
+                tracing::info!(
+                    %peer,
+                    method = request.method,
+                    path = request.path,
+                    status = response.status,
+                    "Handled request"
+                );
+                self.audit(&request, peer, &response);
+                response
+            }
+            Err(error) => {
+                tracing::warn!(%peer, "Invalid request: {error}");
+                Response::error(400, &error.to_string())
+            }
+        };
+        self.metrics
+            .increment(Counter::Requests, &response.status.to_string());
+        // Subscribe before responding, lest clients miss events
+        let events = (response.body == Body::Events).then(|| self.events.subscribe());
+        response.write_to(&mut stream)?;
+        stream.flush()?;
+        if let Some(events) = events {
+            if let Err(error) = events::stream(&events, KEEP_ALIVE, &mut stream) {
+                tracing::debug!(%peer, "Event stream closed: {error}");
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a connection from a client on `stream`.
+    ///
+    /// Speak TLS if configured.
+    #[cfg_attr(not(feature = "tls"), allow(clippy::needless_pass_by_value))]
+    fn connection(&self, stream: TcpStream) -> Result<()> {
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+        let peer = stream.peer_addr()?;
+        #[cfg(feature = "tls")]
+        if let Some(config) = &self.tls {
+            let mut stream = tls::accept(config, stream)?;
+            self.handle(&mut stream, peer)?;
+            return tls::close(&mut stream);
+        }
+        self.handle(&stream, peer)
+    }
+}
+
+#[cfg(feature = "dbus")]
+impl dbus::Service for Server {
+    fn wake(&self, name: &str) -> Result<Vec<Value>> {
+        let response = Self::wake(self, name, None)?;
+        match response.body {
+            Body::Json(Value::Array(results)) => Ok(results),
+            Body::Json(value) => Err(Error::other(
+                value
+                    .get("error")
+                    .and_then(Value::as_str)
+                    .unwrap_or("Failed to wake up systems")
+                    .to_owned(),
+            )),
+            _ => Err(Error::other("Failed to wake up systems")),
+        }
+    }
+
+    fn hosts(&self) -> Result<Vec<Value>> {
+        Ok(self.hosts.get().hosts()?.iter().map(host_json).collect())
+    }
+}
+
+/// Receive magic packets on all `sockets`, and publish every received packet
+/// as `packet` event to `events`.
+fn watch(sockets: Vec<(u16, UdpSocket)>, events: Arc<Events>) {
+    let (sender, packets) = mpsc::channel();
+    for (port, socket) in sockets {
+        let sender = sender.clone();
+        // We listen until killed, so nothing ever interrupts us
+        std::thread::spawn(move || listen::receive(&socket, port, &Interrupt::default(), &sender));
+    }
+    std::thread::spawn(move || {
+        for packet in packets {
+            match packet {
+                Ok(packet) => events.publish(&Event::new("packet", packet.to_json())),
+                Err(error) => tracing::warn!("Failed to receive packets: {error}"),
+            }
+        }
+    });
+}
+
+/// Accept connections on `listener` and serve each in a new thread of
+/// `scope`, until killed.
+fn accept<'scope>(
+    scope: &'scope std::thread::Scope<'scope, '_>,
+    server: &'scope Server,
+    listener: &TcpListener,
+) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                scope.spawn(move || {
+                    if let Err(error) = server.connection(stream) {
+                        tracing::warn!("Failed to handle request: {error}");
+                    }
+                });
+            }
+            Err(error) => tracing::warn!("Failed to accept connection: {error}"),
+        }
+    }
+}
+
+/// Switch users and confine `server` for `args`.
+///
+/// Keep the state of the user we switch to, without touching the environment,
+/// which other threads may read.  In the sandbox permit reading the directory
+/// of the hosts database to notice when editors replace it, and the token
+/// file, and writing the directories of the audit logs to reopen them after
+/// rotation, and the state directory.
+///
+/// # Errors
+///
+/// Return an error if switching users or confining the server fails.
+fn sandbox(args: &ServeArgs, server: &mut Server) -> Result<()> {
+    if let Some(home) = args.sandbox.switch_user()? {
+        let state_dir = history::state_dir_of(&home);
+        server.wake_log = args.wake_log.path(Some(&state_dir));
+        server.state_dir = Some(state_dir);
+    }
+    let mut access = Access::default();
+    if let Some(state_dir) = &server.state_dir {
+        // Create the state directory while we still may
+        if let Err(error) = std::fs::create_dir_all(state_dir) {
+            tracing::warn!(
+                "Failed to create state directory {}: {error}",
+                state_dir.display()
+            );
+        }
+        access.write(state_dir);
+    }
+    let hosts = std::path::absolute(server.hosts.path())?;
+    access.read(hosts.parent().unwrap_or(&hosts));
+    if let Some(token_file) = &args.token_file {
+        access.read(token_file);
+    }
+    for audit_log in args.audit_log.iter().chain(&server.wake_log) {
+        let audit_log = std::path::absolute(audit_log)?;
+        access.write(audit_log.parent().unwrap_or(&audit_log));
+    }
+    // Reloading parses the command line again, with the configuration files
+    for path in config::paths() {
+        access.read(&path);
+    }
+    for arg in std::env::args_os().skip(1) {
+        if let Some(path) = arg
+            .to_str()
+            .and_then(|arg| arg.strip_prefix('@'))
+            .map(Path::new)
+            .filter(|path| path.is_file())
+        {
+            access.read(path);
+        }
+    }
+    args.sandbox.confine(&access)
+}
+
+/// Reload `server` with arguments from `reread` whenever `SIGHUP` sets
+/// `hangup`.
+#[cfg(unix)]
+fn reload_on_hangup(server: &Server, reread: Reread, hangup: &AtomicBool) {
+    loop {
+        std::thread::sleep(HANGUP_POLL_INTERVAL);
+        if hangup.swap(false, Ordering::Relaxed) {
+            match reread().and_then(|args| server.reload(&args)) {
+                Ok(()) => tracing::info!(
+                    "Reloaded configuration, hosts database, API tokens, and audit log"
+                ),
+                Err(error) => anstream::eprintln!(
+                    "{WARNING}warning{WARNING:#}: Failed to reload, keeping hosts database, API tokens, and audit log: {error}"
+                ),
+            }
+        }
+    }
+}
+
+/// The command line of a service which runs this command with `args`.
+///
+/// Drop `flag` from the command line of this process, and give the hosts
+/// database explicitly, as the service has another home directory.
+fn service_command(args: &ServeArgs, flag: &str) -> Result<Vec<OsString>> {
+    let mut command = vec![std::env::current_exe()?.into_os_string()];
+    command.extend(
+        argfile::expand(std::env::args_os())?
+            .into_iter()
+            .skip(1)
+            .filter(|arg| arg != flag),
+    );
+    if !args.hosts.is_explicit() {
+        command.push("--hosts".into());
+        command.push(std::path::absolute(args.hosts.path()?)?.into_os_string());
+    }
+    Ok(command)
+}
+
+/// A systemd service unit which runs this command with `args`.
+fn systemd_unit(args: &ServeArgs) -> Result<String> {
+    let command = service_command(args, "--print-systemd-unit")?;
+    systemd::service_unit(DESCRIPTION, &command, &std::env::current_dir()?)
+}
+
+/// A launchd property list which runs this command with `args`.
+fn launchd_plist(args: &ServeArgs) -> Result<String> {
+    let command = service_command(args, "--print-launchd-plist")?;
+    launchd::daemon_plist(LAUNCHD_LABEL, &command, &std::env::current_dir()?)
+}
+
+/// Install a Windows service which runs this command with `args`.
+#[cfg(all(windows, feature = "windows-service"))]
+fn install_service(args: &ServeArgs) -> Result<()> {
+    let mut command = service_command(args, "--install-service")?;
+    command.push("--run-as-service".into());
+    windows::install(DESCRIPTION, &command)
+}
+
+/// Serve the HTTP API until killed.
+///
+/// On reload take the token file and the audit log from the arguments which
+/// `reread` parses with the current configuration.
+///
+/// # Errors
+///
+/// Return an error if listening on the given address or ports fails, or if
+/// reading the tokens, or the TLS certificate and key fails.
+// Only Unix reloads
+#[cfg_attr(not(unix), allow(unused_variables))]
+pub fn serve(args: &ServeArgs, reread: Reread) -> Result<()> {
+    if args.print_systemd_unit {
+        anstream::print!("{}", systemd_unit(args)?);
+        return Ok(());
+    }
+    if args.print_launchd_plist {
+        anstream::print!("{}", launchd_plist(args)?);
+        return Ok(());
+    }
+    #[cfg(all(windows, feature = "windows-service"))]
+    if args.install_service {
+        install_service(args)?;
+        anstream::println!("Installed and started service {}", windows::SERVICE_NAME);
+        return Ok(());
+    }
+    #[cfg(all(windows, feature = "windows-service"))]
+    if args.run_as_service {
+        let mut args = args.clone();
+        args.run_as_service = false;
+        return windows::run(move || serve(&args, reread));
+    }
+    let activated = systemd::listen_fds()?;
+    let listeners = if activated.listeners.is_empty() {
+        vec![TcpListener::bind(args.listen).map_err(|error| {
+            Error::new(
+                error.kind(),
+                format!("Failed to listen on {}: {error}", args.listen),
+            )
+        })?]
+    } else {
+        activated.listeners
+    };
+    let mut sockets = activated
+        .sockets
+        .into_iter()
+        .map(|socket| Ok((socket.local_addr()?.port(), socket)))
+        .collect::<Result<Vec<_>>>()?;
+    for port in &args.watch_ports {
+        sockets.push((*port, listen::bind(*port)?));
+    }
+    #[cfg(unix)]
+    let control = args
+        .control_socket
+        .as_deref()
+        .map(control::bind)
+        .transpose()?;
+    let mut server = Server::new(args)?;
+    sandbox(args, &mut server)?;
+    let server = Arc::new(server);
+    // Connect only after sandboxing: the connection runs its own thread, which
+    // must not escape the sandbox.  Landlock does not restrict connecting to
+    // the socket of the bus.
+    #[cfg(feature = "dbus")]
+    let bus = args
+        .dbus
+        .map(|bus| {
+            let events = server.events.subscribe();
+            let service = Arc::clone(&server);
+            dbus::connect(bus, service).map(|connection| (connection, events))
+        })
+        .transpose()?;
+    watch(sockets, Arc::clone(&server.events));
+    for listener in &listeners {
+        let address = listener.local_addr()?;
+        if !server.has_tokens() && !address.ip().is_loopback() {
+            anstream::eprintln!(
+                "{WARNING}warning{WARNING:#}: Serving {address} without API tokens; anyone on the network can wake up systems",
+            );
+        }
+        anstream::println!("Listening on {}://{address}", server.scheme());
+    }
+    let addresses = listeners
+        .iter()
+        .map(|listener| Ok(listener.local_addr()?.to_string()))
+        .collect::<Result<Vec<_>>>()?;
+    // Catch SIGHUP before we tell systemd that we're ready to reload
+    #[cfg(unix)]
+    let hangup = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    if let Err(error) =
+        signal_hook::flag::register(signal_hook::consts::SIGHUP, Arc::clone(&hangup))
+    {
+        tracing::warn!("Failed to catch SIGHUP: {error}");
+    }
+    systemd::notify_ready(&format!("Listening on {}", addresses.join(", ")));
+    std::thread::scope(|scope| {
+        #[cfg(unix)]
+        scope.spawn(|| reload_on_hangup(&server, reread, &hangup));
+        scope.spawn(|| reload::watch(&server.hosts));
+        #[cfg(unix)]
+        if let Some(control) = &control {
+            let server = &server;
+            scope
+                .spawn(move || control::accept(control, |request| server.control(request, reread)));
+        }
+        #[cfg(feature = "dbus")]
+        if let Some((connection, events)) = bus {
+            scope.spawn(move || dbus::emit(&connection, &events));
+        }
+        for listener in &listeners {
+            let server = &server;
+            scope.spawn(move || accept(scope, server, listener));
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::UdpSocket;
+    use std::path::Path;
+
+    use super::*;
+
+    fn serve_args(args: &[&str]) -> ServeArgs {
+        use clap::Parser;
+
+        #[derive(Parser)]
+        struct Command {
+            #[command(flatten)]
+            args: ServeArgs,
+        }
+
+        Command::parse_from(std::iter::once("serve").chain(args.iter().copied())).args
+    }
+
+    fn server(hosts: &Path) -> Server {
+        Server::new(&serve_args(&["--hosts", &hosts.to_string_lossy()])).unwrap()
+    }
+
+    fn request(method: &str, path: &str) -> Request {
+        Request {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            headers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn parse_request() {
+        let request = read_request(
+            "POST /wake/nas HTTP/1.1\r\nHost: localhost\r\nContent-Length: 2\r\n\r\n{}".as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/wake/nas");
+        assert_eq!(request.header("host"), Some("localhost"));
+        assert_eq!(request.header("content-length"), Some("2"));
+
+        let error = read_request("GET /hosts\r\n\r\n".as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "Malformed request line");
+        let error =
+            read_request("GET /hosts HTTP/1.1\r\nHost: localhost\r\n".as_bytes()).unwrap_err();
+        assert_eq!(error.to_string(), "Incomplete request");
+    }
+
+    #[test]
+    fn decode() {
+        assert_eq!(
+            percent_decode("12%3A13%3a14:15:16:17").as_deref(),
+            Some("12:13:14:15:16:17")
+        );
+        assert_eq!(percent_decode("office-pc").as_deref(), Some("office-pc"));
+        assert_eq!(percent_decode("%4"), None);
+        assert_eq!(percent_decode("%zz"), None);
+    }
+
+    #[test]
+    fn response() {
+        let mut buffer = Vec::new();
+        Response::error(404, "Not found")
+            .write_to(&mut buffer)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "HTTP/1.1 404 Not Found\r\nContent-Type: application/json\r\nContent-Length: 21\r\nConnection: close\r\n\r\n{\"error\":\"Not found\"}"
+        );
+
+        let mut buffer = Vec::new();
+        Response::new(200, Body::Events)
+            .write_to(&mut buffer)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n"
+        );
+
+        let mut buffer = Vec::new();
+        Response::too_many_requests("global rate limit", Duration::from_millis(1500))
+            .write_to(&mut buffer)
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            "HTTP/1.1 429 Too Many Requests\r\nRetry-After: 2\r\nContent-Type: application/json\r\nContent-Length: 57\r\nConnection: close\r\n\r\n{\"error\":\"Too many requests, exceeded global rate limit\"}"
+        );
+    }
+
+    #[test]
+    fn rate_limits() {
+        let mut server = server(Path::new("/does/not/exist/hosts.toml"));
+        let hardware_address = MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]);
+        server.host_limit = RateLimit::per_minute(1);
+        server
+            .host_limit
+            .acquire(&[hardware_address], Instant::now())
+            .unwrap();
+        let response = server.respond(&request("POST", "/wake/12:13:14:15:16:17"));
+        assert_eq!(response.status, 429);
+        assert!(response.retry_after.is_some());
+
+        server.global_limit = RateLimit::per_minute(1);
+        server.global_limit.acquire(&[()], Instant::now()).unwrap();
+        let response = server.respond(&request("POST", "/wake/12:13:14:15:16:18"));
+        assert_eq!(
+            response.body,
+            Response::error(429, "Too many requests, exceeded global rate limit").body
+        );
+
+        let Body::Metrics(metrics) = server.respond(&request("GET", "/metrics")).body else {
+            panic!("Expected metrics");
+        };
+        assert!(metrics.contains("wol_rate_limited_total{limit=\"host\"} 1\n"));
+        assert!(metrics.contains("wol_rate_limited_total{limit=\"global\"} 1\n"));
+    }
+
+    #[test]
+    fn routes() {
+        let path = std::env::temp_dir().join(format!("wol-serve-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[nas]\nmac = \"12:13:14:15:16:17\"\ntags = [\"lab\"]\n",
+        )
+        .unwrap();
+        let server = server(&path);
+
+        let response = server.respond(&request("GET", "/hosts"));
+        assert_eq!(
+            response,
+            Response::ok(json!([{
+                "name": "nas",
+                "mac": "12:13:14:15:16:17",
+                "destination": null,
+                "port": null,
+                "tags": ["lab"],
+            }]))
+        );
+        let response = server.respond(&request("GET", "/status"));
+        assert_eq!(
+            response,
+            Response::ok(json!([{
+                "name": "nas",
+                "mac": "12:13:14:15:16:17",
+                "status": "unknown",
+                "reason": "no address",
+            }]))
+        );
+        assert_eq!(
+            server.respond(&request("GET", "/events")),
+            Response::new(200, Body::Events)
+        );
+        assert_eq!(server.respond(&request("POST", "/events")).status, 405);
+        assert_eq!(server.respond(&request("GET", "/foo")).status, 404);
+        assert_eq!(server.respond(&request("GET", "/")).status, 404);
+        assert_eq!(server.respond(&request("DELETE", "/hosts")).status, 405);
+        assert_eq!(server.respond(&request("GET", "/wake/nas")).status, 405);
+        assert_eq!(
+            server.respond(&request("POST", "/wake/desktop")),
+            Response::error(404, "No system matches desktop")
+        );
+
+        let targets = server.targets("@lab").unwrap();
+        assert_eq!(targets.len(), 1);
+        let targets = server.targets("12:13:14:15:16:18").unwrap();
+        let [target] = targets.as_slice() else {
+            panic!("Expected one target, got {targets:?}");
+        };
+        assert_eq!(
+            target.hardware_address(),
+            MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x18])
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn openapi_document() {
+        let document = openapi().unwrap();
+        assert_eq!(
+            document.pointer("/info/version").and_then(Value::as_str),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
+        let paths = document.get("paths").and_then(Value::as_object).unwrap();
+        // The document describes exactly the operations the server routes
+        let mut documented = paths
+            .iter()
+            .flat_map(|(path, operations)| {
+                let operations = operations.as_object().unwrap();
+                operations
+                    .keys()
+                    .map(move |method| (method.to_uppercase(), path.clone()))
+            })
+            .collect::<Vec<_>>();
+        documented.sort();
+        let mut routed = OPERATIONS
+            .iter()
+            .map(|(method, path, _)| ((*method).to_owned(), (*path).to_owned()))
+            .collect::<Vec<_>>();
+        routed.sort();
+        assert_eq!(documented, routed);
+        // Every operation in the document exists
+        let mut server = server(Path::new("/does/not/exist/hosts.toml"));
+        // Exhaust the rate limit, so that the wake route never sends magic
+        // packets or records history
+        server.global_limit = RateLimit::per_minute(1);
+        server.global_limit.acquire(&[()], Instant::now()).unwrap();
+        for (path, operations) in paths {
+            for method in operations.as_object().unwrap().keys() {
+                let path = path.replace("{name}", "12:13:14:15:16:17");
+                let response = server.respond(&request(&method.to_uppercase(), &path));
+                assert_ne!(
+                    response,
+                    Response::error(404, "Not found"),
+                    "{method} {path}"
+                );
+                assert_ne!(response.status, 405, "{method} {path}");
+                if path.starts_with("/wake/") {
+                    assert_eq!(response.status, 429, "{method} {path}");
+                }
+            }
+        }
+        assert_eq!(
+            server.respond(&request("GET", "/openapi.json")),
+            Response::ok(document)
+        );
+    }
+
+    #[test]
+    fn authorization() {
+        let mut server = server(Path::new("/does/not/exist/hosts.toml"));
+        server.tokens = RwLock::new(Some(
+            Tokens::parse("wake-token wake\nadmin-token admin\n").unwrap(),
+        ));
+        server.web_ui = true;
+        let with_header = |method: &str, path: &str, header: (&str, &str)| {
+            let mut request = request(method, path);
+            request
+                .headers
+                .push((header.0.to_owned(), header.1.to_owned()));
+            server.respond(&request).status
+        };
+        assert_eq!(server.respond(&request("GET", "/hosts")).status, 401);
+        assert_eq!(
+            server.respond(&request("GET", "/")),
+            Response::new(200, Body::Html(WEB_UI))
+        );
+        assert_eq!(
+            with_header("GET", "/hosts", ("authorization", "Bearer wrong")),
+            401
+        );
+        assert_eq!(
+            with_header("GET", "/hosts", ("authorization", "Bearer wake-token")),
+            403
+        );
+        assert_eq!(
+            with_header("GET", "/hosts", ("x-api-key", "admin-token")),
+            200
+        );
+        assert_eq!(
+            with_header("GET", "/events", ("x-api-key", "wake-token")),
+            403
+        );
+        assert_eq!(
+            with_header("POST", "/wake/nas", ("x-api-key", "wake-token")),
+            404
+        );
+        assert_eq!(
+            with_header("POST", "/wake/nas", ("authorization", "bearer admin-token")),
+            404
+        );
+        let mut buffer = Vec::new();
+        Response::error(401, "Missing or invalid API token")
+            .write_to(&mut buffer)
+            .unwrap();
+        assert!(
+            String::from_utf8(buffer)
+                .unwrap()
+                .starts_with("HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Bearer\r\n")
+        );
+    }
+
+    #[test]
+    fn reload() {
+        let path =
+            std::env::temp_dir().join(format!("wol-serve-reload-{}.tokens", std::process::id()));
+        std::fs::write(&path, "old-token admin\n").unwrap();
+        let args = serve_args(&[
+            "--hosts",
+            "/does/not/exist/hosts.toml",
+            "--token-file",
+            &path.to_string_lossy(),
+        ]);
+        let server = Server::new(&args).unwrap();
+        let status = |token: &str| {
+            let mut request = request("GET", "/metrics");
+            request
+                .headers
+                .push(("x-api-key".to_owned(), token.to_owned()));
+            server.respond(&request).status
+        };
+        assert_eq!(status("old-token"), 200);
+        std::fs::write(&path, "new-token admin\n").unwrap();
+        server.reload(&args).unwrap();
+        assert_eq!(status("old-token"), 401);
+        assert_eq!(status("new-token"), 200);
+        // Keep the current tokens if the token file is invalid
+        std::fs::write(&path, "new-token admin\nnew-token wake\n").unwrap();
+        assert!(server.reload(&args).is_err());
+        assert_eq!(status("new-token"), 200);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn scoped_tokens() {
+        let path =
+            std::env::temp_dir().join(format!("wol-serve-scoped-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "[nas]\nmac = \"12:13:14:15:16:17\"\n\n[ceo]\nmac = \"12:13:14:15:16:18\"\n",
+        )
+        .unwrap();
+        let mut server = server(&path);
+        server.tokens = RwLock::new(Some(Tokens::parse("office wake nas\n").unwrap()));
+        let wake = |name: &str| {
+            let mut request = request("POST", &format!("/wake/{name}"));
+            request
+                .headers
+                .push(("x-api-key".to_owned(), "office".to_owned()));
+            server.respond(&request)
+        };
+        assert_eq!(
+            wake("ceo"),
+            Response::error(403, "API token may not wake up ceo")
+        );
+        assert_eq!(
+            wake("*"),
+            Response::error(403, "API token may not wake up ceo")
+        );
+        assert_eq!(
+            wake("12:13:14:15:16:19"),
+            Response::error(403, "API token may not wake up 12:13:14:15:16:19")
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn send() {
+        let receiver = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let port = receiver.local_addr().unwrap().port();
+        let server = server(Path::new("/does/not/exist/hosts.toml"));
+        let hardware_address = MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]);
+        server
+            .send(
+                &WakeUpTarget::new(hardware_address),
+                &MagicPacketDestination::Ip(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+                port,
+            )
+            .unwrap();
+        let mut buffer = [0; 200];
+        let size = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(
+            wol::parse_magic_packet(buffer.get(..size).unwrap_or_default()),
+            Some((hardware_address, None))
+        );
+    }
+}
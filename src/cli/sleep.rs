@@ -0,0 +1,307 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Put systems to sleep with Sleep-on-LAN.
+//!
+//! Sleep-on-LAN daemons put a system to sleep when they receive a magic packet
+//! for the hardware address of the system in reverse byte order, or an HTTP
+//! request to `/sleep`.
+
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::process::ExitCode;
+use std::time::Duration;
+
+use clap::{ArgAction, Args};
+use wol::MacAddress;
+use wol::file::{MagicPacketDestination, WakeUpTarget};
+
+use super::dns::Resolver;
+use super::hosts::HostsArgs;
+use super::status;
+use super::target::TargetArg;
+
+/// How long to wait for the HTTP API of Sleep-on-LAN daemons.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct SleepArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Send the sleep packet to HOST.
+    ///
+    /// Like for wake up, HOST is usually a broadcast address.
+    /// Systems from the hosts database use their own host.
+    #[arg(
+        short = 'h',
+        long = "host",
+        default_value = "255.255.255.255",
+        verbatim_doc_comment
+    )]
+    host: MagicPacketDestination,
+    /// Send the sleep packet to PORT.
+    #[arg(short = 'p', long = "port", default_value = "9")]
+    port: u16,
+    /// Ask the HTTP API of the Sleep-on-LAN daemon instead.
+    ///
+    /// Send GET /sleep to the address of each system, i.e.
+    /// address= in the hosts database, or a unicast host.
+    #[arg(long = "http", verbatim_doc_comment)]
+    http: bool,
+    /// The port of the HTTP API of the Sleep-on-LAN daemon.
+    #[arg(
+        long = "http-port",
+        value_name = "PORT",
+        default_value = "8009",
+        requires = "http"
+    )]
+    http_port: u16,
+    /// The systems to put to sleep, by hardware address, name,
+    /// or pattern, or @GROUP for all systems in a group.
+    #[arg(value_name = "NAME|MAC", required = true, verbatim_doc_comment)]
+    #[cfg_attr(
+        feature = "dynamic-completions",
+        arg(add = clap_complete::ArgValueCandidates::new(super::complete::systems))
+    )]
+    targets: Vec<TargetArg>,
+    #[command(flatten)]
+    hosts: HostsArgs,
+}
+
+/// Reverse the bytes of `hardware_address`, as Sleep-on-LAN daemons expect.
+fn reversed(hardware_address: MacAddress) -> MacAddress {
+    let mut bytes = <[u8; 6]>::from(hardware_address);
+    bytes.reverse();
+    MacAddress::new(bytes)
+}
+
+/// Get the systems to put to sleep for `args`.
+///
+/// # Errors
+///
+/// Return an error if reading the hosts database fails, or if a name or group
+/// matches no system.
+fn targets(args: &SleepArgs) -> Result<Vec<WakeUpTarget>> {
+    let hosts = if args
+        .targets
+        .iter()
+        .any(|target| !matches!(target, TargetArg::Target(_)))
+    {
+        args.hosts.read()?
+    } else {
+        super::hosts::Hosts::default()
+    };
+    let named = hosts.targets()?;
+    let mut targets = Vec::new();
+    for target in &args.targets {
+        match target {
+            TargetArg::Target(target) => targets.push(
+                WakeUpTarget::new(target.hardware_address)
+                    .with_packet_destination(target.host.clone())
+                    .with_port(target.port),
+            ),
+            TargetArg::Hosts(selector) => {
+                let count = targets.len();
+                targets.extend(named.iter().filter(|host| selector.matches(host)).cloned());
+                if count == targets.len() {
+                    return Err(Error::new(
+                        ErrorKind::NotFound,
+                        format!(
+                            "{selector} is neither a hardware address nor a system in {}",
+                            args.hosts.path()?.display()
+                        ),
+                    ));
+                }
+            }
+            TargetArg::Group(group) => targets.extend(hosts.group(group)?),
+        }
+    }
+    Ok(targets)
+}
+
+/// Send a sleep packet for `target` over UDP.
+fn send_packet(target: &WakeUpTarget, args: &SleepArgs, resolver: &Resolver) -> Result<()> {
+    let host = target.packet_destination().unwrap_or(&args.host);
+    let mut socket_addr = status::resolve(host, resolver)?;
+    socket_addr.set_port(target.port().unwrap_or(args.port));
+    wol::send_magic_packet(reversed(target.hardware_address()), None, socket_addr)
+}
+
+/// Send `GET /sleep` to `socket_addr` and check the response.
+fn request_sleep(socket_addr: SocketAddr) -> Result<()> {
+    let mut stream = TcpStream::connect_timeout(&socket_addr, HTTP_TIMEOUT)?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    // Send the request at once; some daemons answer before reading all of it
+    let request =
+        format!("GET /sleep HTTP/1.0\r\nHost: {socket_addr}\r\nConnection: close\r\n\r\n");
+    stream.write_all(request.as_bytes())?;
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    let status_line = status_line.trim_end();
+    let code = status_line
+        .strip_prefix("HTTP/")
+        .and_then(|rest| rest.split_whitespace().nth(1))
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidData,
+                format!("Invalid HTTP response: {status_line}"),
+            )
+        })?;
+    if code.starts_with('2') {
+        Ok(())
+    } else {
+        let reason = status_line
+            .split_once(code)
+            .map_or("", |(_, reason)| reason);
+        Err(Error::other(format!(
+            "Sleep-on-LAN daemon answered {code}{reason}"
+        )))
+    }
+}
+
+/// Ask the HTTP API of the Sleep-on-LAN daemon on `target` to sleep.
+fn send_http(target: &WakeUpTarget, args: &SleepArgs, resolver: &Resolver) -> Result<()> {
+    let address = status::probe_address(target).ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "No address for {}, use address= in the hosts database or a unicast host",
+                target.hardware_address()
+            ),
+        )
+    })?;
+    let mut socket_addr = status::resolve(address, resolver)?;
+    socket_addr.set_port(args.http_port);
+    request_sleep(socket_addr)
+}
+
+/// Put all systems given by `args` to sleep.
+///
+/// Print an error for every system we could not send a sleep request to, and
+/// exit with 1 if there was any.
+///
+/// # Errors
+///
+/// Return an error if reading the hosts database fails, or if a name or group
+/// matches no system.
+pub fn sleep(args: &SleepArgs) -> Result<ExitCode> {
+    let resolver = Resolver::default();
+    let mut failed = false;
+    for target in targets(args)? {
+        let system = target
+            .name()
+            .map_or_else(|| target.hardware_address().to_string(), str::to_owned);
+        let result = if args.http {
+            send_http(&target, args, &resolver)
+        } else {
+            send_packet(&target, args, &resolver)
+        };
+        match result {
+            Ok(()) => anstream::println!("Sent sleep request to {system}"),
+            Err(error) => {
+                failed = true;
+                anstream::eprintln!("Failed to put {system} to sleep: {error}");
+            }
+        }
+    }
+    Ok(if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, TcpListener, UdpSocket};
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn args(targets: &[&str]) -> SleepArgs {
+        use clap::Parser;
+
+        #[derive(Parser)]
+        struct Command {
+            #[command(flatten)]
+            args: SleepArgs,
+        }
+
+        Command::parse_from(std::iter::once("sleep").chain(targets.iter().copied())).args
+    }
+
+    #[test]
+    fn reversed_hardware_address() {
+        assert_eq!(
+            reversed(MacAddress::from_str("12:13:14:15:16:17").unwrap()),
+            MacAddress::from_str("17:16:15:14:13:12").unwrap()
+        );
+    }
+
+    #[test]
+    fn sleep_packet() {
+        let receiver = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let port = receiver.local_addr().unwrap().port();
+        let args = args(&[&format!("12:13:14:15:16:17@127.0.0.1:{port}")]);
+        let targets = targets(&args).unwrap();
+        send_packet(targets.first().unwrap(), &args, &Resolver::default()).unwrap();
+        let mut buffer = [0; 200];
+        let size = receiver.recv(&mut buffer).unwrap();
+        assert_eq!(
+            wol::parse_magic_packet(buffer.get(..size).unwrap_or_default()),
+            Some((MacAddress::from_str("17:16:15:14:13:12").unwrap(), None))
+        );
+    }
+
+    fn serve_once(response: &'static str) -> (SocketAddr, std::thread::JoinHandle<String>) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let mut header = String::new();
+            while reader.read_line(&mut header).unwrap() > 2 {
+                header.clear();
+            }
+            stream.write_all(response.as_bytes()).unwrap();
+            request
+        });
+        (socket_addr, handle)
+    }
+
+    #[test]
+    fn http() {
+        let (socket_addr, handle) = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n");
+        request_sleep(socket_addr).unwrap();
+        assert_eq!(handle.join().unwrap(), "GET /sleep HTTP/1.0\r\n");
+
+        let (socket_addr, handle) = serve_once("HTTP/1.1 401 Unauthorized\r\n\r\n");
+        let error = request_sleep(socket_addr).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Sleep-on-LAN daemon answered 401 Unauthorized"
+        );
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn http_without_address() {
+        let args = args(&["--http", "12:13:14:15:16:17"]);
+        let targets = targets(&args).unwrap();
+        let error = send_http(targets.first().unwrap(), &args, &Resolver::default()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "No address for 12:13:14:15:16:17, use address= in the hosts database or a unicast host"
+        );
+    }
+}
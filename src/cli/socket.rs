@@ -0,0 +1,509 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Sockets to send magic packets over.
+
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt::Display;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::net::{
+    IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, SocketAddrV6, TcpStream, UdpSocket,
+};
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use socket2::{Domain, Protocol, Socket, Type};
+use wol::{MacAddress, SecureOn};
+
+use super::ethernet::RawSocket;
+use super::socks::{Association, Proxy};
+
+/// A local address to bind sockets to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BindAddress {
+    /// The local IP address, or `None` for the unspecified address.
+    pub ip: Option<IpAddr>,
+    /// The local port, or 0 for any port.
+    pub port: u16,
+}
+
+impl Display for BindAddress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.ip {
+            Some(IpAddr::V4(ip)) => write!(f, "{ip}:{}", self.port),
+            Some(IpAddr::V6(ip)) => write!(f, "[{ip}]:{}", self.port),
+            None => write!(f, ":{}", self.port),
+        }
+    }
+}
+
+/// Parse a bind address from `ADDR`, `ADDR:PORT`, `[ADDR]:PORT` or `:PORT`.
+impl FromStr for BindAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if let Ok(address) = SocketAddr::from_str(s) {
+            Ok(Self {
+                ip: Some(address.ip()),
+                port: address.port(),
+            })
+        } else if let Ok(ip) = IpAddr::from_str(s) {
+            Ok(Self {
+                ip: Some(ip),
+                port: 0,
+            })
+        } else if let Some(port) = s.strip_prefix(':') {
+            u16::from_str(port)
+                .map(|port| Self { ip: None, port })
+                .map_err(|error| format!("Invalid port {port}: {error}"))
+        } else {
+            Err(format!("Expected ADDR, ADDR:PORT, or :PORT, got {s}"))
+        }
+    }
+}
+
+/// How long to wait for TCP connections.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A transport to send magic packets over.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, ValueEnum)]
+pub enum Transport {
+    /// Send magic packets in UDP datagrams.
+    #[default]
+    Udp,
+    /// Send magic packets over TCP connections, to gateways which only accept TCP.
+    Tcp,
+}
+
+impl Display for Transport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Udp => write!(f, "udp"),
+            Self::Tcp => write!(f, "tcp"),
+        }
+    }
+}
+
+/// Options for sockets to send magic packets over.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct SocketOptions {
+    /// The transport to send packets over.
+    pub transport: Transport,
+    /// The SOCKS5 proxy to send packets through.
+    pub proxy: Option<Proxy>,
+    /// The network interface to send packets from.
+    pub interface: Option<String>,
+    /// The local address to bind to.
+    pub bind: Option<BindAddress>,
+    /// The IP TTL or IPv6 hop limit for packets.
+    pub ttl: Option<u32>,
+    /// The IP type of service or IPv6 traffic class for packets.
+    pub tos: Option<u32>,
+}
+
+/// Describe all options which are set, or an empty string if none.
+impl Display for SocketOptions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let options = [
+            (self.transport != Transport::default())
+                .then(|| format!("transport {}", self.transport)),
+            self.proxy.as_ref().map(|proxy| format!("proxy {proxy}")),
+            self.interface
+                .as_ref()
+                .map(|interface| format!("interface {interface}")),
+            self.bind.map(|bind| format!("bind {bind}")),
+            self.ttl.map(|ttl| format!("ttl {ttl}")),
+            self.tos.map(|tos| format!("tos {tos:#04x}")),
+        ];
+        let options = options.into_iter().flatten().collect::<Vec<_>>();
+        write!(f, "{}", options.join(", "))
+    }
+}
+
+/// Find the address of `interface` to bind to for sending to `destination`.
+fn interface_address(interface: &str, destination: &SocketAddr) -> Result<IpAddr> {
+    let addresses = if_addrs::get_if_addrs()?
+        .into_iter()
+        .filter(|candidate| candidate.name == interface)
+        .collect::<Vec<_>>();
+    if addresses.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("Interface {interface} not found"),
+        ));
+    }
+    addresses
+        .iter()
+        .map(if_addrs::Interface::ip)
+        .find(|ip| ip.is_ipv4() == destination.is_ipv4())
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::AddrNotAvailable,
+                format!("Interface {interface} has no address to reach {destination}"),
+            )
+        })
+}
+
+/// Get the IPv6 scope ID for `zone`.
+///
+/// `zone` is either a numeric scope ID, or the name of a network interface.
+///
+/// # Errors
+///
+/// Return an error if `zone` is not numeric and no interface named `zone` exists.
+pub fn scope_id(zone: &str) -> Result<u32> {
+    if let Ok(scope_id) = u32::from_str(zone) {
+        return Ok(scope_id);
+    }
+    if_addrs::get_if_addrs()?
+        .into_iter()
+        .find(|candidate| candidate.name == zone)
+        .and_then(|interface| interface.index)
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("Interface {zone} not found")))
+}
+
+/// Bind `socket` to `ip` and `port` to send to `destination`.
+///
+/// If `ip` is `None` bind to the unspecified address of the address family of
+/// `destination`.
+fn bind_local(
+    socket: &Socket,
+    ip: Option<IpAddr>,
+    port: u16,
+    destination: &SocketAddr,
+) -> Result<()> {
+    let ip = ip.unwrap_or_else(|| {
+        if destination.is_ipv4() {
+            IpAddr::from(Ipv4Addr::UNSPECIFIED)
+        } else {
+            IpAddr::from(Ipv6Addr::UNSPECIFIED)
+        }
+    });
+    socket.bind(&SocketAddr::new(ip, port).into())
+}
+
+/// Bind `socket` to the address of `interface`, or to `local` if given.
+fn bind_interface_address(
+    socket: &Socket,
+    interface: &str,
+    local: Option<BindAddress>,
+    destination: &SocketAddr,
+) -> Result<()> {
+    let ip = match local.and_then(|local| local.ip) {
+        Some(ip) => ip,
+        None => interface_address(interface, destination)?,
+    };
+    bind_local(
+        socket,
+        Some(ip),
+        local.map_or(0, |local| local.port),
+        destination,
+    )
+}
+
+/// Bind `socket` to `interface`.
+///
+/// On Linux bind the socket to the device, so that all packets, even those to
+/// the limited broadcast address, leave through `interface`.  If that's not
+/// permitted, fall back to binding the address of `interface`.
+#[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+fn bind_interface(
+    socket: &Socket,
+    interface: &str,
+    local: Option<BindAddress>,
+    destination: &SocketAddr,
+) -> Result<()> {
+    match socket.bind_device(Some(interface.as_bytes())) {
+        Ok(()) => bind_local(
+            socket,
+            local.and_then(|local| local.ip),
+            local.map_or(0, |local| local.port),
+            destination,
+        ),
+        // Kernels before 5.7 require CAP_NET_RAW to bind to a device.
+        Err(error) if error.kind() == ErrorKind::PermissionDenied => {
+            bind_interface_address(socket, interface, local, destination)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+/// Bind `socket` to `interface`.
+///
+/// Bind the socket to the address of `interface`, so that packets leave through
+/// `interface`.
+#[cfg(not(any(target_os = "android", target_os = "fuchsia", target_os = "linux")))]
+fn bind_interface(
+    socket: &Socket,
+    interface: &str,
+    local: Option<BindAddress>,
+    destination: &SocketAddr,
+) -> Result<()> {
+    bind_interface_address(socket, interface, local, destination)
+}
+
+/// Set the IP TTL or IPv6 hop limit of `socket` for `destination`.
+fn set_ttl(socket: &Socket, ttl: u32, destination: &SocketAddr) -> Result<()> {
+    if destination.is_ipv4() {
+        socket.set_ttl_v4(ttl)?;
+        socket.set_multicast_ttl_v4(ttl)
+    } else {
+        socket.set_unicast_hops_v6(ttl)?;
+        socket.set_multicast_hops_v6(ttl)
+    }
+}
+
+/// Set the type of service or IPv6 traffic class of `socket` for `destination`.
+#[cfg(any(
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "netbsd",
+    target_os = "openbsd",
+))]
+fn set_tos(socket: &Socket, tos: u32, destination: &SocketAddr) -> Result<()> {
+    if destination.is_ipv4() {
+        socket.set_tos_v4(tos)
+    } else {
+        socket.set_tclass_v6(tos)
+    }
+}
+
+/// Set the type of service of `socket` for `destination`.
+#[cfg(not(any(
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "linux",
+    target_os = "macos",
+    target_os = "netbsd",
+    target_os = "openbsd",
+)))]
+fn set_tos(socket: &Socket, tos: u32, destination: &SocketAddr) -> Result<()> {
+    if destination.is_ipv4() {
+        socket.set_tos_v4(tos)
+    } else {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "Setting the IPv6 traffic class is not supported on this system",
+        ))
+    }
+}
+
+/// Apply `options` to `socket` for sending to `destination`, and bind it.
+fn configure(socket: &Socket, destination: &SocketAddr, options: &SocketOptions) -> Result<()> {
+    if let Some(ttl) = options.ttl {
+        set_ttl(socket, ttl, destination)?;
+    }
+    if let Some(tos) = options.tos {
+        set_tos(socket, tos, destination)?;
+    }
+    match &options.interface {
+        Some(interface) => bind_interface(socket, interface, options.bind, destination),
+        None => bind_local(
+            socket,
+            options.bind.and_then(|local| local.ip),
+            options.bind.map_or(0, |local| local.port),
+            destination,
+        ),
+    }
+}
+
+/// Bind a new UDP socket to send magic packets to `destination`.
+pub fn bind(destination: &SocketAddr, options: &SocketOptions) -> Result<UdpSocket> {
+    let socket = Socket::new(
+        Domain::for_address(*destination),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    socket.set_broadcast(true)?;
+    configure(&socket, destination, options)?;
+    Ok(socket.into())
+}
+
+/// Connect to `destination` over TCP with `options`.
+fn connect(destination: &SocketAddr, options: &SocketOptions) -> Result<TcpStream> {
+    let socket = Socket::new(
+        Domain::for_address(*destination),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    configure(&socket, destination, options)?;
+    socket.connect_timeout(&(*destination).into(), CONNECT_TIMEOUT)?;
+    socket.set_tcp_nodelay(true)?;
+    socket.set_write_timeout(Some(CONNECT_TIMEOUT))?;
+    Ok(socket.into())
+}
+
+/// Send a magic packet for `mac_address` over a new TCP connection to
+/// `destination`, and close the connection.
+///
+/// See [`wol::send_magic_packet_tcp`].
+pub fn send_tcp(
+    destination: &SocketAddr,
+    options: &SocketOptions,
+    mac_address: MacAddress,
+    secure_on: Option<SecureOn>,
+) -> Result<()> {
+    let mut stream = connect(destination, options)?;
+    let mut packet = Vec::with_capacity(108);
+    wol::write_magic_packet(&mut packet, mac_address, secure_on)?;
+    stream.write_all(&packet)?;
+    stream.shutdown(Shutdown::Write)
+}
+
+/// Sockets to send magic packets over, bound once and reused for all
+/// destinations.
+///
+/// Keep one socket for each address family and set of options, to avoid
+/// binding a new socket for every magic packet, and to send all magic packets
+/// from the same source port.  Likewise keep the raw socket for --raw, and
+/// the UDP relays of SOCKS5 proxies for --proxy.
+#[derive(Debug, Default)]
+pub struct Sockets {
+    udp: HashMap<(bool, SocketOptions), UdpSocket>,
+    raw: Option<RawSocket>,
+    proxies: HashMap<Proxy, Association>,
+}
+
+impl Sockets {
+    /// Get a socket to send magic packets to `destination` with `options`.
+    ///
+    /// Bind a new socket if there's none yet for the address family of
+    /// `destination` and `options`.
+    ///
+    /// # Errors
+    ///
+    /// Return any error from binding a new socket.
+    pub fn get(&mut self, destination: &SocketAddr, options: &SocketOptions) -> Result<&UdpSocket> {
+        match self.udp.entry((destination.is_ipv4(), options.clone())) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(bind(destination, options)?)),
+        }
+    }
+
+    /// Get a raw socket to send Wake-on-LAN frames out of `interface`.
+    ///
+    /// Open the socket on first use.
+    ///
+    /// # Errors
+    ///
+    /// Return any error from opening the socket.
+    pub fn raw(&mut self, interface: &str) -> Result<&mut RawSocket> {
+        let raw = match self.raw.take() {
+            Some(raw) => raw,
+            None => RawSocket::open(interface)?,
+        };
+        Ok(self.raw.insert(raw))
+    }
+
+    /// Get a UDP relay of `proxy`.
+    ///
+    /// Associate a new relay if there's none yet for `proxy`.
+    ///
+    /// # Errors
+    ///
+    /// Return any error from associating a new relay.
+    pub fn proxy(&mut self, proxy: &Proxy) -> Result<&Association> {
+        match self.proxies.entry(proxy.clone()) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(Association::open(proxy)?)),
+        }
+    }
+}
+
+/// Broadcast destinations on all network interfaces.
+///
+/// Return the IPv4 broadcast address of every non-loopback interface, and the
+/// link-local all-nodes multicast address `ff02::1` scoped to every non-loopback
+/// interface with IPv6, each along with the name of the interface.
+pub fn broadcast_destinations(port: u16) -> Result<Vec<(String, SocketAddr)>> {
+    let mut destinations: Vec<(String, SocketAddr)> = Vec::new();
+    for interface in if_addrs::get_if_addrs()? {
+        if interface.is_loopback() || !interface.is_oper_up() {
+            continue;
+        }
+        let destination = match &interface.addr {
+            if_addrs::IfAddr::V4(addr) => addr
+                .broadcast
+                .map(|broadcast| SocketAddr::new(broadcast.into(), port)),
+            if_addrs::IfAddr::V6(_) => interface.index.map(|index| {
+                SocketAddrV6::new(Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1), port, 0, index).into()
+            }),
+        };
+        if let Some(destination) = destination {
+            let entry = (interface.name, destination);
+            if !destinations.contains(&entry) {
+                destinations.push(entry);
+            }
+        }
+    }
+    Ok(destinations)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+    use std::str::FromStr;
+
+    use super::{BindAddress, SocketOptions, Transport};
+
+    #[test]
+    fn bind_address() {
+        let cases = [
+            (
+                "192.0.2.1",
+                Some(IpAddr::from(Ipv4Addr::new(192, 0, 2, 1))),
+                0,
+            ),
+            (
+                "192.0.2.1:4000",
+                Some(IpAddr::from(Ipv4Addr::new(192, 0, 2, 1))),
+                4000,
+            ),
+            ("::1", Some(IpAddr::from(Ipv6Addr::LOCALHOST)), 0),
+            ("[::1]:4000", Some(IpAddr::from(Ipv6Addr::LOCALHOST)), 4000),
+            (":4000", None, 4000),
+        ];
+        for (s, ip, port) in cases {
+            assert_eq!(
+                BindAddress::from_str(s).unwrap(),
+                BindAddress { ip, port },
+                "{s}"
+            );
+        }
+        for s in ["", ":", "foo", "192.0.2.1:", ":70000"] {
+            assert!(BindAddress::from_str(s).is_err(), "{s}");
+        }
+        for s in ["192.0.2.1:4000", "[::1]:4000", ":4000"] {
+            assert_eq!(BindAddress::from_str(s).unwrap().to_string(), s);
+        }
+    }
+
+    #[test]
+    fn socket_options_display() {
+        assert_eq!(SocketOptions::default().to_string(), "");
+        let options = SocketOptions {
+            transport: Transport::Udp,
+            proxy: None,
+            interface: Some("eth0".into()),
+            bind: None,
+            ttl: Some(2),
+            tos: Some(0xc0),
+        };
+        assert_eq!(options.to_string(), "interface eth0, ttl 2, tos 0xc0");
+        let options = SocketOptions {
+            transport: Transport::Tcp,
+            ..options
+        };
+        assert_eq!(
+            options.to_string(),
+            "transport tcp, interface eth0, ttl 2, tos 0xc0"
+        );
+    }
+}
@@ -0,0 +1,277 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Wake up systems through SSH gateways.
+//!
+//! Log in to a gateway on the network of the systems to wake up with `ssh`,
+//! and run `wol wake` on the gateway, which then sends the magic packet on its
+//! network.  Routers rarely forward broadcasts, but an SSH login to any system
+//! on the other network is often at hand.
+//!
+//! Use the `ssh` of the system, so that all its configuration, keys, and agents
+//! apply.  Pass SecureON passwords on standard input, to keep them out of the
+//! process list of the gateway.
+
+use std::ffi::OsString;
+use std::fmt::Display;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+use wol::{MacAddress, SecureOn};
+
+/// How long `ssh` waits for the gateway, in seconds.
+const CONNECT_TIMEOUT: u32 = 10;
+
+/// An SSH gateway.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gateway {
+    user: Option<String>,
+    host: String,
+    port: Option<u16>,
+}
+
+impl Display for Gateway {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ssh://")?;
+        if let Some(user) = &self.user {
+            write!(f, "{user}@")?;
+        }
+        if self.host.contains(':') {
+            write!(f, "[{}]", self.host)?;
+        } else {
+            write!(f, "{}", self.host)?;
+        }
+        if let Some(port) = self.port {
+            write!(f, ":{port}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a gateway from `ssh://[USER@]HOST[:PORT]`.
+impl FromStr for Gateway {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let rest = s
+            .strip_prefix("ssh://")
+            .ok_or_else(|| format!("Expected ssh://[USER@]HOST[:PORT], got {s}"))?
+            .trim_end_matches('/');
+        let (user, address) = match rest.rsplit_once('@') {
+            Some((user, address)) => (Some(user), address),
+            None => (None, rest),
+        };
+        let (host, port) = if let Some(address) = address.strip_prefix('[') {
+            let (host, port) = address
+                .split_once(']')
+                .ok_or_else(|| format!("Missing ] in {s}"))?;
+            (host, port.strip_prefix(':'))
+        } else {
+            match address.split_once(':') {
+                Some((host, port)) => (host, Some(port)),
+                None => (address, None),
+            }
+        };
+        // Keep ssh from taking the user or host for an option
+        if host.is_empty() || host.starts_with('-') {
+            return Err(format!("Missing or invalid host in {s}"));
+        }
+        if user.is_some_and(|user| user.is_empty() || user.starts_with('-')) {
+            return Err(format!("Invalid user in {s}"));
+        }
+        let port = port
+            .map(u16::from_str)
+            .transpose()
+            .map_err(|error| format!("Invalid port in {s}: {error}"))?;
+        Ok(Self {
+            user: user.map(ToOwned::to_owned),
+            host: host.to_owned(),
+            port,
+        })
+    }
+}
+
+/// What to wake up through a gateway.
+#[derive(Debug)]
+pub struct Wakeup<'a> {
+    /// The hardware address to wake up.
+    pub hardware_address: MacAddress,
+    /// The SecureON password, if any.
+    pub secure_on: Option<SecureOn>,
+    /// The host to send magic packets to from the gateway.
+    pub host: String,
+    /// The ports to send magic packets to.
+    pub ports: &'a [u16],
+}
+
+/// The command line for `helper` to run on the gateway for `wakeup`.
+fn remote_command(helper: &str, wakeup: &Wakeup) -> Result<String> {
+    let ports = wakeup
+        .ports
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(",");
+    let mut words = vec![
+        helper.to_owned(),
+        "wake".to_owned(),
+        "--quiet".to_owned(),
+        "--host".to_owned(),
+        wakeup.host.clone(),
+        if 1 < wakeup.ports.len() {
+            "--ports"
+        } else {
+            "--port"
+        }
+        .to_owned(),
+        ports,
+    ];
+    if wakeup.secure_on.is_some() {
+        words.push("--passwd-stdin".to_owned());
+    }
+    words.push(wakeup.hardware_address.to_string());
+    shlex::try_join(words.iter().map(String::as_str))
+        .map_err(|error| Error::new(ErrorKind::InvalidInput, error))
+}
+
+/// The `ssh` command to run `remote_command` on `gateway`.
+fn ssh_command(gateway: &Gateway, remote_command: String) -> Command {
+    let mut command = Command::new("ssh");
+    command.args(["-T", "-o"]);
+    command.arg(format!("ConnectTimeout={CONNECT_TIMEOUT}"));
+    if let Some(port) = gateway.port {
+        command.arg("-p").arg(port.to_string());
+    }
+    if let Some(user) = &gateway.user {
+        command.arg("-l").arg(user);
+    }
+    command.args([OsString::from("--"), OsString::from(&gateway.host)]);
+    command.arg(remote_command);
+    command
+}
+
+/// Wake up a system through `gateway`, by running `helper` on the gateway.
+///
+/// `helper` is `wol` on the gateway.
+///
+/// # Errors
+///
+/// Return an error if `ssh` fails to run, if logging in to the gateway fails,
+/// or if `helper` fails on the gateway.
+pub fn wake(gateway: &Gateway, helper: &str, wakeup: &Wakeup) -> Result<()> {
+    let mut command = ssh_command(gateway, remote_command(helper, wakeup)?);
+    tracing::debug!(%gateway, ?command, "Waking up through SSH gateway");
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|error| Error::new(error.kind(), format!("Failed to run ssh: {error}")))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Some(secure_on) = wakeup.secure_on {
+            writeln!(stdin, "{secure_on}")?;
+        }
+    }
+    let output = child.wait_with_output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let reason = stderr
+        .lines()
+        .rfind(|line| !line.trim().is_empty())
+        .map_or_else(|| output.status.to_string(), |line| line.trim().to_owned());
+    // ssh exits with 255 if it fails itself, and with the exit code of the
+    // remote command otherwise
+    if output.status.code() == Some(255) {
+        Err(Error::other(format!(
+            "Failed to log in to {gateway}: {reason}"
+        )))
+    } else {
+        Err(Error::other(format!(
+            "{helper} failed on {gateway}: {reason}"
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_gateway() {
+        let cases = [
+            ("ssh://gateway", None, "gateway", None),
+            (
+                "ssh://alice@gateway:2222/",
+                Some("alice"),
+                "gateway",
+                Some(2222),
+            ),
+            ("ssh://[2001:db8::1]:22", None, "2001:db8::1", Some(22)),
+        ];
+        for (s, user, host, port) in cases {
+            let gateway = Gateway::from_str(s).unwrap();
+            assert_eq!(gateway.user.as_deref(), user, "{s}");
+            assert_eq!(gateway.host, host, "{s}");
+            assert_eq!(gateway.port, port, "{s}");
+            assert_eq!(gateway.to_string(), s.trim_end_matches('/'));
+        }
+        for s in [
+            "gateway",
+            "ssh://",
+            "ssh://-oProxyCommand=evil",
+            "ssh://-l@gateway",
+            "ssh://gateway:99999",
+        ] {
+            assert!(Gateway::from_str(s).is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn commands() {
+        let wakeup = Wakeup {
+            hardware_address: MacAddress::new([0x26, 0xCE, 0x55, 0xA5, 0xC2, 0x33]),
+            secure_on: None,
+            host: "192.168.1.255".to_owned(),
+            ports: &[9],
+        };
+        assert_eq!(
+            remote_command("wol", &wakeup).unwrap(),
+            "wol wake --quiet --host 192.168.1.255 --port 9 26:CE:55:A5:C2:33"
+        );
+        let wakeup = Wakeup {
+            secure_on: Some(SecureOn::new([0, 0xDE, 0xAD, 0xBE, 0xEF, 0])),
+            host: "ff02::1%eth0".to_owned(),
+            ports: &[7, 9],
+            ..wakeup
+        };
+        assert_eq!(
+            remote_command("/opt/wol/bin/wol", &wakeup).unwrap(),
+            "/opt/wol/bin/wol wake --quiet --host 'ff02::1%eth0' --ports '7,9' --passwd-stdin 26:CE:55:A5:C2:33"
+        );
+
+        let gateway = Gateway::from_str("ssh://alice@gateway:2222").unwrap();
+        let command = ssh_command(&gateway, "wol wake".to_owned());
+        assert_eq!(command.get_program(), "ssh");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            [
+                "-T",
+                "-o",
+                "ConnectTimeout=10",
+                "-p",
+                "2222",
+                "-l",
+                "alice",
+                "--",
+                "gateway",
+                "wol wake"
+            ]
+        );
+    }
+}
@@ -0,0 +1,371 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Show which systems are online.
+
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufReader, Error, ErrorKind, Result, stdin};
+use std::net::{IpAddr, SocketAddr, SocketAddrV6};
+use std::str::FromStr;
+use std::time::Duration;
+
+use clap::{ArgAction, Args, ValueHint};
+use wol::file::{MagicPacketDestination, WakeUpTarget};
+
+use super::PathOrStdin;
+use super::dns::Resolver;
+use super::hosts::HostsArgs;
+use super::online::{self, Probe};
+use super::socket;
+use super::target::Selector;
+
+/// The maximum number of systems to probe at the same time.
+const MAX_CONCURRENT_PROBES: usize = 32;
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct StatusArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Also check systems from wakeup FILE.
+    ///
+    /// Use - to read from standard input; repeat to check
+    /// systems from multiple files.
+    #[arg(
+        short = 'f',
+        long = "file",
+        value_name = "FILE",
+        action = ArgAction::Append,
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    file: Vec<PathOrStdin>,
+    /// Check whether systems are online with PROBE.
+    ///
+    /// PROBE is either ping to send ICMP echo requests, or
+    /// tcp:PORT to connect to PORT, e.g. tcp:22 for SSH.
+    #[arg(
+        long = "probe",
+        value_name = "PROBE",
+        default_value = "ping",
+        verbatim_doc_comment
+    )]
+    probe: Probe,
+    /// The systems to check, by name, hardware address, or
+    /// pattern, or @GROUP for all systems in a group.
+    ///
+    /// Check all systems if omitted.
+    #[arg(value_name = "NAME", verbatim_doc_comment)]
+    #[cfg_attr(
+        feature = "dynamic-completions",
+        arg(add = clap_complete::ArgValueCandidates::new(super::complete::systems))
+    )]
+    names: Vec<String>,
+    #[command(flatten)]
+    hosts: HostsArgs,
+}
+
+/// Whether a system is online.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    /// The system responded to the probe.
+    Up,
+    /// The system did not respond to the probe.
+    Down,
+    /// We could not probe the system.
+    Unknown(String),
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Status::Up => write!(f, "up"),
+            Status::Down => write!(f, "down"),
+            Status::Unknown(reason) => write!(f, "unknown ({reason})"),
+        }
+    }
+}
+
+/// The address to probe for `target`.
+///
+/// Use the address given with address=, or the destination of magic packets
+/// unless it is a broadcast or multicast address.
+pub fn probe_address(target: &WakeUpTarget) -> Option<&MagicPacketDestination> {
+    target.address().or_else(|| {
+        target
+            .packet_destination()
+            .filter(|destination| match destination {
+                MagicPacketDestination::Ip(IpAddr::V4(ip)) => {
+                    !ip.is_broadcast() && !ip.is_multicast()
+                }
+                MagicPacketDestination::Ip(ip) => !ip.is_multicast(),
+                MagicPacketDestination::ScopedIpv6(ip, _) => !ip.is_multicast(),
+                MagicPacketDestination::Dns(_) => true,
+                _ => false,
+            })
+    })
+}
+
+/// Resolve `address` to a socket address with port 0.
+///
+/// # Errors
+///
+/// Return an error if resolving a DNS name fails, or if the zone of a scoped
+/// IPv6 address is not a network interface.
+pub fn resolve(address: &MagicPacketDestination, resolver: &Resolver) -> Result<SocketAddr> {
+    match address {
+        MagicPacketDestination::Dns(name) => {
+            resolver.resolve(name, 0)?.first().copied().ok_or_else(|| {
+                Error::new(
+                    ErrorKind::HostUnreachable,
+                    format!("Host {name} not reachable"),
+                )
+            })
+        }
+        MagicPacketDestination::Ip(ip) => Ok(SocketAddr::new(*ip, 0)),
+        MagicPacketDestination::ScopedIpv6(ip, zone) => Ok(SocketAddr::V6(SocketAddrV6::new(
+            *ip,
+            0,
+            0,
+            socket::scope_id(zone)?,
+        ))),
+        address => Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("Unsupported address {address}"),
+        )),
+    }
+}
+
+/// Probe `target` once with `probe`.
+fn check(target: &WakeUpTarget, probe: Probe, resolver: &Resolver) -> Status {
+    let Some(address) = probe_address(target) else {
+        return Status::Unknown("no address".to_owned());
+    };
+    let socket_addr = match resolve(address, resolver) {
+        Ok(socket_addr) => socket_addr,
+        Err(error) => return Status::Unknown(error.to_string()),
+    };
+    // Without timeout wait_online gives up after the first attempt
+    match online::wait_online(probe, socket_addr, Duration::ZERO, || false) {
+        Ok(_) => Status::Up,
+        Err(error) if error.kind() == ErrorKind::TimedOut => Status::Down,
+        Err(error) => Status::Unknown(error.to_string()),
+    }
+}
+
+/// Probe all `targets` once with `probe`, concurrently.
+///
+/// Return the status of every target, in the order of `targets`.
+pub fn check_all(targets: &[WakeUpTarget], probe: Probe, resolver: &Resolver) -> Vec<Status> {
+    let mut statuses = Vec::with_capacity(targets.len());
+    for chunk in targets.chunks(MAX_CONCURRENT_PROBES) {
+        std::thread::scope(|scope| {
+            let handles = chunk
+                .iter()
+                .map(|target| scope.spawn(|| check(target, probe, resolver)))
+                .collect::<Vec<_>>();
+            for handle in handles {
+                statuses.push(
+                    handle
+                        .join()
+                        .unwrap_or_else(|_| Status::Unknown("probe failed".to_owned())),
+                );
+            }
+        });
+    }
+    statuses
+}
+
+/// Select all systems in `targets` which match any of `names`.
+///
+/// Resolve `@GROUP` with `groups`, and select all `targets` if `names` is
+/// empty.
+///
+/// # Errors
+///
+/// Return an error if a name or pattern is invalid, if it matches no system,
+/// or if a group is invalid.
+fn select(
+    targets: Vec<WakeUpTarget>,
+    names: &[String],
+    groups: impl Fn(&str) -> Result<Vec<WakeUpTarget>>,
+) -> Result<Vec<WakeUpTarget>> {
+    if names.is_empty() {
+        return Ok(targets);
+    }
+    let mut selected: Vec<WakeUpTarget> = Vec::new();
+    for name in names {
+        let matching = if let Some(group) = name.strip_prefix('@') {
+            groups(group)?
+        } else {
+            let selector = Selector::from_str(name)
+                .map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+            targets
+                .iter()
+                .filter(|target| selector.matches(target))
+                .cloned()
+                .collect()
+        };
+        if matching.is_empty() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("No system matches {name}"),
+            ));
+        }
+        for target in matching {
+            if !selected.contains(&target) {
+                selected.push(target);
+            }
+        }
+    }
+    Ok(selected)
+}
+
+/// Format `targets` and their `statuses` as a table with aligned columns.
+fn table(targets: &[WakeUpTarget], statuses: &[Status]) -> Vec<String> {
+    let header = ["NAME", "MAC", "ADDRESS", "STATUS"].map(str::to_owned);
+    let lines = std::iter::once(header)
+        .chain(targets.iter().zip(statuses).map(|(target, status)| {
+            [
+                target.name().unwrap_or("-").to_owned(),
+                target.hardware_address().to_string(),
+                probe_address(target).map_or_else(|| "-".to_owned(), ToString::to_string),
+                status.to_string(),
+            ]
+        }))
+        .collect::<Vec<_>>();
+    let mut widths = [0; 4];
+    for cells in &lines {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    lines
+        .iter()
+        .map(|cells| {
+            cells
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:width$}"))
+                .collect::<Vec<_>>()
+                .join("  ")
+                .trim_end()
+                .to_owned()
+        })
+        .collect()
+}
+
+/// Check which systems from the hosts database and all files are online,
+/// and print a table.
+///
+/// # Errors
+///
+/// Return an error if reading the hosts database or a file fails, if any
+/// system is invalid, or if a name matches no system.
+pub fn status(args: &StatusArgs) -> Result<()> {
+    let database = args.hosts.read()?;
+    let mut targets = database.targets()?;
+    for file in &args.file {
+        let file_targets: Box<dyn Iterator<Item = Result<WakeUpTarget>>> = match file {
+            PathOrStdin::Stdin => Box::new(wol::file::from_reader(BufReader::new(stdin()))),
+            PathOrStdin::Path(path) => {
+                Box::new(wol::file::from_reader(BufReader::new(File::open(path)?)))
+            }
+        };
+        for target in file_targets {
+            targets.push(target?);
+        }
+    }
+    let targets = select(targets, &args.names, |group| database.group(group))?;
+    let statuses = check_all(&targets, args.probe, &Resolver::default());
+    for line in table(&targets, &statuses) {
+        anstream::println!("{line}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, TcpListener};
+
+    use super::*;
+
+    fn targets() -> Vec<WakeUpTarget> {
+        [
+            "12:13:14:15:16:17 192.168.1.255 name=nas address=127.0.0.1",
+            "12:13:14:15:16:18 127.0.0.1 name=desktop",
+            "12:13:14:15:16:19 name=laptop",
+        ]
+        .into_iter()
+        .map(|line| WakeUpTarget::from_str(line).unwrap())
+        .collect()
+    }
+
+    #[test]
+    fn addresses() {
+        let addresses = targets()
+            .iter()
+            .map(|target| probe_address(target).map(ToString::to_string))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            addresses,
+            [
+                Some("127.0.0.1".to_owned()),
+                Some("127.0.0.1".to_owned()),
+                None
+            ]
+        );
+        let broadcast = WakeUpTarget::from_str("12:13:14:15:16:17 255.255.255.255").unwrap();
+        assert_eq!(probe_address(&broadcast), None);
+    }
+
+    #[test]
+    fn selection() {
+        let no_groups = |group: &str| -> Result<Vec<WakeUpTarget>> {
+            Err(Error::new(
+                ErrorKind::NotFound,
+                format!("No system in group {group}"),
+            ))
+        };
+        assert_eq!(select(targets(), &[], no_groups).unwrap(), targets());
+        let names = ["laptop", "12:13:14:15:16:17", "n*"].map(str::to_owned);
+        let selected = select(targets(), &names, no_groups).unwrap();
+        assert_eq!(
+            selected
+                .iter()
+                .map(|target| target.name().unwrap())
+                .collect::<Vec<_>>(),
+            ["laptop", "nas"]
+        );
+        let error = select(targets(), &["server".to_owned()], no_groups).unwrap_err();
+        assert_eq!(error.to_string(), "No system matches server");
+        let error = select(targets(), &["@lab".to_owned()], no_groups).unwrap_err();
+        assert_eq!(error.to_string(), "No system in group lab");
+    }
+
+    #[test]
+    fn check_and_table() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let probe = Probe::Tcp(listener.local_addr().unwrap().port());
+        let targets = targets();
+        let resolver = Resolver::default();
+        let statuses = targets
+            .iter()
+            .map(|target| check(target, probe, &resolver))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            table(&targets, &statuses),
+            [
+                "NAME     MAC                ADDRESS    STATUS",
+                "nas      12:13:14:15:16:17  127.0.0.1  up",
+                "desktop  12:13:14:15:16:18  127.0.0.1  up",
+                "laptop   12:13:14:15:16:19  -          unknown (no address)",
+            ]
+        );
+    }
+}
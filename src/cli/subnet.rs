@@ -0,0 +1,160 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! IPv4 subnets.
+
+use std::fmt::Display;
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+
+/// An IPv4 subnet in CIDR notation, e.g. `192.168.10.0/24`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ipv4Subnet {
+    address: Ipv4Addr,
+    prefix_len: u8,
+}
+
+impl Ipv4Subnet {
+    /// The netmask of this subnet.
+    pub fn netmask(self) -> Ipv4Addr {
+        Ipv4Addr::from_bits(
+            u32::MAX
+                .checked_shl(32 - u32::from(self.prefix_len))
+                .unwrap_or(0),
+        )
+    }
+
+    /// The network address of this subnet.
+    pub fn network(self) -> Ipv4Addr {
+        self.address & self.netmask()
+    }
+
+    /// The directed broadcast address of this subnet.
+    pub fn broadcast(self) -> Ipv4Addr {
+        self.address | !self.netmask()
+    }
+
+    /// The length of the network prefix of this subnet.
+    pub fn prefix_len(self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Whether `address` is in this subnet.
+    pub fn contains(self, address: Ipv4Addr) -> bool {
+        address & self.netmask() == self.network()
+    }
+
+    /// All host addresses in this subnet.
+    ///
+    /// Exclude the network and the broadcast address, except for /31 and /32
+    /// subnets which have no such addresses.
+    pub fn hosts(self) -> impl Iterator<Item = Ipv4Addr> {
+        let (first, last) = (self.network().to_bits(), self.broadcast().to_bits());
+        let (first, last) = if self.prefix_len < 31 {
+            (first + 1, last - 1)
+        } else {
+            (first, last)
+        };
+        (first..=last).map(Ipv4Addr::from_bits)
+    }
+}
+
+impl Display for Ipv4Subnet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network(), self.prefix_len)
+    }
+}
+
+impl FromStr for Ipv4Subnet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = s
+            .split_once('/')
+            .ok_or_else(|| format!("Expected IPv4 subnet ADDRESS/PREFIX, got {s}"))?;
+        let address = Ipv4Addr::from_str(address)
+            .map_err(|error| format!("Invalid IPv4 address {address}: {error}"))?;
+        let prefix_len = u8::from_str(prefix_len)
+            .ok()
+            .filter(|prefix_len| *prefix_len <= 32)
+            .ok_or_else(|| format!("Invalid prefix length {prefix_len}"))?;
+        Ok(Self {
+            address,
+            prefix_len,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+    use std::str::FromStr;
+
+    use super::Ipv4Subnet;
+
+    #[test]
+    fn broadcast() {
+        let cases = [
+            ("192.168.10.0/24", Ipv4Addr::new(192, 168, 10, 255)),
+            ("192.168.10.17/24", Ipv4Addr::new(192, 168, 10, 255)),
+            ("10.1.0.0/16", Ipv4Addr::new(10, 1, 255, 255)),
+            ("172.16.4.0/22", Ipv4Addr::new(172, 16, 7, 255)),
+            ("192.0.2.1/32", Ipv4Addr::new(192, 0, 2, 1)),
+            ("0.0.0.0/0", Ipv4Addr::BROADCAST),
+        ];
+        for (subnet, broadcast) in cases {
+            assert_eq!(
+                Ipv4Subnet::from_str(subnet).unwrap().broadcast(),
+                broadcast,
+                "{subnet}"
+            );
+        }
+    }
+
+    #[test]
+    fn network() {
+        let subnet = Ipv4Subnet::from_str("172.16.5.9/22").unwrap();
+        assert_eq!(subnet.network(), Ipv4Addr::new(172, 16, 4, 0));
+        assert_eq!(subnet.netmask(), Ipv4Addr::new(255, 255, 252, 0));
+        assert_eq!(subnet.to_string(), "172.16.4.0/22");
+    }
+
+    #[test]
+    fn hosts() {
+        let subnet = Ipv4Subnet::from_str("192.168.10.17/30").unwrap();
+        assert_eq!(
+            subnet.hosts().collect::<Vec<_>>(),
+            [
+                Ipv4Addr::new(192, 168, 10, 17),
+                Ipv4Addr::new(192, 168, 10, 18)
+            ]
+        );
+        assert!(subnet.contains(Ipv4Addr::new(192, 168, 10, 19)));
+        assert!(!subnet.contains(Ipv4Addr::new(192, 168, 10, 20)));
+        let subnet = Ipv4Subnet::from_str("192.0.2.1/32").unwrap();
+        assert_eq!(
+            subnet.hosts().collect::<Vec<_>>(),
+            [Ipv4Addr::new(192, 0, 2, 1)]
+        );
+        assert_eq!(
+            Ipv4Subnet::from_str("10.0.0.0/16").unwrap().hosts().count(),
+            65534
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        for subnet in [
+            "192.168.10.0",
+            "192.168.10.0/33",
+            "192.168.10/24",
+            "::1/64",
+            "/24",
+        ] {
+            assert!(Ipv4Subnet::from_str(subnet).is_err(), "{subnet}");
+        }
+    }
+}
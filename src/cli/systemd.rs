@@ -0,0 +1,412 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Integrate daemons with systemd.
+//!
+//! Take over sockets from systemd socket activation, so that systemd can start
+//! daemons on demand, and bind privileged ports for them.  With the `systemd`
+//! feature also tell systemd when daemons are ready, and ping its watchdog,
+//! with the notification protocol of systemd, without linking libsystemd.
+//!
+//! Also generate units which run daemons under systemd.
+
+use std::ffi::OsStr;
+use std::io::{Error, ErrorKind, Result};
+use std::net::{TcpListener, UdpSocket};
+#[cfg(all(unix, feature = "systemd"))]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(all(unix, feature = "systemd"))]
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+#[cfg(all(unix, feature = "systemd"))]
+use std::str::FromStr;
+#[cfg(all(unix, feature = "systemd"))]
+use std::time::Duration;
+
+#[cfg(unix)]
+use listenfd::ListenFd;
+
+use super::schedule::Schedule;
+
+/// Sockets which systemd passed to this process.
+#[derive(Debug, Default)]
+pub struct ListenFds {
+    /// Stream sockets to accept connections on.
+    pub listeners: Vec<TcpListener>,
+    /// Datagram sockets to receive packets on.
+    pub sockets: Vec<UdpSocket>,
+}
+
+/// Take over all sockets which systemd passed to this process.
+///
+/// Only the first call takes over sockets; later calls return no sockets.
+///
+/// # Errors
+///
+/// Return an error if systemd passed sockets other than TCP or UDP sockets.
+#[cfg(unix)]
+pub fn listen_fds() -> Result<ListenFds> {
+    // Takes the sockets out of the environment, lest we own them twice
+    let mut passed = ListenFd::from_env();
+    let mut fds = ListenFds::default();
+    for index in 0..passed.len() {
+        if let Ok(Some(listener)) = passed.take_tcp_listener(index) {
+            fds.listeners.push(listener);
+        } else if let Ok(Some(socket)) = passed.take_udp_socket(index) {
+            fds.sockets.push(socket);
+        } else {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("Unsupported type of socket {index} from systemd"),
+            ));
+        }
+    }
+    Ok(fds)
+}
+
+/// Take over all sockets which systemd passed to this process.
+///
+/// Without systemd there are never any sockets.
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+pub fn listen_fds() -> Result<ListenFds> {
+    Ok(ListenFds::default())
+}
+
+/// Send `state` to the notification socket of systemd at `path`.
+///
+/// Paths starting with `@` denote sockets in the abstract namespace of Linux.
+///
+/// # Errors
+///
+/// Return an error if sending fails.
+#[cfg(all(unix, feature = "systemd"))]
+fn notify(path: &OsStr, state: &str) -> Result<()> {
+    let socket = UnixDatagram::unbound()?;
+    let sent = match path.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &address)?
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "Abstract notification sockets need Linux",
+            ));
+        }
+        None => socket.send_to(state.as_bytes(), path)?,
+    };
+    if sent == state.len() {
+        Ok(())
+    } else {
+        Err(Error::new(
+            ErrorKind::WriteZero,
+            "Failed to send the whole notification",
+        ))
+    }
+}
+
+/// How often systemd wants to hear from the watchdog of process `pid`.
+///
+/// `watchdog_usec` and `watchdog_pid` are the values of `$WATCHDOG_USEC` and
+/// `$WATCHDOG_PID`.  Return `None` if systemd watches no process, or another
+/// process.
+#[cfg(all(unix, feature = "systemd"))]
+fn watchdog_interval(
+    watchdog_usec: Option<&str>,
+    watchdog_pid: Option<&str>,
+    pid: u32,
+) -> Option<Duration> {
+    if watchdog_pid.is_some_and(|watchdog_pid| u32::from_str(watchdog_pid).ok() != Some(pid)) {
+        return None;
+    }
+    watchdog_usec
+        .and_then(|usec| u64::from_str(usec).ok())
+        .filter(|usec| 0 < *usec)
+        .map(Duration::from_micros)
+}
+
+/// Tell systemd that this daemon is ready, with `status` for `systemctl
+/// status`, and ping the watchdog of systemd, if any, until the process exits.
+///
+/// Do nothing if systemd did not start this process.
+#[cfg(all(unix, feature = "systemd"))]
+pub fn notify_ready(status: &str) {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET").filter(|path| !path.is_empty()) else {
+        return;
+    };
+    if let Err(error) = notify(&path, &format!("READY=1\nSTATUS={status}")) {
+        tracing::warn!("Failed to notify systemd: {error}");
+        return;
+    }
+    let interval = watchdog_interval(
+        std::env::var("WATCHDOG_USEC").ok().as_deref(),
+        std::env::var("WATCHDOG_PID").ok().as_deref(),
+        std::process::id(),
+    );
+    if let Some(interval) = interval {
+        tracing::debug!("Pinging the systemd watchdog every {:?}", interval / 2);
+        std::thread::spawn(move || {
+            loop {
+                // Ping twice per interval, as systemd recommends
+                std::thread::sleep(interval / 2);
+                if let Err(error) = notify(&path, "WATCHDOG=1") {
+                    tracing::warn!("Failed to ping the systemd watchdog: {error}");
+                }
+            }
+        });
+    }
+}
+
+/// Tell systemd that this daemon is ready.
+///
+/// Without the `systemd` feature do nothing.
+#[cfg(not(all(unix, feature = "systemd")))]
+pub fn notify_ready(_status: &str) {}
+
+/// Quote `word` for a command line in a systemd unit.
+///
+/// Escape specifiers and variables, and quote words with whitespace, quotes,
+/// or backslashes.
+fn quote(word: &str) -> String {
+    let escaped = word.replace('%', "%%").replace('$', "$$");
+    let special = |c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | ';');
+    if !escaped.is_empty() && !escaped.contains(special) {
+        return escaped;
+    }
+    let mut quoted = String::with_capacity(escaped.len() + 2);
+    quoted.push('"');
+    for c in escaped.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\t' => quoted.push_str("\\t"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Convert `s` to UTF-8 for a systemd unit.
+fn utf8(s: &OsStr) -> Result<&str> {
+    s.to_str().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!("systemd units need UTF-8, got {}", s.to_string_lossy()),
+        )
+    })
+}
+
+/// The command line and the working directory for a systemd unit.
+fn command_line<S: AsRef<OsStr>>(command: &[S], directory: &Path) -> Result<(String, String)> {
+    let command = command
+        .iter()
+        .map(|word| utf8(word.as_ref()).map(quote))
+        .collect::<Result<Vec<_>>>()?
+        .join(" ");
+    let directory = utf8(directory.as_os_str())?.replace('%', "%%");
+    Ok((command, directory))
+}
+
+/// A systemd service unit with `description`, which runs `command` in
+/// `directory`.
+///
+/// Reload the service with `SIGHUP`.  With the `systemd` feature the service
+/// notifies systemd when it is ready, and pings the watchdog of systemd.
+///
+/// # Errors
+///
+/// Return an error if `command` or `directory` are not valid UTF-8.
+pub fn service_unit<S: AsRef<OsStr>>(
+    description: &str,
+    command: &[S],
+    directory: &Path,
+) -> Result<String> {
+    let (command, directory) = command_line(command, directory)?;
+    let service_type = if cfg!(feature = "systemd") {
+        "Type=notify\nWatchdogSec=30"
+    } else {
+        "Type=exec"
+    };
+    Ok(format!(
+        "[Unit]
+Description={description}
+Documentation={homepage}
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+{service_type}
+ExecStart={command}
+ExecReload=kill -HUP $MAINPID
+WorkingDirectory={directory}
+Restart=on-failure
+
+[Install]
+WantedBy=multi-user.target
+",
+        homepage = env!("CARGO_PKG_HOMEPAGE"),
+    ))
+}
+
+/// A systemd service unit with `description`, which runs `command` once in
+/// `directory`.
+///
+/// The unit has no install section; a timer of the same name starts it.
+///
+/// # Errors
+///
+/// Return an error if `command` or `directory` are not valid UTF-8.
+pub fn oneshot_unit<S: AsRef<OsStr>>(
+    description: &str,
+    command: &[S],
+    directory: &Path,
+) -> Result<String> {
+    let (command, directory) = command_line(command, directory)?;
+    Ok(format!(
+        "[Unit]
+Description={description}
+Documentation={homepage}
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+Type=oneshot
+ExecStart={command}
+WorkingDirectory={directory}
+",
+        homepage = env!("CARGO_PKG_HOMEPAGE"),
+    ))
+}
+
+/// A systemd timer with `description`, which starts the service of the same
+/// name on `schedule`.
+pub fn timer_unit(description: &str, schedule: &Schedule) -> String {
+    format!(
+        "[Unit]
+Description={description}
+Documentation={homepage}
+
+[Timer]
+OnCalendar={calendar}
+
+[Install]
+WantedBy=timers.target
+",
+        homepage = env!("CARGO_PKG_HOMEPAGE"),
+        calendar = schedule.on_calendar(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(all(unix, feature = "systemd"))]
+    #[test]
+    fn notify_socket() {
+        let path = std::env::temp_dir().join(format!("wol-notify-{}.sock", std::process::id()));
+        let socket = UnixDatagram::bind(&path).unwrap();
+        notify(path.as_os_str(), "READY=1\nSTATUS=Listening").unwrap();
+        let mut buffer = [0; 64];
+        let size = socket.recv(&mut buffer).unwrap();
+        assert_eq!(
+            buffer.get(..size),
+            Some(b"READY=1\nSTATUS=Listening".as_slice())
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[cfg(feature = "systemd")]
+    #[test]
+    fn notify_abstract_socket() {
+        use std::os::linux::net::SocketAddrExt;
+
+        let name = format!("wol-notify-{}", std::process::id());
+        let address = std::os::unix::net::SocketAddr::from_abstract_name(&name).unwrap();
+        let socket = UnixDatagram::bind_addr(&address).unwrap();
+        notify(OsStr::new(&format!("@{name}")), "WATCHDOG=1").unwrap();
+        let mut buffer = [0; 64];
+        let size = socket.recv(&mut buffer).unwrap();
+        assert_eq!(buffer.get(..size), Some(b"WATCHDOG=1".as_slice()));
+    }
+
+    #[cfg(all(unix, feature = "systemd"))]
+    #[test]
+    fn watchdog() {
+        assert_eq!(
+            watchdog_interval(Some("30000000"), None, 42),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            watchdog_interval(Some("30000000"), Some("42"), 42),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(watchdog_interval(Some("30000000"), Some("41"), 42), None);
+        assert_eq!(watchdog_interval(Some("0"), None, 42), None);
+        assert_eq!(watchdog_interval(None, None, 42), None);
+    }
+
+    #[test]
+    fn quote_words() {
+        assert_eq!(quote("/usr/bin/wol"), "/usr/bin/wol");
+        assert_eq!(quote("100%"), "100%%");
+        assert_eq!(quote("$HOME"), "$$HOME");
+        assert_eq!(quote(""), "\"\"");
+        assert_eq!(quote("my hosts.toml"), "\"my hosts.toml\"");
+        assert_eq!(quote(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+        assert_eq!(quote("a;b"), "\"a;b\"");
+    }
+
+    #[test]
+    fn unit() {
+        let unit = service_unit(
+            "Wake-on-LAN HTTP API",
+            &["/usr/bin/wol", "serve", "--hosts", "/etc/wol/my hosts.toml"],
+            Path::new("/var/lib/wol"),
+        )
+        .unwrap();
+        assert!(unit.starts_with("[Unit]\nDescription=Wake-on-LAN HTTP API\n"));
+        assert!(unit.contains(
+            "\nExecStart=/usr/bin/wol serve --hosts \"/etc/wol/my hosts.toml\"\nExecReload=kill -HUP $MAINPID\nWorkingDirectory=/var/lib/wol\n"
+        ));
+        assert!(unit.ends_with("[Install]\nWantedBy=multi-user.target\n"));
+        if cfg!(feature = "systemd") {
+            assert!(unit.contains("\nType=notify\nWatchdogSec=30\n"));
+        } else {
+            assert!(unit.contains("\nType=exec\n"));
+        }
+    }
+
+    #[test]
+    fn timer() {
+        use std::str::FromStr;
+
+        let unit = oneshot_unit(
+            "Wake up the NAS",
+            &["/usr/bin/wol", "wake", "nas"],
+            Path::new("/var/lib/wol"),
+        )
+        .unwrap();
+        assert!(unit.ends_with(
+            "[Service]\nType=oneshot\nExecStart=/usr/bin/wol wake nas\nWorkingDirectory=/var/lib/wol\n"
+        ));
+        let timer = timer_unit(
+            "Wake up the NAS",
+            &Schedule::from_str("Mon..Fri 7:30").unwrap(),
+        );
+        assert!(timer.starts_with("[Unit]\nDescription=Wake up the NAS\n"));
+        assert!(timer.ends_with(
+            "[Timer]\nOnCalendar=Mon,Tue,Wed,Thu,Fri *-*-* 07:30:00\n\n[Install]\nWantedBy=timers.target\n"
+        ));
+    }
+}
@@ -0,0 +1,452 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Targets given on the command line.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use regex::Regex;
+use wol::file::MagicPacketDestination;
+use wol::{MacAddress, SecureOn};
+
+/// A target given on the command line.
+///
+/// Parse targets in the format `MAC[@HOST[:PORT]][,passwd=SECUREON]`; enclose
+/// IPv6 addresses in brackets to give a port, as in `[2001:db8::1]:9`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CliTarget {
+    /// The hardware address to wake up.
+    pub hardware_address: MacAddress,
+    /// The host to send the magic packet to, if any.
+    pub host: Option<MagicPacketDestination>,
+    /// The port to send the magic packet to, if any.
+    pub port: Option<u16>,
+    /// The SecureON password, if any.
+    pub secure_on: Option<SecureOn>,
+}
+
+impl From<MacAddress> for CliTarget {
+    fn from(hardware_address: MacAddress) -> Self {
+        Self {
+            hardware_address,
+            host: None,
+            port: None,
+            secure_on: None,
+        }
+    }
+}
+
+/// Parse `HOST[:PORT]`.
+fn parse_destination(s: &str) -> Result<(MagicPacketDestination, Option<u16>), String> {
+    let (host, port) = if let Some(bracketed) = s.strip_prefix('[') {
+        let (host, rest) = bracketed
+            .split_once(']')
+            .ok_or_else(|| format!("Missing ] in {s}"))?;
+        match rest {
+            "" => (host, None),
+            _ => match rest.strip_prefix(':') {
+                Some(port) => (host, Some(port)),
+                None => return Err(format!("Expected :PORT after ] in {s}")),
+            },
+        }
+    } else {
+        match s.split_once(':') {
+            // More than one colon: An IPv6 address without port
+            Some((_, rest)) if rest.contains(':') => (s, None),
+            Some((host, port)) => (host, Some(port)),
+            None => (s, None),
+        }
+    };
+    if host.is_empty() {
+        return Err(format!("Missing host in {s}"));
+    }
+    let port = port
+        .map(|port| u16::from_str(port).map_err(|error| format!("Invalid port {port}: {error}")))
+        .transpose()?;
+    Ok((MagicPacketDestination::from(host.to_owned()), port))
+}
+
+impl FromStr for CliTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',');
+        let address = parts.next().unwrap_or_default();
+        let (hardware_address, destination) = match address.split_once('@') {
+            Some((hardware_address, destination)) => (hardware_address, Some(destination)),
+            None => (address, None),
+        };
+        let mut target =
+            Self::from(MacAddress::from_str(hardware_address).map_err(|error| {
+                format!("Invalid hardware address {hardware_address}: {error}")
+            })?);
+        if let Some(destination) = destination {
+            let (host, port) = parse_destination(destination)?;
+            target.host = Some(host);
+            target.port = port;
+        }
+        for option in parts {
+            match option.split_once('=') {
+                Some(("passwd", value)) => {
+                    target.secure_on = Some(
+                        SecureOn::from_str(value)
+                            .map_err(|error| format!("Invalid SecureON password: {error}"))?,
+                    );
+                }
+                _ => return Err(format!("Invalid option {option}, expected passwd=SECUREON")),
+            }
+        }
+        Ok(target)
+    }
+}
+
+/// The prefix of a regular expression [`Selector`].
+const REGEX_PREFIX: &str = "re:";
+
+/// Select a system from --file by hardware address, by name, or by pattern.
+#[derive(Debug, Clone)]
+pub enum Selector {
+    /// Select the system with this hardware address.
+    HardwareAddress(MacAddress),
+    /// Select the system with this name.
+    Name(String),
+    /// Select all systems whose name or hosts match `regex`.
+    Pattern {
+        /// The pattern as given.
+        source: String,
+        /// The regular expression of the pattern.
+        regex: Regex,
+    },
+}
+
+/// Convert a `glob` with `*` and `?` wildcards to an anchored regex.
+fn glob_to_regex(glob: &str) -> String {
+    let pattern = glob
+        .split_inclusive(['*', '?'])
+        .map(|part| match part.strip_suffix('*') {
+            Some(literal) => format!("{}.*", regex::escape(literal)),
+            None => match part.strip_suffix('?') {
+                Some(literal) => format!("{}.", regex::escape(literal)),
+                None => regex::escape(part),
+            },
+        })
+        .collect::<String>();
+    format!("^{pattern}$")
+}
+
+impl Selector {
+    /// Whether this selector selects `target`.
+    pub fn matches(&self, target: &wol::file::WakeUpTarget) -> bool {
+        match self {
+            Self::HardwareAddress(hardware_address) => {
+                *hardware_address == target.hardware_address()
+            }
+            Self::Name(name) => target.name() == Some(name),
+            Self::Pattern { regex, .. } => {
+                target.name().is_some_and(|name| regex.is_match(name))
+                    || target
+                        .packet_destination()
+                        .into_iter()
+                        .chain(target.address())
+                        .any(|host| regex.is_match(&host.to_string()))
+            }
+        }
+    }
+}
+
+impl Display for Selector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::HardwareAddress(hardware_address) => hardware_address.fmt(f),
+            Self::Name(name) => name.fmt(f),
+            Self::Pattern { source, .. } => source.fmt(f),
+        }
+    }
+}
+
+impl FromStr for Selector {
+    type Err = String;
+
+    /// Parse a hardware address, a regular expression after `re:`, a glob
+    /// with `*` or `?`, or otherwise a name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let pattern = match s.strip_prefix(REGEX_PREFIX) {
+            Some(regex) => regex.to_owned(),
+            None if s.contains(['*', '?']) => glob_to_regex(s),
+            None if s.is_empty() => return Err("Empty name".to_owned()),
+            None => {
+                return Ok(MacAddress::from_str(s)
+                    .map_or_else(|_| Self::Name(s.to_owned()), Self::HardwareAddress));
+            }
+        };
+        let regex =
+            Regex::new(&pattern).map_err(|error| format!("Invalid pattern {s}: {error}"))?;
+        Ok(Self::Pattern {
+            source: s.to_owned(),
+            regex,
+        })
+    }
+}
+
+/// A target on the command line, or systems from the hosts database.
+#[derive(Debug, Clone)]
+pub enum TargetArg {
+    /// A target given on the command line.
+    Target(CliTarget),
+    /// Systems in the hosts database.
+    Hosts(Selector),
+    /// All systems in a group in the hosts database.
+    Group(String),
+}
+
+impl FromStr for TargetArg {
+    type Err = String;
+
+    /// Parse a group after `@`, a [`CliTarget`], or otherwise a [`Selector`]
+    /// for the hosts database.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(group) = s.strip_prefix('@') {
+            return if group.is_empty() {
+                Err("Empty group".to_owned())
+            } else {
+                Ok(Self::Group(group.to_owned()))
+            };
+        }
+        match CliTarget::from_str(s) {
+            Ok(target) => Ok(Self::Target(target)),
+            // Hosts have no destination or password on the command line
+            Err(error) if s.contains(['@', ',']) => Err(error),
+            Err(_) => Selector::from_str(s).map(Self::Hosts),
+        }
+    }
+}
+
+/// The maximum number of hardware addresses in a [`MacRange`].
+const MAX_RANGE_LEN: u64 = 4096;
+
+/// An inclusive range of hardware addresses, in the format `START-END`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MacRange {
+    start: u64,
+    end: u64,
+}
+
+/// Convert a hardware address to an integer.
+fn to_u64(address: MacAddress) -> u64 {
+    let mut bytes = [0; 8];
+    let [_, _, address_bytes @ ..] = &mut bytes;
+    *address_bytes = address.into();
+    u64::from_be_bytes(bytes)
+}
+
+/// Convert an integer to a hardware address.
+fn from_u64(value: u64) -> MacAddress {
+    let [_, _, address_bytes @ ..] = value.to_be_bytes();
+    MacAddress::new(address_bytes)
+}
+
+impl MacRange {
+    /// Iterate over all hardware addresses in this range.
+    pub fn iter(self) -> impl Iterator<Item = MacAddress> {
+        (self.start..=self.end).map(from_u64)
+    }
+}
+
+impl FromStr for MacRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Hardware addresses may contain dashes themselves, so try every dash
+        let (start, end) = s
+            .match_indices('-')
+            .find_map(|(index, _)| {
+                let start = MacAddress::from_str(s.get(..index)?).ok()?;
+                let end = MacAddress::from_str(s.get(index + 1..)?).ok()?;
+                Some((to_u64(start), to_u64(end)))
+            })
+            .ok_or_else(|| format!("Invalid range {s}, expected START-END"))?;
+        if end < start {
+            Err(format!("Invalid range {s}: END before START"))
+        } else if MAX_RANGE_LEN <= end - start {
+            Err(format!(
+                "Range {s} too large, expected at most {MAX_RANGE_LEN} addresses"
+            ))
+        } else {
+            Ok(Self { start, end })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    use super::*;
+
+    #[test]
+    fn hardware_address_only() {
+        assert_eq!(
+            CliTarget::from_str("12:13:14:15:16:17").unwrap(),
+            CliTarget::from(MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]))
+        );
+    }
+
+    #[test]
+    fn with_destination() {
+        let target = CliTarget::from_str("12:13:14:15:16:17@192.168.1.255:7").unwrap();
+        assert_eq!(
+            target.host,
+            Some(MagicPacketDestination::Ip(IpAddr::V4(Ipv4Addr::new(
+                192, 168, 1, 255
+            ))))
+        );
+        assert_eq!(target.port, Some(7));
+
+        let target = CliTarget::from_str("12:13:14:15:16:17@nas.example.com").unwrap();
+        assert_eq!(
+            target.host,
+            Some(MagicPacketDestination::Dns("nas.example.com".into()))
+        );
+        assert_eq!(target.port, None);
+    }
+
+    #[test]
+    fn with_ipv6_destination() {
+        let target = CliTarget::from_str("12:13:14:15:16:17@ff02::1").unwrap();
+        assert_eq!(
+            target.host,
+            Some(MagicPacketDestination::Ip(IpAddr::V6(Ipv6Addr::new(
+                0xff02, 0, 0, 0, 0, 0, 0, 1
+            ))))
+        );
+        assert_eq!(target.port, None);
+
+        let target = CliTarget::from_str("12:13:14:15:16:17@[ff02::1%eth0]:9").unwrap();
+        assert_eq!(
+            target.host,
+            Some(MagicPacketDestination::ScopedIpv6(
+                Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1),
+                "eth0".into()
+            ))
+        );
+        assert_eq!(target.port, Some(9));
+    }
+
+    #[test]
+    fn with_passwd() {
+        let target = CliTarget::from_str("12:13:14:15:16:17,passwd=cd-23-ae-9d-3f-c2").unwrap();
+        assert_eq!(target.host, None);
+        assert_eq!(
+            target.secure_on,
+            Some(SecureOn::new([0xcd, 0x23, 0xae, 0x9d, 0x3f, 0xc2]))
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(CliTarget::from_str("12:13:14:15:16").is_err());
+        assert!(CliTarget::from_str("12:13:14:15:16:17@").is_err());
+        assert!(CliTarget::from_str("12:13:14:15:16:17@host:port").is_err());
+        assert!(CliTarget::from_str("12:13:14:15:16:17@[ff02::1").is_err());
+        assert!(CliTarget::from_str("12:13:14:15:16:17@[ff02::1]9").is_err());
+        assert!(CliTarget::from_str("12:13:14:15:16:17,name=foo").is_err());
+    }
+
+    #[test]
+    fn selector() {
+        let target =
+            wol::file::WakeUpTarget::new(MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]))
+                .with_name(Some("nas".to_owned()));
+        assert!(Selector::from_str("nas").unwrap().matches(&target));
+        assert!(
+            Selector::from_str("12-13-14-15-16-17")
+                .unwrap()
+                .matches(&target)
+        );
+        assert!(!Selector::from_str("router").unwrap().matches(&target));
+        assert!(
+            !Selector::from_str("12:13:14:15:16:18")
+                .unwrap()
+                .matches(&target)
+        );
+        assert!(Selector::from_str("").is_err());
+    }
+
+    #[test]
+    fn target_arg() {
+        assert!(matches!(
+            TargetArg::from_str("@renderfarm"),
+            Ok(TargetArg::Group(group)) if group == "renderfarm"
+        ));
+        assert!(matches!(
+            TargetArg::from_str("12:13:14:15:16:17@192.168.1.255"),
+            Ok(TargetArg::Target(_))
+        ));
+        assert!(matches!(
+            TargetArg::from_str("nas"),
+            Ok(TargetArg::Hosts(Selector::Name(name))) if name == "nas"
+        ));
+        assert!(TargetArg::from_str("@").is_err());
+        assert!(TargetArg::from_str("nas@192.168.1.255").is_err());
+    }
+
+    #[test]
+    fn pattern_selector() {
+        let target =
+            wol::file::WakeUpTarget::new(MacAddress::new([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]))
+                .with_name(Some("web-1".to_owned()))
+                .with_address(Some(MagicPacketDestination::Dns(
+                    "agent-3.build.example.com".to_owned(),
+                )));
+        assert!(Selector::from_str("web-*").unwrap().matches(&target));
+        assert!(Selector::from_str("web-?").unwrap().matches(&target));
+        assert!(!Selector::from_str("web").unwrap().matches(&target));
+        assert!(!Selector::from_str("*.web-1").unwrap().matches(&target));
+        assert!(
+            Selector::from_str("*.build.example.com")
+                .unwrap()
+                .matches(&target)
+        );
+        assert!(
+            Selector::from_str("re:^agent-\\d+\\.")
+                .unwrap()
+                .matches(&target)
+        );
+        assert!(!Selector::from_str("re:^db-").unwrap().matches(&target));
+        assert!(Selector::from_str("re:(").is_err());
+    }
+
+    #[test]
+    fn glob_regex() {
+        assert_eq!(glob_to_regex("web-*"), "^web\\-.*$");
+        assert_eq!(glob_to_regex("a.?*"), "^a\\...*$");
+    }
+
+    #[test]
+    fn mac_range() {
+        let range = MacRange::from_str("AA:BB:CC:00:00:fe-AA:BB:CC:00:01:01").unwrap();
+        assert_eq!(
+            range.iter().map(|mac| mac.to_string()).collect::<Vec<_>>(),
+            vec![
+                "AA:BB:CC:00:00:FE",
+                "AA:BB:CC:00:00:FF",
+                "AA:BB:CC:00:01:00",
+                "AA:BB:CC:00:01:01"
+            ]
+        );
+        let range = MacRange::from_str("aa-bb-cc-00-00-01-aa-bb-cc-00-00-01").unwrap();
+        assert_eq!(range.iter().count(), 1);
+    }
+
+    #[test]
+    fn invalid_mac_range() {
+        assert!(MacRange::from_str("AA:BB:CC:00:00:01").is_err());
+        assert!(MacRange::from_str("AA:BB:CC:00:00:02-AA:BB:CC:00:00:01").is_err());
+        assert!(MacRange::from_str("AA:BB:CC:00:00:00-AA:BB:CC:00:10:00").is_err());
+        assert!(MacRange::from_str("AA:BB:CC:00:00:00-AA:BB:CC:00:0f:ff").is_ok());
+    }
+}
@@ -0,0 +1,275 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! API tokens for the HTTP API, with their permissions, and the systems they
+//! may wake up.
+
+use std::fmt::Display;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::str::FromStr;
+
+use wol::file::WakeUpTarget;
+
+use super::hosts::Hosts;
+use super::target::Selector;
+
+/// What a token permits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Permission {
+    /// Only wake up systems.
+    Wake,
+    /// Everything, including listing and probing systems.
+    Admin,
+}
+
+impl Display for Permission {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Permission::Wake => write!(f, "wake"),
+            Permission::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+impl FromStr for Permission {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "wake" => Ok(Self::Wake),
+            "admin" => Ok(Self::Admin),
+            _ => Err(format!("Invalid permission {s}, expected wake or admin")),
+        }
+    }
+}
+
+/// Systems a token may wake up.
+#[derive(Debug, Clone)]
+pub enum Scope {
+    /// All systems the selector selects.
+    Systems(Selector),
+    /// All systems in a group in the hosts database.
+    Group(String),
+}
+
+impl Scope {
+    /// Whether this scope includes `target`, with groups from `hosts`.
+    fn includes(&self, target: &WakeUpTarget, hosts: &Hosts) -> bool {
+        match self {
+            Self::Systems(selector) => selector.matches(target),
+            // A group which does not exist has no systems
+            Self::Group(group) => hosts.group(group).is_ok_and(|members| {
+                members
+                    .iter()
+                    .any(|member| member.hardware_address() == target.hardware_address())
+            }),
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    /// Parse a group after `@`, or otherwise a [`Selector`].
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.strip_prefix('@') {
+            Some("") => Err("Empty group".to_owned()),
+            Some(group) => Ok(Self::Group(group.to_owned())),
+            None => Selector::from_str(s).map(Self::Systems),
+        }
+    }
+}
+
+/// What a token grants.
+#[derive(Debug, Clone)]
+pub struct Grant {
+    /// The name of the token, to tell clients apart in the audit log.
+    pub name: Option<String>,
+    /// What the token permits.
+    pub permission: Permission,
+    /// The systems the token may wake up, or empty for all systems.
+    pub scopes: Vec<Scope>,
+}
+
+impl Grant {
+    /// Whether this grant permits waking up `target`, with groups from
+    /// `hosts`.
+    pub fn may_wake(&self, target: &WakeUpTarget, hosts: &Hosts) -> bool {
+        self.scopes.is_empty()
+            || self
+                .scopes
+                .iter()
+                .any(|scope| scope.includes(target, hosts))
+    }
+}
+
+/// Compare `a` and `b` in time independent of their contents.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// API tokens and what they grant.
+#[derive(Debug, Default, Clone)]
+pub struct Tokens(Vec<(String, Grant)>);
+
+impl Tokens {
+    /// Parse tokens from `contents`.
+    ///
+    /// Each line has a token and its permission, either `wake` or `admin`,
+    /// and optionally a name as `name=NAME`, and the systems the token may
+    /// wake up, as names, patterns, hardware addresses, or `@GROUP`, all
+    /// separated by whitespace.  Ignore empty lines and lines starting with
+    /// `#`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if a line is invalid.
+    pub fn parse(contents: &str) -> Result<Self> {
+        let mut tokens = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = |message: String| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    format!("Line {}: {message}", index + 1),
+                )
+            };
+            let mut fields = line.split_whitespace();
+            let (Some(token), Some(permission)) = (fields.next(), fields.next()) else {
+                return Err(invalid("Expected token and permission".to_owned()));
+            };
+            if tokens.iter().any(|(other, _)| other == token) {
+                return Err(invalid("Duplicate token".to_owned()));
+            }
+            let mut grant = Grant {
+                name: None,
+                permission: Permission::from_str(permission).map_err(invalid)?,
+                scopes: Vec::new(),
+            };
+            for field in fields {
+                match field.strip_prefix("name=") {
+                    Some("") => return Err(invalid("Empty token name".to_owned())),
+                    Some(name) => grant.name = Some(name.to_owned()),
+                    None => grant.scopes.push(Scope::from_str(field).map_err(invalid)?),
+                }
+            }
+            tokens.push((token.to_owned(), grant));
+        }
+        Ok(Self(tokens))
+    }
+
+    /// Read tokens from the file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if the file could not be read, or is invalid.
+    pub fn read(path: &Path) -> Result<Self> {
+        std::fs::read_to_string(path)
+            .and_then(|contents| Self::parse(&contents))
+            .map_err(|error| {
+                Error::new(
+                    error.kind(),
+                    format!("Failed to read API tokens from {}: {error}", path.display()),
+                )
+            })
+    }
+
+    /// Get what `token` grants, if it's a known token.
+    ///
+    /// Compare against all tokens in constant time, lest the time of the
+    /// comparison reveal how much of a token was right.
+    pub fn grant(&self, token: &str) -> Option<&Grant> {
+        self.0.iter().fold(None, |found, (candidate, grant)| {
+            if constant_time_eq(candidate.as_bytes(), token.as_bytes()) {
+                Some(grant)
+            } else {
+                found
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn permission(tokens: &Tokens, token: &str) -> Option<Permission> {
+        tokens.grant(token).map(|grant| grant.permission)
+    }
+
+    #[test]
+    fn parse() {
+        let tokens = Tokens::parse(
+            "# Home automation\n\nc2f3a1d8 wake name=home-assistant\n  9e8d7c6b\tadmin\n",
+        )
+        .unwrap();
+        assert_eq!(permission(&tokens, "c2f3a1d8"), Some(Permission::Wake));
+        assert_eq!(permission(&tokens, "9e8d7c6b"), Some(Permission::Admin));
+        assert_eq!(permission(&tokens, "9e8d7c6"), None);
+        assert_eq!(permission(&tokens, ""), None);
+        let name = |token| tokens.grant(token).and_then(|grant| grant.name.as_deref());
+        assert_eq!(name("c2f3a1d8"), Some("home-assistant"));
+        assert_eq!(name("9e8d7c6b"), None);
+    }
+
+    #[test]
+    fn scopes() {
+        let hosts = Hosts::parse(
+            "[nas]\nmac = \"12:13:14:15:16:17\"\n\n[office-pc]\nmac = \"12:13:14:15:16:18\"\n\n[ceo]\nmac = \"12:13:14:15:16:19\"\n\n[groups]\nstorage = [\"nas\"]\n",
+        )
+        .unwrap();
+        let targets = hosts.targets().unwrap();
+        let [nas, office, ceo] = targets.as_slice() else {
+            panic!("Expected three systems, got {targets:?}");
+        };
+        let tokens = Tokens::parse(
+            "all wake\nstorage wake @storage\noffice wake office-* 12:13:14:15:16:17\nnone wake @missing\n",
+        )
+        .unwrap();
+        let may_wake = |token: &str, target: &WakeUpTarget| {
+            tokens.grant(token).unwrap().may_wake(target, &hosts)
+        };
+        assert!(may_wake("all", ceo));
+        assert!(may_wake("storage", nas));
+        assert!(!may_wake("storage", office));
+        assert!(may_wake("office", office));
+        assert!(may_wake("office", nas));
+        assert!(!may_wake("office", ceo));
+        assert!(!may_wake("none", nas));
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(
+            Tokens::parse("c2f3a1d8\n").unwrap_err().to_string(),
+            "Line 1: Expected token and permission"
+        );
+        assert_eq!(
+            Tokens::parse("\nc2f3a1d8 root\n").unwrap_err().to_string(),
+            "Line 2: Invalid permission root, expected wake or admin"
+        );
+        assert_eq!(
+            Tokens::parse("c2f3a1d8 wake @\n").unwrap_err().to_string(),
+            "Line 1: Empty group"
+        );
+        assert_eq!(
+            Tokens::parse("c2f3a1d8 wake name=\n")
+                .unwrap_err()
+                .to_string(),
+            "Line 1: Empty token name"
+        );
+        assert_eq!(
+            Tokens::parse("c2f3a1d8 wake\nc2f3a1d8 admin\n")
+                .unwrap_err()
+                .to_string(),
+            "Line 2: Duplicate token"
+        );
+    }
+}
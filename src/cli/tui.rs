@@ -0,0 +1,390 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Pick systems to wake up interactively.
+
+use std::io::{Error, ErrorKind, IsTerminal, Result, stdin, stdout};
+use std::process::ExitCode;
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+
+use clap::{ArgAction, Args, Parser};
+use ratatui::Frame;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Span;
+use ratatui::widgets::{Cell, Row, Table, TableState};
+use wol::file::WakeUpTarget;
+
+use super::dns::Resolver;
+use super::hosts::{Host, HostsArgs};
+use super::online::Probe;
+use super::status::{self, Status};
+use super::wake::{self, WakeArgs, parse_secs};
+
+/// How often to check for key presses and new statuses.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct TuiArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Check whether systems are online with PROBE.
+    ///
+    /// PROBE is either ping to send ICMP echo requests, or
+    /// tcp:PORT to connect to PORT, e.g. tcp:22 for SSH.
+    #[arg(
+        long = "probe",
+        value_name = "PROBE",
+        default_value = "ping",
+        verbatim_doc_comment
+    )]
+    probe: Probe,
+    /// Check all systems every SECS seconds.
+    #[arg(
+        long = "interval",
+        value_name = "SECS",
+        default_value = "5",
+        value_parser = parse_secs
+    )]
+    interval: Duration,
+    #[command(flatten)]
+    hosts: HostsArgs,
+}
+
+/// Parse arguments for waking up systems from the picker.
+#[derive(Parser, Debug)]
+#[command(name = "wol", disable_help_flag = true)]
+struct WakeCommand {
+    #[command(flatten)]
+    wake: WakeArgs,
+}
+
+/// What to do after a key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    /// Keep picking.
+    Continue,
+    /// Wake up the picked systems.
+    Wake,
+    /// Quit without waking up any system.
+    Quit,
+}
+
+/// The state of the picker.
+struct Picker {
+    hosts: Vec<Host>,
+    picked: Vec<bool>,
+    statuses: Vec<Option<Status>>,
+    table: TableState,
+}
+
+impl Picker {
+    fn new(hosts: Vec<Host>) -> Self {
+        let table = TableState::default().with_selected((!hosts.is_empty()).then_some(0));
+        Self {
+            picked: vec![false; hosts.len()],
+            statuses: vec![None; hosts.len()],
+            hosts,
+            table,
+        }
+    }
+
+    /// The names of all picked systems, or the system under the cursor if no
+    /// system is picked.
+    fn names(&self) -> Vec<&str> {
+        let picked = self
+            .hosts
+            .iter()
+            .zip(&self.picked)
+            .filter(|(_, picked)| **picked)
+            .map(|(host, _)| host.target.name().unwrap_or_default())
+            .collect::<Vec<_>>();
+        if picked.is_empty() {
+            self.table
+                .selected()
+                .and_then(|index| self.hosts.get(index))
+                .and_then(|host| host.target.name())
+                .into_iter()
+                .collect()
+        } else {
+            picked
+        }
+    }
+
+    /// Handle a press of `key` with `modifiers`.
+    fn handle(&mut self, key: KeyCode, modifiers: KeyModifiers) -> Action {
+        match key {
+            KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => Action::Quit,
+            KeyCode::Char('q') | KeyCode::Esc => Action::Quit,
+            KeyCode::Enter | KeyCode::Char('w') => {
+                if self.names().is_empty() {
+                    Action::Continue
+                } else {
+                    Action::Wake
+                }
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.table.select_previous();
+                Action::Continue
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                if self
+                    .table
+                    .selected()
+                    .is_some_and(|index| index + 1 < self.hosts.len())
+                {
+                    self.table.select_next();
+                }
+                Action::Continue
+            }
+            KeyCode::Char(' ') => {
+                if let Some(picked) = self
+                    .table
+                    .selected()
+                    .and_then(|index| self.picked.get_mut(index))
+                {
+                    *picked = !*picked;
+                }
+                Action::Continue
+            }
+            KeyCode::Char('a') => {
+                let all = self.picked.iter().all(|picked| *picked);
+                self.picked.fill(!all);
+                Action::Continue
+            }
+            _ => Action::Continue,
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame<'_>) {
+        let [main, footer] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let rows = self.hosts.iter().zip(&self.picked).zip(&self.statuses).map(
+            |((host, picked), status)| {
+                let status = match status {
+                    None => Span::styled("…", Style::new().fg(Color::DarkGray)),
+                    Some(Status::Up) => Span::styled("up", Style::new().fg(Color::Green)),
+                    Some(Status::Down) => Span::styled("down", Style::new().fg(Color::Red)),
+                    Some(status @ Status::Unknown(_)) => {
+                        Span::styled(status.to_string(), Style::new().fg(Color::Yellow))
+                    }
+                };
+                Row::new([
+                    Cell::from(if *picked { "[x]" } else { "[ ]" }),
+                    Cell::from(host.target.name().unwrap_or("-").to_owned()),
+                    Cell::from(host.target.hardware_address().to_string()),
+                    Cell::from(host.tags.join(",")),
+                    Cell::from(status),
+                ])
+            },
+        );
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(3),
+                Constraint::Fill(1),
+                Constraint::Length(17),
+                Constraint::Fill(1),
+                Constraint::Fill(1),
+            ],
+        )
+        .header(
+            Row::new(["", "NAME", "MAC", "TAGS", "STATUS"])
+                .style(Style::new().add_modifier(Modifier::BOLD)),
+        )
+        .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, main, &mut self.table);
+        frame.render_widget(
+            Span::styled(
+                "↑/↓ move  space pick  a pick all  enter wake up  q quit",
+                Style::new().fg(Color::DarkGray),
+            ),
+            footer,
+        );
+    }
+}
+
+/// Check all `targets` with `probe` every `interval`, in a background thread.
+///
+/// Stop once the returned receiver is dropped.
+fn watch(targets: Vec<WakeUpTarget>, probe: Probe, interval: Duration) -> Receiver<Vec<Status>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let resolver = Resolver::default();
+        loop {
+            if sender
+                .send(status::check_all(&targets, probe, &resolver))
+                .is_err()
+            {
+                break;
+            }
+            std::thread::sleep(interval);
+        }
+    });
+    receiver
+}
+
+/// Show the picker until the user wakes up systems or quits.
+///
+/// Return the names of the systems to wake up, or `None` to quit.
+fn pick(picker: &mut Picker, statuses: &Receiver<Vec<Status>>) -> Result<Option<Vec<String>>> {
+    let mut terminal = ratatui::init();
+    let result = loop {
+        if let Some(latest) = statuses.try_iter().last() {
+            picker.statuses = latest.into_iter().map(Some).collect();
+        }
+        if let Err(error) = terminal.draw(|frame| picker.draw(frame)) {
+            break Err(error);
+        }
+        match event::poll(POLL_INTERVAL).and_then(|ready| ready.then(event::read).transpose()) {
+            Ok(Some(Event::Key(key))) if key.kind == KeyEventKind::Press => {
+                match picker.handle(key.code, key.modifiers) {
+                    Action::Continue => {}
+                    Action::Wake => {
+                        break Ok(Some(
+                            picker.names().into_iter().map(str::to_owned).collect(),
+                        ));
+                    }
+                    Action::Quit => break Ok(None),
+                }
+            }
+            Ok(_) => {}
+            Err(error) => break Err(error),
+        }
+    };
+    ratatui::restore();
+    result
+}
+
+/// Pick systems from the hosts database interactively, and wake them up.
+///
+/// Show all systems with their current status, and wake up the picked
+/// systems, as `wol` would.  Return to the picker afterwards.
+///
+/// # Errors
+///
+/// Return an error if not running in a terminal, if reading the hosts
+/// database fails, or if the terminal fails.
+pub fn tui(args: &TuiArgs) -> Result<ExitCode> {
+    if !stdout().is_terminal() || !stdin().is_terminal() {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "wol tui needs a terminal",
+        ));
+    }
+    let path = args.hosts.path()?;
+    let hosts = args.hosts.read()?.hosts()?;
+    if hosts.is_empty() {
+        return Err(Error::new(
+            ErrorKind::NotFound,
+            format!("No systems in {}", path.display()),
+        ));
+    }
+    let statuses = watch(
+        hosts.iter().map(|host| host.target.clone()).collect(),
+        args.probe,
+        args.interval,
+    );
+    let mut picker = Picker::new(hosts);
+    while let Some(names) = pick(&mut picker, &statuses)? {
+        let wake_args = std::iter::once("wol".into())
+            .chain(["--hosts".into(), path.clone().into_os_string()])
+            .chain(names.into_iter().map(Into::into))
+            .collect::<Vec<std::ffi::OsString>>();
+        let command = WakeCommand::try_parse_from(wake_args)
+            .map_err(|error| Error::other(error.to_string()))?;
+        wake::wake(&command.wake);
+        anstream::println!("Press enter to return");
+        stdin().read_line(&mut String::new())?;
+        picker.picked.fill(false);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+
+    use super::*;
+
+    fn picker() -> Picker {
+        Picker::new(
+            ["nas", "desktop", "laptop"]
+                .into_iter()
+                .enumerate()
+                .map(|(index, name)| Host {
+                    target: WakeUpTarget::from_str(&format!("12:13:14:15:16:1{index} name={name}"))
+                        .unwrap(),
+                    tags: vec!["lab".to_owned()],
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn keys() {
+        let mut picker = picker();
+        let none = KeyModifiers::NONE;
+        assert_eq!(picker.names(), ["nas"]);
+        assert_eq!(picker.handle(KeyCode::Up, none), Action::Continue);
+        assert_eq!(picker.names(), ["nas"]);
+        picker.handle(KeyCode::Down, none);
+        picker.handle(KeyCode::Char(' '), none);
+        picker.handle(KeyCode::Down, none);
+        picker.handle(KeyCode::Down, none);
+        picker.handle(KeyCode::Char(' '), none);
+        assert_eq!(picker.names(), ["desktop", "laptop"]);
+        picker.handle(KeyCode::Char('a'), none);
+        assert_eq!(picker.names(), ["nas", "desktop", "laptop"]);
+        picker.handle(KeyCode::Char('a'), none);
+        assert_eq!(picker.names(), ["laptop"]);
+        assert_eq!(picker.handle(KeyCode::Enter, none), Action::Wake);
+        assert_eq!(picker.handle(KeyCode::Char('q'), none), Action::Quit);
+        assert_eq!(
+            picker.handle(KeyCode::Char('c'), KeyModifiers::CONTROL),
+            Action::Quit
+        );
+    }
+
+    #[test]
+    fn draw() {
+        let mut picker = picker();
+        picker.statuses = vec![Some(Status::Up), Some(Status::Down), None];
+        picker.handle(KeyCode::Char(' '), KeyModifiers::NONE);
+        let mut terminal = Terminal::new(TestBackend::new(60, 5)).unwrap();
+        terminal.draw(|frame| picker.draw(frame)).unwrap();
+        let lines = terminal
+            .backend()
+            .buffer()
+            .content()
+            .chunks(60)
+            .map(|line| {
+                line.iter()
+                    .map(ratatui::buffer::Cell::symbol)
+                    .collect::<String>()
+                    .trim_end()
+                    .to_owned()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "    NAME         MAC               TAGS         STATUS",
+                "[x] nas          12:13:14:15:16:10 lab          up",
+                "[ ] desktop      12:13:14:15:16:11 lab          down",
+                "[ ] laptop       12:13:14:15:16:12 lab          …",
+                "↑/↓ move  space pick  a pick all  enter wake up  q quit",
+            ]
+        );
+    }
+}
@@ -0,0 +1,421 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Check wakeup files for errors and suspicious systems.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::io::{Read, Result, stdin};
+use std::net::IpAddr;
+use std::process::ExitCode;
+use std::str::FromStr;
+
+use anstyle::{AnsiColor, Style};
+use clap::{ArgAction, Args, ValueHint};
+use wol::file::{MagicPacketDestination, WakeUpTarget, WakeUpTargetParseError};
+
+use super::PathOrStdin;
+use super::wake::EXIT_INVALID_FILE;
+
+/// The style of errors.
+const ERROR: Style = AnsiColor::Red.on_default().bold();
+
+/// The style of warnings.
+const WARNING: Style = AnsiColor::Yellow.on_default().bold();
+
+#[derive(Args, Debug, Clone)]
+#[command(disable_help_flag = true)]
+pub struct ValidateArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// The wakeup files to check.
+    ///
+    /// Use - to read from standard input.
+    #[arg(
+        value_name = "FILE",
+        required = true,
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    files: Vec<PathOrStdin>,
+}
+
+/// How bad a problem is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Severity {
+    /// The file is invalid.
+    Error,
+    /// The file is valid, but a system looks wrong.
+    Warning,
+}
+
+/// A problem in a wakeup file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Problem {
+    severity: Severity,
+    /// The 1-based line number of the problem.
+    line_no: usize,
+    /// The 1-based column and the width of the problem in the line, if known.
+    span: Option<(usize, usize)>,
+    message: String,
+}
+
+impl Problem {
+    fn new(severity: Severity, line_no: usize, message: impl Display) -> Self {
+        Self {
+            severity,
+            line_no,
+            span: None,
+            message: message.to_string(),
+        }
+    }
+
+    fn with_span(mut self, span: Option<(usize, usize)>) -> Self {
+        self.span = span;
+        self
+    }
+}
+
+/// Get the 1-based column and the width of every field in `line`.
+///
+/// Split fields like wakeup files do, i.e. at whitespace outside of double
+/// quotes, with backslash escapes.
+fn field_spans(line: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut column = 0;
+    for c in line.chars() {
+        column += 1;
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            quoted = !quoted;
+        } else if c.is_ascii_whitespace() && !quoted {
+            spans.extend(start.take().map(|start| (start, column - start)));
+            continue;
+        }
+        start.get_or_insert(column);
+    }
+    spans.extend(start.map(|start| (start, column + 1 - start)));
+    spans
+}
+
+/// Get the span of `error` in `line`.
+fn error_span(line: &str, error: &WakeUpTargetParseError) -> Option<(usize, usize)> {
+    let field = match error {
+        WakeUpTargetParseError::InvalidHardwareAddress(_) => 1,
+        WakeUpTargetParseError::InvalidPort(field, _)
+        | WakeUpTargetParseError::InvalidSecureOn(field, _) => usize::from(*field),
+        WakeUpTargetParseError::InvalidAttribute(field)
+        | WakeUpTargetParseError::UnknownAttribute(field, _) => *field,
+        // Point to the first superfluous field
+        WakeUpTargetParseError::TooManyFields(_) => 5,
+        WakeUpTargetParseError::UnterminatedQuote => {
+            let column = line
+                .chars()
+                .collect::<Vec<_>>()
+                .iter()
+                .rposition(|c| *c == '"')?;
+            return Some((column + 1, 1));
+        }
+        WakeUpTargetParseError::TrailingBackslash => {
+            return Some((line.chars().count(), 1));
+        }
+        WakeUpTargetParseError::Empty => return None,
+    };
+    field_spans(line).get(field - 1).copied()
+}
+
+/// Whether `ip` cannot be a broadcast address.
+///
+/// Assume that `ip` may be the directed broadcast address of some subnet if
+/// its last two bits are set, i.e. if it is the broadcast address of a /30 or
+/// larger subnet.
+fn is_unicast(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !ip.is_broadcast() && !ip.is_multicast() && u32::from(ip) & 0b11 != 0b11,
+        IpAddr::V6(ip) => !ip.is_multicast(),
+    }
+}
+
+/// Where we saw a system first.
+type FirstSeen = HashMap<String, (String, usize)>;
+
+/// Checks across all files.
+#[derive(Debug, Default)]
+struct Checker {
+    /// All valid systems.
+    targets: Vec<WakeUpTarget>,
+    /// Where we first saw a hardware address.
+    hardware_addresses: FirstSeen,
+    /// Where we first saw a name.
+    names: FirstSeen,
+}
+
+/// Remember `key` at `name` and `line_no` in `seen`, and describe where we
+/// saw it before, if at all.
+fn seen_before(seen: &mut FirstSeen, key: String, name: &str, line_no: usize) -> Option<String> {
+    match seen.get(&key) {
+        Some((first_name, first_line)) if first_name == name => Some(format!("line {first_line}")),
+        Some((first_name, first_line)) => Some(format!("{first_name}:{first_line}")),
+        None => {
+            seen.insert(key, (name.to_owned(), line_no));
+            None
+        }
+    }
+}
+
+impl Checker {
+    /// Check a valid `target` in `line` at `line_no` of the file `name`.
+    fn check_target(
+        &mut self,
+        name: &str,
+        line_no: usize,
+        line: &str,
+        target: &WakeUpTarget,
+    ) -> Vec<Problem> {
+        let spans = field_spans(line);
+        let field = |index: usize| spans.get(index).copied();
+        let warning = |message: String| Problem::new(Severity::Warning, line_no, message);
+        let mut problems = Vec::new();
+        let hardware_address = target.hardware_address();
+        let bytes = <[u8; 6]>::from(hardware_address);
+        if bytes == [0; 6] {
+            problems.push(warning("Nil hardware address".to_owned()).with_span(field(0)));
+        } else if bytes.first().is_some_and(|byte| byte & 1 == 1) {
+            problems.push(
+                warning(format!("Multicast hardware address {hardware_address}"))
+                    .with_span(field(0)),
+            );
+        }
+        let key = hardware_address.to_string();
+        if let Some(first) = seen_before(&mut self.hardware_addresses, key, name, line_no) {
+            problems.push(
+                warning(format!(
+                    "Duplicate hardware address {hardware_address}, first at {first}"
+                ))
+                .with_span(field(0)),
+            );
+        }
+        if let Some(system) = target.name() {
+            if let Some(first) = seen_before(&mut self.names, system.to_owned(), name, line_no) {
+                problems.push(warning(format!(
+                    "Duplicate name {system}, first at {first}"
+                )));
+            }
+        }
+        // The destination is always the second field
+        let ip = match target.packet_destination() {
+            Some(MagicPacketDestination::Ip(ip)) => Some(*ip),
+            Some(MagicPacketDestination::ScopedIpv6(ip, _)) => Some(IpAddr::V6(*ip)),
+            _ => None,
+        };
+        if let Some(ip) = ip {
+            if is_unicast(ip) {
+                problems.push(
+                    warning(format!(
+                        "Destination {ip} is no broadcast address; a sleeping system may not receive the magic packet"
+                    ))
+                    .with_span(field(1)),
+                );
+            }
+        }
+        problems
+    }
+
+    /// Check the `contents` of the file `name`.
+    fn check_file(&mut self, name: &str, contents: &str) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line_no = index + 1;
+            if line.trim().is_empty() || line.trim().starts_with('#') {
+                continue;
+            }
+            match WakeUpTarget::from_str(line) {
+                Ok(target) => {
+                    problems.extend(self.check_target(name, line_no, line, &target));
+                    self.targets.push(target);
+                }
+                Err(error) => problems.push(
+                    Problem::new(Severity::Error, line_no, &error)
+                        .with_span(error_span(line, &error)),
+                ),
+            }
+        }
+        problems
+    }
+}
+
+/// Print `problem` in `line` of the file `name`.
+fn print_problem(name: &str, line: &str, problem: &Problem) {
+    let (style, label) = match problem.severity {
+        Severity::Error => (ERROR, "error"),
+        Severity::Warning => (WARNING, "warning"),
+    };
+    let location = match problem.span {
+        Some((column, _)) => format!("{name}:{}:{column}", problem.line_no),
+        None => format!("{name}:{}", problem.line_no),
+    };
+    anstream::println!("{location}: {style}{label}{style:#}: {}", problem.message);
+    anstream::println!("    {line}");
+    if let Some((column, width)) = problem.span {
+        // Keep tabs to align the caret with the line
+        let indent = line
+            .chars()
+            .take(column - 1)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        anstream::println!("    {indent}{style}{}{style:#}", "^".repeat(width.max(1)));
+    }
+}
+
+/// Print all errors in the `contents` of the wakeup file `name`.
+///
+/// Unlike `wol validate` ignore suspicious systems.  Return the number of
+/// errors.
+pub fn print_errors(name: &str, contents: &str) -> usize {
+    let mut checker = Checker::default();
+    let lines = contents.lines().collect::<Vec<_>>();
+    let mut errors = 0;
+    for problem in checker.check_file(name, contents) {
+        if problem.severity == Severity::Error {
+            errors += 1;
+            let line = lines.get(problem.line_no - 1).copied().unwrap_or_default();
+            print_problem(name, line, &problem);
+        }
+    }
+    if errors == 0 {
+        if let Err(error) = wol::file::wake_stages(checker.targets) {
+            errors += 1;
+            anstream::println!("{ERROR}error{ERROR:#}: {error}");
+        }
+    }
+    errors
+}
+
+/// Check all files.
+///
+/// Exit with 4 if any file is invalid, with 1 if any file has suspicious
+/// systems, and with 0 otherwise.
+///
+/// # Errors
+///
+/// Return an error if reading a file fails.
+pub fn validate(args: &ValidateArgs) -> Result<ExitCode> {
+    let mut checker = Checker::default();
+    let mut errors = 0;
+    let mut warnings = 0;
+    for file in &args.files {
+        let (name, contents) = match file {
+            PathOrStdin::Stdin => {
+                let mut contents = String::new();
+                stdin().read_to_string(&mut contents)?;
+                ("<stdin>".to_owned(), contents)
+            }
+            PathOrStdin::Path(path) => (path.display().to_string(), std::fs::read_to_string(path)?),
+        };
+        let lines = contents.lines().collect::<Vec<_>>();
+        for problem in checker.check_file(&name, &contents) {
+            match problem.severity {
+                Severity::Error => errors += 1,
+                Severity::Warning => warnings += 1,
+            }
+            let line = lines.get(problem.line_no - 1).copied().unwrap_or_default();
+            print_problem(&name, line, &problem);
+        }
+    }
+    // Only check the order of valid files, lest we report missing systems
+    // from invalid lines
+    if errors == 0 {
+        if let Err(error) = wol::file::wake_stages(checker.targets) {
+            errors += 1;
+            anstream::println!("{ERROR}error{ERROR:#}: {error}");
+        }
+    }
+    if 0 < errors + warnings {
+        anstream::eprintln!("{errors} error(s), {warnings} warning(s)");
+    }
+    Ok(if 0 < errors {
+        ExitCode::from(EXIT_INVALID_FILE)
+    } else if 0 < warnings {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans() {
+        assert_eq!(
+            field_spans("12:13:14:15:16:17  \"my host\" 9 name=a\\ b"),
+            vec![(1, 17), (20, 9), (30, 1), (32, 9)]
+        );
+        assert_eq!(field_spans("  "), vec![]);
+    }
+
+    #[test]
+    fn errors() {
+        let mut checker = Checker::default();
+        let problems = checker.check_file(
+            "lab.txt",
+            "# Lab\n12:13:14:15:16:17 nas.lan foo\n\n12:13:14:15:16 nas.lan\n12:13:14:15:16:17 \"nas\n",
+        );
+        let [invalid_port, invalid_mac, quote] = problems.as_slice() else {
+            panic!("Unexpected problems: {problems:?}");
+        };
+        assert_eq!(invalid_port.severity, Severity::Error);
+        assert_eq!(
+            (invalid_port.line_no, invalid_port.span),
+            (2, Some((27, 3)))
+        );
+        assert_eq!((invalid_mac.line_no, invalid_mac.span), (4, Some((1, 14))));
+        assert_eq!((quote.line_no, quote.span), (5, Some((19, 1))));
+    }
+
+    #[test]
+    fn warnings() {
+        let mut checker = Checker::default();
+        checker.check_file("lab.txt", "12:13:14:15:16:17 192.168.1.255 name=nas\n");
+        let problems = checker.check_file(
+            "office.txt",
+            "00:00:00:00:00:00\n12:13:14:15:16:17 name=pc\n13:13:14:15:16:18 192.168.1.10 name=nas\n",
+        );
+        let messages = problems
+            .iter()
+            .map(|problem| (problem.line_no, problem.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            messages,
+            [
+                (1, "Nil hardware address"),
+                (
+                    2,
+                    "Duplicate hardware address 12:13:14:15:16:17, first at lab.txt:1"
+                ),
+                (3, "Multicast hardware address 13:13:14:15:16:18"),
+                (3, "Duplicate name nas, first at lab.txt:1"),
+                (
+                    3,
+                    "Destination 192.168.1.10 is no broadcast address; a sleeping system may not receive the magic packet"
+                ),
+            ]
+        );
+        assert!(
+            problems
+                .iter()
+                .all(|problem| problem.severity == Severity::Warning)
+        );
+    }
+}
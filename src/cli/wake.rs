@@ -0,0 +1,2482 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Wake up systems.
+
+use std::ffi::OsString;
+use std::fs::File;
+use std::hash::{BuildHasher, RandomState};
+use std::io::{BufReader, BufWriter, Error, ErrorKind, IsTerminal, Result, Write, stdin, stdout};
+#[cfg(feature = "signature")]
+use std::io::{Cursor, Read};
+use std::net::{IpAddr, SocketAddr, SocketAddrV6};
+use std::num::{NonZeroUsize, ParseIntError};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use clap::{ArgAction, ArgGroup, Args, ValueEnum, ValueHint, builder::ArgPredicate};
+use wol::file::MagicPacketDestination;
+use wol::{MacAddress, SecureOn, SendMagicPacket};
+
+use super::PathOrStdin;
+use super::argfile;
+use super::command::CommandTemplate;
+use super::dns::{self, DnsOptions, Resolver};
+use super::emit::{self, PacketFormat};
+use super::fuzzy;
+use super::hint;
+use super::history::{self, WakeLogArgs};
+use super::hosts::{Hosts, HostsArgs};
+use super::input::{self, StdinFormat};
+use super::interrupt::{EXIT_INTERRUPTED, EXIT_TIMED_OUT, Interrupt};
+use super::launchd;
+use super::online::{self, Probe};
+use super::oui::{self, Vendors};
+use super::passwd::PasswdMap;
+#[cfg(feature = "relay")]
+use super::relay::{self, Key};
+use super::report::{ColorChoice, ErrorFormat, Failure, OutputFormat, Report, TargetResult};
+use super::schedule::Schedule;
+use super::socket::{self, BindAddress, SocketOptions, Sockets, Transport};
+use super::socks::Proxy;
+use super::ssh::{self, Gateway};
+use super::subnet::Ipv4Subnet;
+use super::systemd;
+use super::target::{CliTarget, MacRange, Selector, TargetArg};
+
+/// How much to print.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Verbosity {
+    /// Print only errors.
+    Quiet,
+    /// Print a line for every system.
+    Normal,
+    /// Print more details about every system.
+    Verbose,
+    /// Print resolved addresses, socket options, and timing.
+    Debug,
+}
+
+/// An IP address family to resolve hostnames to.
+#[derive(Debug, Clone, Copy)]
+enum AddressFamily {
+    Ipv4,
+    Ipv6,
+}
+
+impl AddressFamily {
+    fn contains(self, socket_addr: &SocketAddr) -> bool {
+        match self {
+            Self::Ipv4 => socket_addr.is_ipv4(),
+            Self::Ipv6 => socket_addr.is_ipv6(),
+        }
+    }
+}
+
+impl std::fmt::Display for AddressFamily {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ipv4 => write!(f, "IPv4"),
+            Self::Ipv6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+/// Which systems to wake up first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Order {
+    /// Systems from files first, then systems from the command line.
+    FilesFirst,
+    /// Systems from the command line first, then systems from files.
+    MacsFirst,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+enum ResolveMode {
+    #[default]
+    Default,
+    /// Prefer addresses of the given family, but fall back to other addresses.
+    Prefer(AddressFamily),
+    /// Only use addresses of the given family.
+    Only(AddressFamily),
+}
+
+/// Get `host` if it is the address of a single system.
+///
+/// Return `None` if `host` is a broadcast or multicast address.
+fn unicast(host: &MagicPacketDestination) -> Option<&MagicPacketDestination> {
+    match host {
+        MagicPacketDestination::Ip(IpAddr::V4(ip)) if ip.is_broadcast() => None,
+        MagicPacketDestination::Ip(ip) if ip.is_multicast() => None,
+        MagicPacketDestination::ScopedIpv6(ip, _) if ip.is_multicast() => None,
+        host => Some(host),
+    }
+}
+
+/// A destination to send magic packets to.
+#[derive(Debug)]
+struct Destination {
+    socket_addr: SocketAddr,
+    socket_options: SocketOptions,
+}
+
+#[derive(Debug)]
+struct WakeUpTarget {
+    hardware_address: MacAddress,
+    host: MagicPacketDestination,
+    port: u16,
+    /// Further ports to send magic packets to.
+    extra_ports: Vec<u16>,
+    secure_on: Option<SecureOn>,
+    name: Option<String>,
+    /// The address of the system itself, to check whether it is online.
+    address: Option<MagicPacketDestination>,
+}
+
+impl WakeUpTarget {
+    /// All ports to send magic packets to.
+    fn ports(&self) -> impl Iterator<Item = u16> {
+        std::iter::once(self.port).chain(self.extra_ports.iter().copied())
+    }
+
+    /// All ports to send magic packets to, for display.
+    fn ports_string(&self) -> String {
+        self.ports()
+            .map(|port| port.to_string())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Resolve `host` to socket addresses with `port`.
+///
+/// Return all addresses which match `mode`, in order of preference.
+fn resolve(
+    host: &MagicPacketDestination,
+    port: u16,
+    mode: ResolveMode,
+    resolver: &Resolver,
+) -> Result<Vec<SocketAddr>> {
+    match host {
+        MagicPacketDestination::Dns(dns) => {
+            let mut socket_addrs = resolver.resolve(dns, port)?;
+            match mode {
+                ResolveMode::Default => {}
+                ResolveMode::Prefer(family) => {
+                    socket_addrs.sort_by_key(|addr| !family.contains(addr));
+                }
+                ResolveMode::Only(family) => {
+                    if !socket_addrs.is_empty() {
+                        socket_addrs.retain(|addr| family.contains(addr));
+                        if socket_addrs.is_empty() {
+                            return Err(Error::new(
+                                ErrorKind::HostUnreachable,
+                                format!("Host {dns} has no {family} address"),
+                            ));
+                        }
+                    }
+                }
+            }
+            if socket_addrs.is_empty() {
+                Err(Error::new(
+                    ErrorKind::HostUnreachable,
+                    format!("Host {dns} not reachable"),
+                ))
+            } else {
+                Ok(socket_addrs)
+            }
+        }
+        MagicPacketDestination::Ip(ip_addr) => Ok(vec![SocketAddr::new(*ip_addr, port)]),
+        MagicPacketDestination::ScopedIpv6(ip_addr, zone) => Ok(vec![SocketAddr::V6(
+            SocketAddrV6::new(*ip_addr, port, 0, socket::scope_id(zone)?),
+        )]),
+        host => Err(Error::new(
+            ErrorKind::Unsupported,
+            format!("Unsupported destination {host}"),
+        )),
+    }
+}
+
+/// Look up the SecureON token stored under `name` in the system keyring.
+#[cfg(feature = "keyring")]
+pub fn secure_on_from_keyring(name: &str) -> Result<SecureOn> {
+    let secret = keyring::Entry::new("wol", name)
+        .and_then(|entry| entry.get_password())
+        .map_err(|error| {
+            Error::other(format!(
+                "Failed to read SecureON token {name} from keyring: {error}"
+            ))
+        })?;
+    SecureOn::from_str(secret.trim()).map_err(|error| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid SecureON token {name} in keyring: {error}"),
+        )
+    })
+}
+
+#[cfg(not(feature = "keyring"))]
+pub fn secure_on_from_keyring(name: &str) -> Result<SecureOn> {
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        format!("Cannot read SecureON token {name} from keyring: keyring support not enabled"),
+    ))
+}
+
+/// Parse a SecureON password read from `source`.
+fn parse_secure_on(value: &str, source: &str) -> Result<SecureOn> {
+    // Do not include the value in the error message, lest we leak a password
+    SecureOn::from_str(value.trim()).map_err(|error| {
+        Error::new(
+            ErrorKind::InvalidData,
+            format!("Invalid SecureON password in {source}: {error}"),
+        )
+    })
+}
+
+/// Arguments to wake up systems.
+// Command line flags are naturally bools
+#[allow(clippy::struct_excessive_bools)]
+#[derive(Args, Debug, Clone)]
+#[command(
+    disable_help_flag = true,
+    group(ArgGroup::new("address_family").args(["ipv4", "ipv6"])),
+    group(ArgGroup::new("passwd_source").multiple(false))
+)]
+#[cfg_attr(
+    feature = "relay",
+    command(group(ArgGroup::new("relay_key_source").multiple(false)))
+)]
+pub struct WakeArgs {
+    /// Show this help message.
+    #[arg(short = '?', long = "help", action = ArgAction::Help)]
+    help: (),
+    /// Send the magic packet to HOST.
+    ///
+    /// HOST may either be a DNS name, or an IPv4/IPv6 address.
+    /// HOST may and most likely will be different from the
+    /// target system to wake up: Instead the magic packet needs
+    /// to be sent so that it physically passes the system to
+    /// wake up.  As such, you will most likely want to use a
+    /// broadcast or multicast address here.
+    ///
+    /// IPv6 addresses may include a zone ID, i.e. the name or
+    /// index of the network interface to send the magic packet
+    /// out of, e.g. `ff02::1%eth0` or `ff02::1%3`.
+    ///
+    /// Defaults to the IPv4 broadcast address 255.255.255.255
+    /// or the IPv6 `ff02::1`, if --ipv6 is given.
+    #[arg(
+        short = 'h',
+        long = "host",
+        visible_short_alias = 'i',
+        visible_alias = "ipaddr",
+        default_value = "255.255.255.255",
+        default_value_if("ipv6", ArgPredicate::IsPresent, Some("ff02::1")),
+        verbatim_doc_comment
+    )]
+    host: MagicPacketDestination,
+    /// Send the magic packet to the broadcast address of SUBNET.
+    ///
+    /// SUBNET is an IPv4 subnet in CIDR notation, e.g.
+    /// 192.168.10.0/24; send the magic packet to the directed
+    /// broadcast address of this subnet, e.g. 192.168.10.255,
+    /// instead of HOST.
+    #[arg(
+        long = "broadcast",
+        value_name = "SUBNET",
+        conflicts_with_all = ["host", "all_interfaces"],
+        verbatim_doc_comment
+    )]
+    broadcast: Option<Ipv4Subnet>,
+    /// Prefer IPv4 addresses over IPv6 for DNS resolution.
+    ///
+    /// This only affects DNS resolution for hostnames
+    /// given to --host; literal IPv4 and IPv6 addresses will
+    /// always use the respective protocol.
+    ///
+    /// If a hostname has no IPv4 address, fall back to its
+    /// IPv6 address, unless --strict is given.
+    #[arg(short = '4', long = "ipv4", verbatim_doc_comment)]
+    ipv4: bool,
+    /// Prefer IPv6 addresses over IPv4 for DNS resolution.
+    ///
+    /// This only affects DNS resolution for hostnames
+    /// given to --host; literal IPv4 and IPv6 addresses will
+    /// always use the respective protocol.
+    ///
+    /// If a hostname has no IPv6 address, fall back to its
+    /// IPv4 address, unless --strict is given.
+    ///
+    /// If neither --ipv4 nor --ipv6 is given use the first
+    /// resolved address returned by the operating system,
+    /// regardless of whether it is an IPv4 or IPv6 address.
+    #[arg(short = '6', long = "ipv6", verbatim_doc_comment)]
+    ipv6: bool,
+    /// Fail if a hostname has no address of the preferred family.
+    ///
+    /// With --ipv4 or --ipv6 fail to wake up a system if its
+    /// hostname does not resolve to an address of the given
+    /// family, instead of falling back to the other family.
+    #[arg(long = "strict", requires = "address_family", verbatim_doc_comment)]
+    strict: bool,
+    /// Send the magic packet to PORT.
+    #[arg(
+        short = 'p',
+        long = "port",
+        default_value = "40000",
+        verbatim_doc_comment
+    )]
+    port: u16,
+    /// Send the magic packet to each of PORTS.
+    ///
+    /// Send the magic packet to every port in the comma
+    /// separated list of PORTS, e.g. 7,9,40000, to wake up
+    /// systems listening on different ports, unless a system
+    /// has a port of its own.
+    #[arg(
+        long = "ports",
+        value_name = "PORTS",
+        value_delimiter = ',',
+        num_args = 1,
+        conflicts_with = "port",
+        verbatim_doc_comment
+    )]
+    ports: Vec<u16>,
+    /// Read systems to wake up from FILE.
+    ///
+    /// Read lines of hardware address, and (optionally) IP
+    /// addresses/hostnames, ports, and SecureON passwords from
+    /// FILE, or stdin, if FILE is -.
+    ///
+    /// Fields in each line are separated by one or more spaces
+    /// or tabs; for each missing field the value of the
+    /// corresponding option or the global default will be used.
+    /// Enclose fields containing whitespace in double quotes,
+    /// and escape double quotes and backslashes with backslash.
+    ///
+    /// Instead of a literal SecureON password a line may refer
+    /// to a password in the system keyring with keyring:NAME;
+    /// wol then looks up the password for service "wol" and
+    /// user NAME.
+    ///
+    /// Repeat to read systems from multiple files, in the given
+    /// order; after= may refer to systems in any of these files.
+    #[arg(
+        short = 'f',
+        long = "file",
+        action = ArgAction::Append,
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    file: Vec<PathOrStdin>,
+    /// Read systems from --file - in FORMAT.
+    ///
+    /// plain reads lines in the format of --file, json reads
+    /// JSON objects, one after another or in arrays, and csv
+    /// reads comma-separated values with a header line.  JSON
+    /// objects and CSV columns have the fields mac, host, port,
+    /// passwd, name, address, and after; wol ignores all other
+    /// fields.
+    #[arg(
+        long = "stdin-format",
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t = StdinFormat::Plain,
+        hide_possible_values = true,
+        verbatim_doc_comment
+    )]
+    stdin_format: StdinFormat,
+    /// Wake up only these systems from --file.
+    ///
+    /// SYSTEM is the name= or the MAC address of a system in
+    /// --file.  Separate multiple systems with commas, or
+    /// repeat to select more systems.
+    ///
+    /// SYSTEM may also be a glob with * and ?, e.g. web-*, or
+    /// a regular expression after re:, e.g. re:^agent-\d+, to
+    /// select all systems whose name=, host, or address=
+    /// matches.
+    #[arg(
+        long = "only",
+        value_name = "SYSTEM",
+        value_delimiter = ',',
+        action = ArgAction::Append,
+        requires = "file",
+        verbatim_doc_comment
+    )]
+    only: Vec<Selector>,
+    /// Do not wake up these systems from --file.
+    ///
+    /// Like --only, but skip the given systems instead.
+    #[arg(
+        long = "exclude",
+        value_name = "SYSTEM",
+        value_delimiter = ',',
+        action = ArgAction::Append,
+        requires = "file",
+        verbatim_doc_comment
+    )]
+    exclude: Vec<Selector>,
+    /// Which systems to wake up first.
+    ///
+    /// files-first wakes up systems from --file first, and
+    /// systems given on the command line along with the last
+    /// stage of systems from --file; macs-first wakes up
+    /// systems given on the command line first, along with
+    /// the first stage of systems from --file.
+    #[arg(
+        long = "order",
+        value_name = "ORDER",
+        value_enum,
+        default_value_t = Order::FilesFirst,
+        verbatim_doc_comment
+    )]
+    order: Order,
+    /// Verify FILE with the given minisign public KEY.
+    ///
+    /// Refuse to wake up any system from FILE unless FILE
+    /// has a valid minisign signature made with KEY.  KEY is
+    /// the base64 public key, as printed by `minisign -P`.
+    #[cfg(feature = "signature")]
+    #[arg(
+        long = "verify-key",
+        value_name = "KEY",
+        requires = "file",
+        verbatim_doc_comment
+    )]
+    verify_key: Option<wol::file::signature::VerificationKey>,
+    /// Read the minisign signature of FILE from SIGNATURE.
+    ///
+    /// Defaults to FILE with a `.minisig` extension appended.
+    /// Required if FILE is stdin; only valid for a single FILE.
+    #[cfg(feature = "signature")]
+    #[arg(
+        long = "signature",
+        value_name = "SIGNATURE",
+        requires = "verify_key",
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    signature: Option<PathBuf>,
+    /// Verbose output.
+    ///
+    /// Repeat to also show resolved addresses, socket options,
+    /// and timing.
+    #[arg(short = 'v', long = "verbose", action = ArgAction::Count, verbatim_doc_comment)]
+    verbose: u8,
+    /// Do not print a line for every system to wake up.
+    ///
+    /// Only print errors.
+    #[arg(
+        short = 'q',
+        long = "quiet",
+        conflicts_with = "verbose",
+        verbatim_doc_comment
+    )]
+    quiet: bool,
+    /// Color output WHEN.
+    ///
+    /// Color successes green and failures red.  With auto only
+    /// color output on terminals, and not if the environment
+    /// variable `NO_COLOR` is set.
+    #[arg(
+        long = "color",
+        value_name = "WHEN",
+        value_enum,
+        default_value_t,
+        verbatim_doc_comment
+    )]
+    color: ColorChoice,
+    /// Do not show a progress bar.
+    ///
+    /// By default show a progress bar instead of a line for
+    /// each system when waking up many systems on a terminal.
+    #[arg(long = "no-progress", verbatim_doc_comment)]
+    no_progress: bool,
+    /// Print results in FORMAT.
+    ///
+    /// With json print one JSON object per line for every
+    /// system, with its MAC address, name, destination, port,
+    /// result, error, and duration, and finally a summary
+    /// object.  Implies --quiet.
+    #[arg(
+        long = "output",
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t,
+        verbatim_doc_comment
+    )]
+    output: OutputFormat,
+    /// Print a line from TEMPLATE for every system.
+    ///
+    /// Replace the following placeholders in TEMPLATE, and
+    /// print the result for every system, e.g. --format
+    /// '{name} {mac} {dest}:{port} {status}'.  Implies --quiet.
+    ///
+    /// {mac}          The MAC address of the system.
+    /// {name}         The name= of the system in --file.
+    /// {dest}         Where magic packets went.
+    /// {port}         The port magic packets went to.
+    /// {status}       ok, skipped, or what failed.
+    /// {error}        The error, if any.
+    /// {duration}     How long waking up the system took, in
+    ///                milliseconds.
+    ///
+    /// Replace values a system lacks with -.
+    #[arg(
+        long = "format",
+        value_name = "TEMPLATE",
+        conflicts_with = "output",
+        verbatim_doc_comment
+    )]
+    format: Option<String>,
+    /// Print errors in FORMAT.
+    ///
+    /// With json print one JSON object per line for every error
+    /// to stderr, with an error code, the MAC address of the
+    /// system, the phase which failed, and the error message.
+    /// The phase is one of usage, setup, parse, resolve, send,
+    /// online, or command.
+    #[arg(
+        long = "error-format",
+        value_name = "FORMAT",
+        value_enum,
+        default_value_t,
+        verbatim_doc_comment
+    )]
+    error_format: ErrorFormat,
+    /// Exit with the number of systems which failed.
+    ///
+    /// If any system failed to wake up exit with 100 plus the
+    /// number of failed systems, up to 199 for 99 or more
+    /// failed systems, instead of 1 or 3.
+    #[arg(long = "exit-failure-count", verbatim_doc_comment)]
+    exit_failure_count: bool,
+    /// Give up after SECS seconds.
+    ///
+    /// Stop waking up systems after SECS seconds, including
+    /// all waits, and --wait-online; report all remaining
+    /// systems as skipped and exit with 124.
+    #[arg(
+        long = "timeout",
+        value_name = "SECS",
+        value_parser = parse_secs,
+        verbatim_doc_comment
+    )]
+    timeout: Option<Duration>,
+    /// Wait after each magic packet.
+    ///
+    /// After each magic packet wait for the given number of
+    /// milliseconds; use this to avoid waking up too many
+    /// systems too fast.
+    #[arg(
+        short = 'w',
+        long = "wait",
+        value_name = "MSECS",
+        value_parser = parse_millis,
+        verbatim_doc_comment
+    )]
+    wait: Option<Duration>,
+    /// Wait up to MSECS longer after each magic packet.
+    ///
+    /// Add a random delay of up to the given number of
+    /// milliseconds to the wait after each magic packet, to
+    /// keep systems from booting in lockstep.
+    #[arg(
+        long = "wait-jitter",
+        value_name = "MSECS",
+        value_parser = parse_millis,
+        verbatim_doc_comment
+    )]
+    wait_jitter: Option<Duration>,
+    /// Wait MSECS longer after each further magic packet.
+    ///
+    /// Increase the wait after each magic packet by the given
+    /// number of milliseconds over the run, i.e. wait --wait
+    /// after the first system, --wait plus MSECS after the
+    /// second system, --wait plus twice MSECS after the third
+    /// system, etc.
+    #[arg(
+        long = "ramp",
+        value_name = "MSECS",
+        value_parser = parse_millis,
+        verbatim_doc_comment
+    )]
+    ramp: Option<Duration>,
+    /// Wake up systems in batches of COUNT systems.
+    ///
+    /// After every COUNT systems wait for --batch-wait before
+    /// waking up the next batch, e.g. to avoid powering up too
+    /// many systems at once.
+    #[arg(
+        long = "batch-size",
+        value_name = "COUNT",
+        requires = "batch_wait",
+        verbatim_doc_comment
+    )]
+    batch_size: Option<NonZeroUsize>,
+    /// Wait between batches of systems.
+    ///
+    /// With --batch-size wait for the given number of
+    /// milliseconds after each batch of systems.
+    #[arg(
+        long = "batch-wait",
+        value_name = "MSECS",
+        requires = "batch_size",
+        value_parser = parse_millis,
+        verbatim_doc_comment
+    )]
+    batch_wait: Option<Duration>,
+    /// Wait between stages of dependent systems.
+    ///
+    /// Systems in FILE may declare that they need to be woken
+    /// up after other systems with after=NAME; after waking up
+    /// all systems of one stage wait for the given number of
+    /// milliseconds before waking up the systems which depend
+    /// on them.
+    #[arg(
+        long = "stage-wait",
+        value_name = "MSECS",
+        value_parser = parse_millis,
+        verbatim_doc_comment
+    )]
+    stage_wait: Option<Duration>,
+    /// Send COUNT magic packets to each system.
+    ///
+    /// Send the magic packet multiple times to each system, to
+    /// make up for lossy networks.
+    #[arg(
+        long = "repeat",
+        value_name = "COUNT",
+        default_value = "1",
+        value_parser = clap::value_parser!(u32).range(1..),
+        verbatim_doc_comment
+    )]
+    repeat: u32,
+    /// Wait between repeated magic packets.
+    ///
+    /// With --repeat wait for the given number of milliseconds
+    /// between repeated magic packets to the same system.
+    #[arg(
+        long = "interval",
+        value_name = "MSECS",
+        requires = "repeat",
+        value_parser = parse_millis,
+        verbatim_doc_comment
+    )]
+    interval: Option<Duration>,
+    /// Retry COUNT times after transient errors.
+    ///
+    /// If DNS resolution fails, or the network is unreachable,
+    /// e.g. right after resuming from sleep, retry up to COUNT
+    /// times, with exponential backoff starting at 250ms,
+    /// before giving up on a system.
+    #[arg(
+        long = "retries",
+        value_name = "COUNT",
+        default_value = "0",
+        verbatim_doc_comment
+    )]
+    retries: u32,
+    /// Send magic packets from the network interface IFACE.
+    ///
+    /// Use this to choose the network segment of the magic
+    /// packets on systems with multiple network interfaces, or
+    /// with VPNs.
+    #[arg(long = "interface", value_name = "IFACE", verbatim_doc_comment)]
+    interface: Option<String>,
+    /// Bind to the local address ADDR and PORT.
+    ///
+    /// Send magic packets from the local address ADDR and the
+    /// source port PORT, e.g. 192.0.2.1, 192.0.2.1:4000,
+    /// `[2001:db8::1]:4000`, or just :4000 for a fixed source
+    /// port on any address.  Use this if your firewall only passes
+    /// packets from specific source addresses or ports.
+    #[arg(long = "bind", value_name = "ADDR[:PORT]", verbatim_doc_comment)]
+    bind: Option<BindAddress>,
+    /// Send magic packets with the given IP TTL.
+    ///
+    /// Set the TTL of IPv4 packets or the hop limit of IPv6
+    /// packets to HOPS, to limit the number of routers magic
+    /// packets may pass.
+    #[arg(
+        long = "ttl",
+        value_name = "HOPS",
+        value_parser = clap::value_parser!(u32).range(1..=255),
+        verbatim_doc_comment
+    )]
+    ttl: Option<u32>,
+    /// Send magic packets with the given type of service.
+    ///
+    /// Set the type of service field of IPv4 packets or the
+    /// traffic class of IPv6 packets to TOS, in decimal or as
+    /// hexadecimal with 0x prefix.  The DSCP makes up the upper
+    /// six bits, e.g. 0xc0 or 192 for DSCP CS6.
+    #[arg(
+        long = "tos",
+        value_name = "TOS",
+        value_parser = parse_tos,
+        verbatim_doc_comment
+    )]
+    tos: Option<u32>,
+    /// Send magic packets over TRANSPORT.
+    ///
+    /// With tcp connect to HOST and write the magic packet to
+    /// the connection, for gateways or relays which only accept
+    /// magic packets over TCP and send them on to their network.
+    /// HOST must be the address of the gateway, not a broadcast
+    /// address.
+    #[arg(
+        long = "transport",
+        value_name = "TRANSPORT",
+        value_enum,
+        default_value_t,
+        conflicts_with_all = ["broadcast", "all_interfaces", "raw"],
+        verbatim_doc_comment
+    )]
+    transport: Transport,
+    /// Send magic packets through the SOCKS5 proxy at URL.
+    ///
+    /// Ask the proxy at socks5://[USER[:PASSWORD]@]HOST[:PORT]
+    /// to relay magic packets to HOST, e.g. an SSH tunnel to a
+    /// UDP-capable proxy, or the SOCKS endpoint of a VPN, to
+    /// reach remote networks without opening any ports.  The
+    /// proxy must support UDP associate.
+    #[arg(
+        long = "proxy",
+        value_name = "URL",
+        conflicts_with_all = ["all_interfaces", "raw", "transport", "interface", "bind", "ttl", "tos"],
+        verbatim_doc_comment
+    )]
+    proxy: Option<Proxy>,
+    /// Wake up systems through the SSH gateway at URL.
+    ///
+    /// Log in to ssh://[USER@]GATEWAY[:PORT] with ssh, and run
+    /// --via-helper on the gateway to send magic packets to HOST
+    /// on the network of the gateway, instead of sending them
+    /// from this system.  Use this if your router does not
+    /// forward broadcasts, but you can log in to any system on
+    /// the network of the systems to wake up.
+    #[arg(
+        long = "via",
+        value_name = "URL",
+        conflicts_with_all = ["all_interfaces", "raw", "transport", "proxy", "interface", "bind", "ttl", "tos", "emit"],
+        verbatim_doc_comment
+    )]
+    via: Option<Gateway>,
+    /// Run PROGRAM as wol on the --via gateway.
+    #[arg(
+        long = "via-helper",
+        value_name = "PROGRAM",
+        default_value = "wol",
+        requires = "via",
+        verbatim_doc_comment
+    )]
+    via_helper: String,
+    /// Send magic packets out of all network interfaces.
+    ///
+    /// Send each magic packet to the broadcast address of every
+    /// network interface except loopback, and to the IPv6
+    /// link-local all-nodes address `ff02::1` on every interface
+    /// with IPv6, instead of HOST.  Use this if you do not know
+    /// which network segment the system to wake up is on.
+    #[arg(
+        long = "all-interfaces",
+        conflicts_with_all = ["host", "interface"],
+        verbatim_doc_comment
+    )]
+    all_interfaces: bool,
+    /// Send magic packets in raw Ethernet frames on --interface.
+    ///
+    /// Send each magic packet in an Ethernet frame of type
+    /// 0x0842 out of --interface, like etherwake, instead of a
+    /// UDP packet to HOST.  Use this if UDP broadcasts never
+    /// reach the system to wake up.  Only supported on Linux,
+    /// and requires root privileges.
+    #[arg(
+        long = "raw",
+        requires = "interface",
+        conflicts_with_all = ["host", "broadcast", "all_addresses", "bind", "ttl", "tos"],
+        verbatim_doc_comment
+    )]
+    raw: bool,
+    /// Wake up systems through the relay at ADDR.
+    ///
+    /// Send an encrypted wake request to wol relay serve at ADDR,
+    /// e.g. home.example.com:4009, which sends the magic packet
+    /// on its own network, instead of sending magic packets to
+    /// HOST.  Requires --relay-key or --relay-key-file.  The relay
+    /// decides the port; with --repeat send repeated requests.
+    #[cfg(feature = "relay")]
+    #[arg(
+        long = "relay",
+        value_name = "ADDR",
+        requires = "relay_key_source",
+        conflicts_with_all = ["host", "broadcast", "all_interfaces", "raw", "emit", "transport", "proxy", "via", "port", "ports"],
+        verbatim_doc_comment
+    )]
+    relay: Option<String>,
+    /// Sign wake requests for --relay with KEY.
+    ///
+    /// Other users may see KEY in the process list; prefer
+    /// --relay-key-file, or relay-key in the configuration file.
+    #[cfg(feature = "relay")]
+    #[arg(
+        long = "relay-key",
+        value_name = "KEY",
+        group = "relay_key_source",
+        requires = "relay",
+        verbatim_doc_comment
+    )]
+    relay_key: Option<Key>,
+    /// Read the key for --relay from the first line of FILE.
+    #[cfg(feature = "relay")]
+    #[arg(
+        long = "relay-key-file",
+        value_name = "FILE",
+        group = "relay_key_source",
+        requires = "relay",
+        value_hint = ValueHint::FilePath
+    )]
+    relay_key_file: Option<PathBuf>,
+    /// Send magic packets to all addresses of HOST.
+    ///
+    /// If HOST is a DNS name which resolves to multiple
+    /// addresses send each magic packet to all of these
+    /// addresses instead of only to the first one.  With
+    /// --ipv4 or --ipv6 and --strict send magic packets to all
+    /// addresses of the respective family.
+    #[arg(
+        long = "all-addresses",
+        conflicts_with = "all_interfaces",
+        verbatim_doc_comment
+    )]
+    all_addresses: bool,
+    /// Do not warn about unicast destinations.
+    ///
+    /// By default warn if a magic packet goes to the address
+    /// of a single system instead of a broadcast or multicast
+    /// address, because sleeping systems usually do not answer
+    /// ARP requests, so such packets rarely arrive.
+    #[arg(long = "allow-unicast", verbatim_doc_comment)]
+    allow_unicast: bool,
+    /// Write magic packets in FORMAT instead of sending them.
+    ///
+    /// Write the magic packet for every system to standard
+    /// output, or to --emit-file, e.g. to pipe it into netcat
+    /// or to attach it to a bug report.
+    ///
+    /// hex     One line of hexadecimal digits per packet.
+    /// base64  One line of base64 per packet.
+    /// raw     The bytes of all packets.
+    #[arg(
+        long = "emit",
+        value_name = "FORMAT",
+        value_enum,
+        hide_possible_values = true,
+        conflicts_with_all = ["wait_online", "then"],
+        verbatim_doc_comment
+    )]
+    emit: Option<PacketFormat>,
+    /// Write magic packets for --emit to FILE.
+    #[arg(
+        long = "emit-file",
+        value_name = "FILE",
+        requires = "emit",
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    emit_file: Option<PathBuf>,
+    /// Print a systemd service unit which runs wol wake once
+    /// with all other arguments, and exit.
+    ///
+    /// The unit runs in the current directory, and uses the
+    /// current hosts database.  Start it on a schedule with a
+    /// timer of the same name from --print-systemd-timer.
+    #[arg(
+        long = "print-systemd-unit",
+        conflicts_with_all = ["emit", "pick", "passwd_stdin", "passwd_prompt"],
+        verbatim_doc_comment
+    )]
+    print_systemd_unit: bool,
+    /// Print a systemd timer which starts the service unit of
+    /// the same name at SCHEDULE, and exit.
+    ///
+    /// SCHEDULE is a time HH:MM, optionally preceded by a comma
+    /// separated list of weekdays or ranges of weekdays, e.g.
+    /// "Mon..Fri,Sun 07:30".  Save the timer next to the unit
+    /// from --print-systemd-unit, e.g. as wake-nas.timer and
+    /// wake-nas.service.
+    #[arg(
+        long = "print-systemd-timer",
+        value_name = "SCHEDULE",
+        conflicts_with = "print_systemd_unit",
+        verbatim_doc_comment
+    )]
+    print_systemd_timer: Option<Schedule>,
+    /// Print a launchd property list for a launch daemon which
+    /// runs wol wake with all other arguments at SCHEDULE, and
+    /// exit.
+    ///
+    /// SCHEDULE is as for --print-systemd-timer.  The daemon
+    /// runs in the current directory, and uses the current
+    /// hosts database.  Change its label to schedule more than
+    /// one wake up.
+    #[arg(
+        long = "print-launchd-plist",
+        value_name = "SCHEDULE",
+        conflicts_with_all = [
+            "print_systemd_unit", "print_systemd_timer", "emit", "pick",
+            "passwd_stdin", "passwd_prompt"
+        ],
+        verbatim_doc_comment
+    )]
+    print_launchd_plist: Option<Schedule>,
+    /// Wait until woken up systems are online.
+    ///
+    /// After waking up systems, probe each system until it
+    /// responds, and fail if it does not respond within the
+    /// timeout given by --online-timeout.  Wait for all systems
+    /// of a stage before waking up the next stage.
+    ///
+    /// Probe the address given with address= in --file, or
+    /// HOST, unless HOST is a broadcast or multicast address.
+    #[arg(long = "wait-online", verbatim_doc_comment)]
+    wait_online: bool,
+    /// Check whether systems are online with PROBE.
+    ///
+    /// PROBE is either ping to send ICMP echo requests, or
+    /// tcp:PORT to connect to PORT, e.g. tcp:22 for SSH.
+    #[arg(
+        long = "probe",
+        value_name = "PROBE",
+        default_value = "ping",
+        requires = "wait_online",
+        verbatim_doc_comment
+    )]
+    probe: Probe,
+    /// Wait at most SECS seconds for systems to come online.
+    #[arg(
+        long = "online-timeout",
+        value_name = "SECS",
+        default_value = "120",
+        value_parser = parse_secs,
+        requires = "wait_online",
+        verbatim_doc_comment
+    )]
+    online_timeout: Duration,
+    /// Show a desktop notification when a system comes online
+    /// or fails to, with --wait-online.
+    ///
+    /// Start waking up systems, switch away, and get notified
+    /// when they are ready.
+    #[cfg(feature = "notifications")]
+    #[arg(long = "notify", requires = "wait_online", verbatim_doc_comment)]
+    notify: bool,
+    /// Run COMMAND for every woken up system.
+    ///
+    /// Run COMMAND after waking up a system, and after it came
+    /// online with --wait-online.  COMMAND is split into words
+    /// like a shell does, but not run through a shell.  In
+    /// each word replace the following placeholders:
+    ///
+    /// {mac}   The MAC address of the system.
+    /// {name}  The name= of the system in --file.
+    /// {host}  The address of the system, as for --wait-online.
+    /// {ip}    The IP address of {host}.
+    ///
+    /// E.g. --then 'ssh {host}' connects to each system.
+    #[arg(long = "then", value_name = "COMMAND", verbatim_doc_comment)]
+    then: Option<CommandTemplate>,
+    /// Time out DNS queries after MILLIS milliseconds.
+    ///
+    /// Query DNS servers directly instead of using the system
+    /// resolver, and give up on each query after MILLIS.
+    #[arg(
+        long = "dns-timeout",
+        value_name = "MILLIS",
+        value_parser = parse_millis,
+        verbatim_doc_comment
+    )]
+    dns_timeout: Option<Duration>,
+    /// Resolve hostnames with the DNS server at ADDR.
+    ///
+    /// ADDR is an IP address, optionally with a port, e.g.
+    /// 192.0.2.53 or 192.0.2.53:5353.  Query this DNS server
+    /// directly instead of using the system resolver.  Repeat
+    /// to query multiple DNS servers.
+    #[arg(
+        long = "dns-server",
+        value_name = "ADDR",
+        value_parser = dns::parse_server,
+        action = ArgAction::Append,
+        verbatim_doc_comment
+    )]
+    dns_servers: Vec<SocketAddr>,
+    /// Refuse DNS names and require literal IP addresses.
+    ///
+    /// Fail if --host, a target on the command line, or any
+    /// host= or address= in --file is a DNS name, so that a
+    /// spoofed DNS resolver cannot redirect magic packets.
+    #[arg(
+        long = "no-dns",
+        conflicts_with_all = ["dns_timeout", "dns_servers"],
+        verbatim_doc_comment
+    )]
+    no_dns: bool,
+    /// Include the given SecureON password in the magic packet.
+    ///
+    /// The password is in the same format as a MAC address, i.e.
+    /// XX-XX-XX-XX-XX-XX or XX:XX:XX:XX:XX:XX.
+    ///
+    /// Other users may see PASSWD in the process list; prefer
+    /// --passwd-file, --passwd-stdin, or --passwd-prompt.
+    #[arg(long = "passwd", group = "passwd_source", verbatim_doc_comment)]
+    passwd: Option<SecureOn>,
+    /// Read the SecureON password from FILE.
+    #[arg(
+        long = "passwd-file",
+        value_name = "FILE",
+        group = "passwd_source",
+        value_hint = ValueHint::FilePath
+    )]
+    passwd_file: Option<PathBuf>,
+    /// Read the SecureON password from the first line of stdin.
+    #[arg(long = "passwd-stdin", group = "passwd_source")]
+    passwd_stdin: bool,
+    /// Prompt for the SecureON password on the terminal.
+    #[arg(long = "passwd-prompt", group = "passwd_source")]
+    passwd_prompt: bool,
+    /// Read SecureON passwords for hardware addresses from FILE.
+    ///
+    /// Each line of FILE has a hardware address and the
+    /// SecureON password for this address, separated by
+    /// whitespace.  Lines starting with # are ignored.
+    ///
+    /// Use these passwords for all systems which do not have a
+    /// password of their own, in favour of --passwd.
+    #[arg(
+        long = "passwd-map",
+        value_name = "FILE",
+        value_hint = ValueHint::FilePath,
+        verbatim_doc_comment
+    )]
+    passwd_map: Option<PathBuf>,
+    /// Wake up all hardware addresses from START to END.
+    ///
+    /// Wake up START, END, and all addresses in between, e.g.
+    /// AA:BB:CC:00:00:01-AA:BB:CC:00:00:40, after all
+    /// hardware addresses given as arguments.  Repeat to wake
+    /// up multiple ranges.
+    #[arg(
+        long = "range",
+        value_name = "START-END",
+        action = ArgAction::Append,
+        verbatim_doc_comment
+    )]
+    ranges: Vec<MacRange>,
+    /// Wake up the system in the hosts database which best
+    /// matches QUERY.
+    ///
+    /// Match QUERY fuzzily against the names, tags, and hosts
+    /// of all systems, e.g. dsk for office-desktop, and ask
+    /// which system to wake up if multiple systems match.
+    #[arg(long = "pick", value_name = "QUERY", verbatim_doc_comment)]
+    pick: Option<String>,
+    /// Hardware addresses or names of systems to wake up.
+    ///
+    /// Append @HOST[:PORT] to send the magic packet for this
+    /// hardware address to HOST and PORT instead of --host and
+    /// --port, and ,passwd=SECUREON to use a different SecureON
+    /// password than --passwd, e.g.
+    /// 12:13:14:15:16:17@192.168.1.255:7,passwd=cd-23-ae-9d-3f-c2.
+    /// Enclose IPv6 addresses in brackets to give a port.
+    ///
+    /// Instead of a hardware address give the name of a system
+    /// in the hosts database, or a pattern as for --only to
+    /// wake up all matching systems in the hosts database, or
+    /// @GROUP to wake up all systems in GROUP.
+    #[arg(
+        value_name = "MAC-ADDRESS",
+        required_unless_present_any(["file", "ranges", "pick"]),
+        verbatim_doc_comment
+    )]
+    #[cfg_attr(
+        feature = "dynamic-completions",
+        arg(add = clap_complete::ArgValueCandidates::new(super::complete::targets))
+    )]
+    hardware_addresses: Vec<TargetArg>,
+    #[command(flatten)]
+    hosts: HostsArgs,
+    #[command(flatten)]
+    wake_log: WakeLogArgs,
+}
+
+/// Why getting the systems to wake up failed.
+#[derive(Debug)]
+enum TargetsError {
+    /// The command line was invalid.
+    Usage(Error),
+    /// A wakeup file was invalid.
+    File(Error),
+    /// Something else failed, e.g. reading the hosts database.
+    Other(Error),
+}
+
+impl TargetsError {
+    /// Print this error to `report`, and get the exit code.
+    fn report(&self, report: &Report) -> ExitCode {
+        match self {
+            Self::Usage(error) => {
+                report.error(error, "usage");
+                ExitCode::from(EXIT_USAGE)
+            }
+            Self::File(error) => {
+                report.invalid_file(error);
+                ExitCode::from(EXIT_INVALID_FILE)
+            }
+            Self::Other(error) => {
+                report.error(error, "setup");
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
+
+impl From<Error> for TargetsError {
+    fn from(error: Error) -> Self {
+        Self::Other(error)
+    }
+}
+
+impl WakeArgs {
+    #[cfg(feature = "signature")]
+    fn iter_verified_file(
+        &self,
+        file: &PathOrStdin,
+        key: &wol::file::signature::VerificationKey,
+    ) -> std::result::Result<Box<dyn Iterator<Item = Result<wol::file::WakeUpTarget>>>, TargetsError>
+    {
+        if self.signature.is_some() && 1 < self.file.len() {
+            return Err(TargetsError::Usage(Error::new(
+                ErrorKind::InvalidInput,
+                "--signature requires a single --file",
+            )));
+        }
+        let (contents, signature_path) = match (file, &self.signature) {
+            (PathOrStdin::Stdin, Some(signature)) => {
+                let mut contents = Vec::new();
+                stdin()
+                    .read_to_end(&mut contents)
+                    .map_err(TargetsError::File)?;
+                (contents, signature.clone())
+            }
+            (PathOrStdin::Stdin, None) => {
+                return Err(TargetsError::Usage(Error::new(
+                    ErrorKind::InvalidInput,
+                    "--signature is required to verify a file from stdin",
+                )));
+            }
+            (PathOrStdin::Path(path), signature) => {
+                let signature = signature.clone().unwrap_or_else(|| {
+                    let mut signature = path.clone().into_os_string();
+                    signature.push(".minisig");
+                    signature.into()
+                });
+                (std::fs::read(path).map_err(TargetsError::File)?, signature)
+            }
+        };
+        std::fs::read_to_string(signature_path)
+            .and_then(|signature| key.verify(&contents, &signature).map_err(Error::from))
+            .map_err(TargetsError::File)?;
+        let format = match file {
+            PathOrStdin::Stdin => self.stdin_format,
+            PathOrStdin::Path(_) => StdinFormat::Plain,
+        };
+        input::read_targets(Cursor::new(contents), format).map_err(TargetsError::File)
+    }
+
+    #[cfg_attr(not(feature = "signature"), allow(clippy::unused_self))]
+    fn iter_file(
+        &self,
+        file: &PathOrStdin,
+    ) -> std::result::Result<Box<dyn Iterator<Item = Result<wol::file::WakeUpTarget>>>, TargetsError>
+    {
+        #[cfg(feature = "signature")]
+        if let Some(key) = &self.verify_key {
+            return self.iter_verified_file(file, key);
+        }
+        let targets: Box<dyn Iterator<Item = _>> = match file {
+            PathOrStdin::Stdin => input::read_targets(BufReader::new(stdin()), self.stdin_format)
+                .map_err(TargetsError::File)?,
+            PathOrStdin::Path(path) => Box::new(wol::file::from_reader(BufReader::new(
+                File::open(path).map_err(TargetsError::File)?,
+            ))),
+        };
+        Ok(targets)
+    }
+
+    /// Get the SecureON password from the command line, a file, stdin, or
+    /// the terminal.
+    fn passwd(&self) -> Result<Option<SecureOn>> {
+        if let Some(path) = &self.passwd_file {
+            let contents = std::fs::read_to_string(path).map_err(|error| {
+                Error::new(
+                    error.kind(),
+                    format!(
+                        "Failed to read SecureON password from {}: {error}",
+                        path.display()
+                    ),
+                )
+            })?;
+            parse_secure_on(&contents, &path.display().to_string()).map(Some)
+        } else if self.passwd_stdin {
+            let mut line = String::new();
+            stdin().read_line(&mut line)?;
+            parse_secure_on(&line, "stdin").map(Some)
+        } else if self.passwd_prompt {
+            let password = rpassword::prompt_password("SecureON password: ")?;
+            parse_secure_on(&password, "prompt").map(Some)
+        } else {
+            Ok(self.passwd)
+        }
+    }
+
+    /// Check that at most one of --file and --passwd-stdin reads from stdin.
+    fn check_stdin(&self) -> Result<()> {
+        let stdin_count = self
+            .file
+            .iter()
+            .filter(|file| matches!(file, PathOrStdin::Stdin))
+            .count();
+        if 1 < stdin_count {
+            Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Cannot read more than one --file from stdin",
+            ))
+        } else if self.passwd_stdin && stdin_count == 1 {
+            Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Cannot read --passwd-stdin and --file from stdin",
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Get all selected targets from all files, in stages.
+    ///
+    /// Use `default_passwd` for targets without a SecureON password.
+    fn file_stages(
+        &self,
+        default_passwd: impl Fn(MacAddress) -> Option<SecureOn>,
+    ) -> std::result::Result<Vec<Vec<WakeUpTarget>>, TargetsError> {
+        let file_targets = self.read_files()?;
+        if let Some(selector) = self
+            .only
+            .iter()
+            .find(|selector| !file_targets.iter().any(|target| selector.matches(target)))
+        {
+            return Err(TargetsError::Usage(Error::new(
+                ErrorKind::InvalidInput,
+                format!("--only {selector} matches no system in --file"),
+            )));
+        }
+        // Filter after ordering, so that after= may refer to systems we skip
+        let mut stages = wol::file::wake_stages(file_targets)
+            .map_err(|error| TargetsError::File(Error::new(ErrorKind::InvalidData, error)))?
+            .iter()
+            .map(|stage| {
+                stage
+                    .iter()
+                    .filter(|target| self.selected(target))
+                    .map(|target| {
+                        self.file_target(target, default_passwd(target.hardware_address()))
+                    })
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        stages.retain(|stage| !stage.is_empty());
+        Ok(stages)
+    }
+
+    /// Read targets from all files, in order.
+    fn read_files(&self) -> std::result::Result<Vec<wol::file::WakeUpTarget>, TargetsError> {
+        let mut targets = Vec::new();
+        for file in &self.file {
+            for target in self.iter_file(file)? {
+                targets.push(target.map_err(TargetsError::File)?);
+            }
+        }
+        Ok(targets)
+    }
+
+    /// Wake up `target` from the command line.
+    ///
+    /// Use `passwd` unless `target` has its own SecureON password.
+    fn cli_target(&self, target: &CliTarget, passwd: Option<SecureOn>) -> WakeUpTarget {
+        WakeUpTarget {
+            hardware_address: target.hardware_address,
+            host: target.host.clone().unwrap_or_else(|| self.host()),
+            port: target.port.unwrap_or_else(|| self.default_port()),
+            extra_ports: if target.port.is_some() {
+                Vec::new()
+            } else {
+                self.extra_ports()
+            },
+            secure_on: target.secure_on.or(passwd),
+            name: None,
+            address: match &target.host {
+                Some(host) if !self.all_interfaces && self.transport == Transport::Udp => {
+                    unicast(host).cloned()
+                }
+                _ => self.unicast_host(),
+            },
+        }
+    }
+
+    /// Whether to wake up `target` from --file, according to --only and
+    /// --exclude.
+    fn selected(&self, target: &wol::file::WakeUpTarget) -> bool {
+        (self.only.is_empty() || self.only.iter().any(|selector| selector.matches(target)))
+            && !self.exclude.iter().any(|selector| selector.matches(target))
+    }
+
+    fn file_target(
+        &self,
+        target: &wol::file::WakeUpTarget,
+        passwd: Option<SecureOn>,
+    ) -> Result<WakeUpTarget> {
+        let secure_on = match target.keyring_secure_on() {
+            Some(name) => Some(secure_on_from_keyring(name)?),
+            None => target.secure_on().or(passwd),
+        };
+        Ok(WakeUpTarget {
+            hardware_address: target.hardware_address(),
+            host: target
+                .packet_destination()
+                .cloned()
+                .unwrap_or_else(|| self.host()),
+            port: target.port().unwrap_or_else(|| self.default_port()),
+            extra_ports: if target.port().is_some() {
+                Vec::new()
+            } else {
+                self.extra_ports()
+            },
+            secure_on,
+            name: target.name().map(str::to_owned),
+            address: target.address().cloned(),
+        })
+    }
+
+    /// Get all targets to wake up, in stages.
+    ///
+    /// Targets from --file get ordered by their dependencies; targets from the
+    /// command line join the last or the first stage, according to --order.
+    fn targets(&self) -> std::result::Result<Vec<Vec<WakeUpTarget>>, TargetsError> {
+        self.check_stdin().map_err(TargetsError::Usage)?;
+        let passwd = self.passwd()?;
+        let passwd_map = match &self.passwd_map {
+            Some(path) => PasswdMap::read(path)?,
+            None => PasswdMap::default(),
+        };
+        let default_passwd = |hardware_address| passwd_map.get(hardware_address).or(passwd);
+        let mut stages = self.file_stages(default_passwd)?;
+        let hosts = if self.pick.is_some()
+            || self
+                .hardware_addresses
+                .iter()
+                .any(|target| !matches!(target, TargetArg::Target(_)))
+        {
+            self.hosts.read()?
+        } else {
+            Hosts::default()
+        };
+        let named = hosts.targets()?;
+        let mut cli_targets = Vec::with_capacity(self.hardware_addresses.len());
+        for target in &self.hardware_addresses {
+            match target {
+                TargetArg::Target(target) => {
+                    let passwd = default_passwd(target.hardware_address);
+                    cli_targets.push(self.cli_target(target, passwd));
+                }
+                TargetArg::Hosts(selector) => {
+                    let count = cli_targets.len();
+                    for host in named.iter().filter(|host| selector.matches(host)) {
+                        let passwd = default_passwd(host.hardware_address());
+                        cli_targets.push(self.file_target(host, passwd)?);
+                    }
+                    if count == cli_targets.len() {
+                        return Err(TargetsError::Usage(Error::new(
+                            ErrorKind::NotFound,
+                            format!(
+                                "{selector} is neither a hardware address nor a system in {}",
+                                self.hosts.path()?.display()
+                            ),
+                        )));
+                    }
+                }
+                TargetArg::Group(group) => {
+                    for host in hosts.group(group)? {
+                        let passwd = default_passwd(host.hardware_address());
+                        cli_targets.push(self.file_target(&host, passwd)?);
+                    }
+                }
+            }
+        }
+        if let Some(query) = &self.pick {
+            for host in fuzzy::pick(query, &hosts.hosts()?)? {
+                let passwd = default_passwd(host.hardware_address());
+                cli_targets.push(self.file_target(&host, passwd)?);
+            }
+        }
+        for target in self.ranges.iter().flat_map(|range| range.iter()) {
+            let target = CliTarget::from(target);
+            cli_targets.push(self.cli_target(&target, default_passwd(target.hardware_address)));
+        }
+        match self.order {
+            Order::FilesFirst => match stages.last_mut() {
+                Some(last_stage) => last_stage.extend(cli_targets),
+                None => stages.push(cli_targets),
+            },
+            Order::MacsFirst => {
+                let mut first_stage = cli_targets;
+                if !stages.is_empty() {
+                    first_stage.append(&mut stages.remove(0));
+                }
+                stages.insert(0, first_stage);
+            }
+        }
+        if self.no_dns {
+            stages
+                .iter()
+                .flatten()
+                .try_for_each(require_literal)
+                .map_err(TargetsError::Usage)?;
+        }
+        Ok(stages)
+    }
+
+    /// The default port to send magic packets to.
+    fn default_port(&self) -> u16 {
+        self.ports.first().copied().unwrap_or(self.port)
+    }
+
+    /// Further default ports to send magic packets to.
+    fn extra_ports(&self) -> Vec<u16> {
+        self.ports.iter().skip(1).copied().collect()
+    }
+
+    /// The default host to send magic packets to.
+    fn host(&self) -> MagicPacketDestination {
+        match self.broadcast {
+            Some(subnet) => MagicPacketDestination::Ip(subnet.broadcast().into()),
+            None => self.host.clone(),
+        }
+    }
+
+    /// The default host, if it is the address of a single system.
+    ///
+    /// Return `None` if magic packets go out to a broadcast or multicast
+    /// address, to all interfaces, or to a gateway over TCP.
+    fn unicast_host(&self) -> Option<MagicPacketDestination> {
+        if self.all_interfaces || self.broadcast.is_some() || self.transport == Transport::Tcp {
+            return None;
+        }
+        unicast(&self.host).cloned()
+    }
+
+    fn socket_options(&self) -> SocketOptions {
+        SocketOptions {
+            transport: self.transport,
+            proxy: self.proxy.clone(),
+            interface: self.interface.clone(),
+            bind: self.bind,
+            ttl: self.ttl,
+            tos: self.tos,
+        }
+    }
+
+    /// Whether to show a progress bar for `count` systems.
+    fn show_progress(&self, count: usize) -> bool {
+        !self.no_progress
+            && PROGRESS_THRESHOLD < count
+            && self.verbosity() == Verbosity::Normal
+            && self.then.is_none()
+            && std::io::stderr().is_terminal()
+    }
+
+    fn verbosity(&self) -> Verbosity {
+        match (
+            self.quiet || self.output == OutputFormat::Json || self.format.is_some(),
+            self.verbose,
+        ) {
+            (true, _) => Verbosity::Quiet,
+            (false, 0) => Verbosity::Normal,
+            (false, 1) => Verbosity::Verbose,
+            (false, _) => Verbosity::Debug,
+        }
+    }
+
+    /// The interface to send raw Ethernet frames out of, with --raw.
+    fn raw_interface(&self) -> Option<&str> {
+        self.interface.as_deref().filter(|_| self.raw)
+    }
+
+    /// Vendors of hardware addresses for verbose output.
+    fn vendors(&self) -> Option<Vendors> {
+        (Verbosity::Verbose <= self.verbosity())
+            .then(oui::load)
+            .flatten()
+    }
+
+    fn dns_options(&self) -> DnsOptions {
+        DnsOptions {
+            timeout: self.dns_timeout,
+            servers: self.dns_servers.clone(),
+        }
+    }
+
+    /// How long to pause after having woken up `count` systems.
+    ///
+    /// Return --batch-wait after each batch of systems, and zero otherwise.
+    fn batch_pause(&self, count: usize) -> Duration {
+        match (self.batch_size, self.batch_wait) {
+            (Some(size), Some(wait)) if count % size == 0 => wait,
+            _ => Duration::ZERO,
+        }
+    }
+
+    /// How long to wait after having woken up `count` systems.
+    fn wait_after(&self, count: usize) -> Duration {
+        let ramp = self.ramp.map_or(Duration::ZERO, |ramp| {
+            ramp.saturating_mul(u32::try_from(count.saturating_sub(1)).unwrap_or(u32::MAX))
+        });
+        let jitter = self.wait_jitter.map_or(Duration::ZERO, random_duration);
+        self.wait
+            .unwrap_or_default()
+            .saturating_add(ramp)
+            .saturating_add(jitter)
+            .max(self.batch_pause(count))
+    }
+
+    fn resolve_mode(&self) -> ResolveMode {
+        let family = if self.ipv4 {
+            AddressFamily::Ipv4
+        } else if self.ipv6 {
+            AddressFamily::Ipv6
+        } else {
+            return ResolveMode::Default;
+        };
+        if self.strict {
+            ResolveMode::Only(family)
+        } else {
+            ResolveMode::Prefer(family)
+        }
+    }
+}
+
+/// A random duration between zero and `max`.
+fn random_duration(max: Duration) -> Duration {
+    // Good enough to spread out wake ups, without pulling in a random number generator
+    let random = RandomState::new().hash_one(Instant::now());
+    let max_millis = u64::try_from(max.as_millis()).unwrap_or(u64::MAX);
+    Duration::from_millis(random % max_millis.saturating_add(1))
+}
+
+/// Fail if `target` has a DNS name as host or address.
+fn require_literal(target: &WakeUpTarget) -> Result<()> {
+    for host in std::iter::once(&target.host).chain(&target.address) {
+        if let MagicPacketDestination::Dns(dns) = host {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "Host {dns} of {} is not an IP address, but --no-dns requires IP addresses",
+                    target.hardware_address
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Parse a duration in milliseconds.
+pub fn parse_millis(value: &str) -> std::result::Result<Duration, ParseIntError> {
+    u64::from_str(value).map(Duration::from_millis)
+}
+
+/// Parse a duration in seconds.
+pub fn parse_secs(value: &str) -> std::result::Result<Duration, ParseIntError> {
+    u64::from_str(value).map(Duration::from_secs)
+}
+
+/// Parse a type of service byte in decimal or hexadecimal notation.
+fn parse_tos(value: &str) -> std::result::Result<u32, ParseIntError> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => u8::from_str(value),
+    }
+    .map(u32::from)
+}
+
+/// The delay before the first retry.
+const RETRY_BACKOFF: Duration = Duration::from_millis(250);
+
+/// The maximum delay between retries.
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Whether `error` is transient, and worth retrying.
+fn is_transient(error: &Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::NetworkUnreachable
+            | ErrorKind::NetworkDown
+            | ErrorKind::HostUnreachable
+            | ErrorKind::AddrNotAvailable
+            | ErrorKind::TimedOut
+            | ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+    )
+}
+
+/// Call `f` and retry according to `args` if it fails with a transient error.
+///
+/// `transient` decides whether an error is transient.  Stop retrying once
+/// `interrupt` is set.
+fn with_retries<T>(
+    target: &WakeUpTarget,
+    args: &WakeArgs,
+    interrupt: &Interrupt,
+    transient: impl Fn(&Error) -> bool,
+    mut f: impl FnMut() -> Result<T>,
+) -> Result<T> {
+    let mut backoff = RETRY_BACKOFF;
+    let mut retries = 0;
+    loop {
+        match f() {
+            Err(error) if retries < args.retries && transient(&error) => {
+                tracing::warn!(
+                    mac = %target.hardware_address,
+                    retry = retries + 1,
+                    "Retrying after error: {error}"
+                );
+                if Verbosity::Verbose <= args.verbosity() {
+                    println!(
+                        "Retrying {} in {}ms after error: {error}",
+                        target.hardware_address,
+                        backoff.as_millis()
+                    );
+                }
+                if !interrupt.sleep(backoff) {
+                    return Err(error);
+                }
+                backoff = (backoff * 2).min(MAX_RETRY_BACKOFF);
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Get all destinations to send magic packets for `target` to.
+fn destinations(
+    target: &WakeUpTarget,
+    args: &WakeArgs,
+    resolver: &Resolver,
+    interrupt: &Interrupt,
+) -> Result<Vec<Destination>> {
+    if args.all_interfaces {
+        let mut destinations = Vec::new();
+        for port in target.ports() {
+            destinations.extend(socket::broadcast_destinations(port)?.into_iter().map(
+                |(interface, socket_addr)| Destination {
+                    socket_addr,
+                    socket_options: SocketOptions {
+                        interface: Some(interface),
+                        ..args.socket_options()
+                    },
+                },
+            ));
+        }
+        if destinations.is_empty() {
+            Err(Error::new(
+                ErrorKind::AddrNotAvailable,
+                "No network interface to broadcast on",
+            ))
+        } else {
+            Ok(destinations)
+        }
+    } else {
+        // We can't tell temporary DNS failures from permanent ones, so always
+        // retry resolution.
+        let mut socket_addrs = with_retries(
+            target,
+            args,
+            interrupt,
+            |_| true,
+            || resolve(&target.host, target.port, args.resolve_mode(), resolver),
+        )?;
+        if let MagicPacketDestination::Dns(dns) = &target.host {
+            let addrs = socket_addrs
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            tracing::debug!(host = %dns, "Resolved to {}", addrs.join(", "));
+            if args.verbosity() == Verbosity::Debug {
+                println!("Resolved {dns} to {}", addrs.join(", "));
+            }
+        }
+        if !args.all_addresses {
+            socket_addrs.truncate(1);
+        }
+        Ok(socket_addrs
+            .into_iter()
+            .flat_map(|socket_addr| {
+                target.ports().map(move |port| {
+                    let mut socket_addr = socket_addr;
+                    socket_addr.set_port(port);
+                    socket_addr
+                })
+            })
+            .map(|socket_addr| Destination {
+                socket_addr,
+                socket_options: args.socket_options(),
+            })
+            .collect())
+    }
+}
+
+/// Whether `ip` is the address of a single system.
+///
+/// Consider the broadcast address of --broadcast and of all local network
+/// interfaces as broadcast addresses.
+fn is_unicast(ip: IpAddr, args: &WakeArgs) -> bool {
+    if ip.is_multicast() || matches!(ip, IpAddr::V4(ip) if ip.is_broadcast()) {
+        return false;
+    }
+    if args
+        .broadcast
+        .is_some_and(|subnet| IpAddr::from(subnet.broadcast()) == ip)
+    {
+        return false;
+    }
+    // Failing to list interfaces just means we can't rule out directed broadcasts
+    !socket::broadcast_destinations(0)
+        .unwrap_or_default()
+        .iter()
+        .any(|(_, socket_addr)| socket_addr.ip() == ip)
+}
+
+/// Warn if any of `destinations` of `target` is a unicast address.
+fn warn_unicast(
+    target: &WakeUpTarget,
+    destinations: &[Destination],
+    args: &WakeArgs,
+    report: &Report,
+) {
+    let Some(destination) = destinations
+        .iter()
+        .find(|destination| is_unicast(destination.socket_addr.ip(), args))
+    else {
+        return;
+    };
+    let ip = destination.socket_addr.ip();
+    tracing::warn!(mac = %target.hardware_address, destination = %ip, "Unicast destination");
+    if Verbosity::Normal <= args.verbosity() {
+        report.warning(&format!(
+            "Magic packet for {} goes to unicast address {ip}; a sleeping system may not receive it, \
+             so use a broadcast address, or --allow-unicast to silence this warning",
+            target.hardware_address
+        ));
+    }
+}
+
+/// Send a magic packet for `target` to all `destinations`.
+///
+/// Fail only if sending to all destinations failed.
+fn send_to_all(
+    target: &WakeUpTarget,
+    destinations: &[Destination],
+    args: &WakeArgs,
+    sockets: &mut Sockets,
+    interrupt: &Interrupt,
+) -> Result<()> {
+    let mut last_error = None;
+    let mut sent = false;
+    for destination in destinations {
+        let result = with_retries(target, args, interrupt, is_transient, || {
+            if let Some(proxy) = &destination.socket_options.proxy {
+                return sockets.proxy(proxy)?.send_magic_packet(
+                    target.hardware_address,
+                    target.secure_on,
+                    destination.socket_addr,
+                );
+            }
+            match destination.socket_options.transport {
+                Transport::Udp => sockets
+                    .get(&destination.socket_addr, &destination.socket_options)?
+                    .send_magic_packet(
+                        target.hardware_address,
+                        target.secure_on,
+                        destination.socket_addr,
+                    ),
+                Transport::Tcp => socket::send_tcp(
+                    &destination.socket_addr,
+                    &destination.socket_options,
+                    target.hardware_address,
+                    target.secure_on,
+                ),
+            }
+        })
+        .map_err(|error| hint::explain(error, destination.socket_addr));
+        match result {
+            Ok(()) => sent = true,
+            Err(error) => {
+                if Verbosity::Verbose <= args.verbosity() && 1 < destinations.len() {
+                    println!("Failed to send to {}: {error}", destination.socket_addr);
+                }
+                last_error = Some(error);
+            }
+        }
+    }
+    match last_error {
+        Some(error) if !sent => Err(error),
+        _ => Ok(()),
+    }
+}
+
+/// Send magic packets for `target` in raw Ethernet frames out of `interface`.
+fn send_raw(
+    target: &WakeUpTarget,
+    interface: &str,
+    args: &WakeArgs,
+    sockets: &mut Sockets,
+    interrupt: &Interrupt,
+) -> Result<()> {
+    tracing::debug!(
+        mac = %target.hardware_address,
+        interface,
+        "Sending raw magic packet"
+    );
+    for i in 0..args.repeat {
+        // Stop repeating once interrupted
+        if 0 < i && !interrupt.sleep(args.interval.unwrap_or_default()) {
+            break;
+        }
+        with_retries(target, args, interrupt, is_transient, || {
+            sockets
+                .raw(interface)?
+                .send_magic_packet(target.hardware_address, target.secure_on)
+        })?;
+    }
+    Ok(())
+}
+
+/// Wake up `target` through the relay at `relay`.
+#[cfg(feature = "relay")]
+fn wakeup_relay(
+    target: &WakeUpTarget,
+    relay: &str,
+    args: &WakeArgs,
+    report: &Report,
+    vendors: Option<&Vendors>,
+    interrupt: &Interrupt,
+) -> Result<()> {
+    match args.verbosity() {
+        Verbosity::Quiet => {}
+        Verbosity::Normal => report.begin(target.hardware_address),
+        Verbosity::Verbose | Verbosity::Debug => println!(
+            "Waking up {} through relay {relay}...",
+            oui::with_vendor(target.hardware_address, vendors),
+        ),
+    }
+    let key = match (&args.relay_key, &args.relay_key_file) {
+        (Some(key), _) => key.clone(),
+        (None, Some(path)) => Key::read(path)?,
+        (None, None) => {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Missing --relay-key or --relay-key-file",
+            ));
+        }
+    };
+    for i in 0..args.repeat {
+        // Stop repeating once interrupted
+        if 0 < i && !interrupt.sleep(args.interval.unwrap_or_default()) {
+            break;
+        }
+        relay::wake(relay, &key, target.hardware_address, target.secure_on)?;
+    }
+    Ok(())
+}
+
+/// Wake up `target` through the SSH gateway `gateway`.
+fn wakeup_via(
+    target: &WakeUpTarget,
+    gateway: &Gateway,
+    args: &WakeArgs,
+    report: &Report,
+    vendors: Option<&Vendors>,
+    interrupt: &Interrupt,
+) -> Result<()> {
+    match args.verbosity() {
+        Verbosity::Quiet => {}
+        Verbosity::Normal => report.begin(target.hardware_address),
+        Verbosity::Verbose | Verbosity::Debug => println!(
+            "Waking up {} with {}:{} via {gateway}...",
+            oui::with_vendor(target.hardware_address, vendors),
+            target.host,
+            target.ports_string()
+        ),
+    }
+    let ports = target.ports().collect::<Vec<_>>();
+    let wakeup = ssh::Wakeup {
+        hardware_address: target.hardware_address,
+        secure_on: target.secure_on,
+        host: target.host.to_string(),
+        ports: &ports,
+    };
+    with_retries(target, args, interrupt, is_transient, || {
+        ssh::wake(gateway, &args.via_helper, &wakeup)
+    })
+}
+
+fn wakeup(
+    target: &WakeUpTarget,
+    args: &WakeArgs,
+    report: &Report,
+    sockets: &mut Sockets,
+    resolver: &Resolver,
+    vendors: Option<&Vendors>,
+    interrupt: &Interrupt,
+) -> std::result::Result<(), Failure> {
+    #[cfg(feature = "relay")]
+    if let Some(relay) = &args.relay {
+        return wakeup_relay(target, relay, args, report, vendors, interrupt)
+            .map_err(Failure::Send);
+    }
+    if let Some(gateway) = &args.via {
+        return wakeup_via(target, gateway, args, report, vendors, interrupt)
+            .map_err(Failure::Send);
+    }
+    let start = Instant::now();
+    match args.verbosity() {
+        Verbosity::Quiet => {}
+        Verbosity::Normal => report.begin(target.hardware_address),
+        Verbosity::Verbose | Verbosity::Debug => {
+            if let Some(interface) = args.raw_interface() {
+                println!(
+                    "Waking up {} with raw Ethernet frames on {interface}...",
+                    oui::with_vendor(target.hardware_address, vendors),
+                );
+            } else if args.all_interfaces {
+                println!(
+                    "Waking up {} on all interfaces with port {}...",
+                    oui::with_vendor(target.hardware_address, vendors),
+                    target.ports_string()
+                );
+            } else {
+                println!(
+                    "Waking up {} with {}:{}...",
+                    oui::with_vendor(target.hardware_address, vendors),
+                    target.host,
+                    target.ports_string()
+                );
+            }
+        }
+    }
+    if let Some(interface) = args.raw_interface() {
+        return send_raw(target, interface, args, sockets, interrupt).map_err(Failure::Send);
+    }
+    let destinations = destinations(target, args, resolver, interrupt).map_err(Failure::Resolve)?;
+    // Gateways for TCP are unicast addresses by nature, and we can't tell
+    // broadcast addresses on the network of a proxy
+    if !args.allow_unicast && args.transport == Transport::Udp && args.proxy.is_none() {
+        warn_unicast(target, &destinations, args, report);
+    }
+    for destination in &destinations {
+        tracing::debug!(
+            mac = %target.hardware_address,
+            destination = %destination.socket_addr,
+            "Sending magic packet"
+        );
+    }
+    if args.verbosity() == Verbosity::Debug {
+        for destination in &destinations {
+            let options = destination.socket_options.to_string();
+            if options.is_empty() {
+                println!("Sending to {}", destination.socket_addr);
+            } else {
+                println!("Sending to {} with {options}", destination.socket_addr);
+            }
+        }
+    }
+    for i in 0..args.repeat {
+        // Stop repeating once interrupted
+        if 0 < i && !interrupt.sleep(args.interval.unwrap_or_default()) {
+            break;
+        }
+        send_to_all(target, &destinations, args, sockets, interrupt).map_err(Failure::Send)?;
+    }
+    if args.verbosity() == Verbosity::Debug {
+        println!(
+            "Sent magic packets for {} in {}ms",
+            target.hardware_address,
+            start.elapsed().as_millis()
+        );
+    }
+    Ok(())
+}
+
+/// Resolve the address of the system of `target` itself.
+fn target_address(
+    target: &WakeUpTarget,
+    args: &WakeArgs,
+    resolver: &Resolver,
+) -> Result<SocketAddr> {
+    let address = target.address.as_ref().ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "No address for {}, use address= in --file or a unicast --host",
+                target.hardware_address
+            ),
+        )
+    })?;
+    resolve(address, 0, args.resolve_mode(), resolver)?
+        .first()
+        .copied()
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::HostUnreachable,
+                format!("Host {address} not reachable"),
+            )
+        })
+}
+
+/// Wait until `target` is online.
+fn wait_online(
+    target: &WakeUpTarget,
+    args: &WakeArgs,
+    report: &Report,
+    resolver: &Resolver,
+    interrupt: &Interrupt,
+) -> Result<()> {
+    let socket_addr = target_address(target, args, resolver)?;
+    if Verbosity::Verbose <= args.verbosity() {
+        println!(
+            "Waiting for {} to come online at {} with {}...",
+            target.hardware_address,
+            socket_addr.ip(),
+            args.probe
+        );
+    }
+    let elapsed = online::wait_online(args.probe, socket_addr, args.online_timeout, || {
+        interrupt.is_set()
+    })?;
+    tracing::info!(
+        mac = %target.hardware_address,
+        elapsed_ms = u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX),
+        "Online"
+    );
+    if Verbosity::Normal <= args.verbosity() {
+        report.success(&format!(
+            "{} online after {}s",
+            target.hardware_address,
+            elapsed.as_secs()
+        ));
+    }
+    Ok(())
+}
+
+/// Wait until the woken up `target` is online with --wait-online, and run
+/// --then for it.
+fn follow_up(
+    target: &WakeUpTarget,
+    args: &WakeArgs,
+    report: &Report,
+    resolver: &Resolver,
+    interrupt: &Interrupt,
+) -> std::result::Result<(), Failure> {
+    if args.wait_online {
+        let result = wait_online(target, args, report, resolver, interrupt);
+        // Nobody waits for systems anymore once interrupted
+        #[cfg(feature = "notifications")]
+        if args.notify && !interrupt.is_set() {
+            notify_online(target, result.as_ref().err());
+        }
+        result.map_err(Failure::Online)?;
+    }
+    match &args.then {
+        Some(command) => run_command(command, target, args, resolver).map_err(Failure::Command),
+        None => Ok(()),
+    }
+}
+
+/// Show a desktop notification whether `target` came online, after waking it
+/// up.
+///
+/// Only warn if showing the notification fails; it is no reason to fail.
+#[cfg(feature = "notifications")]
+fn notify_online(target: &WakeUpTarget, error: Option<&Error>) {
+    let system = target
+        .name
+        .clone()
+        .unwrap_or_else(|| target.hardware_address.to_string());
+    let (summary, body) = match error {
+        None => (format!("{system} is online"), String::new()),
+        Some(error) => (format!("{system} did not come online"), error.to_string()),
+    };
+    let result = notify_rust::Notification::new()
+        .appname("wol")
+        .summary(&summary)
+        .body(&body)
+        .show();
+    if let Err(error) = result {
+        tracing::warn!("Failed to show desktop notification: {error}");
+    }
+}
+
+/// Run `command` for `target`.
+fn run_command(
+    command: &CommandTemplate,
+    target: &WakeUpTarget,
+    args: &WakeArgs,
+    resolver: &Resolver,
+) -> Result<()> {
+    let mut command = command.command(|key| match key {
+        "mac" => Ok(Some(target.hardware_address.to_string())),
+        "name" => target.name.clone().map(Some).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("No name for {}", target.hardware_address),
+            )
+        }),
+        "host" => target
+            .address
+            .as_ref()
+            .map(|address| Some(address.to_string()))
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("No host for {}", target.hardware_address),
+                )
+            }),
+        "ip" => {
+            target_address(target, args, resolver).map(|address| Some(address.ip().to_string()))
+        }
+        _ => Ok(None),
+    })?;
+    if Verbosity::Verbose <= args.verbosity() {
+        println!(
+            "Running {} for {}...",
+            command.get_program().to_string_lossy(),
+            target.hardware_address
+        );
+    }
+    let status = command.status()?;
+    tracing::debug!(mac = %target.hardware_address, %status, "Command finished");
+    if status.success() {
+        Ok(())
+    } else {
+        Err(Error::other(format!("Command failed with {status}")))
+    }
+}
+
+/// Report the result of waking up `target` in `duration`, and record it in the
+/// audit log.
+fn report_result(
+    report: &mut Report,
+    target: &WakeUpTarget,
+    args: &WakeArgs,
+    duration: Duration,
+    failure: Option<Failure>,
+) {
+    let result = TargetResult {
+        hardware_address: target.hardware_address,
+        name: target.name.as_deref(),
+        destination: if args.all_interfaces || args.raw {
+            None
+        } else {
+            Some(target.host.to_string())
+        },
+        port: target.port,
+        duration,
+        failure,
+    };
+    report.add(&result);
+    // Like the history, the audit log is no reason to fail
+    if let Some(path) = args.wake_log.path(history::state_dir().as_deref()) {
+        if let Err(error) = history::audit_now(&path, &result) {
+            tracing::warn!("Failed to record wake attempt in audit log: {error}");
+        }
+    }
+}
+
+/// Write magic packets for all targets in `stages` in `format`.
+///
+/// Write to --emit-file, or to standard output.
+fn emit(stages: &[Vec<WakeUpTarget>], format: PacketFormat, args: &WakeArgs) -> Result<()> {
+    let mut sink: Box<dyn Write> = match &args.emit_file {
+        Some(path) => Box::new(BufWriter::new(File::create(path).map_err(|error| {
+            Error::new(
+                error.kind(),
+                format!("Failed to create {}: {error}", path.display()),
+            )
+        })?)),
+        None => Box::new(stdout().lock()),
+    };
+    for target in stages.iter().flatten() {
+        emit::write_packet(&mut sink, format, target.hardware_address, target.secure_on)?;
+    }
+    sink.flush()
+}
+
+/// The description of units which wake up systems on a schedule.
+const SCHEDULED_DESCRIPTION: &str = "Wake up systems with Wake-on-LAN";
+
+/// The label of launch daemons which wake up systems on a schedule.
+const LAUNCHD_LABEL: &str = "de.swsnr.wol.wake";
+
+/// The command line of a unit which runs this command with `args`.
+///
+/// Drop `flag`, and its value if `takes_value`, from the command line of this
+/// process, and give the hosts database explicitly, as the unit has another
+/// home directory.
+fn scheduled_command(args: &WakeArgs, flag: &str, takes_value: bool) -> Result<Vec<OsString>> {
+    let mut command = vec![std::env::current_exe()?.into_os_string()];
+    let mut words = argfile::expand(std::env::args_os())?.into_iter().skip(1);
+    while let Some(word) = words.next() {
+        if word == flag {
+            if takes_value {
+                words.next();
+            }
+        } else if !takes_value
+            || !word
+                .to_str()
+                .and_then(|word| word.strip_prefix(flag))
+                .is_some_and(|value| value.starts_with('='))
+        {
+            command.push(word);
+        }
+    }
+    if !args.hosts.is_explicit() {
+        command.push("--hosts".into());
+        command.push(std::path::absolute(args.hosts.path()?)?.into_os_string());
+    }
+    Ok(command)
+}
+
+/// Print the unit which --print-systemd-unit, --print-systemd-timer, or
+/// --print-launchd-plist ask for, if any, and get the exit code.
+fn print_scheduled_unit(args: &WakeArgs, report: &Report) -> Option<ExitCode> {
+    match scheduled_unit(args) {
+        Ok(Some(unit)) => {
+            anstream::print!("{unit}");
+            Some(ExitCode::SUCCESS)
+        }
+        Ok(None) => None,
+        Err(error) => {
+            report.error(&error, "setup");
+            Some(ExitCode::FAILURE)
+        }
+    }
+}
+
+/// The unit which --print-systemd-unit, --print-systemd-timer, or
+/// --print-launchd-plist ask for, if any.
+fn scheduled_unit(args: &WakeArgs) -> Result<Option<String>> {
+    if args.print_systemd_unit {
+        let command = scheduled_command(args, "--print-systemd-unit", false)?;
+        systemd::oneshot_unit(SCHEDULED_DESCRIPTION, &command, &std::env::current_dir()?).map(Some)
+    } else if let Some(schedule) = &args.print_systemd_timer {
+        Ok(Some(systemd::timer_unit(SCHEDULED_DESCRIPTION, schedule)))
+    } else if let Some(schedule) = &args.print_launchd_plist {
+        let command = scheduled_command(args, "--print-launchd-plist", true)?;
+        launchd::calendar_plist(LAUNCHD_LABEL, &command, &std::env::current_dir()?, schedule)
+            .map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Show a progress bar for more than this number of systems.
+const PROGRESS_THRESHOLD: usize = 5;
+
+/// Some but not all systems failed to wake up.
+const EXIT_SOME_FAILED: u8 = 3;
+
+/// The command line or the configuration was invalid.
+const EXIT_USAGE: u8 = 2;
+
+/// The wakeup file was invalid.
+pub const EXIT_INVALID_FILE: u8 = 4;
+
+/// The base exit code for --exit-failure-count.
+const EXIT_FAILURE_COUNT_BASE: u8 = 100;
+
+/// The maximum failure count for --exit-failure-count.
+const MAX_EXIT_FAILURE_COUNT: u8 = 99;
+
+/// Get the exit code for the results in `report`.
+fn exit_code(report: &Report, args: &WakeArgs) -> ExitCode {
+    let failed = report.failed();
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else if args.exit_failure_count {
+        let count = u8::try_from(failed)
+            .unwrap_or(MAX_EXIT_FAILURE_COUNT)
+            .min(MAX_EXIT_FAILURE_COUNT);
+        ExitCode::from(EXIT_FAILURE_COUNT_BASE + count)
+    } else if failed < report.total() {
+        ExitCode::from(EXIT_SOME_FAILED)
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+/// Remember that magic packets were `sent` to systems.
+///
+/// Only log a warning if the history fails to update; that's no reason to fail.
+fn record_history(sent: &[MacAddress]) {
+    let Some(state_dir) = history::state_dir().filter(|_| !sent.is_empty()) else {
+        return;
+    };
+    if let Err(error) = history::record_now(&state_dir, sent) {
+        tracing::warn!("Failed to record wake-up history: {error}");
+    }
+}
+
+/// Wake up all systems given by `args`.
+pub fn wake(args: &WakeArgs) -> ExitCode {
+    args.color.apply();
+    let mut report = Report::new(args.output, args.verbosity() == Verbosity::Quiet)
+        .with_error_format(args.error_format)
+        .with_template(args.format.clone());
+    if let Some(code) = print_scheduled_unit(args, &report) {
+        return code;
+    }
+    let stages = match args.targets() {
+        Ok(stages) => stages,
+        Err(error) => return error.report(&report),
+    };
+    if let Some(format) = args.emit {
+        return match emit(&stages, format, args) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(error) => {
+                report.error(&error, "emit");
+                ExitCode::FAILURE
+            }
+        };
+    }
+    let count = stages.iter().map(Vec::len).sum();
+    if args.show_progress(count) {
+        report = report.with_progress(count);
+    }
+    // Register for signals first, so that the timeout covers all of the run
+    let interrupt = Interrupt::register().with_timeout(args.timeout);
+    let mut sockets = Sockets::default();
+    let resolver = Resolver::new(args.dns_options());
+    let vendors = args.vendors();
+    resolver.prefetch(stages.iter().flatten().flat_map(|target| {
+        let host = Some(&target.host).filter(|_| !args.all_interfaces && !args.raw);
+        let address = target
+            .address
+            .as_ref()
+            .filter(|_| args.wait_online || args.then.is_some());
+        host.into_iter()
+            .chain(address)
+            .filter_map(|host| match host {
+                MagicPacketDestination::Dns(dns) => Some(dns.as_str()),
+                _ => None,
+            })
+    }));
+    let mut skipped = Vec::new();
+    let mut sent = Vec::new();
+    let mut attempted = 0;
+    for (stage_index, stage) in stages.iter().enumerate() {
+        let mut interrupted = 0 < stage_index
+            && !interrupt.sleep(args.stage_wait.map_or_else(
+                || args.wait_after(attempted),
+                |wait| wait.max(args.batch_pause(attempted)),
+            ));
+        let mut woken = Vec::with_capacity(stage.len());
+        for (i, target) in stage.iter().enumerate() {
+            interrupted = interrupted
+                || interrupt.is_set()
+                || (0 < i && !interrupt.sleep(args.wait_after(attempted)));
+            // Skip all remaining systems once interrupted
+            if interrupted {
+                skipped.push(target.hardware_address);
+                continue;
+            }
+            attempted += 1;
+            let start = Instant::now();
+            match wakeup(
+                target,
+                args,
+                &report,
+                &mut sockets,
+                &resolver,
+                vendors.as_ref(),
+                &interrupt,
+            ) {
+                Ok(()) => {
+                    sent.push(target.hardware_address);
+                    if args.wait_online || args.then.is_some() {
+                        woken.push((target, start.elapsed()));
+                    } else {
+                        report_result(&mut report, target, args, start.elapsed(), None);
+                    }
+                }
+                // Do not exit early; instead attempt to wake up all devices even if one fails.
+                Err(error) => {
+                    report_result(&mut report, target, args, start.elapsed(), Some(error));
+                }
+            }
+        }
+        // Follow up on all systems of this stage only after sending all magic
+        // packets, so that they boot in parallel; but don't count the time spent
+        // on other systems against each system.
+        for (target, sending) in woken {
+            let start = Instant::now();
+            let result = follow_up(target, args, &report, &resolver, &interrupt);
+            report_result(
+                &mut report,
+                target,
+                args,
+                sending + start.elapsed(),
+                result.err(),
+            );
+        }
+    }
+    for hardware_address in skipped {
+        report.skip(hardware_address);
+    }
+    record_history(&sent);
+    finish(&report, &interrupt, args)
+}
+
+/// Finish `report`, and get the exit code.
+fn finish(report: &Report, interrupt: &Interrupt, args: &WakeArgs) -> ExitCode {
+    if interrupt.signalled() {
+        report.stopped("Interrupted");
+    } else if interrupt.timed_out() {
+        report.stopped("Timed out");
+    }
+    report.finish();
+
+    if interrupt.signalled() {
+        ExitCode::from(EXIT_INTERRUPTED)
+    } else if interrupt.timed_out() {
+        ExitCode::from(EXIT_TIMED_OUT)
+    } else {
+        exit_code(report, args)
+    }
+}
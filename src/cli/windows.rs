@@ -0,0 +1,194 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Run daemons as Windows services.
+//!
+//! Install daemons into the service control manager, so that Windows starts
+//! them at boot, and restarts them if they fail, and run daemons under the
+//! service control manager.
+
+use std::ffi::{OsStr, OsString};
+use std::io::{Error, Result};
+use std::sync::Mutex;
+use std::sync::mpsc::channel;
+use std::time::Duration;
+
+use windows_service::service::{
+    ServiceAccess, ServiceAction, ServiceActionType, ServiceControl, ServiceControlAccept,
+    ServiceErrorControl, ServiceExitCode, ServiceFailureActions, ServiceFailureResetPeriod,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::service_dispatcher;
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+
+/// The name of our service.
+pub const SERVICE_NAME: &str = "wol";
+
+/// The Win32 error when the process does not run under the service control
+/// manager.
+const ERROR_FAILED_SERVICE_CONTROLLER_CONNECT: i32 = 1063;
+
+/// How long to wait before restarting a failed service.
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// A daemon to run as service.
+type Daemon = Box<dyn FnOnce() -> Result<()> + Send>;
+
+/// The daemon for `service_main`, which the service control manager calls
+/// without arguments of ours.
+static DAEMON: Mutex<Option<Daemon>> = Mutex::new(None);
+
+/// The status of our service in `state`.
+fn status(state: ServiceState, exit_code: ServiceExitCode) -> ServiceStatus {
+    let controls_accepted = if state == ServiceState::Running {
+        ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+    } else {
+        ServiceControlAccept::empty()
+    };
+    ServiceStatus {
+        service_type: ServiceType::OWN_PROCESS,
+        current_state: state,
+        controls_accepted,
+        exit_code,
+        checkpoint: 0,
+        wait_hint: Duration::ZERO,
+        process_id: None,
+    }
+}
+
+/// Run the daemon until it fails or the service control manager stops it.
+fn run_daemon(daemon: Daemon) -> Result<()> {
+    let (stop, stopped) = channel();
+    let on_control = stop.clone();
+    let handle = service_control_handler::register(SERVICE_NAME, move |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            on_control.send(Ok(())).ok();
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })
+    .map_err(Error::other)?;
+    handle
+        .set_service_status(status(ServiceState::Running, ServiceExitCode::NO_ERROR))
+        .map_err(Error::other)?;
+    std::thread::spawn(move || {
+        stop.send(daemon()).ok();
+    });
+    let result = stopped.recv().unwrap_or(Ok(()));
+    let exit_code = match &result {
+        Ok(()) => ServiceExitCode::NO_ERROR,
+        Err(_) => ServiceExitCode::ServiceSpecific(1),
+    };
+    handle
+        .set_service_status(status(ServiceState::Stopped, exit_code))
+        .map_err(Error::other)?;
+    result
+}
+
+/// Run the daemon which `run` left for us.
+fn service_main() {
+    let daemon = DAEMON.lock().ok().and_then(|mut daemon| daemon.take());
+    if let Some(daemon) = daemon {
+        if let Err(error) = run_daemon(daemon) {
+            tracing::error!("Service {SERVICE_NAME} failed: {error}");
+        }
+    }
+}
+
+/// The entry point of our service, for the service control manager.
+///
+/// We take no arguments from the service control manager; the service gets
+/// all arguments on its command line.
+extern "system" fn ffi_service_main(_argc: u32, _argv: *mut *mut u16) {
+    service_main();
+}
+
+/// Run `daemon` as service, until the service control manager stops it.
+///
+/// # Errors
+///
+/// Return an error if this process does not run under the service control
+/// manager, or if `daemon` fails.
+pub fn run(daemon: impl FnOnce() -> Result<()> + Send + 'static) -> Result<()> {
+    if let Ok(mut slot) = DAEMON.lock() {
+        *slot = Some(Box::new(daemon));
+    }
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main).map_err(|error| match error {
+        windows_service::Error::Winapi(error)
+            if error.raw_os_error() == Some(ERROR_FAILED_SERVICE_CONTROLLER_CONNECT) =>
+        {
+            Error::new(
+                error.kind(),
+                "Only the service control manager can start the service; use --install-service to install it",
+            )
+        }
+        error => Error::other(error),
+    })
+}
+
+/// Install and start a service with `description`, which runs `command` at
+/// boot.
+///
+/// The first word of `command` is the executable.  The service runs as
+/// `LocalSystem`, and Windows restarts it when it fails.
+///
+/// # Errors
+///
+/// Return an error if `command` is empty, or if installing the service fails,
+/// e.g. because the service already exists, or for lack of privileges.
+pub fn install<S: AsRef<OsStr>>(description: &str, command: &[S]) -> Result<()> {
+    let (executable, arguments) = command
+        .split_first()
+        .ok_or_else(|| Error::new(std::io::ErrorKind::InvalidInput, "Empty service command"))?;
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .map_err(Error::other)?;
+    let info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from(description),
+        service_type: ServiceType::OWN_PROCESS,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: executable.as_ref().into(),
+        launch_arguments: arguments
+            .iter()
+            .map(|argument| argument.as_ref().to_owned())
+            .collect(),
+        dependencies: Vec::new(),
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG | ServiceAccess::START)
+        .map_err(|error| {
+            Error::other(format!("Failed to install service {SERVICE_NAME}: {error}"))
+        })?;
+    service
+        .set_description(format!("{description}; see {}", env!("CARGO_PKG_HOMEPAGE")))
+        .map_err(Error::other)?;
+    service
+        .update_failure_actions(ServiceFailureActions {
+            reset_period: ServiceFailureResetPeriod::After(Duration::from_secs(86400)),
+            reboot_msg: None,
+            command: None,
+            actions: Some(vec![ServiceAction {
+                action_type: ServiceActionType::Restart,
+                delay: RESTART_DELAY,
+            }]),
+        })
+        .map_err(Error::other)?;
+    // Also restart the service if it stops with an error, not only if it crashes
+    service
+        .set_failure_actions_on_non_crash_failures(true)
+        .map_err(Error::other)?;
+    service
+        .start::<&OsStr>(&[])
+        .map_err(|error| Error::other(format!("Failed to start service {SERVICE_NAME}: {error}")))
+}
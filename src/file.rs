@@ -8,28 +8,43 @@
 //!
 //! A "wakeup file" is a file containing lines denoting systems to wake up.
 //! Each line is a whitespace-separated sequence of hardware address, and
-//! optionally packet destination, port, and SecureON token. See
+//! optionally packet destination, port, SecureON token, and attributes. See
 //! [`WakeUpTarget`] for documentation for details.
 //!
+//! Targets may declare dependencies on other targets; use [`wake_stages`] to
+//! wake them up in order.
+//!
 //! Blank lines and lines starting with `#` are ignored.
 //!
 //! Use [`from_lines`] or [`from_reader`] to read wakeup files.
+//!
+//! With the `signature` feature, the `signature` module verifies detached
+//! minisign signatures of wakeup files, to refuse acting on tampered files.
 
 use std::fmt::Display;
 use std::io::{BufRead, Error, ErrorKind};
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv6Addr};
 use std::num::ParseIntError;
 use std::str::FromStr;
 
-use crate::{MacAddress, ParseError, SecureOn};
+use crate::{MacAddress, ParseError, ParseErrorKind, SecureOn};
+
+#[cfg(feature = "signature")]
+pub mod signature;
 
 /// A destination to send a magic packet to.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum MagicPacketDestination {
     /// A DNS name to be resolved into an IP address.
     Dns(String),
     /// An IP address.
     Ip(IpAddr),
+    /// An IPv6 address with a zone ID.
+    ///
+    /// The zone ID is either the name or the index of a network interface, as
+    /// in `ff02::1%eth0` or `ff02::1%3`.
+    ScopedIpv6(Ipv6Addr, String),
 }
 
 impl Display for MagicPacketDestination {
@@ -37,12 +52,20 @@ impl Display for MagicPacketDestination {
         match self {
             MagicPacketDestination::Dns(name) => write!(f, "{name}"),
             MagicPacketDestination::Ip(ip_addr) => write!(f, "{ip_addr}"),
+            MagicPacketDestination::ScopedIpv6(ip_addr, zone) => write!(f, "{ip_addr}%{zone}"),
         }
     }
 }
 
 impl From<String> for MagicPacketDestination {
     fn from(value: String) -> Self {
+        if let Some((address, zone)) = value.split_once('%') {
+            if let Ok(address) = Ipv6Addr::from_str(address) {
+                if !zone.is_empty() {
+                    return Self::ScopedIpv6(address, zone.to_owned());
+                }
+            }
+        }
         IpAddr::from_str(&value)
             .ok()
             .map_or_else(|| Self::Dns(value), Self::Ip)
@@ -56,21 +79,67 @@ impl From<String> for MagicPacketDestination {
 /// Wake up targets can be parsed from strings in the following format:
 ///
 /// ```text
-/// <hardware-address> [<IP/DNS name>] [<port>] [<secure-on>]
+/// <hardware-address> [<IP/DNS name>] [<port>] [<secure-on>] [<key>=<value>...]
 /// ```
 ///
 /// Except for the hardware address all other fields are optional.
 ///
+/// The positional fields may be followed by attributes of the form
+/// `<key>=<value>`:
+///
+/// - `name=<name>` names the target.
+/// - `address=<IP/DNS name>` gives the address of the target system itself,
+///   e.g. to check whether it is online after waking it up.
+/// - `after=<name>[,<name>...]` wakes up the target only after all targets with
+///   any of the given names; see [`wake_stages`].
+///
+/// Fields are separated by whitespace.  To include whitespace in a field,
+/// enclose the field or parts of it in double quotes, e.g. `name="rack 3
+/// switch"`.  A backslash escapes the following character, both inside and
+/// outside double quotes, e.g. `name=rack\ 3\ switch` or `name="the \"big\"
+/// one"`.  See [`quote_field`] to quote fields for wakeup files.
+///
 /// The MAC address is given as six hexadecimal bytes separated by dashes or
 /// colons, e.g `XX-XX-XX-XX-XX-XX` or `XX:XX:XX:XX:XX:XX`.
 ///
-/// The SecureON is given in the same format.
+/// The SecureON is given in the same format.  Alternatively, the SecureON
+/// field may refer to a token in the system keyring with `keyring:<name>`; see
+/// [`WakeUpTarget::keyring_secure_on`].
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct WakeUpTarget {
     hardware_address: MacAddress,
     packet_destination: Option<MagicPacketDestination>,
     port: Option<u16>,
-    secure_on: Option<SecureOn>,
+    secure_on: Option<SecureOnField>,
+    name: Option<String>,
+    address: Option<MagicPacketDestination>,
+    after: Vec<String>,
+}
+
+/// The prefix of a SecureON field which refers to a keyring entry.
+const KEYRING_PREFIX: &str = "keyring:";
+
+/// The SecureON field of a wake up target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SecureOnField {
+    /// A literal SecureON token.
+    Token(SecureOn),
+    /// The name of a keyring entry holding the SecureON token.
+    Keyring(String),
+}
+
+impl FromStr for SecureOnField {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix(KEYRING_PREFIX) {
+            Some("") => Err(ParseError {
+                kind: ParseErrorKind::TooShort,
+            }),
+            Some(name) => Ok(Self::Keyring(name.to_owned())),
+            None => SecureOn::from_str(s).map(Self::Token),
+        }
+    }
 }
 
 impl WakeUpTarget {
@@ -82,6 +151,9 @@ impl WakeUpTarget {
             packet_destination: None,
             port: None,
             secure_on: None,
+            name: None,
+            address: None,
+            after: Vec::new(),
         }
     }
 
@@ -107,9 +179,52 @@ impl WakeUpTarget {
     }
 
     /// Get the SecureON token to include in the packet if any.
+    ///
+    /// Return `None` if this target has no SecureON token, or refers to a
+    /// token in the keyring.
     #[must_use]
     pub fn secure_on(&self) -> Option<SecureOn> {
-        self.secure_on
+        match self.secure_on {
+            Some(SecureOnField::Token(secure_on)) => Some(secure_on),
+            Some(SecureOnField::Keyring(_)) | None => None,
+        }
+    }
+
+    /// Get the name of the keyring entry holding the SecureON token if any.
+    ///
+    /// In a wakeup file, the SecureON field may be given as `keyring:<name>`
+    /// to keep the actual token out of the file; it's up to the application
+    /// to look up `<name>` in a keyring or secret store, and parse the secret
+    /// as [`SecureOn`].
+    #[must_use]
+    pub fn keyring_secure_on(&self) -> Option<&str> {
+        match &self.secure_on {
+            Some(SecureOnField::Keyring(name)) => Some(name),
+            Some(SecureOnField::Token(_)) | None => None,
+        }
+    }
+
+    /// Get the name of this target if any.
+    #[must_use]
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Get the address of the target system itself if any.
+    ///
+    /// Unlike the packet destination, which is most likely a broadcast or
+    /// multicast address, this is the address the system has once awake.
+    #[must_use]
+    pub fn address(&self) -> Option<&MagicPacketDestination> {
+        self.address.as_ref()
+    }
+
+    /// Get the names of targets to wake up before this target.
+    ///
+    /// See [`wake_stages`].
+    #[must_use]
+    pub fn after(&self) -> &[String] {
+        &self.after
     }
 
     /// Change the hardware address.
@@ -153,7 +268,42 @@ impl WakeUpTarget {
     /// Change the SecureON token for this target.
     #[must_use]
     pub fn with_secure_on(mut self, secure_on: Option<SecureOn>) -> Self {
-        self.secure_on = secure_on;
+        self.secure_on = secure_on.map(SecureOnField::Token);
+        self
+    }
+
+    /// Refer to a keyring entry for the SecureON token of this target.
+    ///
+    /// See [`Self::keyring_secure_on`].
+    #[must_use]
+    pub fn with_keyring_secure_on(mut self, name: Option<String>) -> Self {
+        self.secure_on = name.map(SecureOnField::Keyring);
+        self
+    }
+
+    /// Change the name of this target.
+    #[must_use]
+    pub fn with_name(mut self, name: Option<String>) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Change the address of the target system itself.
+    #[must_use]
+    pub fn with_address(mut self, address: Option<MagicPacketDestination>) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Change the names of targets to wake up before this target.
+    #[must_use]
+    pub fn with_after(mut self, after: Vec<String>) -> Self {
+        self.after = after;
+        self
+    }
+
+    fn with_secure_on_field(mut self, secure_on: SecureOnField) -> Self {
+        self.secure_on = Some(secure_on);
         self
     }
 }
@@ -171,6 +321,14 @@ pub enum WakeUpTargetParseError {
     InvalidSecureOn(u8, ParseError),
     /// The line had more than the expected number of fields.
     TooManyFields(usize),
+    /// The attribute in the given field was not of the form `<key>=<value>`.
+    InvalidAttribute(usize),
+    /// The attribute in the given field had an unknown key.
+    UnknownAttribute(usize, String),
+    /// A double quote was not closed.
+    UnterminatedQuote,
+    /// A backslash at the end of the line did not escape any character.
+    TrailingBackslash,
 }
 
 impl Display for WakeUpTargetParseError {
@@ -189,6 +347,14 @@ impl Display for WakeUpTargetParseError {
             }
 
             Self::TooManyFields(fields) => write!(f, "Expected 4 fields, got {fields}"),
+            Self::InvalidAttribute(field) => {
+                write!(f, "Field {field}: Expected attribute <key>=<value>")
+            }
+            Self::UnknownAttribute(field, key) => {
+                write!(f, "Field {field}: Unknown attribute {key}")
+            }
+            Self::UnterminatedQuote => write!(f, "Unterminated quote"),
+            Self::TrailingBackslash => write!(f, "Trailing backslash"),
         }
     }
 }
@@ -199,26 +365,90 @@ impl std::error::Error for WakeUpTargetParseError {
             Self::InvalidHardwareAddress(parse_error) => Some(parse_error),
             Self::InvalidPort(_, error) => Some(error),
             Self::InvalidSecureOn(_, error) => Some(error),
-            Self::TooManyFields(_) | Self::Empty => None,
+            Self::TooManyFields(_)
+            | Self::Empty
+            | Self::InvalidAttribute(_)
+            | Self::UnknownAttribute(_, _)
+            | Self::UnterminatedQuote
+            | Self::TrailingBackslash => None,
         }
     }
 }
 
-impl FromStr for WakeUpTarget {
-    type Err = WakeUpTargetParseError;
+/// Split `s` into whitespace-separated fields.
+///
+/// Double quotes group characters including whitespace into a single field,
+/// and a backslash escapes the following character, both inside and outside
+/// quotes.
+fn split_fields(s: &str) -> Result<Vec<String>, WakeUpTargetParseError> {
+    let mut fields = Vec::new();
+    let mut field: Option<String> = None;
+    let mut quoted = false;
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let escaped = chars
+                    .next()
+                    .ok_or(WakeUpTargetParseError::TrailingBackslash)?;
+                field.get_or_insert_default().push(escaped);
+            }
+            '"' => {
+                quoted = !quoted;
+                field.get_or_insert_default();
+            }
+            c if c.is_ascii_whitespace() && !quoted => fields.extend(field.take()),
+            c => field.get_or_insert_default().push(c),
+        }
+    }
+    if quoted {
+        Err(WakeUpTargetParseError::UnterminatedQuote)
+    } else {
+        fields.extend(field);
+        Ok(fields)
+    }
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts = s.split_ascii_whitespace().collect::<Vec<_>>();
-        match parts[..] {
-            [] => Err(Self::Err::Empty),
+/// Quote `field` for a wakeup file if necessary.
+///
+/// Return `field` unchanged if it contains neither whitespace nor quotes nor
+/// backslashes; otherwise enclose it in double quotes and escape all quotes
+/// and backslashes inside.
+#[must_use]
+pub fn quote_field(field: &str) -> std::borrow::Cow<'_, str> {
+    if !field.is_empty()
+        && !field
+            .chars()
+            .any(|c| c.is_ascii_whitespace() || c == '"' || c == '\\')
+    {
+        field.into()
+    } else {
+        let mut quoted = String::with_capacity(field.len() + 2);
+        quoted.push('"');
+        for c in field.chars() {
+            if c == '"' || c == '\\' {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+        quoted.push('"');
+        quoted.into()
+    }
+}
+
+impl WakeUpTarget {
+    /// Parse a target from its positional fields.
+    fn from_fields(parts: &[&str]) -> Result<Self, WakeUpTargetParseError> {
+        match *parts {
+            [] => Err(WakeUpTargetParseError::Empty),
             [field_1] => MacAddress::from_str(field_1)
-                .map_err(Self::Err::InvalidHardwareAddress)
+                .map_err(WakeUpTargetParseError::InvalidHardwareAddress)
                 .map(Self::new),
             [field_1, field_2] => {
                 let mut line = MacAddress::from_str(field_1)
-                    .map_err(Self::Err::InvalidHardwareAddress)
+                    .map_err(WakeUpTargetParseError::InvalidHardwareAddress)
                     .map(Self::new)?;
-                if let Ok(secure_on) = SecureOn::from_str(field_2) {
+                if let Ok(secure_on) = SecureOnField::from_str(field_2) {
                     line.secure_on = Some(secure_on);
                 } else if let Ok(port) = u16::from_str(field_2) {
                     line.port = Some(port);
@@ -230,9 +460,9 @@ impl FromStr for WakeUpTarget {
             }
             [field_1, field_2, field_3] => {
                 let mut line = MacAddress::from_str(field_1)
-                    .map_err(Self::Err::InvalidHardwareAddress)
+                    .map_err(WakeUpTargetParseError::InvalidHardwareAddress)
                     .map(Self::new)?;
-                match SecureOn::from_str(field_3) {
+                match SecureOnField::from_str(field_3) {
                     Ok(secure_on) => {
                         line.secure_on = Some(secure_on);
                         if let Ok(port) = u16::from_str(field_2) {
@@ -246,33 +476,84 @@ impl FromStr for WakeUpTarget {
                     Err(error) if field_3.contains(['.', ':', '-']) => {
                         // If the 3rd field contains MAC address separators, it definitely can't be a valid numeric port,
                         // and is likely just an invalid SecureON password.
-                        Err(Self::Err::InvalidSecureOn(3, error))
+                        Err(WakeUpTargetParseError::InvalidSecureOn(3, error))
                     }
                     Err(_) => {
                         // If field 3 is not a SecureON password, then field 3 must be a port
                         line.packet_destination =
                             Some(MagicPacketDestination::from(field_2.to_owned()));
                         line.port = Some(
-                            u16::from_str(field_3).map_err(|err| Self::Err::InvalidPort(3, err))?,
+                            u16::from_str(field_3)
+                                .map_err(|err| WakeUpTargetParseError::InvalidPort(3, err))?,
                         );
                         Ok(line)
                     }
                 }
             }
             [field_1, field_2, field_3, field_4] => Ok(MacAddress::from_str(field_1)
-                .map_err(Self::Err::InvalidHardwareAddress)
+                .map_err(WakeUpTargetParseError::InvalidHardwareAddress)
                 .map(Self::new)?
                 .with_packet_destination(Some(MagicPacketDestination::from(field_2.to_owned())))
                 .with_port(Some(
-                    u16::from_str(field_3).map_err(|err| Self::Err::InvalidPort(3, err))?,
+                    u16::from_str(field_3)
+                        .map_err(|err| WakeUpTargetParseError::InvalidPort(3, err))?,
                 ))
-                .with_secure_on(Some(
-                    SecureOn::from_str(field_4)
-                        .map_err(|error| Self::Err::InvalidSecureOn(4, error))?,
-                ))),
-            _ => Err(Self::Err::TooManyFields(parts.len())),
+                .with_secure_on_field(
+                    SecureOnField::from_str(field_4)
+                        .map_err(|error| WakeUpTargetParseError::InvalidSecureOn(4, error))?,
+                )),
+            _ => Err(WakeUpTargetParseError::TooManyFields(parts.len())),
         }
     }
+
+    /// Apply the attribute `field` at the 1-based field number `field_no`.
+    fn with_attribute(self, field_no: usize, field: &str) -> Result<Self, WakeUpTargetParseError> {
+        let (key, value) = field
+            .split_once('=')
+            .filter(|(_, value)| !value.is_empty())
+            .ok_or(WakeUpTargetParseError::InvalidAttribute(field_no))?;
+        match key {
+            "name" => Ok(self.with_name(Some(value.to_owned()))),
+            "address" => {
+                Ok(self.with_address(Some(MagicPacketDestination::from(value.to_owned()))))
+            }
+            "after" => {
+                let after = value.split(',').map(str::to_owned).collect::<Vec<_>>();
+                if after.iter().any(String::is_empty) {
+                    Err(WakeUpTargetParseError::InvalidAttribute(field_no))
+                } else {
+                    Ok(self.with_after(after))
+                }
+            }
+            _ => Err(WakeUpTargetParseError::UnknownAttribute(
+                field_no,
+                key.to_owned(),
+            )),
+        }
+    }
+}
+
+impl FromStr for WakeUpTarget {
+    type Err = WakeUpTargetParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields = split_fields(s)?;
+        let fields = fields.iter().map(String::as_str).collect::<Vec<_>>();
+        // Attributes follow the positional fields; the first field is always
+        // the hardware address, even if it contains a `=`.
+        let positional = fields
+            .iter()
+            .skip(1)
+            .position(|field| field.contains('='))
+            .map_or(fields.len(), |i| i + 1);
+        let (parts, attributes) = fields.split_at(positional);
+        attributes
+            .iter()
+            .enumerate()
+            .try_fold(Self::from_fields(parts)?, |target, (i, field)| {
+                target.with_attribute(positional + i + 1, field)
+            })
+    }
 }
 
 /// An invalid [`WakeUpTarget`] in an iterator over lines.
@@ -362,6 +643,112 @@ pub fn from_reader<R: BufRead>(reader: R) -> impl Iterator<Item = Result<WakeUpT
     })
 }
 
+/// Invalid dependencies between wake up targets.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WakeOrderError {
+    /// The target with the given hardware address is to be woken up after an
+    /// unknown name.
+    UnknownDependency(MacAddress, String),
+    /// The targets with the given hardware addresses depend on each other.
+    Cycle(Vec<MacAddress>),
+}
+
+impl Display for WakeOrderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownDependency(hardware_address, name) => {
+                write!(f, "{hardware_address}: Unknown target {name} in after")
+            }
+            Self::Cycle(hardware_addresses) => {
+                write!(f, "Cyclic dependencies between ")?;
+                for (i, hardware_address) in hardware_addresses.iter().enumerate() {
+                    if 0 < i {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{hardware_address}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for WakeOrderError {}
+
+/// Sort targets into stages according to their dependencies.
+///
+/// The first stage contains all targets without dependencies, and each further
+/// stage all targets which only depend on targets in earlier stages, see
+/// [`WakeUpTarget::after`].  Within each stage targets retain their original
+/// order.
+///
+/// Wake up all targets of a stage before moving to the next stage.  Stages
+/// carry no delay: wakeup files do not say how long systems take to boot, so
+/// callers decide whether and how long to wait between stages, e.g. with
+/// [`std::thread::sleep`], or by waiting until the systems of a stage respond.
+///
+/// # Errors
+///
+/// Return an error if a target depends on a name no target has, or if targets
+/// depend on each other.
+pub fn wake_stages(targets: Vec<WakeUpTarget>) -> Result<Vec<Vec<WakeUpTarget>>, WakeOrderError> {
+    for target in &targets {
+        if let Some(name) = target
+            .after()
+            .iter()
+            .find(|name| !targets.iter().any(|t| t.name() == Some(name.as_str())))
+        {
+            return Err(WakeOrderError::UnknownDependency(
+                target.hardware_address(),
+                name.clone(),
+            ));
+        }
+    }
+
+    let mut stage_of: Vec<Option<usize>> = vec![None; targets.len()];
+    let mut stage = 0;
+    while stage_of.iter().any(Option::is_none) {
+        let ready = targets
+            .iter()
+            .zip(&stage_of)
+            .map(|(target, target_stage)| {
+                target_stage.is_none()
+                    && target.after().iter().all(|name| {
+                        targets
+                            .iter()
+                            .zip(&stage_of)
+                            .filter(|(t, _)| t.name() == Some(name.as_str()))
+                            .all(|(_, s)| s.is_some_and(|s| s < stage))
+                    })
+            })
+            .collect::<Vec<_>>();
+        if !ready.contains(&true) {
+            return Err(WakeOrderError::Cycle(
+                targets
+                    .iter()
+                    .zip(&stage_of)
+                    .filter(|(_, s)| s.is_none())
+                    .map(|(t, _)| t.hardware_address())
+                    .collect(),
+            ));
+        }
+        for (target_stage, ready) in stage_of.iter_mut().zip(ready) {
+            if ready {
+                *target_stage = Some(stage);
+            }
+        }
+        stage += 1;
+    }
+
+    let mut stages = vec![Vec::new(); stage];
+    for (target, target_stage) in targets.into_iter().zip(stage_of) {
+        if let Some(stage) = target_stage.and_then(|s| stages.get_mut(s)) {
+            stage.push(target);
+        }
+    }
+    Ok(stages)
+}
+
 #[cfg(test)]
 mod tests {
     use std::{io::BufReader, net::IpAddr, str::FromStr};
@@ -511,6 +898,61 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_target_from_string_keyring_secure_on() {
+        assert_eq!(
+            WakeUpTarget::from_str("12:13:14:15:16:17 keyring:nas-token").unwrap(),
+            WakeUpTarget::new(MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]))
+                .with_keyring_secure_on(Some("nas-token".into()))
+        );
+        assert_eq!(
+            WakeUpTarget::from_str("12:13:14:15:16:17 192.0.2.4 keyring:nas-token").unwrap(),
+            WakeUpTarget::new(MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]))
+                .with_ip_packet_destination(IpAddr::from_str("192.0.2.4").unwrap())
+                .with_keyring_secure_on(Some("nas-token".into()))
+        );
+        let target =
+            WakeUpTarget::from_str("12:13:14:15:16:17 192.0.2.4 9 keyring:nas-token").unwrap();
+        assert_eq!(target.keyring_secure_on(), Some("nas-token"));
+        assert_eq!(target.secure_on(), None);
+        assert_eq!(
+            WakeUpTarget::from_str("12:13:14:15:16:17 192.0.2.4 9 keyring:").unwrap_err(),
+            WakeUpTargetParseError::InvalidSecureOn(
+                4,
+                ParseError {
+                    kind: ParseErrorKind::TooShort
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_packet_destination_scoped_ipv6() {
+        assert_eq!(
+            MagicPacketDestination::from("ff02::1%eth0".to_owned()),
+            MagicPacketDestination::ScopedIpv6(
+                Ipv6Addr::from_str("ff02::1").unwrap(),
+                "eth0".into()
+            )
+        );
+        assert_eq!(
+            MagicPacketDestination::from("fe80::1%3".to_owned()),
+            MagicPacketDestination::ScopedIpv6(Ipv6Addr::from_str("fe80::1").unwrap(), "3".into())
+        );
+        assert_eq!(
+            MagicPacketDestination::from("ff02::1%eth0".to_owned()).to_string(),
+            "ff02::1%eth0"
+        );
+        assert_eq!(
+            MagicPacketDestination::from("ff02::1%".to_owned()),
+            MagicPacketDestination::Dns("ff02::1%".into())
+        );
+        assert_eq!(
+            MagicPacketDestination::from("192.0.2.1%eth0".to_owned()),
+            MagicPacketDestination::Dns("192.0.2.1%eth0".into())
+        );
+    }
+
     #[test]
     fn test_target_from_string_full() {
         let line =
@@ -532,6 +974,153 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_target_from_string_attributes() {
+        assert_eq!(
+            WakeUpTarget::from_str("12:13:14:15:16:17 192.0.2.4 9 name=nas").unwrap(),
+            WakeUpTarget::new(MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]))
+                .with_ip_packet_destination(IpAddr::from_str("192.0.2.4").unwrap())
+                .with_port(Some(9))
+                .with_name(Some("nas".into()))
+        );
+        assert_eq!(
+            WakeUpTarget::from_str("12:13:14:15:16:17 name=vm after=nas,hypervisor").unwrap(),
+            WakeUpTarget::new(MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]))
+                .with_name(Some("vm".into()))
+                .with_after(vec!["nas".into(), "hypervisor".into()])
+        );
+        assert_eq!(
+            WakeUpTarget::from_str("12:13:14:15:16:17 name=nas address=nas.example.com").unwrap(),
+            WakeUpTarget::new(MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]))
+                .with_name(Some("nas".into()))
+                .with_address(Some(MagicPacketDestination::Dns("nas.example.com".into())))
+        );
+        assert_eq!(
+            WakeUpTarget::from_str("12:13:14:15:16:17 address=192.0.2.42")
+                .unwrap()
+                .address(),
+            Some(&MagicPacketDestination::Ip(
+                IpAddr::from_str("192.0.2.42").unwrap()
+            ))
+        );
+        assert_eq!(
+            WakeUpTarget::from_str("12:13:14:15:16:17 name=vm 9").unwrap_err(),
+            WakeUpTargetParseError::InvalidAttribute(3)
+        );
+        assert_eq!(
+            WakeUpTarget::from_str("12:13:14:15:16:17 name=").unwrap_err(),
+            WakeUpTargetParseError::InvalidAttribute(2)
+        );
+        assert_eq!(
+            WakeUpTarget::from_str("12:13:14:15:16:17 after=nas,").unwrap_err(),
+            WakeUpTargetParseError::InvalidAttribute(2)
+        );
+        assert_eq!(
+            WakeUpTarget::from_str("12:13:14:15:16:17 9 color=red").unwrap_err(),
+            WakeUpTargetParseError::UnknownAttribute(3, "color".into())
+        );
+    }
+
+    #[test]
+    fn test_target_from_string_quoted() {
+        assert_eq!(
+            WakeUpTarget::from_str(r#"12:13:14:15:16:17 "192.0.2.4" name="rack 3 switch""#)
+                .unwrap(),
+            WakeUpTarget::new(MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]))
+                .with_ip_packet_destination(IpAddr::from_str("192.0.2.4").unwrap())
+                .with_name(Some("rack 3 switch".into()))
+        );
+        assert_eq!(
+            WakeUpTarget::from_str(r#"12:13:14:15:16:17 name=rack\ 3\ \"switch\""#).unwrap(),
+            WakeUpTarget::new(MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]))
+                .with_name(Some(r#"rack 3 "switch""#.into()))
+        );
+        assert_eq!(
+            WakeUpTarget::from_str(r#"12:13:14:15:16:17 name="C:\\My \"PC\"""#).unwrap(),
+            WakeUpTarget::new(MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]))
+                .with_name(Some(r#"C:\My "PC""#.into()))
+        );
+        assert_eq!(
+            WakeUpTarget::from_str(r#"12:13:14:15:16:17 name="rack 3"#).unwrap_err(),
+            WakeUpTargetParseError::UnterminatedQuote
+        );
+        assert_eq!(
+            WakeUpTarget::from_str(r"12:13:14:15:16:17 name=rack\").unwrap_err(),
+            WakeUpTargetParseError::TrailingBackslash
+        );
+        assert_eq!(
+            WakeUpTarget::from_str(r#"12:13:14:15:16:17 name="""#).unwrap_err(),
+            WakeUpTargetParseError::InvalidAttribute(2)
+        );
+    }
+
+    #[test]
+    fn test_quote_field() {
+        for field in ["nas", "rack 3 switch", r#"the "big" one"#, r"C:\My PC", ""] {
+            let quoted = quote_field(field);
+            assert_eq!(split_fields(&quoted).unwrap(), vec![field.to_owned()]);
+        }
+        assert_eq!(quote_field("nas"), "nas");
+        assert_eq!(quote_field("rack 3"), r#""rack 3""#);
+        assert_eq!(quote_field(r#"a"b\c"#), r#""a\"b\\c""#);
+    }
+
+    fn named(last: u8, name: &str, after: &[&str]) -> WakeUpTarget {
+        WakeUpTarget::new(MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, last]))
+            .with_name(Some(name.into()))
+            .with_after(after.iter().map(|s| (*s).to_owned()).collect())
+    }
+
+    #[test]
+    fn test_wake_stages() {
+        let targets = vec![
+            named(1, "vm1", &["bridge"]),
+            named(2, "nas", &[]),
+            named(3, "bridge", &["nas", "hypervisor"]),
+            named(4, "hypervisor", &[]),
+            named(5, "vm2", &["bridge", "nas"]),
+        ];
+        assert_eq!(
+            wake_stages(targets).unwrap(),
+            vec![
+                vec![named(2, "nas", &[]), named(4, "hypervisor", &[])],
+                vec![named(3, "bridge", &["nas", "hypervisor"])],
+                vec![
+                    named(1, "vm1", &["bridge"]),
+                    named(5, "vm2", &["bridge", "nas"])
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wake_stages_unknown_dependency() {
+        let targets = vec![named(1, "vm", &["nas"])];
+        assert_eq!(
+            wake_stages(targets).unwrap_err(),
+            WakeOrderError::UnknownDependency(
+                MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 1]),
+                "nas".into()
+            )
+        );
+    }
+
+    #[test]
+    fn test_wake_stages_cycle() {
+        let targets = vec![
+            named(1, "a", &["b"]),
+            named(2, "b", &["a"]),
+            named(3, "c", &[]),
+        ];
+        assert_eq!(
+            wake_stages(targets).unwrap_err(),
+            WakeOrderError::Cycle(vec![
+                MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 1]),
+                MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 2]),
+            ])
+        );
+    }
+
     #[test]
     fn test_line_from_string_too_many_fields() {
         assert_eq!(
@@ -0,0 +1,139 @@
+// Copyright Sebastian Wiesner <sebastian@swsnr.de>
+//
+// Licensed under the EUPL
+//
+// See https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12
+
+//! Verify signatures of wakeup files.
+//!
+//! Wakeup files fetched from shared locations can be signed with
+//! [minisign](https://jedisct1.github.io/minisign/); use a [`VerificationKey`]
+//! to check the detached signature of a file before parsing it:
+//!
+//! ```
+//! # use std::str::FromStr;
+//! use wol::file::signature::VerificationKey;
+//!
+//! let key = VerificationKey::from_str("RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3").unwrap();
+//! let signature = "untrusted comment: signature from minisign secret key
+//! RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+//! trusted comment: timestamp:1633700835\tfile:test\tprehashed
+//! wLMDjy9FLAuxZ3q4NlEvkgtyhrr0gtTu6KC4KBJdITbbOeAi1zBIYo0v4iTgt8jJpIidRJnp94ABQkJAgAooBQ==";
+//! assert!(key.verify(b"test", signature).is_ok());
+//! assert!(key.verify(b"tampered", signature).is_err());
+//! ```
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// An invalid key or signature, or a failed verification.
+#[derive(Debug)]
+pub struct SignatureError(minisign_verify::Error);
+
+impl Display for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for SignatureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.0)
+    }
+}
+
+impl From<SignatureError> for std::io::Error {
+    fn from(value: SignatureError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, value)
+    }
+}
+
+/// A minisign public key to verify wakeup files with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerificationKey(minisign_verify::PublicKey);
+
+impl VerificationKey {
+    /// Decode a key from the contents of a minisign public key file.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if `contents` is not a valid minisign public key file.
+    pub fn decode(contents: &str) -> Result<Self, SignatureError> {
+        minisign_verify::PublicKey::decode(contents)
+            .map(Self)
+            .map_err(SignatureError)
+    }
+
+    /// Verify a detached `signature` for `contents`.
+    ///
+    /// `signature` is the contents of a `.minisig` file for `contents`.
+    ///
+    /// # Errors
+    ///
+    /// Return an error if the signature is malformed, was made with a
+    /// different key, or does not match `contents`.
+    pub fn verify(&self, contents: &[u8], signature: &str) -> Result<(), SignatureError> {
+        let signature = minisign_verify::Signature::decode(signature).map_err(SignatureError)?;
+        self.0
+            .verify(contents, &signature, false)
+            .map_err(SignatureError)
+    }
+}
+
+/// Parse a verification key from its base64 representation.
+///
+/// This is the format `minisign -P` accepts, and the second line of a minisign
+/// public key file.
+impl FromStr for VerificationKey {
+    type Err = SignatureError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        minisign_verify::PublicKey::from_base64(s.trim())
+            .map(Self)
+            .map_err(SignatureError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBLIC_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+
+    const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1633700835\tfile:test\tprehashed
+wLMDjy9FLAuxZ3q4NlEvkgtyhrr0gtTu6KC4KBJdITbbOeAi1zBIYo0v4iTgt8jJpIidRJnp94ABQkJAgAooBQ==";
+
+    #[test]
+    fn test_verify_valid_signature() {
+        let key = VerificationKey::from_str(PUBLIC_KEY).unwrap();
+        key.verify(b"test", SIGNATURE).unwrap();
+    }
+
+    #[test]
+    fn test_verify_key_file() {
+        let key = VerificationKey::decode(&format!(
+            "untrusted comment: minisign public key\n{PUBLIC_KEY}\n"
+        ))
+        .unwrap();
+        key.verify(b"test", SIGNATURE).unwrap();
+    }
+
+    #[test]
+    fn test_verify_tampered_contents() {
+        let key = VerificationKey::from_str(PUBLIC_KEY).unwrap();
+        assert!(key.verify(b"12:13:14:15:16:17", SIGNATURE).is_err());
+    }
+
+    #[test]
+    fn test_verify_malformed_signature() {
+        let key = VerificationKey::from_str(PUBLIC_KEY).unwrap();
+        assert!(key.verify(b"test", "not a signature").is_err());
+    }
+
+    #[test]
+    fn test_invalid_key() {
+        assert!(VerificationKey::from_str("RWQ").is_err());
+    }
+}
@@ -64,11 +64,30 @@
 //! socket.send_magic_packet(mac_address, None, (Ipv4Addr::BROADCAST, 9)).unwrap();
 //! ```
 //!
+//! ## Send magic packets over TCP
+//!
+//! Some gateways and relays only accept magic packets over TCP, and then send
+//! them on as UDP broadcast on their network.  Use [`send_magic_packet_tcp`] to
+//! send a magic packet to these:
+//!
+//! ```no_run
+//! use std::net::{Ipv4Addr, SocketAddr};
+//! use std::time::Duration;
+//! let mac_address = wol::MacAddress::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x17]);
+//! let gateway = SocketAddr::from((Ipv4Addr::new(192, 0, 2, 1), 9));
+//! wol::send_magic_packet_tcp(mac_address, None, gateway, Duration::from_secs(5)).unwrap();
+//! ```
+//!
 //! ## Assemble magic packets
 //!
 //! To send magic packets over other socket APIs, use [`fill_magic_packet`] or [`write_magic_packet`]
 //! to assmble magic packets.
 //!
+//! ## Parse magic packets
+//!
+//! Use [`parse_magic_packet`] to find the hardware address and SecureON token
+//! in a received magic packet.
+//!
 //! ## SecureON
 //!
 //! This crate supports SecureON magic packets.
@@ -76,14 +95,17 @@
 use std::error::Error;
 use std::fmt::Display;
 use std::io::Write;
-use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::net::{
+    IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket,
+};
 use std::str::FromStr;
+use std::time::Duration;
 
 #[cfg(feature = "file")]
 pub mod file;
 
 /// A MAC address as a newtype wrapper around `[u8; 6]`.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct MacAddress([u8; 6]);
 
 impl MacAddress {
@@ -352,6 +374,31 @@ pub fn write_magic_packet<W: Write>(
     Ok(())
 }
 
+/// Parse a magic packet.
+///
+/// Find a magic packet in `payload`, i.e. six bytes `0xFF` followed by 16
+/// repetitions of a hardware address, and return the hardware address.  If
+/// exactly six more bytes follow the magic packet, return them as SecureON
+/// token.
+///
+/// Return `None` if `payload` contains no magic packet.
+#[must_use]
+pub fn parse_magic_packet(payload: &[u8]) -> Option<(MacAddress, Option<SecureOn>)> {
+    (0..payload.len().saturating_sub(101)).find_map(|start| {
+        let (sync, repetitions) = payload.get(start..start + 102)?.split_at(6);
+        let mut repetitions = repetitions.chunks_exact(6);
+        let first = repetitions.next()?;
+        if sync != [0xff; 6] || !repetitions.all(|repetition| repetition == first) {
+            return None;
+        }
+        let secure_on = payload
+            .get(start + 102..)
+            .and_then(|rest| <[u8; 6]>::try_from(rest).ok())
+            .map(SecureOn::new);
+        Some((MacAddress::new(<[u8; 6]>::try_from(first).ok()?), secure_on))
+    })
+}
+
 /// A socket which supports sending a magic packet.
 pub trait SendMagicPacket {
     /// Send a magic packet over this socket.
@@ -447,11 +494,43 @@ pub fn send_magic_packet(
     socket.send_magic_packet(mac_address, secure_on, addr)
 }
 
+/// Send one magic packet over TCP.
+///
+/// Connect to `addr`, waiting at most `timeout` for the connection, and write
+/// a magic packet to wake up `mac_address` to the connection.  Then close the
+/// connection.
+///
+/// Only gateways or relays which accept magic packets over TCP and send them
+/// on to their network understand these; a sleeping system never accepts a TCP
+/// connection, so `addr` must never be a broadcast address or the address of
+/// the system to wake up.
+///
+/// If `secure_on` is not `None`, include the SecureON token in the magic
+/// packet. See [`SecureOn`] for more information about SecureON.
+///
+/// # Errors
+///
+/// Return errors from connecting, or from writing the magic packet.
+pub fn send_magic_packet_tcp(
+    mac_address: MacAddress,
+    secure_on: Option<SecureOn>,
+    addr: SocketAddr,
+    timeout: Duration,
+) -> std::io::Result<()> {
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_nodelay(true)?;
+    stream.set_write_timeout(Some(timeout))?;
+    let mut packet = Vec::with_capacity(108);
+    write_magic_packet(&mut packet, mac_address, secure_on)?;
+    stream.write_all(&packet)?;
+    stream.shutdown(Shutdown::Write)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::{fill_magic_packet, fill_magic_packet_secure_on};
 
-    use super::{MacAddress, write_magic_packet};
+    use super::{MacAddress, SecureOn, parse_magic_packet, write_magic_packet};
 
     mod parse {
         use super::super::*;
@@ -598,4 +677,61 @@ mod tests {
         ];
         assert_eq!(buffer.as_slice(), expected_packet.as_slice());
     }
+
+    #[test]
+    fn test_send_magic_packet_tcp() {
+        use std::io::Read;
+        use std::net::{Ipv4Addr, TcpListener};
+        use std::time::Duration;
+
+        let mac_address = MacAddress::from([0x26, 0xCE, 0x55, 0xA5, 0xC2, 0x33]);
+        let secure_on = SecureOn::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x42]);
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let receiver = std::thread::spawn(move || {
+            let mut payload = Vec::new();
+            listener
+                .accept()
+                .unwrap()
+                .0
+                .read_to_end(&mut payload)
+                .unwrap();
+            payload
+        });
+        crate::send_magic_packet_tcp(mac_address, Some(secure_on), addr, Duration::from_secs(5))
+            .unwrap();
+        let payload = receiver.join().unwrap();
+        assert_eq!(payload.len(), 108);
+        assert_eq!(
+            parse_magic_packet(&payload),
+            Some((mac_address, Some(secure_on)))
+        );
+    }
+
+    #[test]
+    fn test_parse_magic_packet() {
+        let mac_address = MacAddress::from([0x26, 0xCE, 0x55, 0xA5, 0xC2, 0x33]);
+        let secure_on = SecureOn::from([0x12, 0x13, 0x14, 0x15, 0x16, 0x42]);
+        let mut packet = Vec::new();
+        write_magic_packet(&mut packet, mac_address, None).unwrap();
+        assert_eq!(parse_magic_packet(&packet), Some((mac_address, None)));
+
+        let mut packet = Vec::new();
+        write_magic_packet(&mut packet, mac_address, Some(secure_on)).unwrap();
+        assert_eq!(
+            parse_magic_packet(&packet),
+            Some((mac_address, Some(secure_on)))
+        );
+
+        // Find magic packets within other data
+        let mut packet = vec![0xff, 0x42];
+        write_magic_packet(&mut packet, mac_address, None).unwrap();
+        packet.extend_from_slice(b"trailing data");
+        assert_eq!(parse_magic_packet(&packet), Some((mac_address, None)));
+
+        packet.truncate(100);
+        assert_eq!(parse_magic_packet(&packet), None);
+        assert_eq!(parse_magic_packet(&[0xff; 6]), None);
+        assert_eq!(parse_magic_packet(b""), None);
+    }
 }
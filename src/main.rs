@@ -30,87 +30,41 @@
 )]
 #![forbid(unsafe_code)]
 
-use std::fs::File;
-use std::io::{BufReader, Error, ErrorKind, Result, stdin};
-use std::net::{SocketAddr, ToSocketAddrs};
-use std::path::PathBuf;
+use std::io::{Error, ErrorKind, Result};
 use std::process::ExitCode;
-use std::str::FromStr;
-use std::thread::sleep;
-use std::time::Duration;
 
-use clap::{ArgAction, Parser, ValueHint, builder::ArgPredicate};
-use wol::file::MagicPacketDestination;
-use wol::{MacAddress, SecureOn};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
 
-#[derive(Debug)]
-struct ResolvedWakeUpTarget {
-    hardware_address: MacAddress,
-    socket_addr: SocketAddr,
-    secure_on: Option<SecureOn>,
-}
-
-#[derive(Debug, Default, Clone, Copy)]
-enum ResolveMode {
-    #[default]
-    Default,
-    PreferIpv6,
-}
-
-#[derive(Debug)]
-struct WakeUpTarget {
-    hardware_address: MacAddress,
-    host: MagicPacketDestination,
-    port: u16,
-    secure_on: Option<SecureOn>,
-}
-
-impl WakeUpTarget {
-    fn resolve(&self, mode: ResolveMode) -> Result<ResolvedWakeUpTarget> {
-        match &self.host {
-            MagicPacketDestination::Dns(dns) => {
-                let mut socket_addrs = (dns.as_str(), self.port).to_socket_addrs()?;
-                let socket_addr = match mode {
-                    ResolveMode::Default => socket_addrs.next(),
-                    ResolveMode::PreferIpv6 => socket_addrs.find(SocketAddr::is_ipv6),
-                };
-                if let Some(socket_addr) = socket_addr {
-                    Ok(ResolvedWakeUpTarget {
-                        hardware_address: self.hardware_address,
-                        socket_addr,
-                        secure_on: self.secure_on,
-                    })
-                } else {
-                    Err(Error::new(
-                        ErrorKind::HostUnreachable,
-                        format!("Host {dns} not reachable"),
-                    ))
-                }
-            }
-            MagicPacketDestination::Ip(ip_addr) => Ok(ResolvedWakeUpTarget {
-                hardware_address: self.hardware_address,
-                socket_addr: SocketAddr::new(*ip_addr, self.port),
-                secure_on: self.secure_on,
-            }),
-        }
-    }
-}
+mod cli;
 
-#[derive(Debug, Clone)]
-enum PathOrStdin {
-    Stdin,
-    Path(PathBuf),
-}
-
-impl From<String> for PathOrStdin {
-    fn from(value: String) -> Self {
-        if value == "-" {
-            Self::Stdin
-        } else {
-            Self::Path(value.into())
-        }
-    }
-}
+use cli::check::CheckArgs;
+use cli::config::Config;
+#[cfg(unix)]
+use cli::control::CtlArgs;
+use cli::discover::DiscoverArgs;
+use cli::doctor::DoctorArgs;
+use cli::edit::EditArgs;
+use cli::export::ExportArgs;
+use cli::fmt::FmtArgs;
+use cli::history::HistoryArgs;
+use cli::import::ImportArgs;
+use cli::inspect::InspectArgs;
+use cli::list::ListArgs;
+use cli::listen::ListenArgs;
+use cli::log::LogArgs;
+use cli::manage::{AddArgs, RemoveArgs, RenameArgs, SetArgs};
+#[cfg(feature = "relay")]
+use cli::relay::RelayArgs;
+use cli::resolve::ResolveArgs;
+use cli::scan::ScanArgs;
+use cli::secureon::GenSecureOnArgs;
+use cli::serve::ServeArgs;
+use cli::sleep::SleepArgs;
+use cli::status::StatusArgs;
+#[cfg(feature = "tui")]
+use cli::tui::TuiArgs;
+use cli::validate::ValidateArgs;
+use cli::wake::WakeArgs;
 
 const AFTER_HELP: &str = "Copyright (C) Sebastian Wiesner <sebastian@swsnr.de>
 https://codeberg.org/swsnr/wol.rs
@@ -119,146 +73,192 @@ Licensed under the EUPL
 
 See <https://interoperable-europe.ec.europa.eu/collection/eupl/eupl-text-eupl-12>";
 
-#[derive(Parser, Debug, Clone)]
-#[command(
-    version,
-    about,
-    disable_help_flag = true,
-
-    after_help = AFTER_HELP
-)]
-#[group()]
-struct CliArgs {
-    /// Show this help message.
-    #[arg(short = '?', long = "help", action = ArgAction::Help)]
-    help: (),
-    /// Send the magic packet to HOST.
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Wake up systems.
+    ///
+    /// This is the default command, if no command is given.
+    Wake(Box<WakeArgs>),
+    /// List systems in the hosts database.
+    List(ListArgs),
+    /// Show which systems are online.
+    ///
+    /// Probe each system once, with ping or a TCP port, and
+    /// print whether it is up or down, to see which systems
+    /// need waking up.
+    #[command(verbatim_doc_comment)]
+    Status(StatusArgs),
+    /// Show who woke up which systems when.
+    ///
+    /// Print every wake attempt from the audit log, with its
+    /// time, the user who ran wol, the system, the destination
+    /// of magic packets, and the result.
+    #[command(verbatim_doc_comment)]
+    History(HistoryArgs),
+    /// Put systems to sleep.
+    ///
+    /// Send a Sleep-on-LAN packet, i.e. a magic packet for the
+    /// reversed hardware address, or an HTTP request to the
+    /// Sleep-on-LAN daemon on each system.
+    #[command(verbatim_doc_comment)]
+    Sleep(SleepArgs),
+    /// Serve an HTTP API to wake up systems.
+    ///
+    /// Answer POST /wake/NAME, GET /hosts, and GET /status
+    /// with JSON, for home automation and other tools, backed
+    /// by the hosts database.
+    #[command(verbatim_doc_comment)]
+    Serve(ServeArgs),
+    /// Control a running wol serve.
+    ///
+    /// Reload it, wake up systems through it, or show its
+    /// state, over the Unix socket given to wol serve with
+    /// --control-socket.
+    #[cfg(unix)]
+    #[command(verbatim_doc_comment)]
+    Ctl(CtlArgs),
+    /// Relay wake requests from the internet.
+    ///
+    /// Receive encrypted wake requests from wol wake --relay, and
+    /// send magic packets on the network of the relay.
+    #[cfg(feature = "relay")]
+    #[command(verbatim_doc_comment)]
+    Relay(RelayArgs),
+    /// Pick systems to wake up interactively.
+    ///
+    /// Show all systems in the hosts database with their
+    /// current status; pick systems with space, and wake them
+    /// up with enter.
+    #[cfg(feature = "tui")]
+    #[command(verbatim_doc_comment)]
+    Tui(TuiArgs),
+    /// Check wakeup files for errors and suspicious systems.
+    ///
+    /// Exit with 4 if any file is invalid, with 1 if any file
+    /// has suspicious systems, e.g. duplicate hardware
+    /// addresses or unicast destinations, and with 0 otherwise.
+    #[command(verbatim_doc_comment)]
+    Validate(ValidateArgs),
+    /// Rewrite wakeup files into canonical form.
+    ///
+    /// Normalize hardware addresses and DNS names, align fields
+    /// in columns, sort systems by name, and drop duplicates.
+    #[command(alias = "normalize", verbatim_doc_comment)]
+    Fmt(FmtArgs),
+    /// Receive and display magic packets.
+    ///
+    /// Print every packet received on the given ports, with
+    /// its source and the hardware address of the magic packet,
+    /// to check whether magic packets arrive at a system.
+    #[command(verbatim_doc_comment)]
+    Listen(ListenArgs),
+    /// Find magic packets in a capture file.
+    ///
+    /// Print every magic packet in a pcap or pcapng file, with
+    /// its time, source, and destination, including magic
+    /// packets sent over Ethernet, and magic packets embedded
+    /// in other payloads.
+    #[command(verbatim_doc_comment)]
+    Inspect(InspectArgs),
+    /// Discover systems on a subnet.
     ///
-    /// HOST may either be a DNS name, or an IPv4/IPv6 address.
-    /// HOST may and most likely will be different from the
-    /// target system to wake up: Instead the magic packet needs
-    /// to be sent so that it physically passes the system to
-    /// wake up.  As such, you will most likely want to use a
-    /// broadcast or multicast address here.
+    /// Probe every address in an IPv4 subnet, and print the IP
+    /// address, hardware address, and vendor of every system
+    /// which answers, as well as systems from the hosts
+    /// database in the subnet which stay silent.
+    #[command(verbatim_doc_comment)]
+    Scan(ScanArgs),
+    /// Discover systems passively.
     ///
-    /// Defaults to the IPv4 broadcast address 255.255.255.255
-    /// or the IPv6 `ff02::1`, if --ipv6 is given.
-    #[arg(
-        short = 'h',
-        long = "host",
-        visible_short_alias = 'i',
-        visible_alias = "ipaddr",
-        default_value = "255.255.255.255",
-        default_value_if("ipv6", ArgPredicate::IsPresent, Some("ff02::1")),
-        verbatim_doc_comment
-    )]
-    host: MagicPacketDestination,
-    /// Prefer IPv6 addresses over IPv4 for DNS resolution.
+    /// Listen for DHCP broadcasts and watch the neighbour table
+    /// for a while, and print the hardware address, IP address,
+    /// and hostname of every system overheard, for networks
+    /// which do not permit active scanning.
+    #[command(verbatim_doc_comment)]
+    Discover(DiscoverArgs),
+    /// Find the hardware address of a system.
     ///
-    /// This only affects DNS resolution for hostnames
-    /// given to --host; literal IPv4 and IPv6 addresses will
-    /// always use the respective protocol.
+    /// Look up the IP address or hostname of a system in the
+    /// neighbour table, and print its hardware address.
+    #[command(verbatim_doc_comment)]
+    Resolve(ResolveArgs),
+    /// Check Wake-on-LAN on network interfaces
     ///
-    /// If omitted use the first resolved address returned
-    /// by the operating system, regardless of whether it is
-    /// an IPv4 or IPv6 address.
-    #[arg(short = '6', long = "ipv6")]
-    ipv6: bool,
-    /// Send the magic packet to PORT.
-    #[arg(
-        short = 'p',
-        long = "port",
-        default_value = "40000",
-        verbatim_doc_comment
-    )]
-    port: u16,
-    /// Read systems to wake up from FILE.
+    /// Check whether network interfaces wake up on magic packets.
+    /// Read the Wake-on-LAN settings of network interfaces with
+    /// ethtool, on the system to wake up.  Exit with 1 if any
+    /// interface does not wake up on magic packets.
+    #[command(verbatim_doc_comment)]
+    Check(CheckArgs),
+    /// Diagnose problems with sending magic packets.
     ///
-    /// Read lines of hardware address, and (optionally) IP
-    /// addresses/hostnames, ports, and SecureON passwords from
-    /// FILE, or stdin, if FILE is -.
+    /// Check whether broadcasts are permitted, list network
+    /// interfaces with their broadcast addresses, and check
+    /// IPv6, Wake-on-LAN settings, and firewalls.  Exit with 1
+    /// if magic packets cannot be sent at all.
+    #[command(verbatim_doc_comment)]
+    Doctor(DoctorArgs),
+    /// Generate random SecureON tokens.
     ///
-    /// Fields in each line are separated by one or more spaces
-    /// or tabs; for each missing field the value of the
-    /// corresponding option or the global default will be used.
-    #[arg(short = 'f', long = "file", value_hint = ValueHint::FilePath)]
-    file: Option<PathOrStdin>,
-    /// Verbose output.
-    #[arg(short = 'v', long = "verbose")]
-    verbose: bool,
-    /// Wait after each magic packet.
+    /// Print tokens from the random number generator of the
+    /// operating system, to set in the firmware of systems and
+    /// in wakeup files or the hosts database.
+    #[command(name = "gen-secureon", verbatim_doc_comment)]
+    GenSecureOn(GenSecureOnArgs),
+    /// Add a system to the hosts database.
+    Add(AddArgs),
+    /// Remove a system from the hosts database.
+    Remove(RemoveArgs),
+    /// Rename a system in the hosts database.
+    Rename(RenameArgs),
+    /// Import systems from other inventories.
     ///
-    /// After each magic packet wait for the given number of
-    /// milliseconds; use this to avoid waking up too many
-    /// systems too fast.
-    #[arg(
-        short = 'w',
-        long = "wait",
-        value_name = "MSECS",
-        value_parser = |v: &str| u64::from_str(v).map(Duration::from_millis),
-        verbatim_doc_comment
-    )]
-    wait: Option<Duration>,
-    /// Include the given SecureON password in the magic packet.
+    /// Merge systems from ethers files, DHCP leases, or CSV
+    /// into the hosts database or a wakeup file.  Add fields
+    /// which existing systems lack, and skip systems which
+    /// conflict with existing systems; exit with 1 if there
+    /// were any conflicts.
+    #[command(verbatim_doc_comment)]
+    Import(ImportArgs),
+    /// Export systems to other formats.
     ///
-    /// The password is in the same format as a MAC address, i.e.
-    /// XX-XX-XX-XX-XX-XX or XX:XX:XX:XX:XX:XX.
-    #[arg(long = "passwd")]
-    passwd: Option<SecureOn>,
-    /// Hardware addresses to wake up.
-    #[arg(
-        value_name = "MAC-ADDRESS",
-        required_unless_present("file"),
-        verbatim_doc_comment
-    )]
-    hardware_addresses: Vec<wol::MacAddress>,
+    /// Print systems from the hosts database or from wakeup
+    /// files as wakeup file, hosts database, JSON, or CSV.
+    #[command(verbatim_doc_comment)]
+    Export(ExportArgs),
+    /// Change a system in the hosts database.
+    ///
+    /// Set KEY of the system NAME to VALUE, or remove KEY if
+    /// VALUE is empty.
+    #[command(verbatim_doc_comment)]
+    Set(SetArgs),
+    /// Edit the hosts database or a wakeup file.
+    ///
+    /// Open a copy in $VISUAL or $EDITOR, and only install it
+    /// if it is valid, like visudo.  Exit with 4 if the copy
+    /// was invalid and not installed.
+    #[command(verbatim_doc_comment)]
+    Edit(EditArgs),
 }
 
-impl CliArgs {
-    fn iter_file(&self) -> Result<Box<dyn Iterator<Item = Result<wol::file::WakeUpTarget>>>> {
-        match &self.file {
-            Some(PathOrStdin::Stdin) => {
-                Ok(Box::new(wol::file::from_reader(BufReader::new(stdin()))))
-            }
-            Some(PathOrStdin::Path(path)) => Ok(Box::new(wol::file::from_reader(BufReader::new(
-                File::open(path)?,
-            )))),
-            None => Ok(Box::new(std::iter::empty())),
-        }
-    }
-
-    fn targets(&self) -> Result<impl Iterator<Item = Result<WakeUpTarget>>> {
-        let file_targets = self.iter_file()?.map(|target| {
-            target.map(|target| WakeUpTarget {
-                hardware_address: target.hardware_address(),
-                host: target
-                    .packet_destination()
-                    .cloned()
-                    .unwrap_or(self.host.clone()),
-                port: target.port().unwrap_or(self.port),
-                secure_on: target.secure_on().or(self.passwd),
-            })
-        });
-        let cli_targets = self
-            .hardware_addresses
-            .iter()
-            .map(move |hardware_address| WakeUpTarget {
-                hardware_address: *hardware_address,
-                host: self.host.clone(),
-                port: self.port,
-                secure_on: self.passwd,
-            })
-            .map(Ok);
-        Ok(file_targets.chain(cli_targets))
-    }
+#[derive(Parser, Debug, Clone)]
+#[command(
+    version,
+    about,
+    disable_help_flag = true,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
 
-    fn resolve_mode(&self) -> ResolveMode {
-        if self.ipv6 {
-            ResolveMode::PreferIpv6
-        } else {
-            ResolveMode::Default
-        }
-    }
+    after_help = AFTER_HELP
+)]
+struct CliArgs {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Arguments to wake up systems without a command.
+    #[command(flatten)]
+    wake: WakeArgs,
+    #[command(flatten)]
+    log: LogArgs,
 }
 
 #[derive(Debug, Parser)]
@@ -266,6 +266,8 @@ impl CliArgs {
     version,
     about,
     disable_help_flag = true,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
 
     after_help = AFTER_HELP
 )]
@@ -282,34 +284,15 @@ struct Cli {
     completions: Option<clap_complete::Shell>,
 }
 
-fn wakeup(target: &WakeUpTarget, mode: ResolveMode, verbose: bool) -> Result<()> {
-    if verbose {
-        println!(
-            "Waking up {} with {}:{}...",
-            target.hardware_address, target.host, target.port
-        );
-    } else {
-        println!("Waking up {}...", target.hardware_address);
-    }
-    let target = target.resolve(mode)?;
-    wol::send_magic_packet(
-        target.hardware_address,
-        target.secure_on,
-        target.socket_addr,
-    )
-}
-
 fn process_cli(cli: Cli) -> Result<ExitCode> {
     #[cfg(feature = "manpage")]
     if cli.manpage {
-        use clap::CommandFactory;
         clap_mangen::Man::new(CliArgs::command()).render(&mut std::io::stdout())?;
         return Ok(ExitCode::SUCCESS);
     }
 
     #[cfg(feature = "completions")]
     if let Some(shell) = cli.completions {
-        use clap::CommandFactory;
         clap_complete::generate(
             shell,
             &mut CliArgs::command(),
@@ -320,28 +303,92 @@ fn process_cli(cli: Cli) -> Result<ExitCode> {
     }
 
     let args = cli.args;
-    let resolve_mode = args.resolve_mode();
-    let mut exit_code = ExitCode::SUCCESS;
-    for (i, target) in args.targets()?.enumerate() {
-        let target = target?;
-        if 0 < i {
-            if let Some(wait) = args.wait.filter(|d| !d.is_zero()) {
-                sleep(wait);
-            }
+    args.log.init()?;
+    match args.command {
+        Some(Command::Wake(wake)) => Ok(cli::wake::wake(&wake)),
+        Some(Command::List(args)) => cli::list::list(&args).map(|()| ExitCode::SUCCESS),
+        Some(Command::Status(args)) => cli::status::status(&args).map(|()| ExitCode::SUCCESS),
+        Some(Command::History(args)) => cli::history::history(&args).map(|()| ExitCode::SUCCESS),
+        Some(Command::Sleep(args)) => cli::sleep::sleep(&args),
+        Some(Command::Serve(args)) => {
+            cli::serve::serve(&args, reread_serve_args).map(|()| ExitCode::SUCCESS)
         }
-        if let Err(error) = wakeup(&target, resolve_mode, args.verbose) {
-            // Do not exit early; instead attempt to wake up all devices even if one fails.
-            eprintln!("Failed to wake up {}: {error}", target.hardware_address);
-            // But indicate failure in the exit code
-            exit_code = ExitCode::FAILURE;
+        #[cfg(unix)]
+        Some(Command::Ctl(args)) => cli::control::ctl(&args),
+        #[cfg(feature = "relay")]
+        Some(Command::Relay(args)) => cli::relay::relay(&args),
+        #[cfg(feature = "tui")]
+        Some(Command::Tui(args)) => cli::tui::tui(&args),
+        Some(Command::Validate(args)) => cli::validate::validate(&args),
+        Some(Command::Fmt(args)) => cli::fmt::fmt(&args),
+        Some(Command::Listen(args)) => cli::listen::listen(&args),
+        Some(Command::Inspect(args)) => cli::inspect::inspect(&args).map(|()| ExitCode::SUCCESS),
+        Some(Command::Scan(args)) => cli::scan::scan(&args).map(|()| ExitCode::SUCCESS),
+        Some(Command::Discover(args)) => cli::discover::discover(&args).map(|()| ExitCode::SUCCESS),
+        Some(Command::Resolve(args)) => cli::resolve::resolve(&args).map(|()| ExitCode::SUCCESS),
+        Some(Command::Check(args)) => cli::check::check(&args),
+        Some(Command::Doctor(args)) => cli::doctor::doctor(&args),
+        Some(Command::GenSecureOn(args)) => {
+            cli::secureon::gen_secureon(&args).map(|()| ExitCode::SUCCESS)
         }
+        Some(Command::Add(args)) => cli::manage::add(&args).map(|()| ExitCode::SUCCESS),
+        Some(Command::Remove(args)) => cli::manage::remove(&args).map(|()| ExitCode::SUCCESS),
+        Some(Command::Rename(args)) => cli::manage::rename(&args).map(|()| ExitCode::SUCCESS),
+        Some(Command::Import(args)) => cli::import::import(&args),
+        Some(Command::Export(args)) => cli::export::export(&args).map(|()| ExitCode::SUCCESS),
+        Some(Command::Set(args)) => cli::manage::set(&args).map(|()| ExitCode::SUCCESS),
+        Some(Command::Edit(args)) => cli::edit::edit(&args),
+        None => Ok(cli::wake::wake(&args.wake)),
     }
+}
 
-    Ok(exit_code)
+/// The command line interface, with defaults from configuration files and the
+/// environment.
+fn command() -> Result<clap::Command> {
+    let command = Cli::command();
+    let env = Config::from_env(std::env::vars_os(), &command);
+    Config::read_all().and_then(|config| config.merge(env).apply(command))
+}
+
+/// Parse the arguments of `wol serve` again, with the current configuration
+/// files.
+fn reread_serve_args() -> Result<ServeArgs> {
+    let args = cli::argfile::expand(std::env::args_os())?;
+    let invalid = |error: clap::Error| Error::new(ErrorKind::InvalidInput, error.to_string());
+    let matches = command()?.try_get_matches_from(args).map_err(invalid)?;
+    match Cli::from_arg_matches(&matches)
+        .map_err(invalid)?
+        .args
+        .command
+    {
+        Some(Command::Serve(args)) => Ok(args),
+        _ => Err(Error::new(
+            ErrorKind::InvalidInput,
+            "The command line no longer serves the HTTP API",
+        )),
+    }
 }
 
 fn main() -> ExitCode {
-    match process_cli(Cli::parse()) {
+    #[cfg(feature = "dynamic-completions")]
+    clap_complete::CompleteEnv::with_factory(Cli::command).complete();
+    let args = match cli::argfile::expand(std::env::args_os()) {
+        Ok(args) => args,
+        Err(error) => {
+            eprintln!("{error}");
+            return ExitCode::from(2);
+        }
+    };
+    let command = match command() {
+        Ok(command) => command,
+        Err(error) => {
+            eprintln!("{error}");
+            return ExitCode::from(2);
+        }
+    };
+    let cli =
+        Cli::from_arg_matches(&command.get_matches_from(args)).unwrap_or_else(|error| error.exit());
+    match process_cli(cli) {
         Err(error) => {
             eprintln!("{error}");
             ExitCode::FAILURE
@@ -349,3 +396,15 @@ fn main() -> ExitCode {
         Ok(exit_code) => exit_code,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::CommandFactory;
+
+    use super::*;
+
+    #[test]
+    fn verify_cli() {
+        Cli::command().debug_assert();
+    }
+}